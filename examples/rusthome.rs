@@ -17,10 +17,9 @@ use std::io;
 use std::str::FromStr;
 
 use futures::Future;
-use native_tls::TlsConnector;
 use tokio_core::net::TcpStream;
 use tokio_core::reactor::Core;
-use tokio_tls::TlsConnectorExt;
+use tokio_tls::TlsConnector;
 use domain::bits::DNameBuf;
 use domain::resolv::Resolver;
 use domain::resolv::lookup::lookup_host;
@@ -32,13 +31,13 @@ fn main() {
     let resolver = Resolver::new(&handle);
     let addr = lookup_host(resolver, DNameBuf::from_str("www.rust-lang.org")
                                               .unwrap()).map_err(Into::into);
-    let cx = TlsConnector::builder().unwrap().build().unwrap();
+    let cx = TlsConnector::from(native_tls::TlsConnector::new().unwrap());
     let socket = addr.and_then(|addr| {
         TcpStream::connect(&addr.port_iter(443).next().unwrap(), &handle)
     });
 
     let tls_handshake = socket.and_then(|socket| {
-        let tls = cx.connect_async("www.rust-lang.org", socket);
+        let tls = cx.connect("www.rust-lang.org", socket);
         tls.map_err(|e| {
             io::Error::new(io::ErrorKind::Other, e)
         })
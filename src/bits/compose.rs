@@ -242,6 +242,19 @@ impl Composer {
     pub fn is_truncated(&self) -> bool {
         self.truncated
     }
+
+    /// Returns the number of bytes that can still be added.
+    ///
+    /// Returns `None` if the composer is unlimited.
+    pub fn remaining(&self) -> Option<usize> {
+        if self.truncated { return Some(0) }
+        let maxlen = match self.mode {
+            ComposeMode::Unlimited => return None,
+            ComposeMode::Limited(len) => len,
+            ComposeMode::Stream => 0xFFFF,
+        };
+        Some(maxlen.saturating_sub(self.vec.len() + self.start))
+    }
 }
 
 
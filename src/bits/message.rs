@@ -16,8 +16,9 @@
 use std::collections::HashMap;
 use std::{borrow, mem, ops};
 use std::marker::PhantomData;
-use ::iana::{Rcode, Rtype};
+use ::iana::{OptRcode, Rcode, Rtype};
 use ::rdata::Cname;
+use super::opt::Opt;
 use super::{HeaderSection, GenericRecord, Header, HeaderCounts, ParsedDName,
             ParsedRecordData, Parser, ParseError, ParseResult, Question,
             Record};
@@ -311,6 +312,34 @@ impl Message {
         self.first_question().map(|x| x.qtype())
     }
 
+    /// Returns the OPT record of the message, if there is one.
+    ///
+    /// The method will return `None` both if there is no OPT record in
+    /// the additional section or if parsing fails.
+    pub fn opt(&self) -> Option<Record<ParsedDName, Opt>> {
+        let additional = match self.additional() {
+            Ok(additional) => additional,
+            Err(..) => return None
+        };
+        match additional.limit_to::<Opt>().next() {
+            Some(Ok(record)) => Some(record),
+            _ => None
+        }
+    }
+
+    /// Returns the extended rcode of the message.
+    ///
+    /// The lower four bits are taken from the header while the upper eight
+    /// bits are taken from the OPT record, if there is one. If there isn’t,
+    /// the result is simply the header’s rcode.
+    pub fn opt_rcode(&self) -> OptRcode {
+        let ext = match self.opt() {
+            Some(opt) => (opt.ttl() >> 24) as u8,
+            None => 0
+        };
+        OptRcode::from_parts(self.header().rcode(), ext)
+    }
+
     /// Returns whether the message contains answers of a given type.
     pub fn contains_answer<'a, D: ParsedRecordData<'a>>(&'a self) -> bool {
         let answer = match self.answer() {
@@ -784,7 +813,7 @@ mod test {
     use bits::compose::ComposeMode;
     use bits::message_builder::MessageBuilder;
     use bits::name::DNameBuf;
-    use iana::Rtype;
+    use iana::{OptRcode, Rcode, Rtype};
     use rdata::owned::Cname;
     use super::*;
 
@@ -827,4 +856,24 @@ mod test {
         assert_eq!(DNameBuf::from_str("baz.example.com.").unwrap(),
                    msg.canonical_name().unwrap());
     }
+
+    #[test]
+    fn opt_rcode() {
+        // Message without OPT record.
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited,
+                                          true).unwrap();
+        msg.header_mut().set_rcode(Rcode::FormErr);
+        let msg = MessageBuf::from_vec(msg.finish()).unwrap();
+        assert!(msg.opt().is_none());
+        assert_eq!(msg.opt_rcode().to_int(), 1);
+
+        // Message with OPT record and extended rcode.
+        let msg = MessageBuilder::new(ComposeMode::Unlimited,
+                                      true).unwrap();
+        let msg = msg.additional().build_opt(1232, OptRcode::BadVers, false)
+                     .unwrap().complete().unwrap();
+        let msg = MessageBuf::from_vec(msg.finish()).unwrap();
+        assert_eq!(msg.opt().unwrap().class().to_int(), 1232);
+        assert_eq!(msg.opt_rcode().to_int(), 16);
+    }
 }
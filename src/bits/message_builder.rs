@@ -83,13 +83,14 @@
 //! [`new()`]: struct.MessageBuilder.html#method.new
 //! [`from_vec()`]: struct.MessageBuilder.html#method.from_vec
 
-use std::mem;
+use std::{cmp, mem};
 use ::iana::{Class, OptRcode, Rtype};
 use super::{Composer, ComposeError, ComposeMode, ComposeResult,
             ComposeSnapshot, DName, DNameSlice, HeaderSection, Header,
             HeaderCounts, Message, Question, Record, RecordData};
 use super::record::RecordBuilder;
 use super::opt::OptData;
+use super::opt::rfc7830::{Padding, PaddingMode, PaddingPolicy};
 
 
 //------------ MessageBuilder -----------------------------------------------
@@ -535,6 +536,29 @@ impl OptBuilder {
         option.compose(&mut self.builder)
     }
 
+    /// Pads the message according to the given policy.
+    ///
+    /// This pushes a padding option as defined in RFC 7830 whose length is
+    /// determined by `policy` based on the size of the message assembled
+    /// so far, including the four octets of the option header. The
+    /// padding octets are zero.
+    ///
+    /// Since padding needs to know the final size of the message, this
+    /// should be the last option pushed and the OPT record should be the
+    /// last record of the message. If the message is size limited, the
+    /// padding is cut short so the message doesn’t exceed the limit.
+    pub fn pad(&mut self, policy: PaddingPolicy) -> ComposeResult<()> {
+        let composer = self.builder.as_mut();
+        let mut len = policy.padding_len(composer.so_far().len());
+        if let Some(remaining) = composer.remaining() {
+            if remaining < 4 {
+                return Ok(())
+            }
+            len = cmp::min(len as usize, remaining - 4) as u16;
+        }
+        self.push(Padding::new(len, PaddingMode::Zero))
+    }
+
     /// Completes the OPT record and returns the additional section builder.
     pub fn complete(self) -> ComposeResult<AdditionalBuilder> {
         let mut target = MessageTarget { composer: self.builder.finish()? };
@@ -965,7 +965,9 @@ impl<'a> hash::Hash for Labelette<'a> {
             }
             Labelette::Normal(slice) => {
                 state.write_u8(2);
-                state.write(slice)
+                for ch in slice {
+                    state.write_u8(ch.to_ascii_lowercase())
+                }
             }
         }
     }
@@ -1029,4 +1031,21 @@ mod test {
         assert_eq!(bits, b"11010000011101");
     }
 
+    #[test]
+    fn labelette_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash(labelette: &Labelette) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            labelette.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let upper = Labelette::Normal(b"WwW");
+        let lower = Labelette::Normal(b"www");
+        assert_eq!(upper, lower);
+        assert_eq!(hash(&upper), hash(&lower));
+        assert!(hash(&lower) != hash(&Labelette::Normal(b"ftp")));
+    }
 }
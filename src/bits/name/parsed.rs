@@ -85,6 +85,19 @@ impl<'a> ParsedDName<'a> {
         }
     }
 
+    /// Creates a parsed domain name from an uncompressed absolute name.
+    ///
+    /// This is useful if a parsed domain name is expected but the name
+    /// didn’t actually come from a message.
+    ///
+    /// # Panics
+    ///
+    /// The function panics if `name` is not absolute.
+    pub fn from_slice(name: &'a DNameSlice) -> Self {
+        assert!(name.is_absolute());
+        ParsedDName{message: name.as_bytes(), start: 0}
+    }
+
     /// Unpacks the name.
     ///
     /// This will return the cow’s borrowed variant for any parsed name that
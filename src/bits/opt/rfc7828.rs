@@ -1,21 +1,34 @@
 //! EDNS Options from RFC 7828
 
-use ::bits::{Composer, ComposeResult, Parser, ParseResult};
+use ::bits::{Composer, ComposeResult, Parser, ParseError, ParseResult};
 use ::iana::OptionCode;
 use super::{OptData, ParsedOptData};
 
 
 //------------ TcpKeepalive --------------------------------------------------
 
+/// The edns-tcp-keepalive Option.
+///
+/// In requests, the option doesn’t carry a timeout. In responses, it
+/// contains the idle timeout in units of 100 milliseconds.
+///
+/// Specified in RFC 7828.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub struct TcpKeepalive(u16);
+pub struct TcpKeepalive(Option<u16>);
 
 impl TcpKeepalive {
+    /// Creates a new option with the given timeout.
     pub fn new(timeout: u16) -> Self {
-        TcpKeepalive(timeout)
+        TcpKeepalive(Some(timeout))
     }
 
-    pub fn timeout(&self) -> u16 {
+    /// Creates a new option without a timeout as used in requests.
+    pub fn empty() -> Self {
+        TcpKeepalive(None)
+    }
+
+    /// Returns the timeout in units of 100 milliseconds if there is one.
+    pub fn timeout(&self) -> Option<u16> {
         self.0
     }
 }
@@ -24,8 +37,13 @@ impl OptData for TcpKeepalive {
     fn compose<C: AsMut<Composer>>(&self, mut target: C) -> ComposeResult<()> {
         let target = target.as_mut();
         target.compose_u16(OptionCode::EdnsTcpKeepalive.into())?;
-        target.compose_u16(2)?;
-        target.compose_u16(self.0)
+        match self.0 {
+            Some(timeout) => {
+                target.compose_u16(2)?;
+                target.compose_u16(timeout)
+            }
+            None => target.compose_u16(0)
+        }
     }
 }
 
@@ -35,9 +53,42 @@ impl<'a> ParsedOptData<'a> for TcpKeepalive {
         if code != OptionCode::EdnsTcpKeepalive {
             return Ok(None)
         }
-        let timeout = parser.parse_u16()?;
-        parser.exhausted()?;
-        Ok(Some(Self::new(timeout)))
+        match parser.remaining() {
+            0 => Ok(Some(Self::empty())),
+            2 => Ok(Some(Self::new(parser.parse_u16()?))),
+            _ => Err(ParseError::FormErr)
+        }
     }
 }
 
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use ::bits::{Composer, ComposeMode, Parser};
+    use ::iana::OptionCode;
+    use super::super::{OptData, ParsedOptData};
+    use super::*;
+
+    fn compose(option: &TcpKeepalive) -> Vec<u8> {
+        let mut composer = Composer::new(ComposeMode::Unlimited, false);
+        option.compose(&mut composer).unwrap();
+        composer.finish()
+    }
+
+    fn parse(data: &[u8]) -> ParseResult<Option<TcpKeepalive>> {
+        let mut parser = Parser::new(data);
+        TcpKeepalive::parse(OptionCode::EdnsTcpKeepalive, &mut parser)
+    }
+
+    #[test]
+    fn compose_and_parse() {
+        assert_eq!(compose(&TcpKeepalive::empty()), b"\x00\x0b\x00\x00");
+        assert_eq!(compose(&TcpKeepalive::new(300)),
+                   b"\x00\x0b\x00\x02\x01\x2c");
+        assert_eq!(parse(b"").unwrap(), Some(TcpKeepalive::empty()));
+        assert_eq!(parse(b"\x01\x2c").unwrap(), Some(TcpKeepalive::new(300)));
+        assert!(parse(b"\x01").is_err());
+    }
+}
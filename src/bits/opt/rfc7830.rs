@@ -1,4 +1,7 @@
 //! EDNS Options from RFC 7830
+//!
+//! This module also contains the padding policies recommended by RFC 8467
+//! for deciding how much padding to add to a message.
 
 use rand::random;
 use ::bits::{Composer, ComposeResult, Parser, ParseResult};
@@ -66,3 +69,115 @@ impl<'a> ParsedOptData<'a> for Padding {
         Ok(Some(Padding::new(parser.remaining() as u16, PaddingMode::Zero)))
     }
 }
+
+
+//------------ PaddingPolicy -------------------------------------------------
+
+/// A policy for deciding how much padding to add to a message.
+///
+/// The policies are those described in RFC 8467. Use the `query()` and
+/// `response()` functions for the block lengths recommended there.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PaddingPolicy {
+    /// Pad the message to a multiple of the given block length.
+    ///
+    /// A block length of zero disables padding.
+    BlockLength(u16),
+
+    /// Add a random amount of padding of up to the given length.
+    Random(u16),
+}
+
+impl PaddingPolicy {
+    /// The block length for queries recommended by RFC 8467.
+    pub const QUERY_BLOCK_LENGTH: u16 = 128;
+
+    /// The block length for responses recommended by RFC 8467.
+    pub const RESPONSE_BLOCK_LENGTH: u16 = 468;
+
+    /// Returns the recommended policy for queries.
+    pub fn query() -> Self {
+        PaddingPolicy::BlockLength(Self::QUERY_BLOCK_LENGTH)
+    }
+
+    /// Returns the recommended policy for responses.
+    pub fn response() -> Self {
+        PaddingPolicy::BlockLength(Self::RESPONSE_BLOCK_LENGTH)
+    }
+
+    /// Returns the length of the padding for a message.
+    ///
+    /// The message is `len` octets long before the padding option is
+    /// added. The returned value is the length of the option’s data, ie.,
+    /// it does not include the four octets of the option header which are
+    /// taken into account when padding to a block boundary.
+    pub fn padding_len(&self, len: usize) -> u16 {
+        match *self {
+            PaddingPolicy::BlockLength(0) => 0,
+            PaddingPolicy::BlockLength(block) => {
+                let block = block as usize;
+                ((block - (len + 4) % block) % block) as u16
+            }
+            PaddingPolicy::Random(0) => 0,
+            PaddingPolicy::Random(max) => {
+                (random::<u32>() % (max as u32 + 1)) as u16
+            }
+        }
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use ::bits::{ComposeMode, DNameBuf, MessageBuilder};
+    use ::iana::{OptRcode, Rtype};
+    use super::*;
+
+    #[test]
+    fn block_length() {
+        let policy = PaddingPolicy::query();
+        assert_eq!(policy.padding_len(40), 84);
+        assert_eq!(policy.padding_len(124), 0);
+        assert_eq!(policy.padding_len(125), 127);
+        assert_eq!(policy.padding_len(300), 80);
+        assert_eq!(PaddingPolicy::BlockLength(0).padding_len(40), 0);
+    }
+
+    #[test]
+    fn random() {
+        for _ in 0..100 {
+            assert!(PaddingPolicy::Random(16).padding_len(40) <= 16);
+        }
+        assert_eq!(PaddingPolicy::Random(0).padding_len(40), 0);
+    }
+
+    #[test]
+    fn pad_message() {
+        for mode in &[ComposeMode::Unlimited, ComposeMode::Stream] {
+            let mut msg = MessageBuilder::new(mode.clone(), false).unwrap();
+            msg.push((DNameBuf::from_str("example.com.").unwrap(),
+                      Rtype::A)).unwrap();
+            let mut opt = msg.additional().build_opt(1232, OptRcode::NoError,
+                                                     false).unwrap();
+            opt.pad(PaddingPolicy::query()).unwrap();
+            let mut msg = opt.complete().unwrap();
+            let len = match *mode {
+                ComposeMode::Stream => msg.preview().len() - 2,
+                _ => msg.preview().len()
+            };
+            assert_eq!(len, 128);
+        }
+
+        let mut msg = MessageBuilder::new(ComposeMode::Limited(100), false)
+                                     .unwrap();
+        msg.push((DNameBuf::from_str("example.com.").unwrap(), Rtype::A))
+           .unwrap();
+        let mut opt = msg.additional().build_opt(1232, OptRcode::NoError,
+                                                 false).unwrap();
+        opt.pad(PaddingPolicy::query()).unwrap();
+        assert_eq!(opt.complete().unwrap().finish().len(), 100);
+    }
+}
@@ -1,23 +1,68 @@
 //! EDNS Options form RFC 7873
 
-use std::mem;
-use ::bits::{Composer, ComposeResult, Parser, ParseResult};
+use ::bits::{Composer, ComposeResult, Parser, ParseError, ParseResult};
 use ::iana::OptionCode;
 use super::{OptData, ParsedOptData};
 
 
 //------------ Cookie --------------------------------------------------------
 
+/// The DNS Cookie Option.
+///
+/// The option always contains an eight octet client cookie. In responses
+/// and in requests to a server the client has talked to before, it is
+/// followed by a server cookie of between 8 and 32 octets.
+///
+/// Specified in RFC 7873.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub struct Cookie([u8; 8]);
+pub struct Cookie {
+    /// The client cookie.
+    client: [u8; 8],
+
+    /// The server cookie, padded with zeros.
+    server: [u8; 32],
+
+    /// The length of the server cookie or zero if there is none.
+    server_len: u8,
+}
 
 impl Cookie {
+    /// Creates a new cookie option with only a client cookie.
     pub fn new(cookie: [u8; 8]) -> Self {
-        Cookie(cookie)
+        Cookie {
+            client: cookie,
+            server: [0; 32],
+            server_len: 0
+        }
+    }
+
+    /// Creates a new cookie option with both client and server cookie.
+    ///
+    /// Returns `None` if the server cookie isn’t between 8 and 32 octets
+    /// long.
+    pub fn with_server(client: [u8; 8], server: &[u8]) -> Option<Self> {
+        if server.len() < 8 || server.len() > 32 {
+            return None
+        }
+        let mut res = Cookie::new(client);
+        res.server[..server.len()].copy_from_slice(server);
+        res.server_len = server.len() as u8;
+        Some(res)
     }
 
+    /// Returns a reference to the client cookie.
     pub fn cookie(&self) -> &[u8; 8] {
-        &self.0
+        &self.client
+    }
+
+    /// Returns a reference to the server cookie if there is one.
+    pub fn server(&self) -> Option<&[u8]> {
+        if self.server_len == 0 {
+            None
+        }
+        else {
+            Some(&self.server[..self.server_len as usize])
+        }
     }
 }
 
@@ -25,8 +70,9 @@ impl OptData for Cookie {
     fn compose<C: AsMut<Composer>>(&self, mut target: C) -> ComposeResult<()> {
         let target = target.as_mut();
         target.compose_u16(OptionCode::Cookie.into())?;
-        target.compose_u16(8)?;
-        target.compose_bytes(&self.0[..])
+        target.compose_u16(8 + self.server_len as u16)?;
+        target.compose_bytes(&self.client[..])?;
+        target.compose_bytes(&self.server[..self.server_len as usize])
     }
 }
 
@@ -36,10 +82,62 @@ impl<'a> ParsedOptData<'a> for Cookie {
         if code != OptionCode::Cookie {
             return Ok(None)
         }
-        let bytes: &[u8; 8] = unsafe {
-            mem::transmute(parser.parse_bytes(8)?.as_ptr())
-        };
-        parser.exhausted()?;
-        Ok(Some(Cookie::new(*bytes)))
+        let mut client = [0u8; 8];
+        client.copy_from_slice(parser.parse_bytes(8)?);
+        let server = parser.parse_remaining()?;
+        if server.is_empty() {
+            Ok(Some(Cookie::new(client)))
+        }
+        else {
+            Cookie::with_server(client, server).map(Some)
+                                               .ok_or(ParseError::FormErr)
+        }
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use ::bits::{Composer, ComposeMode, Parser};
+    use ::iana::OptionCode;
+    use super::super::{OptData, ParsedOptData};
+    use super::*;
+
+    fn compose(cookie: &Cookie) -> Vec<u8> {
+        let mut composer = Composer::new(ComposeMode::Unlimited, false);
+        cookie.compose(&mut composer).unwrap();
+        composer.finish()
+    }
+
+    fn parse(data: &[u8]) -> ParseResult<Option<Cookie>> {
+        let mut parser = Parser::new(data);
+        Cookie::parse(OptionCode::Cookie, &mut parser)
+    }
+
+    #[test]
+    fn client_only() {
+        let cookie = Cookie::new(*b"clientck");
+        assert_eq!(cookie.server(), None);
+        let data = compose(&cookie);
+        assert_eq!(&data[..4], b"\x00\x0a\x00\x08");
+        assert_eq!(parse(&data[4..]).unwrap(), Some(cookie));
+    }
+
+    #[test]
+    fn client_and_server() {
+        let cookie = Cookie::with_server(*b"clientck", b"server cookie")
+                            .unwrap();
+        assert_eq!(cookie.cookie(), b"clientck");
+        assert_eq!(cookie.server(), Some(&b"server cookie"[..]));
+        let data = compose(&cookie);
+        assert_eq!(&data[..4], b"\x00\x0a\x00\x15");
+        assert_eq!(parse(&data[4..]).unwrap(), Some(cookie));
+
+        assert!(Cookie::with_server(*b"clientck", b"short").is_none());
+        assert!(Cookie::with_server(*b"clientck", &[0; 33]).is_none());
+        assert!(parse(b"clientckshort").is_err());
+        assert!(parse(b"short").is_err());
     }
 }
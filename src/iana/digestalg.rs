@@ -0,0 +1,38 @@
+//! Delegation Signer Digest Algorithms
+
+int_enum!{
+    /// Delegation signer digest algorithm numbers.
+    ///
+    /// These numbers are used in the DS record to specify how the digest
+    /// of the referenced DNSKEY record was calculated.
+    ///
+    /// See the [Delegation Signer Digest Algorithms IANA registry] for an
+    /// overview of assigned values.
+    ///
+    /// [Delegation Signer Digest Algorithms IANA registry]: https://www.iana.org/assignments/ds-rr-types/ds-rr-types.xhtml
+    =>
+    DigestAlg, u8;
+
+    /// SHA-1
+    ///
+    /// This algorithm is defined in RFC 3658. Its use for new DS records
+    /// is discouraged by RFC 8624.
+    (Sha1 => 1, b"SHA-1")
+
+    /// SHA-256
+    ///
+    /// This algorithm is defined in RFC 4509 and mandatory to implement.
+    (Sha256 => 2, b"SHA-256")
+
+    /// GOST R 34.11-94
+    ///
+    /// This algorithm is defined in RFC 5933.
+    (Gost => 3, b"GOST R 34.11-94")
+
+    /// SHA-384
+    ///
+    /// This algorithm is defined in RFC 6605.
+    (Sha384 => 4, b"SHA-384")
+}
+
+int_enum_str_with_decimal!(DigestAlg, u8, "unknown digest algorithm");
@@ -191,7 +191,7 @@ macro_rules! int_enum_str_mnemonics_only {
         from_str_error!($error);
     }
 }
-
+*/
 
 /// Adds impls for `FromStr` and `Display` to the type given as first argument.
 ///
@@ -244,7 +244,6 @@ macro_rules! int_enum_str_with_decimal {
         from_str_error!($error);
     }
 }
-*/
 
 /// Adds impls for `FromStr` and `Display` to the type given as first argument.
 ///
@@ -26,6 +26,8 @@
 //! `FromStrError` without having to resort to devilishly long names.
 
 pub use self::class::Class;
+pub use self::digestalg::DigestAlg;
+pub use self::nsec3::Nsec3HashAlg;
 pub use self::opcode::Opcode;
 pub use self::opt::OptionCode;
 pub use self::rcode::{Rcode, OptRcode, TsigRcode};
@@ -35,6 +37,8 @@ pub use self::secalg::SecAlg;
 #[macro_use] mod macros;
 
 pub mod class;
+pub mod digestalg;
+pub mod nsec3;
 pub mod opcode;
 pub mod opt;
 pub mod rcode;
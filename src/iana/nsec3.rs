@@ -0,0 +1,22 @@
+//! NSEC3 Hash Algorithms
+
+int_enum!{
+    /// NSEC3 hash algorithm numbers.
+    ///
+    /// These numbers are used in NSEC3 and NSEC3PARAM records to specify
+    /// how the hashed owner names were calculated.
+    ///
+    /// See the [DNSSEC NSEC3 Parameters IANA registry] for an overview of
+    /// assigned values.
+    ///
+    /// [DNSSEC NSEC3 Parameters IANA registry]: https://www.iana.org/assignments/dnssec-nsec3-parameters/dnssec-nsec3-parameters.xhtml
+    =>
+    Nsec3HashAlg, u8;
+
+    /// SHA-1
+    ///
+    /// This algorithm is defined in RFC 5155 and currently the only one.
+    (Sha1 => 1, b"SHA-1")
+}
+
+int_enum_str_with_decimal!(Nsec3HashAlg, u8, "unknown hash algorithm");
@@ -528,24 +528,19 @@ impl OptRcode {
             NotZone => 10,
             BadVers => 16,
             BadCookie => 23,
-            Int(value) => value & 0x0F
+            Int(value) => value & 0x0FFF
         }
     }
 
     /// Creates an extended rcode value from its parts.
     pub fn from_parts(rcode: Rcode, ext: u8) -> OptRcode {
-        if ext == 0 {
-            rcode.into()
-        }
-        else {
-            OptRcode::from_int((ext as u16) << 4 | (rcode.to_int() as u16))
-        }
+        OptRcode::from_int((ext as u16) << 4 | (rcode.to_int() as u16))
     }
 
     /// Returns the two parts of an extended rcode value.
     pub fn to_parts(self) -> (Rcode, u8) {
         let res = self.to_int();
-        (Rcode::from_int(res as u8), (res >> 8) as u8)
+        (Rcode::from_int(res as u8), (res >> 4) as u8)
     }
 
     /// Returns the rcode part of the extended rcode.
@@ -81,6 +81,18 @@ pub enum SecAlg {
     /// signing only.
     EcdsaP384Sha384,
 
+    /// ED25519
+    ///
+    /// This algorithm is described in RFC 8080. It may be used for zone
+    /// signing only.
+    Ed25519,
+
+    /// ED448
+    ///
+    /// This algorithm is described in RFC 8080. It may be used for zone
+    /// signing only.
+    Ed448,
+
     /// Reserved for Indirect Keys
     ///
     /// This value is reserved by RFC 4034.
@@ -117,6 +129,8 @@ impl SecAlg {
             12 => EccGost,
             13 => EcdsaP256Sha256,
             14 => EcdsaP384Sha384,
+            15 => Ed25519,
+            16 => Ed448,
             252 => Indirect,
             253 => PrivateDns,
             254 => PrivateOid,
@@ -140,6 +154,8 @@ impl SecAlg {
             EccGost => 12,
             EcdsaP256Sha256 => 13,
             EcdsaP384Sha384 => 14,
+            Ed25519 => 15,
+            Ed448 => 16,
             Indirect => 252,
             PrivateDns => 253,
             PrivateOid => 254,
@@ -193,6 +209,8 @@ impl str::FromStr for SecAlg {
         else if s.eq_ignore_ascii_case("ECDSAP384SHA384") {
             Ok(EcdsaP384Sha384)
         }
+        else if s.eq_ignore_ascii_case("ED25519") { Ok(Ed25519) }
+        else if s.eq_ignore_ascii_case("ED448") { Ok(Ed448) }
         else if s.eq_ignore_ascii_case("INDIRECT") { Ok(Indirect) }
         else if s.eq_ignore_ascii_case("PRIVATEDNS") { Ok(PrivateDns) }
         else if s.eq_ignore_ascii_case("PRIVATEOID") { Ok(PrivateOid) }
@@ -224,6 +242,8 @@ impl fmt::Display for SecAlg {
             EccGost => "ECC-GOST".fmt(f),
             EcdsaP256Sha256 => "ECDSAP256SHA256".fmt(f),
             EcdsaP384Sha384 => "ECDSAP384SHA384".fmt(f),
+            Ed25519 => "ED25519".fmt(f),
+            Ed448 => "ED448".fmt(f),
             Indirect => "INDIRECT".fmt(f),
             PrivateDns => "PRVIATEDNS".fmt(f),
             PrivateOid => "PRIVATEOID".fmt(f),
@@ -22,6 +22,15 @@ extern crate byteorder;
 #[macro_use] extern crate futures;
 extern crate rand;
 #[macro_use] extern crate tokio_core;
+#[cfg(feature = "doh")] extern crate base64;
+#[cfg(feature = "doh")] extern crate bytes;
+#[cfg(feature = "doh")] extern crate h2;
+#[cfg(feature = "doh")] extern crate http;
+#[cfg(feature = "tls")] extern crate native_tls;
+#[cfg(feature = "dnssec")] extern crate ring;
+#[cfg(feature = "tls")] extern crate sha2;
+#[cfg(feature = "tls")] extern crate tokio_io;
+#[cfg(feature = "tls")] extern crate tokio_tls;
 
 pub mod bits;
 pub mod iana;
@@ -19,6 +19,8 @@
 pub mod rfc1035;
 pub mod rfc2782;
 pub mod rfc3596;
+pub mod rfc4034;
+pub mod rfc5155;
 
 #[macro_use] mod macros;
 mod generic;
@@ -64,6 +66,8 @@ master_types!{
 // for all the types not part of master_types! above.
 pseudo_types!{
     rfc1035::{Null};
+    rfc4034::{Dnskey, Ds, Nsec, Rrsig};
+    rfc5155::{Nsec3, Nsec3param};
     //rfc6891::{Opt};
 }
 
@@ -99,6 +103,8 @@ pub fn fmt_rdata(rtype: ::iana::Rtype, parser: &mut ::bits::Parser,
 pub mod parsed {
     pub use super::rfc1035::parsed::*;
     pub use super::rfc3596::Aaaa;
+    pub use super::rfc4034::parsed::*;
+    pub use super::rfc5155::parsed::*;
     pub type Srv<'a> = super::rfc2782::Srv<::bits::ParsedDName<'a>>;
 }
 
@@ -113,5 +119,7 @@ pub mod parsed {
 pub mod owned {
     pub use super::rfc1035::owned::*;
     pub use super::rfc3596::Aaaa;
+    pub use super::rfc4034::owned::*;
+    pub use super::rfc5155::owned::*;
     pub type Srv = super::rfc2782::Srv<::bits::DNameBuf>;
 }
@@ -0,0 +1,657 @@
+//! Record data from [RFC 4034].
+//!
+//! This RFC defines the record types for DNSSEC: Dnskey, Rrsig, Nsec, and
+//! Ds. Their master file formats aren’t supported yet, so the types can
+//! only be parsed from and composed into wire format.
+//!
+//! [RFC 4034]: https://tools.ietf.org/html/rfc4034
+
+use std::fmt;
+use ::bits::{Composer, ComposeResult, DName, ParsedDName,
+             ParsedRecordData, Parser, ParseError, ParseResult, RecordData};
+use ::iana::{DigestAlg, Rtype, SecAlg};
+use ::utils::base64;
+
+
+//------------ Dnskey --------------------------------------------------------
+
+/// Dnskey record data.
+///
+/// Dnskey records hold the public keys a zone uses for signing its
+/// records.
+///
+/// The Dnskey record type is defined in RFC 4034, section 2.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dnskey<D: AsRef<[u8]>> {
+    flags: u16,
+    protocol: u8,
+    algorithm: SecAlg,
+    public_key: D,
+}
+
+impl<D: AsRef<[u8]>> Dnskey<D> {
+    /// Creates new Dnskey record data from its components.
+    pub fn new(flags: u16, protocol: u8, algorithm: SecAlg, public_key: D)
+               -> Self {
+        Dnskey {
+            flags: flags,
+            protocol: protocol,
+            algorithm: algorithm,
+            public_key: public_key
+        }
+    }
+
+    pub fn flags(&self) -> u16 { self.flags }
+    pub fn protocol(&self) -> u8 { self.protocol }
+    pub fn algorithm(&self) -> SecAlg { self.algorithm }
+    pub fn public_key(&self) -> &[u8] { self.public_key.as_ref() }
+
+    /// Returns whether the Zone Key flag is set.
+    ///
+    /// Only keys with this flag set may be used to verify signatures over
+    /// the records of a zone.
+    pub fn is_zone_key(&self) -> bool {
+        self.flags & 0x0100 != 0
+    }
+
+    /// Returns whether the Secure Entry Point flag is set.
+    ///
+    /// This flag is only a hint that the key is meant to be referenced by
+    /// a Ds record of the parent zone. It must not be relied upon.
+    pub fn is_secure_entry_point(&self) -> bool {
+        self.flags & 0x0001 != 0
+    }
+
+    /// Returns whether the Revoke flag defined in RFC 5011 is set.
+    pub fn is_revoked(&self) -> bool {
+        self.flags & 0x0080 != 0
+    }
+
+    /// Returns the key tag of the key.
+    ///
+    /// The key tag is a short value that helps finding the key a Rrsig or
+    /// Ds record refers to. It is calculated as described in RFC 4034,
+    /// appendix B.
+    pub fn key_tag(&self) -> u16 {
+        let key = self.public_key();
+        if self.algorithm == SecAlg::RsaMd5 {
+            // The key tag is the third to last and second to last octet
+            // of the modulus which ends the key.
+            if key.len() < 3 {
+                return 0
+            }
+            return u16::from(key[key.len() - 3]) << 8
+                 | u16::from(key[key.len() - 2])
+        }
+        let mut res = u32::from(self.flags);
+        res += u32::from(self.protocol) << 8;
+        res += u32::from(self.algorithm.to_int());
+        for (i, &octet) in key.iter().enumerate() {
+            if i % 2 == 0 { res += u32::from(octet) << 8 }
+            else { res += u32::from(octet) }
+        }
+        res += res >> 16;
+        (res & 0xFFFF) as u16
+    }
+}
+
+impl<'a> Dnskey<&'a [u8]> {
+    fn parse_always(parser: &mut Parser<'a>) -> ParseResult<Self> {
+        Ok(Self::new(parser.parse_u16()?,
+                     parser.parse_u8()?,
+                     SecAlg::from_int(parser.parse_u8()?),
+                     parser.parse_remaining()?))
+    }
+}
+
+impl<D: AsRef<[u8]>> RecordData for Dnskey<D> {
+    fn rtype(&self) -> Rtype { Rtype::Dnskey }
+
+    fn compose<C: AsMut<Composer>>(&self, mut target: C)
+                                   -> ComposeResult<()> {
+        target.as_mut().compose_u16(self.flags)?;
+        target.as_mut().compose_u8(self.protocol)?;
+        target.as_mut().compose_u8(self.algorithm.to_int())?;
+        target.as_mut().compose_bytes(self.public_key())
+    }
+}
+
+impl<'a> ParsedRecordData<'a> for Dnskey<&'a [u8]> {
+    fn parse(rtype: Rtype, parser: &mut Parser<'a>)
+             -> ParseResult<Option<Self>> {
+        if rtype == Rtype::Dnskey { Dnskey::parse_always(parser).map(Some) }
+        else { Ok(None) }
+    }
+}
+
+impl<D: AsRef<[u8]>> fmt::Display for Dnskey<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} ", self.flags, self.protocol,
+               self.algorithm.to_int())?;
+        base64::display(self.public_key(), f)
+    }
+}
+
+
+//------------ Rrsig ---------------------------------------------------------
+
+/// Rrsig record data.
+///
+/// Rrsig records hold the signature over a record set. The signature
+/// covers the record data of the record set as well as all fields of the
+/// Rrsig record data except for the signature itself.
+///
+/// The signature expiration and inception times are seconds since the
+/// Unix epoch modulo 2^32 and have to be compared using serial number
+/// arithmetic. They are displayed as plain integers.
+///
+/// The Rrsig record type is defined in RFC 4034, section 3.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rrsig<N: DName, D: AsRef<[u8]>> {
+    type_covered: Rtype,
+    algorithm: SecAlg,
+    labels: u8,
+    original_ttl: u32,
+    expiration: u32,
+    inception: u32,
+    key_tag: u16,
+    signer_name: N,
+    signature: D,
+}
+
+impl<N: DName, D: AsRef<[u8]>> Rrsig<N, D> {
+    /// Creates new Rrsig record data from its components.
+    #[allow(too_many_arguments)]
+    pub fn new(type_covered: Rtype, algorithm: SecAlg, labels: u8,
+               original_ttl: u32, expiration: u32, inception: u32,
+               key_tag: u16, signer_name: N, signature: D) -> Self {
+        Rrsig {
+            type_covered: type_covered,
+            algorithm: algorithm,
+            labels: labels,
+            original_ttl: original_ttl,
+            expiration: expiration,
+            inception: inception,
+            key_tag: key_tag,
+            signer_name: signer_name,
+            signature: signature
+        }
+    }
+
+    pub fn type_covered(&self) -> Rtype { self.type_covered }
+    pub fn algorithm(&self) -> SecAlg { self.algorithm }
+    pub fn labels(&self) -> u8 { self.labels }
+    pub fn original_ttl(&self) -> u32 { self.original_ttl }
+    pub fn expiration(&self) -> u32 { self.expiration }
+    pub fn inception(&self) -> u32 { self.inception }
+    pub fn key_tag(&self) -> u16 { self.key_tag }
+    pub fn signer_name(&self) -> &N { &self.signer_name }
+    pub fn signature(&self) -> &[u8] { self.signature.as_ref() }
+
+    /// Appends all fields but the signature to a composition.
+    ///
+    /// This is the first part of the data a signature is calculated over.
+    /// As required for that, the signer name is never compressed. It is
+    /// left for the caller to make sure it is in canonical form.
+    pub fn compose_signed_data<C: AsMut<Composer>>(&self, mut target: C)
+                                                   -> ComposeResult<()> {
+        self.type_covered.compose(&mut target)?;
+        target.as_mut().compose_u8(self.algorithm.to_int())?;
+        target.as_mut().compose_u8(self.labels)?;
+        target.as_mut().compose_u32(self.original_ttl)?;
+        target.as_mut().compose_u32(self.expiration)?;
+        target.as_mut().compose_u32(self.inception)?;
+        target.as_mut().compose_u16(self.key_tag)?;
+        target.as_mut().compose_dname(&self.signer_name)
+    }
+}
+
+impl<'a> Rrsig<ParsedDName<'a>, &'a [u8]> {
+    fn parse_always(parser: &mut Parser<'a>) -> ParseResult<Self> {
+        Ok(Self::new(Rtype::parse(parser)?,
+                     SecAlg::from_int(parser.parse_u8()?),
+                     parser.parse_u8()?,
+                     parser.parse_u32()?,
+                     parser.parse_u32()?,
+                     parser.parse_u32()?,
+                     parser.parse_u16()?,
+                     ParsedDName::parse(parser)?,
+                     parser.parse_remaining()?))
+    }
+}
+
+impl<N: DName, D: AsRef<[u8]>> RecordData for Rrsig<N, D> {
+    fn rtype(&self) -> Rtype { Rtype::Rrsig }
+
+    fn compose<C: AsMut<Composer>>(&self, mut target: C)
+                                   -> ComposeResult<()> {
+        self.compose_signed_data(&mut target)?;
+        target.as_mut().compose_bytes(self.signature())
+    }
+}
+
+impl<'a> ParsedRecordData<'a> for Rrsig<ParsedDName<'a>, &'a [u8]> {
+    fn parse(rtype: Rtype, parser: &mut Parser<'a>)
+             -> ParseResult<Option<Self>> {
+        if rtype == Rtype::Rrsig { Rrsig::parse_always(parser).map(Some) }
+        else { Ok(None) }
+    }
+}
+
+impl<N, D> fmt::Display for Rrsig<N, D>
+     where N: DName + fmt::Display, D: AsRef<[u8]> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {} {} {} {} {} ", self.type_covered,
+               self.algorithm.to_int(), self.labels, self.original_ttl,
+               self.expiration, self.inception, self.key_tag,
+               self.signer_name)?;
+        base64::display(self.signature(), f)
+    }
+}
+
+
+//------------ Nsec ----------------------------------------------------------
+
+/// Nsec record data.
+///
+/// Nsec records provide authenticated denial of existence. Each record
+/// names the next owner name in the zone in canonical order and lists
+/// the record types present at its own owner name.
+///
+/// The Nsec record type is defined in RFC 4034, section 4.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Nsec<N: DName, B: AsRef<[u8]>> {
+    next_name: N,
+    types: RtypeBitmap<B>,
+}
+
+impl<N: DName, B: AsRef<[u8]>> Nsec<N, B> {
+    /// Creates new Nsec record data from its components.
+    pub fn new(next_name: N, types: RtypeBitmap<B>) -> Self {
+        Nsec { next_name: next_name, types: types }
+    }
+
+    pub fn next_name(&self) -> &N { &self.next_name }
+    pub fn types(&self) -> &RtypeBitmap<B> { &self.types }
+}
+
+impl<'a> Nsec<ParsedDName<'a>, &'a [u8]> {
+    fn parse_always(parser: &mut Parser<'a>) -> ParseResult<Self> {
+        Ok(Self::new(ParsedDName::parse(parser)?,
+                     RtypeBitmap::parse(parser)?))
+    }
+}
+
+impl<N: DName, B: AsRef<[u8]>> RecordData for Nsec<N, B> {
+    fn rtype(&self) -> Rtype { Rtype::Nsec }
+
+    fn compose<C: AsMut<Composer>>(&self, mut target: C)
+                                   -> ComposeResult<()> {
+        target.as_mut().compose_dname(&self.next_name)?;
+        target.as_mut().compose_bytes(self.types.as_bytes())
+    }
+}
+
+impl<'a> ParsedRecordData<'a> for Nsec<ParsedDName<'a>, &'a [u8]> {
+    fn parse(rtype: Rtype, parser: &mut Parser<'a>)
+             -> ParseResult<Option<Self>> {
+        if rtype == Rtype::Nsec { Nsec::parse_always(parser).map(Some) }
+        else { Ok(None) }
+    }
+}
+
+impl<N, B> fmt::Display for Nsec<N, B>
+     where N: DName + fmt::Display, B: AsRef<[u8]> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.next_name, self.types)
+    }
+}
+
+
+//------------ Ds ------------------------------------------------------------
+
+/// Ds record data.
+///
+/// Ds records are placed in the parent zone at a delegation. They hold
+/// the digest of a Dnskey record of the child zone, thereby linking the
+/// two zones into a chain of trust.
+///
+/// The Ds record type is defined in RFC 4034, section 5.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ds<D: AsRef<[u8]>> {
+    key_tag: u16,
+    algorithm: SecAlg,
+    digest_type: DigestAlg,
+    digest: D,
+}
+
+impl<D: AsRef<[u8]>> Ds<D> {
+    /// Creates new Ds record data from its components.
+    pub fn new(key_tag: u16, algorithm: SecAlg, digest_type: DigestAlg,
+               digest: D) -> Self {
+        Ds {
+            key_tag: key_tag,
+            algorithm: algorithm,
+            digest_type: digest_type,
+            digest: digest
+        }
+    }
+
+    pub fn key_tag(&self) -> u16 { self.key_tag }
+    pub fn algorithm(&self) -> SecAlg { self.algorithm }
+    pub fn digest_type(&self) -> DigestAlg { self.digest_type }
+    pub fn digest(&self) -> &[u8] { self.digest.as_ref() }
+}
+
+impl<'a> Ds<&'a [u8]> {
+    fn parse_always(parser: &mut Parser<'a>) -> ParseResult<Self> {
+        Ok(Self::new(parser.parse_u16()?,
+                     SecAlg::from_int(parser.parse_u8()?),
+                     DigestAlg::from_int(parser.parse_u8()?),
+                     parser.parse_remaining()?))
+    }
+}
+
+impl<D: AsRef<[u8]>> RecordData for Ds<D> {
+    fn rtype(&self) -> Rtype { Rtype::Ds }
+
+    fn compose<C: AsMut<Composer>>(&self, mut target: C)
+                                   -> ComposeResult<()> {
+        target.as_mut().compose_u16(self.key_tag)?;
+        target.as_mut().compose_u8(self.algorithm.to_int())?;
+        target.as_mut().compose_u8(self.digest_type.to_int())?;
+        target.as_mut().compose_bytes(self.digest())
+    }
+}
+
+impl<'a> ParsedRecordData<'a> for Ds<&'a [u8]> {
+    fn parse(rtype: Rtype, parser: &mut Parser<'a>)
+             -> ParseResult<Option<Self>> {
+        if rtype == Rtype::Ds { Ds::parse_always(parser).map(Some) }
+        else { Ok(None) }
+    }
+}
+
+impl<D: AsRef<[u8]>> fmt::Display for Ds<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} ", self.key_tag, self.algorithm.to_int(),
+               self.digest_type.to_int())?;
+        for octet in self.digest() {
+            write!(f, "{:02X}", octet)?
+        }
+        Ok(())
+    }
+}
+
+
+//------------ RtypeBitmap ---------------------------------------------------
+
+/// The set of record types present at a name.
+///
+/// This is the type bit map used by Nsec and Nsec3 records. It consists
+/// of up to 256 windows for the upper eight bits of the record type, each
+/// of which is a bit map of up to 32 octets for the lower eight bits.
+///
+/// The type bit map is defined in RFC 4034, section 4.1.2.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RtypeBitmap<B: AsRef<[u8]>>(B);
+
+impl<B: AsRef<[u8]>> RtypeBitmap<B> {
+    /// Creates a bitmap from its wire format.
+    ///
+    /// Fails if the data isn’t a correctly formatted bitmap.
+    pub fn from_bytes(bytes: B) -> ParseResult<Self> {
+        {
+            let mut data = bytes.as_ref();
+            let mut last = None;
+            while !data.is_empty() {
+                if data.len() < 2 {
+                    return Err(ParseError::UnexpectedEnd)
+                }
+                let (window, len) = (data[0], data[1] as usize);
+                if len == 0 || len > 32 || last.map_or(false, |l| window <= l)
+                {
+                    return Err(ParseError::FormErr)
+                }
+                if data.len() < len + 2 {
+                    return Err(ParseError::UnexpectedEnd)
+                }
+                last = Some(window);
+                data = &data[len + 2..];
+            }
+        }
+        Ok(RtypeBitmap(bytes))
+    }
+
+    /// Returns the wire format of the bitmap.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+
+    /// Returns whether the bitmap contains the given record type.
+    pub fn contains(&self, rtype: Rtype) -> bool {
+        let rtype = rtype.to_int();
+        let (window, octet, mask) = ((rtype >> 8) as u8,
+                                     (rtype & 0xFF) as usize >> 3,
+                                     0x80u8 >> (rtype & 0x07));
+        let mut data = self.as_bytes();
+        while data.len() >= 2 {
+            let len = data[1] as usize;
+            if data[0] == window {
+                return data.get(2 + octet)
+                           .map_or(false, |bits| bits & mask != 0)
+                    && octet < len
+            }
+            data = &data[len + 2..];
+        }
+        false
+    }
+
+    /// Returns an iterator over the record types in the bitmap.
+    pub fn iter(&self) -> RtypeBitmapIter {
+        RtypeBitmapIter { data: self.as_bytes(), pos: 0 }
+    }
+}
+
+impl<'a> RtypeBitmap<&'a [u8]> {
+    /// Parses a bitmap taking up the remaining data of a parser.
+    pub fn parse(parser: &mut Parser<'a>) -> ParseResult<Self> {
+        Self::from_bytes(parser.parse_remaining()?)
+    }
+}
+
+impl RtypeBitmap<Vec<u8>> {
+    /// Creates an owned bitmap containing the given record types.
+    pub fn from_types<I: IntoIterator<Item=Rtype>>(iter: I) -> Self {
+        let mut types: Vec<u16> = iter.into_iter().map(Rtype::to_int)
+                                      .collect();
+        types.sort();
+        types.dedup();
+        let mut res = Vec::new();
+        let mut start = 0;
+        for &rtype in &types {
+            let (window, octet) = ((rtype >> 8) as u8,
+                                   (rtype & 0xFF) as usize >> 3);
+            if res.is_empty() || res[start] != window {
+                start = res.len();
+                res.push(window);
+                res.push(0);
+            }
+            while (res[start + 1] as usize) <= octet {
+                res.push(0);
+                res[start + 1] += 1;
+            }
+            res[start + 2 + octet] |= 0x80 >> (rtype & 0x07);
+        }
+        RtypeBitmap(res)
+    }
+}
+
+impl<B: AsRef<[u8]>> fmt::Display for RtypeBitmap<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, rtype) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?
+            }
+            rtype.fmt(f)?
+        }
+        Ok(())
+    }
+}
+
+
+//------------ RtypeBitmapIter -----------------------------------------------
+
+/// An iterator over the record types in a bitmap.
+pub struct RtypeBitmapIter<'a> {
+    /// The remaining windows of the bitmap.
+    data: &'a [u8],
+
+    /// The index of the next bit to look at within the current window.
+    pos: usize,
+}
+
+impl<'a> Iterator for RtypeBitmapIter<'a> {
+    type Item = Rtype;
+
+    fn next(&mut self) -> Option<Rtype> {
+        while self.data.len() >= 2 {
+            let len = self.data[1] as usize;
+            while self.pos < len * 8 {
+                let pos = self.pos;
+                self.pos += 1;
+                if self.data[2 + (pos >> 3)] & (0x80 >> (pos & 0x07)) != 0 {
+                    let rtype = u16::from(self.data[0]) << 8 | pos as u16;
+                    return Some(Rtype::from_int(rtype))
+                }
+            }
+            self.data = &self.data[len + 2..];
+            self.pos = 0;
+        }
+        None
+    }
+}
+
+
+//============ Type Aliases =================================================
+
+pub mod parsed {
+    use ::bits::ParsedDName;
+
+    pub type Dnskey<'a> = super::Dnskey<&'a [u8]>;
+    pub type Rrsig<'a> = super::Rrsig<ParsedDName<'a>, &'a [u8]>;
+    pub type Nsec<'a> = super::Nsec<ParsedDName<'a>, &'a [u8]>;
+    pub type Ds<'a> = super::Ds<&'a [u8]>;
+}
+
+pub mod owned {
+    use ::bits::DNameBuf;
+
+    pub type Dnskey = super::Dnskey<Vec<u8>>;
+    pub type Rrsig = super::Rrsig<DNameBuf, Vec<u8>>;
+    pub type Nsec = super::Nsec<DNameBuf, Vec<u8>>;
+    pub type Ds = super::Ds<Vec<u8>>;
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use ::bits::{ComposeMode, Composer, DNameBuf, ParsedRecordData, Parser,
+                 RecordData};
+    use ::iana::{Rtype, SecAlg};
+    use super::*;
+
+    fn compose<D: RecordData>(data: &D) -> Vec<u8> {
+        let mut composer = Composer::new(ComposeMode::Unlimited, false);
+        data.compose(&mut composer).unwrap();
+        composer.finish()
+    }
+
+    #[test]
+    fn key_tag() {
+        // The root zone’s KSK-2017 and its well-known tag.
+        let key = [
+            0x03, 0x01, 0x00, 0x01, 0xac, 0xff, 0xb4, 0x09, 0xbc, 0xc9,
+            0x39, 0xf8, 0x31, 0xf7, 0xa1, 0xe5, 0xec, 0x88, 0xf7, 0xa5,
+            0x92, 0x55, 0xec, 0x53, 0x04, 0x0b, 0xe4, 0x32, 0x02, 0x73,
+            0x90, 0xa4, 0xce, 0x89, 0x6d, 0x6f, 0x90, 0x86, 0xf3, 0xc5,
+            0xe1, 0x77, 0xfb, 0xfe, 0x11, 0x81, 0x63, 0xaa, 0xec, 0x7a,
+            0xf1, 0x46, 0x2c, 0x47, 0x94, 0x59, 0x44, 0xc4, 0xe2, 0xc0,
+            0x26, 0xbe, 0x5e, 0x98, 0xbb, 0xcd, 0xed, 0x25, 0x97, 0x82,
+            0x72, 0xe1, 0xe3, 0xe0, 0x79, 0xc5, 0x09, 0x4d, 0x57, 0x3f,
+            0x0e, 0x83, 0xc9, 0x2f, 0x02, 0xb3, 0x2d, 0x35, 0x13, 0xb1,
+            0x55, 0x0b, 0x82, 0x69, 0x29, 0xc8, 0x0d, 0xd0, 0xf9, 0x2c,
+            0xac, 0x96, 0x6d, 0x17, 0x76, 0x9f, 0xd5, 0x86, 0x7b, 0x64,
+            0x7c, 0x3f, 0x38, 0x02, 0x9a, 0xbd, 0xc4, 0x81, 0x52, 0xeb,
+            0x8f, 0x20, 0x71, 0x59, 0xec, 0xc5, 0xd2, 0x32, 0xc7, 0xc1,
+            0x53, 0x7c, 0x79, 0xf4, 0xb7, 0xac, 0x28, 0xff, 0x11, 0x68,
+            0x2f, 0x21, 0x68, 0x1b, 0xf6, 0xd6, 0xab, 0xa5, 0x55, 0x03,
+            0x2b, 0xf6, 0xf9, 0xf0, 0x36, 0xbe, 0xb2, 0xaa, 0xa5, 0xb3,
+            0x77, 0x8d, 0x6e, 0xeb, 0xfb, 0xa6, 0xbf, 0x9e, 0xa1, 0x91,
+            0xbe, 0x4a, 0xb0, 0xca, 0xea, 0x75, 0x9e, 0x2f, 0x77, 0x3a,
+            0x1f, 0x90, 0x29, 0xc7, 0x3e, 0xcb, 0x8d, 0x57, 0x35, 0xb9,
+            0x32, 0x1d, 0xb0, 0x85, 0xf1, 0xb8, 0xe2, 0xd8, 0x03, 0x8f,
+            0xe2, 0x94, 0x19, 0x92, 0x54, 0x8c, 0xee, 0x0d, 0x67, 0xdd,
+            0x45, 0x47, 0xe1, 0x1d, 0xd6, 0x3a, 0xf9, 0xc9, 0xfc, 0x1c,
+            0x54, 0x66, 0xfb, 0x68, 0x4c, 0xf0, 0x09, 0xd7, 0x19, 0x7c,
+            0x2c, 0xf7, 0x9e, 0x79, 0x2a, 0xb5, 0x01, 0xe6, 0xa8, 0xa1,
+            0xca, 0x51, 0x9a, 0xf2, 0xcb, 0x9b, 0x5f, 0x63, 0x67, 0xe9,
+            0x4c, 0x0d, 0x47, 0x50, 0x24, 0x51, 0x35, 0x7b, 0xe1, 0xb5,
+        ];
+        let dnskey = Dnskey::new(257, 3, SecAlg::RsaSha256, &key[..]);
+        assert!(dnskey.is_zone_key());
+        assert!(dnskey.is_secure_entry_point());
+        assert_eq!(dnskey.key_tag(), 20326);
+    }
+
+    #[test]
+    fn rrsig() {
+        let signer = DNameBuf::from_str("example.com.").unwrap();
+        let rrsig = Rrsig::new(Rtype::A, SecAlg::EcdsaP256Sha256, 3, 3600,
+                               1700000000, 1690000000, 12345, signer,
+                               vec![1, 2, 3]);
+        let bytes = compose(&rrsig);
+        let parsed = Rrsig::parse(Rtype::Rrsig, &mut Parser::new(&bytes))
+                           .unwrap().unwrap();
+        assert_eq!(parsed.type_covered(), Rtype::A);
+        assert_eq!(parsed.labels(), 3);
+        assert_eq!(parsed.expiration(), 1700000000);
+        assert_eq!(parsed.key_tag(), 12345);
+        assert_eq!(*parsed.signer_name(), rrsig.signer_name().clone());
+        assert_eq!(parsed.signature(), &[1, 2, 3]);
+        assert_eq!(format!("{}", parsed),
+                   "A 13 3 3600 1700000000 1690000000 12345 example.com. \
+                    AQID");
+    }
+
+    #[test]
+    fn nsec() {
+        let types = RtypeBitmap::from_types(vec![Rtype::A, Rtype::Mx,
+                                                 Rtype::Rrsig, Rtype::Nsec,
+                                                 Rtype::Caa]);
+        // The example from RFC 4034, section 4.3, plus CAA in window 1.
+        assert_eq!(types.as_bytes(),
+                   &[0x00, 0x06, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03,
+                     0x01, 0x01, 0x40][..]);
+        assert!(types.contains(Rtype::Mx));
+        assert!(types.contains(Rtype::Caa));
+        assert!(!types.contains(Rtype::Aaaa));
+        assert!(!types.contains(Rtype::Ds));
+        let nsec = Nsec::new(DNameBuf::from_str("host.example.com.")
+                                      .unwrap(),
+                             types);
+        let bytes = compose(&nsec);
+        let parsed = Nsec::parse(Rtype::Nsec, &mut Parser::new(&bytes))
+                          .unwrap().unwrap();
+        assert_eq!(parsed.types().iter().collect::<Vec<_>>(),
+                   vec![Rtype::A, Rtype::Mx, Rtype::Rrsig, Rtype::Nsec,
+                        Rtype::Caa]);
+        assert_eq!(format!("{}", parsed),
+                   "host.example.com. A MX RRSIG NSEC CAA");
+        assert!(RtypeBitmap::from_bytes(&[0x00, 0x00][..]).is_err());
+        assert!(RtypeBitmap::from_bytes(&[0x00, 0x02, 0x40][..]).is_err());
+    }
+}
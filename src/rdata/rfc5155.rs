@@ -0,0 +1,255 @@
+//! Record data from [RFC 5155].
+//!
+//! This RFC defines the Nsec3 and Nsec3param record types. As with the
+//! other DNSSEC types, only the wire format is supported for now.
+//!
+//! [RFC 5155]: https://tools.ietf.org/html/rfc5155
+
+use std::fmt;
+use ::bits::{Composer, ComposeResult, ParsedRecordData, Parser, ParseResult,
+             RecordData};
+use ::iana::{Nsec3HashAlg, Rtype};
+use ::utils::base32;
+use super::rfc4034::RtypeBitmap;
+
+
+//------------ Nsec3 ---------------------------------------------------------
+
+/// Nsec3 record data.
+///
+/// Nsec3 records provide authenticated denial of existence much like Nsec
+/// records. However, instead of the owner names themselves, the records
+/// chain the hashes of all owner names of a zone. The owner name of an
+/// Nsec3 record is the Base 32 encoded hash of the original owner name
+/// prepended as a single label to the zone’s apex.
+///
+/// The Nsec3 record type is defined in RFC 5155, section 3.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Nsec3<D: AsRef<[u8]>> {
+    hash_algorithm: Nsec3HashAlg,
+    flags: u8,
+    iterations: u16,
+    salt: D,
+    next_owner: D,
+    types: RtypeBitmap<D>,
+}
+
+impl<D: AsRef<[u8]>> Nsec3<D> {
+    /// Creates new Nsec3 record data from its components.
+    pub fn new(hash_algorithm: Nsec3HashAlg, flags: u8, iterations: u16,
+               salt: D, next_owner: D, types: RtypeBitmap<D>) -> Self {
+        Nsec3 {
+            hash_algorithm: hash_algorithm,
+            flags: flags,
+            iterations: iterations,
+            salt: salt,
+            next_owner: next_owner,
+            types: types
+        }
+    }
+
+    pub fn hash_algorithm(&self) -> Nsec3HashAlg { self.hash_algorithm }
+    pub fn flags(&self) -> u8 { self.flags }
+    pub fn iterations(&self) -> u16 { self.iterations }
+    pub fn salt(&self) -> &[u8] { self.salt.as_ref() }
+    pub fn types(&self) -> &RtypeBitmap<D> { &self.types }
+
+    /// Returns the unencoded hash of the next owner name.
+    pub fn next_owner(&self) -> &[u8] { self.next_owner.as_ref() }
+
+    /// Returns whether the Opt-Out flag is set.
+    ///
+    /// If it is, the record may cover unsigned delegations.
+    pub fn opt_out(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+}
+
+impl<'a> Nsec3<&'a [u8]> {
+    fn parse_always(parser: &mut Parser<'a>) -> ParseResult<Self> {
+        let hash_algorithm = Nsec3HashAlg::from_int(parser.parse_u8()?);
+        let flags = parser.parse_u8()?;
+        let iterations = parser.parse_u16()?;
+        let salt_len = parser.parse_u8()? as usize;
+        let salt = parser.parse_bytes(salt_len)?;
+        let hash_len = parser.parse_u8()? as usize;
+        let next_owner = parser.parse_bytes(hash_len)?;
+        Ok(Self::new(hash_algorithm, flags, iterations, salt, next_owner,
+                     RtypeBitmap::parse(parser)?))
+    }
+}
+
+impl<D: AsRef<[u8]>> RecordData for Nsec3<D> {
+    fn rtype(&self) -> Rtype { Rtype::Nsec3 }
+
+    fn compose<C: AsMut<Composer>>(&self, mut target: C)
+                                   -> ComposeResult<()> {
+        assert!(self.salt().len() <= 255 && self.next_owner().len() <= 255);
+        target.as_mut().compose_u8(self.hash_algorithm.to_int())?;
+        target.as_mut().compose_u8(self.flags)?;
+        target.as_mut().compose_u16(self.iterations)?;
+        target.as_mut().compose_u8(self.salt().len() as u8)?;
+        target.as_mut().compose_bytes(self.salt())?;
+        target.as_mut().compose_u8(self.next_owner().len() as u8)?;
+        target.as_mut().compose_bytes(self.next_owner())?;
+        target.as_mut().compose_bytes(self.types.as_bytes())
+    }
+}
+
+impl<'a> ParsedRecordData<'a> for Nsec3<&'a [u8]> {
+    fn parse(rtype: Rtype, parser: &mut Parser<'a>)
+             -> ParseResult<Option<Self>> {
+        if rtype == Rtype::Nsec3 { Nsec3::parse_always(parser).map(Some) }
+        else { Ok(None) }
+    }
+}
+
+impl<D: AsRef<[u8]>> fmt::Display for Nsec3<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} ", self.hash_algorithm.to_int(), self.flags,
+               self.iterations)?;
+        fmt_salt(self.salt(), f)?;
+        f.write_str(" ")?;
+        base32::display_hex(self.next_owner(), f)?;
+        if !self.types.as_bytes().is_empty() {
+            write!(f, " {}", self.types)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ Nsec3param ----------------------------------------------------
+
+/// Nsec3param record data.
+///
+/// The Nsec3param record is placed at the apex of a zone and tells the
+/// zone’s authoritative servers which parameters to use for Nsec3.
+///
+/// The Nsec3param record type is defined in RFC 5155, section 4.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Nsec3param<D: AsRef<[u8]>> {
+    hash_algorithm: Nsec3HashAlg,
+    flags: u8,
+    iterations: u16,
+    salt: D,
+}
+
+impl<D: AsRef<[u8]>> Nsec3param<D> {
+    /// Creates new Nsec3param record data from its components.
+    pub fn new(hash_algorithm: Nsec3HashAlg, flags: u8, iterations: u16,
+               salt: D) -> Self {
+        Nsec3param {
+            hash_algorithm: hash_algorithm,
+            flags: flags,
+            iterations: iterations,
+            salt: salt
+        }
+    }
+
+    pub fn hash_algorithm(&self) -> Nsec3HashAlg { self.hash_algorithm }
+    pub fn flags(&self) -> u8 { self.flags }
+    pub fn iterations(&self) -> u16 { self.iterations }
+    pub fn salt(&self) -> &[u8] { self.salt.as_ref() }
+}
+
+impl<'a> Nsec3param<&'a [u8]> {
+    fn parse_always(parser: &mut Parser<'a>) -> ParseResult<Self> {
+        let hash_algorithm = Nsec3HashAlg::from_int(parser.parse_u8()?);
+        let flags = parser.parse_u8()?;
+        let iterations = parser.parse_u16()?;
+        let salt_len = parser.parse_u8()? as usize;
+        Ok(Self::new(hash_algorithm, flags, iterations,
+                     parser.parse_bytes(salt_len)?))
+    }
+}
+
+impl<D: AsRef<[u8]>> RecordData for Nsec3param<D> {
+    fn rtype(&self) -> Rtype { Rtype::Nsec3param }
+
+    fn compose<C: AsMut<Composer>>(&self, mut target: C)
+                                   -> ComposeResult<()> {
+        assert!(self.salt().len() <= 255);
+        target.as_mut().compose_u8(self.hash_algorithm.to_int())?;
+        target.as_mut().compose_u8(self.flags)?;
+        target.as_mut().compose_u16(self.iterations)?;
+        target.as_mut().compose_u8(self.salt().len() as u8)?;
+        target.as_mut().compose_bytes(self.salt())
+    }
+}
+
+impl<'a> ParsedRecordData<'a> for Nsec3param<&'a [u8]> {
+    fn parse(rtype: Rtype, parser: &mut Parser<'a>)
+             -> ParseResult<Option<Self>> {
+        if rtype == Rtype::Nsec3param {
+            Nsec3param::parse_always(parser).map(Some)
+        }
+        else { Ok(None) }
+    }
+}
+
+impl<D: AsRef<[u8]>> fmt::Display for Nsec3param<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} ", self.hash_algorithm.to_int(), self.flags,
+               self.iterations)?;
+        fmt_salt(self.salt(), f)
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Formats a salt in hex digits or as a dash if it is empty.
+fn fmt_salt(salt: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+    if salt.is_empty() {
+        return f.write_str("-")
+    }
+    for octet in salt {
+        write!(f, "{:02X}", octet)?
+    }
+    Ok(())
+}
+
+
+//============ Type Aliases =================================================
+
+pub mod parsed {
+    pub type Nsec3<'a> = super::Nsec3<&'a [u8]>;
+    pub type Nsec3param<'a> = super::Nsec3param<&'a [u8]>;
+}
+
+pub mod owned {
+    pub type Nsec3 = super::Nsec3<Vec<u8>>;
+    pub type Nsec3param = super::Nsec3param<Vec<u8>>;
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use ::bits::{ComposeMode, Composer, ParsedRecordData, Parser,
+                 RecordData};
+    use ::iana::{Nsec3HashAlg, Rtype};
+    use super::*;
+
+    #[test]
+    fn nsec3() {
+        let nsec3 = Nsec3::new(Nsec3HashAlg::Sha1, 1, 12,
+                               vec![0xaa, 0xbb, 0xcc, 0xdd],
+                               vec![0x17, 0x6c, 0x79, 0x38, 0x18],
+                               RtypeBitmap::from_types(vec![Rtype::Ns,
+                                                            Rtype::Ds]));
+        let mut composer = Composer::new(ComposeMode::Unlimited, false);
+        nsec3.compose(&mut composer).unwrap();
+        let bytes = composer.finish();
+        let parsed = Nsec3::parse(Rtype::Nsec3, &mut Parser::new(&bytes))
+                           .unwrap().unwrap();
+        assert!(parsed.opt_out());
+        assert_eq!(parsed.iterations(), 12);
+        assert_eq!(parsed.salt(), nsec3.salt());
+        assert_eq!(parsed.next_owner(), nsec3.next_owner());
+        assert!(parsed.types().contains(Rtype::Ds));
+        assert_eq!(format!("{}", parsed), "1 1 12 AABBCCDD 2tm7ie0o NS DS");
+    }
+}
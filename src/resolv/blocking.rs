@@ -0,0 +1,292 @@
+//! A resolver for synchronous code.
+//!
+//! The `BlockingResolver` runs a `Resolver` on a reactor in a thread of its
+//! own. Its methods hand operations over to that thread and wait for their
+//! outcome. Since the resolver and its transports stay alive between
+//! calls, connections, server statistics, and the cache are kept, too.
+
+use std::{fmt, io, thread};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::mpsc as std_mpsc;
+use futures::{Future, Stream};
+use futures::future::{self, Either};
+use futures::sync::{mpsc, oneshot};
+use tokio_core::reactor;
+use ::bits::{DName, DNameSlice, MessageBuf, Question};
+use super::conf::ResolvConf;
+use super::error::Error;
+use super::lookup::{lookup_addr, lookup_host, lookup_srv};
+use super::lookup::addr::FoundAddrs;
+use super::lookup::host::FoundHosts;
+use super::lookup::srv::SrvItem;
+use super::public::Resolver;
+
+
+//------------ BlockingResolver ----------------------------------------------
+
+/// A resolver with synchronous methods.
+///
+/// When created, the blocking resolver spawns a thread running a reactor
+/// core with a [`Resolver`] created from a resolver configuration. All
+/// methods send the operation to that thread and block until it is
+/// finished.
+///
+/// Values of this type can be cloned cheaply and shared between threads.
+/// All clones use the same reactor thread. When the last clone is
+/// dropped, the thread is shut down and joined. Since this blocks, you
+/// must not drop the last clone or call any of its methods from within an
+/// operation running on the reactor thread.
+///
+/// [`Resolver`]: struct.Resolver.html
+#[derive(Clone)]
+pub struct BlockingResolver(Arc<BlockingInner>);
+
+/// The actual blocking resolver.
+struct BlockingInner {
+    /// The sending end of the channel for handing jobs to the thread.
+    ///
+    /// This is `None` only while shutting down.
+    jobs: Option<mpsc::UnboundedSender<Job>>,
+
+    /// The handle of the reactor thread.
+    ///
+    /// This is `None` only while shutting down.
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+/// A job for the reactor thread.
+///
+/// The job is run with the resolver and a handle to the reactor. It is
+/// expected to spawn its operation into the reactor.
+type Job = Box<FnOnce(&Resolver, &reactor::Handle) + Send>;
+
+impl BlockingResolver {
+    /// Creates a new blocking resolver using the system’s configuration.
+    ///
+    /// Returns an error if the reactor thread cannot be started.
+    pub fn new() -> io::Result<Self> {
+        Self::from_conf(ResolvConf::default())
+    }
+
+    /// Creates a new blocking resolver using the given configuration.
+    ///
+    /// Returns an error if the reactor thread cannot be started.
+    pub fn from_conf(conf: ResolvConf) -> io::Result<Self> {
+        let (tx, rx) = mpsc::unbounded::<Job>();
+        let (started_tx, started_rx) = std_mpsc::channel();
+        let thread = thread::Builder::new()
+                                    .name("domain-resolver".into())
+                                    .spawn(move || {
+            let mut core = match reactor::Core::new() {
+                Ok(core) => core,
+                Err(err) => {
+                    started_tx.send(Err(err)).ok();
+                    return
+                }
+            };
+            let handle = core.handle();
+            let resolver = Resolver::from_conf(&handle, conf);
+            started_tx.send(Ok(())).ok();
+            // The stream of jobs ends once the last blocking resolver
+            // is dropped, which ends the thread and with it everything
+            // spawned into the reactor.
+            core.run(rx.for_each(|job| {
+                job(&resolver, &handle);
+                Ok(())
+            })).ok();
+        })?;
+        match started_rx.recv() {
+            Ok(Ok(())) => { }
+            Ok(Err(err)) => {
+                thread.join().ok();
+                return Err(err)
+            }
+            Err(_) => {
+                thread.join().ok();
+                return Err(io::Error::new(io::ErrorKind::Other,
+                                          "resolver thread failed"))
+            }
+        }
+        Ok(BlockingResolver(Arc::new(BlockingInner {
+            jobs: Some(tx),
+            thread: Some(thread),
+        })))
+    }
+
+    /// Synchronously performs a DNS operation on the resolver.
+    ///
+    /// The closure `op` is sent to the reactor thread and run there with
+    /// a clone of the resolver. The future it returns is driven to
+    /// completion on the reactor and its result returned.
+    ///
+    /// This is the blocking equivalent of `Resolver::run()`. All other
+    /// methods of this type are built atop it.
+    pub fn run<R, F>(&self, op: F) -> Result<R::Item, R::Error>
+               where R: Future + 'static,
+                     R::Item: Send + 'static,
+                     R::Error: From<io::Error> + Send + 'static,
+                     F: FnOnce(Resolver) -> R + Send + 'static {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |resolver: &Resolver,
+                                      reactor: &reactor::Handle| {
+            reactor.spawn(op(resolver.clone()).then(move |res| {
+                tx.send(res).ok();
+                Ok(())
+            }))
+        });
+        let sent = match self.0.jobs {
+            Some(ref jobs) => jobs.unbounded_send(job).is_ok(),
+            None => false
+        };
+        if !sent {
+            return Err(Self::gone().into())
+        }
+        match rx.wait() {
+            Ok(res) => res,
+            Err(_) => Err(Self::gone().into())
+        }
+    }
+
+    /// Synchronously performs a DNS query.
+    ///
+    /// This is the blocking equivalent of `Resolver::query()`.
+    pub fn query<N, Q>(&self, question: Q) -> Result<MessageBuf, Error>
+                 where N: DName, Q: Into<Question<N>> {
+        let question = question.into();
+        let question = Question::new(question.qname().to_cow().into_owned(),
+                                     question.qtype(), question.qclass());
+        self.run(move |resolver| resolver.query(question))
+    }
+
+    /// Synchronously looks up the addresses of a host.
+    ///
+    /// This is the blocking equivalent of the [`lookup_host()`] function.
+    ///
+    /// [`lookup_host()`]: lookup/host/fn.lookup_host.html
+    pub fn lookup_host<N>(&self, name: N) -> Result<FoundHosts, Error>
+                       where N: AsRef<DNameSlice> {
+        let name = name.as_ref().to_owned();
+        self.run(move |resolver| lookup_host(resolver, name))
+    }
+
+    /// Synchronously looks up the host names for an address.
+    ///
+    /// This is the blocking equivalent of the [`lookup_addr()`] function.
+    ///
+    /// [`lookup_addr()`]: lookup/addr/fn.lookup_addr.html
+    pub fn lookup_addr(&self, addr: IpAddr) -> Result<FoundAddrs, Error> {
+        self.run(move |resolver| lookup_addr(resolver, addr))
+    }
+
+    /// Synchronously looks up the servers for a service.
+    ///
+    /// This performs the [`lookup_srv()`] function and then resolves the
+    /// addresses of all servers found. The servers are returned in the
+    /// order of the usage rules of RFC 2782. Servers whose addresses
+    /// cannot be found are skipped.
+    ///
+    /// Returns `Ok(None)` if the service is decidedly not available.
+    ///
+    /// [`lookup_srv()`]: lookup/srv/fn.lookup_srv.html
+    pub fn lookup_srv<N>(&self, service: N, name: N, fallback_port: u16)
+                         -> Result<Option<Vec<SrvItem>>, Error>
+                      where N: AsRef<DNameSlice> {
+        let service = service.as_ref().to_owned();
+        let name = name.as_ref().to_owned();
+        self.run(move |resolver| {
+            lookup_srv(resolver.clone(), service, name, fallback_port)
+                .and_then(move |found| {
+                    match found {
+                        Some(found) => {
+                            Either::A(found.to_stream(resolver).collect()
+                                           .map(Some))
+                        }
+                        None => Either::B(future::ok(None))
+                    }
+                })
+        })
+    }
+
+    /// Returns the error for when the reactor thread has gone away.
+    fn gone() -> io::Error {
+        io::Error::new(io::ErrorKind::Other, "resolver thread has gone away")
+    }
+}
+
+
+//--- Debug
+
+impl fmt::Debug for BlockingResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BlockingResolver").finish()
+    }
+}
+
+
+//------------ BlockingInner -------------------------------------------------
+
+impl Drop for BlockingInner {
+    fn drop(&mut self) {
+        // Dropping the sender ends the reactor thread’s job stream.
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use std::thread;
+    use ::bits::DNameBuf;
+    use super::super::hosts::HostsFile;
+    use super::*;
+
+    fn resolver() -> BlockingResolver {
+        let path = env::temp_dir().join(format!("domain-blocking-hosts-{}",
+                                                process::id()));
+        fs::write(&path, "192.0.2.1 host.example.\n").unwrap();
+        let mut conf = ResolvConf::new();
+        conf.hosts = HostsFile::from_path(&path);
+        fs::remove_file(&path).unwrap();
+        conf.finalize();
+        BlockingResolver::from_conf(conf).unwrap()
+    }
+
+    #[test]
+    fn send_sync() {
+        fn check<T: Send + Sync>() { }
+        check::<BlockingResolver>();
+    }
+
+    #[test]
+    fn lookup_from_threads() {
+        let resolver = resolver();
+        let threads: Vec<_> = (0..4).map(|_| {
+            let resolver = resolver.clone();
+            thread::spawn(move || {
+                let name = DNameBuf::from_str("host.example.").unwrap();
+                let found = resolver.lookup_host(&name).unwrap();
+                assert_eq!(found.iter().collect::<Vec<_>>(),
+                           vec![IpAddr::from_str("192.0.2.1").unwrap()]);
+                let found = resolver.lookup_addr(
+                    IpAddr::from_str("192.0.2.1").unwrap()
+                ).unwrap();
+                assert_eq!(found.iter().map(|name| name.to_string())
+                                .collect::<Vec<_>>(),
+                           vec!["host.example."]);
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap()
+        }
+        drop(resolver);
+    }
+}
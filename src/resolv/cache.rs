@@ -0,0 +1,457 @@
+//! A cache for responses to queries.
+//!
+//! If the `cache_size` field of the resolver configuration is not zero, the
+//! resolver keeps the responses it received in a cache shared by all its
+//! queries. Responses are kept for as long as the TTLs of the records in
+//! their answer section allow. Negative responses, i.e., those stating that
+//! a domain name doesn’t exist or doesn’t have any records of the requested
+//! type, are kept as long as determined by the SOA record in their
+//! authority section as described in RFC 2308.
+//!
+//! The cache is limited to a given number of entries. If it is full, the
+//! entry that has been used least recently is dropped.
+//!
+//! When handing out a cached response, the TTLs of all records in the
+//! message are decreased by the time the response has spent in the cache.
+//! The SOA record of a negative response never has a TTL longer than the
+//! time the response is kept.
+
+use std::{cmp, fmt};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use ::bits::{DName, DNameBuf, Message, MessageBuf, ParsedDName, Parser,
+             ParseResult, Question};
+use ::iana::{Rcode, Rtype};
+use ::rdata::parsed::Soa;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The maximum time in seconds a positive response is kept.
+///
+/// This is one week as suggested by RFC 8767.
+const MAX_TTL: u32 = 604_800;
+
+/// The maximum time in seconds a negative response is kept.
+///
+/// RFC 2308 suggests values between one and three hours. We go with the
+/// latter.
+const MAX_NEGATIVE_TTL: u32 = 10_800;
+
+
+//------------ Cache ---------------------------------------------------------
+
+/// A response cache.
+///
+/// The cache is keyed by the question of a query and stores the complete
+/// response message for it. It is safe to share between threads.
+pub struct Cache {
+    /// The maximum number of entries in the cache.
+    capacity: usize,
+
+    /// The actual cache.
+    inner: Mutex<CacheInner>,
+}
+
+impl Cache {
+    /// Creates a new cache that keeps at most `capacity` responses.
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            capacity: capacity,
+            inner: Mutex::new(CacheInner::new())
+        }
+    }
+
+    /// Returns a cached response for `question` if there is one.
+    ///
+    /// The TTLs of the records in the returned message will have been
+    /// decreased by the time the response has spent in the cache.
+    pub fn get(&self, question: &Question<DNameBuf>) -> Option<MessageBuf> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.get(question, Instant::now())
+    }
+
+    /// Adds `response` as the response for `question` to the cache.
+    ///
+    /// If the response can’t be cached, for instance because it is an
+    /// error response or its TTLs are zero, nothing happens. Otherwise,
+    /// it replaces an existing entry for `question`. If the cache is full,
+    /// the least recently used entry is removed.
+    pub fn insert(&self, question: Question<DNameBuf>,
+                  response: &Message) {
+        if self.capacity == 0 {
+            return
+        }
+        let entry = match CacheEntry::new(response, Instant::now()) {
+            Some(entry) => entry,
+            None => return
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.insert(question, entry, self.capacity)
+    }
+
+    /// Removes all entries from the cache.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner = CacheInner::new()
+    }
+
+    /// Returns the number of entries currently in the cache.
+    ///
+    /// This includes entries that have expired but haven’t been removed
+    /// yet.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+}
+
+
+//--- Debug
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cache")
+         .field("capacity", &self.capacity)
+         .field("len", &self.len())
+         .finish()
+    }
+}
+
+
+//------------ CacheInner ----------------------------------------------------
+
+/// The content of a cache.
+///
+/// Least recently used tracking works through a counter that is increased
+/// every time an entry is used. Each entry remembers its counter value and
+/// `lru` maps those values back to the entries’ keys. Its first element
+/// therefore is the least recently used entry.
+struct CacheInner {
+    /// The entries of the cache.
+    entries: HashMap<Question<DNameBuf>, CacheEntry>,
+
+    /// The keys of the entries ordered by the time they were last used.
+    lru: BTreeMap<u64, Question<DNameBuf>>,
+
+    /// The next value for the usage counter.
+    tick: u64,
+}
+
+impl CacheInner {
+    fn new() -> Self {
+        CacheInner {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0
+        }
+    }
+
+    /// Returns the response for `question` as of `now`.
+    ///
+    /// Drops the entry if it has expired.
+    fn get(&mut self, question: &Question<DNameBuf>, now: Instant)
+           -> Option<MessageBuf> {
+        let tick = self.next_tick();
+        let (old_tick, response) = match self.entries.get_mut(question) {
+            Some(entry) => {
+                let old_tick = entry.tick;
+                entry.tick = tick;
+                (old_tick, entry.response(now))
+            }
+            None => return None
+        };
+        self.lru.remove(&old_tick);
+        match response {
+            Some(response) => {
+                self.lru.insert(tick, question.clone());
+                Some(response)
+            }
+            None => {
+                self.entries.remove(question);
+                None
+            }
+        }
+    }
+
+    /// Inserts `entry` for `question`, making room if necessary.
+    fn insert(&mut self, question: Question<DNameBuf>,
+              mut entry: CacheEntry, capacity: usize) {
+        if let Some(old) = self.entries.remove(&question) {
+            self.lru.remove(&old.tick);
+        }
+        while self.entries.len() >= capacity {
+            let oldest = match self.lru.keys().next() {
+                Some(tick) => *tick,
+                None => break
+            };
+            if let Some(key) = self.lru.remove(&oldest) {
+                self.entries.remove(&key);
+            }
+        }
+        entry.tick = self.next_tick();
+        self.lru.insert(entry.tick, question.clone());
+        self.entries.insert(question, entry);
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        let res = self.tick;
+        self.tick += 1;
+        res
+    }
+}
+
+
+//------------ CacheEntry ----------------------------------------------------
+
+/// A single cached response.
+struct CacheEntry {
+    /// The response as it was received.
+    response: MessageBuf,
+
+    /// The position and original value of the TTL of each record.
+    ///
+    /// The OPT record is not included since its TTL field is used for
+    /// something else entirely.
+    ttls: Vec<(usize, u32)>,
+
+    /// The time the response was received.
+    created: Instant,
+
+    /// The time the response expires.
+    expires: Instant,
+
+    /// The value of the usage counter when the entry was last used.
+    tick: u64,
+}
+
+impl CacheEntry {
+    /// Creates a new entry for a response received at `now`.
+    ///
+    /// Returns `None` if the response must not be cached.
+    fn new(response: &Message, now: Instant) -> Option<Self> {
+        let ttl = match cache_ttl(response) {
+            Ok(Some(ttl)) if ttl > 0 => ttl,
+            _ => return None
+        };
+        // The SOA record of a negative response is handed out with the
+        // negative TTL as required by RFC 2308, section 5.
+        let soa_ttl = match is_negative(response) {
+            Some(true) => Some(ttl),
+            _ => None
+        };
+        let ttls = match record_ttls(response, soa_ttl) {
+            Ok(ttls) => ttls,
+            Err(_) => return None
+        };
+        Some(CacheEntry {
+            response: response.to_owned(),
+            ttls: ttls,
+            created: now,
+            expires: now + Duration::from_secs(ttl as u64),
+            tick: 0
+        })
+    }
+
+    /// Returns the response adjusted to `now` or `None` if it expired.
+    fn response(&self, now: Instant) -> Option<MessageBuf> {
+        if now >= self.expires {
+            return None
+        }
+        let elapsed = now.duration_since(self.created).as_secs();
+        let elapsed = cmp::min(elapsed, ::std::u32::MAX as u64) as u32;
+        let mut bytes = self.response.as_bytes().to_vec();
+        for &(pos, ttl) in &self.ttls {
+            let ttl = ttl.saturating_sub(elapsed);
+            bytes[pos] = (ttl >> 24) as u8;
+            bytes[pos + 1] = (ttl >> 16) as u8;
+            bytes[pos + 2] = (ttl >> 8) as u8;
+            bytes[pos + 3] = ttl as u8;
+        }
+        MessageBuf::from_vec(bytes).ok()
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the number of seconds a response may be cached.
+///
+/// Returns `Ok(None)` if the response must not be cached at all.
+///
+/// For a positive response, this is the smallest TTL of the records in the
+/// answer section. For a negative response, i.e., NXDOMAIN or a NOERROR
+/// response without any answers, it is the smaller of the TTL and minimum
+/// field of the SOA record in the authority section. If there is no such
+/// record, negative responses aren’t cached as required by RFC 2308.
+fn cache_ttl(response: &Message) -> ParseResult<Option<u32>> {
+    let negative = match is_negative(response) {
+        Some(negative) => negative,
+        None => return Ok(None)
+    };
+    if negative {
+        match try!(response.authority()).limit_to::<Soa>().next() {
+            Some(record) => {
+                let record = try!(record);
+                let ttl = cmp::min(record.ttl(), record.data().minimum());
+                Ok(Some(cmp::min(ttl, MAX_NEGATIVE_TTL)))
+            }
+            None => Ok(None)
+        }
+    }
+    else {
+        let mut res = MAX_TTL;
+        for record in try!(response.answer()) {
+            res = cmp::min(res, try!(record).ttl());
+        }
+        Ok(Some(res))
+    }
+}
+
+/// Returns whether a response is negative.
+///
+/// A response is negative if it is NXDOMAIN or a NOERROR response without
+/// any answers. Returns `None` if the response must not be cached at all
+/// because it is truncated or has some other response code.
+fn is_negative(response: &Message) -> Option<bool> {
+    let header = response.header();
+    if header.tc() {
+        return None
+    }
+    match header.rcode() {
+        Rcode::NXDomain => Some(true),
+        Rcode::NoError => Some(response.counts().ancount() == 0),
+        _ => None
+    }
+}
+
+/// Returns the positions and values of the TTLs of all records.
+///
+/// If `soa_ttl` is given, the TTLs of SOA records are limited to it.
+fn record_ttls(response: &Message, soa_ttl: Option<u32>)
+               -> ParseResult<Vec<(usize, u32)>> {
+    let counts = response.counts();
+    let mut parser = Parser::new(response.as_bytes());
+    try!(parser.skip(12));
+    for _ in 0..counts.qdcount() {
+        try!(ParsedDName::parse(&mut parser));
+        try!(parser.skip(4));
+    }
+    let records = counts.ancount() as usize + counts.nscount() as usize
+                + counts.arcount() as usize;
+    let mut res = Vec::with_capacity(records);
+    for _ in 0..records {
+        try!(ParsedDName::parse(&mut parser));
+        let rtype = try!(Rtype::parse(&mut parser));
+        try!(parser.skip(2));
+        let pos = parser.pos();
+        let mut ttl = try!(parser.parse_u32());
+        if let (Rtype::Soa, Some(soa_ttl)) = (rtype, soa_ttl) {
+            ttl = cmp::min(ttl, soa_ttl)
+        }
+        let rdlen = try!(parser.parse_u16()) as usize;
+        try!(parser.skip(rdlen));
+        if rtype != Rtype::Opt {
+            res.push((pos, ttl))
+        }
+    }
+    Ok(res)
+}
+
+/// Converts a question into the key used by the cache.
+pub fn cache_key<N: DName>(question: &Question<N>) -> Question<DNameBuf> {
+    Question::new(question.qname().to_cow().into_owned(),
+                  question.qtype(), question.qclass())
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+    use ::bits::{ComposeMode, DNameBuf, MessageBuf, MessageBuilder,
+                 Question};
+    use ::iana::{Class, Rcode, Rtype};
+    use ::rdata::{A, Soa};
+    use super::*;
+
+    fn question(name: &str) -> Question<DNameBuf> {
+        Question::new(DNameBuf::from_str(name).unwrap(), Rtype::A, Class::In)
+    }
+
+    fn positive(name: &str, ttl: u32) -> MessageBuf {
+        let name = DNameBuf::from_str(name).unwrap();
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited,
+                                          true).unwrap();
+        msg.header_mut().set_qr(true);
+        msg.push((&name, Rtype::A)).unwrap();
+        let mut msg = msg.answer();
+        msg.push((&name, ttl, A::from_octets(192, 0, 2, 1))).unwrap();
+        msg.push((&name, ttl + 100, A::from_octets(192, 0, 2, 2))).unwrap();
+        MessageBuf::from_vec(msg.finish()).unwrap()
+    }
+
+    fn negative(name: &str, ttl: u32, minimum: u32) -> MessageBuf {
+        let name = DNameBuf::from_str(name).unwrap();
+        let root = DNameBuf::root();
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited,
+                                          true).unwrap();
+        msg.header_mut().set_qr(true);
+        msg.header_mut().set_rcode(Rcode::NXDomain);
+        msg.push((&name, Rtype::A)).unwrap();
+        let mut msg = msg.authority();
+        msg.push((&root, ttl, Soa::new(&root, &root, 1, 2, 3, 4,
+                                       minimum))).unwrap();
+        MessageBuf::from_vec(msg.finish()).unwrap()
+    }
+
+    fn ttls(msg: &MessageBuf) -> Vec<u32> {
+        msg.answer().unwrap().chain(msg.authority().unwrap())
+           .map(|record| record.unwrap().ttl()).collect()
+    }
+
+    #[test]
+    fn ttls_and_expiry() {
+        let now = Instant::now();
+        let entry = CacheEntry::new(&positive("example.com.", 300),
+                                    now).unwrap();
+        assert_eq!(entry.expires, now + Duration::from_secs(300));
+        let msg = entry.response(now + Duration::from_secs(100)).unwrap();
+        assert_eq!(ttls(&msg), vec![200, 300]);
+        assert!(entry.response(now + Duration::from_secs(300)).is_none());
+
+        let entry = CacheEntry::new(&negative("example.com.", 600, 60),
+                                    now).unwrap();
+        assert_eq!(entry.expires, now + Duration::from_secs(60));
+        let msg = entry.response(now + Duration::from_secs(10)).unwrap();
+        assert_eq!(ttls(&msg), vec![50]);
+
+        // A SOA TTL shorter than the minimum wins.
+        let entry = CacheEntry::new(&negative("example.com.", 30, 60),
+                                    now).unwrap();
+        assert_eq!(entry.expires, now + Duration::from_secs(30));
+        let msg = entry.response(now + Duration::from_secs(10)).unwrap();
+        assert_eq!(ttls(&msg), vec![20]);
+
+        assert!(CacheEntry::new(&positive("example.com.", 0),
+                                now).is_none());
+    }
+
+    #[test]
+    fn lru() {
+        let cache = Cache::new(2);
+        cache.insert(question("a.example.com."),
+                     &positive("a.example.com.", 300));
+        cache.insert(question("b.example.com."),
+                     &positive("b.example.com.", 300));
+        assert!(cache.get(&question("A.EXAMPLE.COM.")).is_some());
+        cache.insert(question("c.example.com."),
+                     &positive("c.example.com.", 300));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&question("a.example.com.")).is_some());
+        assert!(cache.get(&question("b.example.com.")).is_none());
+        assert!(cache.get(&question("c.example.com.")).is_some());
+    }
+}
@@ -0,0 +1,82 @@
+//! Randomizing the case of query names.
+//!
+//! Since name servers compare domain names case-insensitively but are
+//! supposed to copy the question of a request into the response verbatim,
+//! the case of the letters in a query name can be used to carry random
+//! bits. An attacker trying to spoof a response has to guess these bits in
+//! addition to the message ID. This technique is known as 0x20 encoding
+//! after the bit that distinguishes upper and lower case ASCII letters.
+//!
+//! The resolver uses this if the `randomize_case` field of the resolver
+//! configuration is set.
+
+use rand::random;
+use ::bits::{DName, DNameBuf};
+use ::bits::name::LabelContent;
+
+
+//------------ randomize -----------------------------------------------------
+
+/// Returns a copy of `name` with the case of all letters randomized.
+///
+/// Only normal labels are changed. Binary labels are copied as they are.
+pub fn randomize<N: DName>(name: &N) -> DNameBuf {
+    let mut res = DNameBuf::new();
+    for label in name.labels() {
+        // Since the labels come from a valid name, pushing can’t fail.
+        match label.content() {
+            LabelContent::Normal(content) if !content.is_empty() => {
+                let content: Vec<u8> = content.iter().map(|&ch| {
+                    if ch.is_ascii_alphabetic() && random() {
+                        ch ^ 0x20
+                    }
+                    else {
+                        ch
+                    }
+                }).collect();
+                res.push_normal(&content).unwrap()
+            }
+            _ => res.push(label).unwrap()
+        }
+    }
+    res
+}
+
+
+//------------ eq_exact ------------------------------------------------------
+
+/// Returns whether two names are equal including the case of all letters.
+pub fn eq_exact<N: DName, M: DName>(left: &N, right: &M) -> bool {
+    left.labels().map(|label| label.as_bytes())
+        .eq(right.labels().map(|label| label.as_bytes()))
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use ::bits::DNameBuf;
+    use super::*;
+
+    #[test]
+    fn randomize_and_compare() {
+        let name = DNameBuf::from_str("www.example-1.com.").unwrap();
+        let mut changed = false;
+        for _ in 0..20 {
+            let random = randomize(&name);
+            assert_eq!(random, name);
+            assert_eq!(random.len(), name.len());
+            assert!(eq_exact(&random, &random.clone()));
+            changed = changed || !eq_exact(&random, &name);
+        }
+        assert!(changed);
+
+        let upper = DNameBuf::from_str("WWW.example-1.com.").unwrap();
+        assert!(!eq_exact(&name, &upper));
+        assert!(eq_exact(&name, &name.clone()));
+        assert!(!eq_exact(&name,
+                          &DNameBuf::from_str("www.example.com.").unwrap()));
+    }
+}
@@ -10,8 +10,10 @@
 
 use std::{io, mem};
 use std::io::{Read, Write};
+use std::time::Duration;
 use futures::{Async, AsyncSink, Future, Poll, StartSend};
 use ::bits::MessageBuf;
+use ::bits::opt::rfc7828::TcpKeepalive;
 use super::request::TransportRequest;
 
 
@@ -63,6 +65,18 @@ pub trait Channel {
     /// Polls for a received response.
     fn poll_recv(&mut self) -> Poll<MessageBuf, io::Error>;
 
+    /// Returns a request that failed without taking down the channel.
+    ///
+    /// Channels that exchange each message over a separate stream, such as
+    /// DNS over HTTPS, can lose a single request while the others carry on.
+    /// After `poll_recv()`, the owner calls this method until it returns
+    /// `None` and fails the requests with the returned message IDs.
+    ///
+    /// The default implementation always returns `None`.
+    fn poll_failed(&mut self) -> Option<(u16, io::Error)> {
+        None
+    }
+
     /// Sends the channel into sleep mode.
     ///
     /// The channel should surrender all helt resources, ie., close all its
@@ -71,6 +85,19 @@ pub trait Channel {
     /// responsibility to make sure that doesn’t happen or at least does not
     /// cause any harm.
     fn sleep(&mut self) -> Result<(), io::Error>;
+
+    /// Returns the idle timeout requested by the peer.
+    ///
+    /// If the peer has told the channel for how long it is willing to keep
+    /// an idle connection open, returns this time. The owner of the
+    /// channel should send it to sleep once it has been idle for that
+    /// long, even immediately if the time is zero. If `None` is returned,
+    /// the owner uses whatever timeout it sees fit.
+    ///
+    /// The default implementation always returns `None`.
+    fn keep_alive(&self) -> Option<Duration> {
+        None
+    }
 }
 
 
@@ -101,6 +128,11 @@ pub trait ConnectStream {
 ///
 /// This type is generic over the the `ConnectStream` trait which provides
 /// the ability to create a stream socket when needed.
+///
+/// The channel negotiates the edns-tcp-keepalive option defined in RFC
+/// 7828: The first request sent over a new connection carries the option
+/// if it has an OPT record and the idle timeout included by the server
+/// in its responses is made available via `Channel::keep_alive()`.
 pub struct StreamChannel<C: ConnectStream> {
     /// The thing that makes new sockets.
     connect: C,
@@ -121,6 +153,12 @@ pub struct StreamChannel<C: ConnectStream> {
 
     /// How much we have read of the read item so far.
     rd_pos: usize,
+
+    /// Have we sent the edns-tcp-keepalive option on this connection?
+    keepalive_sent: bool,
+
+    /// The idle timeout the server has told us about, if any.
+    keepalive: Option<Duration>,
 }
 
 impl<C: ConnectStream> StreamChannel<C> {
@@ -132,7 +170,9 @@ impl<C: ConnectStream> StreamChannel<C> {
             wr: None,
             wr_pos: 0,
             rd: ReadItem::size(),
-            rd_pos: 0
+            rd_pos: 0,
+            keepalive_sent: false,
+            keepalive: None,
         }
     }
 }
@@ -149,7 +189,8 @@ impl<C: ConnectStream> Channel for StreamChannel<C> {
         self.wr = Some(request);
         self.wr_pos = 0;
         if let SockState::Idle = self.sock {
-            self.sock = SockState::Connecting(self.connect.connect())
+            self.sock = SockState::Connecting(self.connect.connect());
+            self.reset_keepalive();
         }
         Ok(AsyncSink::Ready)
     }
@@ -182,12 +223,16 @@ impl<C: ConnectStream> Channel for StreamChannel<C> {
                     self.rd_pos = 0;
                     let item = mem::replace(&mut self.rd, next);
                     if let Some(message) = item.finish() {
+                        if let Some(timeout) = keepalive_timeout(&message) {
+                            self.keepalive = Some(timeout)
+                        }
                         return Ok(Async::Ready(message))
                     }
                 }
             }
         }
         self.sock = SockState::Idle;
+        self.reset_keepalive();
         Ok(Async::NotReady)
     }
 
@@ -196,8 +241,13 @@ impl<C: ConnectStream> Channel for StreamChannel<C> {
         self.wr = None;
         self.rd = ReadItem::size();
         self.rd_pos = 0;
+        self.reset_keepalive();
         Ok(())
     }
+
+    fn keep_alive(&self) -> Option<Duration> {
+        self.keepalive
+    }
 }
 
 impl<C: ConnectStream> StreamChannel<C> {
@@ -226,6 +276,9 @@ impl<C: ConnectStream> StreamChannel<C> {
             None => return Ok(Async::NotReady),
         };
         let mut msg = wr.message();
+        if self.wr_pos == 0 && !self.keepalive_sent {
+            self.keepalive_sent = msg.add_tcp_keepalive();
+        }
         let buf = msg.stream_bytes();
         let sock = match self.sock {
             SockState::Active(ref mut sock) => sock,
@@ -242,6 +295,31 @@ impl<C: ConnectStream> StreamChannel<C> {
         }
         Ok(Async::Ready(()))
     }
+
+    /// Forgets everything we know about keepalive for a new connection.
+    fn reset_keepalive(&mut self) {
+        self.keepalive_sent = false;
+        self.keepalive = None;
+    }
+}
+
+
+//------------ keepalive_timeout ---------------------------------------------
+
+/// Returns the idle timeout from a response’s edns-tcp-keepalive option.
+///
+/// The timeout is only returned if the response has an OPT record with
+/// the option and it contains a timeout.
+fn keepalive_timeout(message: &MessageBuf) -> Option<Duration> {
+    let opt = match message.opt() {
+        Some(opt) => opt,
+        None => return None
+    };
+    let timeout = match opt.data().iter::<TcpKeepalive>().next() {
+        Some(Ok(option)) => option.timeout(),
+        _ => None
+    };
+    timeout.map(|timeout| Duration::from_millis(u64::from(timeout) * 100))
 }
 
 
@@ -330,3 +408,41 @@ impl ReadItem {
     }
 }
 
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::time::Duration;
+    use ::bits::{ComposeMode, DNameBuf, MessageBuf, MessageBuilder};
+    use ::bits::opt::rfc7828::TcpKeepalive;
+    use ::iana::{OptRcode, Rtype};
+    use super::keepalive_timeout;
+
+    fn response(keepalive: Option<TcpKeepalive>) -> MessageBuf {
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, false)
+                                     .unwrap();
+        msg.header_mut().set_qr(true);
+        msg.push((DNameBuf::from_str("example.com.").unwrap(), Rtype::A))
+           .unwrap();
+        let mut opt = msg.additional().build_opt(1232, OptRcode::NoError,
+                                                 false).unwrap();
+        if let Some(keepalive) = keepalive {
+            opt.push(keepalive).unwrap();
+        }
+        MessageBuf::from_vec(opt.complete().unwrap().finish()).unwrap()
+    }
+
+    #[test]
+    fn keepalive() {
+        assert_eq!(keepalive_timeout(&response(None)), None);
+        assert_eq!(keepalive_timeout(&response(Some(TcpKeepalive::empty()))),
+                   None);
+        assert_eq!(keepalive_timeout(&response(Some(TcpKeepalive::new(25)))),
+                   Some(Duration::from_millis(2500)));
+        assert_eq!(keepalive_timeout(&response(Some(TcpKeepalive::new(0)))),
+                   Some(Duration::from_secs(0)));
+    }
+}
@@ -17,12 +17,19 @@ use std::error;
 use std::fmt;
 use std::fs; 
 use std::io::{self, Read};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::path::Path;
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::str::{self, FromStr, SplitWhitespace};
+use std::sync::Arc;
 use std::result;
 use std::time::Duration;
 use ::bits::name::{self, DNameBuf, DNameSlice};
+use ::bits::opt::rfc7830::PaddingPolicy;
+#[cfg(feature = "dnssec")] use super::dnssec::TrustAnchors;
+use super::hints::RootHints;
+use super::hosts::HostsFile;
+use super::observer::Observer;
+use super::policy::ResponsePolicy;
 
 
 //------------ ResolvOptions ------------------------------------------------
@@ -39,8 +46,8 @@ pub struct ResolvOptions {
     /// Only responses with the AA bit set will be considered. If there
     /// aren’t any, the query will fail.
     ///
-    /// This option is not currently implemented. It is likely to be
-    /// eventually implemented by the query.
+    /// This option is implemented by the standard response policy. It has
+    /// no effect if the resolver configuration provides its own policy.
     pub aa_only: bool,
     
     /// Always use TCP.
@@ -116,7 +123,10 @@ pub struct ResolvOptions {
 
     /// Send each query simultaneously to all name servers.
     ///
-    /// This is not currently implemented. It would be a query option.
+    /// The first acceptable response wins and all other requests are
+    /// abandoned. If `ResolvConf::blast_delay` is not zero, the requests
+    /// aren’t started all at once but one after another with this delay
+    /// between them.
     pub blast: bool,
 
     /// Use bit-label format for IPv6 reverse lookups.
@@ -135,7 +145,12 @@ pub struct ResolvOptions {
 
     /// Use EDNS0.
     ///
-    /// EDNS is not yet supported.
+    /// If enabled, requests will include an OPT record advertising the
+    /// server’s receive buffer size as the UDP payload size. If a server
+    /// responds with FORMERR, NOTIMP, or BADVERS, the request is repeated
+    /// without EDNS and the server won’t be sent EDNS requests anymore.
+    ///
+    /// This option is implemented by the query request.
     pub use_edns0: bool,
 
     /// Perform IPv4 and IPv6 lookups sequentially instead of in parallel.
@@ -185,6 +200,16 @@ impl Default for ResolvOptions {
 /// request and sockets. The timeouts are used for all transports. If you
 /// need different timeouts for, say, UDP and TCP, you can always use two
 /// server entries with the same address.
+///
+/// If the crate is built with the `tls` feature, a server can be reached
+/// via DNS-over-TLS as defined in RFC 7858 by providing a TLS
+/// configuration in the `tls` field. Such a server is never contacted via
+/// plain UDP or TCP. The easiest way to create such a config is the
+/// `new_tls()` function.
+///
+/// Similarly, with the `doh` feature, a server can be reached via
+/// DNS-over-HTTPS as defined in RFC 8484 by providing its URI template in
+/// the `doh` field. The `new_doh()` function helps with that.
 #[derive(Clone, Debug)]
 pub struct ServerConf {
     /// Server address.
@@ -204,6 +229,54 @@ pub struct ServerConf {
 
     /// Size of the message receive buffer in bytes.
     pub recv_size: usize,
+
+    /// The number of UDP sockets to spread requests over.
+    ///
+    /// If this is larger than one, a multiplexed UDP transport binds up to
+    /// this many sockets, each to a random port, and sends each request
+    /// over one of them picked at random. This adds the entropy of the
+    /// source port to that of the message ID, making it harder to spoof
+    /// responses, and allows more requests in flight than there are IDs.
+    /// The field is ignored for other UDP modes. The default is one.
+    pub udp_sockets: usize,
+
+    /// The number of requests sent over a UDP socket before replacing it.
+    ///
+    /// If this is not zero, a multiplexed UDP transport stops using a
+    /// socket after sending this many requests over it. Once all
+    /// responses have arrived or timed out, the socket is closed and a
+    /// new one bound to a different random port. Setting this to one
+    /// gives each request a port of its own. If the value is zero, which
+    /// is the default, sockets are only replaced after being idle for the
+    /// keep alive duration. Either way, a socket that receives a response
+    /// not matching any of its requests is replaced, too.
+    pub udp_socket_uses: usize,
+
+    /// How to pad requests sent over encrypted transports.
+    ///
+    /// Padding as per RFC 7830 hides the exact size of messages and thus
+    /// makes it harder to guess the query name from the size of encrypted
+    /// messages. It is only used if the server is reached via TLS. For
+    /// such servers, EDNS is used even if the `use_edns0` option isn’t
+    /// set. By default, requests are padded to a multiple of 128 octets as
+    /// recommended by RFC 8467.
+    pub padding: Option<PaddingPolicy>,
+
+    /// The TLS configuration for DNS-over-TLS.
+    ///
+    /// If this is `Some(_)`, the `tcp` field determines the mode of the
+    /// TLS transport and the `udp` field is ignored.
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConf>,
+
+    /// The configuration for DNS-over-HTTPS.
+    ///
+    /// If this is `Some(_)`, the server is only contacted via HTTP/2. The
+    /// `tcp` field determines the mode of this transport and the `tls`
+    /// field how the server is authenticated. If `tls` is `None`, plain
+    /// HTTP is used which is really only useful for testing.
+    #[cfg(feature = "doh")]
+    pub doh: Option<DohConf>,
 }
 
 
@@ -236,9 +309,581 @@ impl ServerConf {
             request_timeout: Duration::from_secs(2),
             keep_alive: Duration::from_secs(10),
             recv_size: 4096,
+            udp_sockets: 1,
+            udp_socket_uses: 0,
+            padding: Some(PaddingPolicy::query()),
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "doh")]
+            doh: None,
+        }
+    }
+
+    /// Returns a new server config for DNS-over-TLS.
+    ///
+    /// The server will be contacted on the well-known port 853 for
+    /// DNS-over-TLS. By default, the TLS transport will multiplex requests
+    /// over a single connection.
+    #[cfg(feature = "tls")]
+    pub fn new_tls(addr: IpAddr, tls: TlsConf) -> Self {
+        let mut res = ServerConf::new(SocketAddr::new(addr, 853));
+        res.udp = ServerMode::None;
+        res.tls = Some(tls);
+        res
+    }
+
+    /// Returns a new server config for DNS-over-HTTPS.
+    ///
+    /// The server at `addr` will be contacted using the URI template given
+    /// in `doh`. If the template uses the `https` scheme, the server is
+    /// authenticated through the host name from the template. The
+    /// template’s host name is not resolved, so `addr` must be given
+    /// explicitly, normally with port 443.
+    #[cfg(feature = "doh")]
+    pub fn new_doh(addr: SocketAddr, doh: DohConf) -> Self {
+        let mut res = ServerConf::new(addr);
+        res.udp = ServerMode::None;
+        if doh.is_https() {
+            res.tls = Some(TlsConf::with_auth_name(doh.host()));
+        }
+        res.doh = Some(doh);
+        res
+    }
+
+    /// Returns whether the server is only to be contacted via TLS.
+    ///
+    /// This is true for both DNS-over-TLS and DNS-over-HTTPS.
+    pub fn is_tls(&self) -> bool {
+        #[cfg(feature = "tls")]
+        { self.tls.is_some() }
+        #[cfg(not(feature = "tls"))]
+        { false }
+    }
+
+    /// Returns whether the server is to be contacted via HTTP.
+    pub fn is_doh(&self) -> bool {
+        #[cfg(feature = "doh")]
+        { self.doh.is_some() }
+        #[cfg(not(feature = "doh"))]
+        { false }
+    }
+
+    /// Returns whether a multiplexed UDP transport uses a socket pool.
+    ///
+    /// This is the case if either `udp_sockets` is larger than one or
+    /// `udp_socket_uses` is not zero.
+    pub fn is_udp_pool(&self) -> bool {
+        self.udp_sockets > 1 || self.udp_socket_uses > 0
+    }
+}
+
+
+//------------ TlsConf -------------------------------------------------------
+
+/// Configuration for reaching a server via DNS-over-TLS.
+///
+/// RFC 7858 and RFC 8310 describe several ways to authenticate the server.
+/// If `auth_name` is given, the server’s certificate must be valid for
+/// this name and chain up to one of the system’s trusted root certificates
+/// or one of the certificates in `root_certs`. If `pins` is not empty,
+/// the public key of the server’s certificate must match one of the pins.
+/// If both are given, both checks must succeed.
+///
+/// If neither is given, the server is not authenticated at all and the
+/// connection merely encrypted. This is called *opportunistic privacy* in
+/// RFC 7858. Since it is easily attacked, it has to be asked for
+/// explicitly through the `opportunistic` field, most easily via the
+/// `opportunistic()` function. Otherwise connecting to the server fails.
+#[cfg(feature = "tls")]
+#[derive(Clone, Debug, Default)]
+pub struct TlsConf {
+    /// The authentication domain name of the server.
+    pub auth_name: Option<String>,
+
+    /// The SPKI pin set of the server.
+    ///
+    /// Each pin is the SHA-256 digest of the DER encoded
+    /// SubjectPublicKeyInfo of the server’s certificate as described in
+    /// RFC 7469.
+    pub pins: Vec<[u8; 32]>,
+
+    /// Additional trusted root certificates in DER encoding.
+    pub root_certs: Vec<Vec<u8>>,
+
+    /// Connect even if the server can’t be authenticated.
+    ///
+    /// This only matters if neither `auth_name` nor `pins` are given.
+    pub opportunistic: bool,
+}
+
+#[cfg(feature = "tls")]
+impl TlsConf {
+    /// Creates a config authenticating the server by its domain name.
+    pub fn with_auth_name<S: Into<String>>(name: S) -> Self {
+        TlsConf {
+            auth_name: Some(name.into()),
+            .. Default::default()
+        }
+    }
+
+    /// Creates a config authenticating the server by a pin set.
+    pub fn with_pins(pins: Vec<[u8; 32]>) -> Self {
+        TlsConf {
+            pins: pins,
+            .. Default::default()
+        }
+    }
+
+    /// Creates a config for opportunistic privacy.
+    ///
+    /// The connection will be encrypted but the server isn’t
+    /// authenticated.
+    pub fn opportunistic() -> Self {
+        TlsConf {
+            opportunistic: true,
+            .. Default::default()
+        }
+    }
+}
+
+
+//------------ DohConf -------------------------------------------------------
+
+/// Configuration for reaching a server via DNS-over-HTTPS.
+///
+/// The server is described by a URI template as defined in RFC 8484, for
+/// instance `https://dns.example.com/dns-query{?dns}`. Only the simple
+/// forms `{?dns}` and `{&dns}` of the template variable are supported. If
+/// the template doesn’t contain the variable, it is appended as a query
+/// parameter when needed.
+///
+/// Requests are sent via POST unless `use_get` is set. GET requests are
+/// friendlier to HTTP caches but expose the question in the URI.
+#[cfg(feature = "doh")]
+#[derive(Clone, Debug)]
+pub struct DohConf {
+    /// Use the GET method instead of POST.
+    pub use_get: bool,
+
+    /// Does the template use the `https` scheme?
+    https: bool,
+
+    /// The authority part of the template.
+    authority: String,
+
+    /// The path and query part of the template without the variable.
+    path: String,
+
+    /// The character to separate the variable from the path with.
+    separator: char,
+}
+
+#[cfg(feature = "doh")]
+impl DohConf {
+    /// Creates a new config from a URI template.
+    ///
+    /// Fails if the template is not an absolute `https` or `http` URI or
+    /// uses an unsupported form of the template variable.
+    pub fn new(template: &str) -> Result<Self> {
+        let (https, rest) = if template.starts_with("https://") {
+            (true, &template[8..])
+        }
+        else if template.starts_with("http://") {
+            (false, &template[7..])
+        }
+        else {
+            return Err(Error::ParseError)
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(pos) if pos > 0 => rest.split_at(pos),
+            _ => return Err(Error::ParseError)
+        };
+        let (path, separator) = match path.find('{') {
+            Some(pos) => {
+                let separator = match &path[pos..] {
+                    "{?dns}" => '?',
+                    "{&dns}" => '&',
+                    _ => return Err(Error::ParseError)
+                };
+                (&path[..pos], separator)
+            }
+            None => (path, if path.contains('?') { '&' } else { '?' })
+        };
+        Ok(DohConf {
+            use_get: false,
+            https: https,
+            authority: authority.into(),
+            path: path.into(),
+            separator: separator
+        })
+    }
+
+    /// Returns whether the template uses the `https` scheme.
+    pub fn is_https(&self) -> bool {
+        self.https
+    }
+
+    /// Returns the host name of the template.
+    pub fn host(&self) -> &str {
+        let authority = &self.authority;
+        if authority.starts_with('[') {
+            match authority.find(']') {
+                Some(pos) => &authority[1..pos],
+                None => authority
+            }
+        }
+        else {
+            match authority.rfind(':') {
+                Some(pos) => &authority[..pos],
+                None => authority
+            }
+        }
+    }
+
+    /// Returns the URI for a request.
+    ///
+    /// If `dns` is `Some(_)`, it is added as the value of the `dns`
+    /// variable. It must already be encoded.
+    pub fn uri(&self, dns: Option<&str>) -> String {
+        let scheme = if self.https { "https" } else { "http" };
+        match dns {
+            Some(dns) => {
+                format!("{}://{}{}{}dns={}", scheme, self.authority,
+                        self.path, self.separator, dns)
+            }
+            None => format!("{}://{}{}", scheme, self.authority, self.path)
+        }
+    }
+}
+
+
+//------------ AddrOrder -----------------------------------------------------
+
+/// How the addresses found by a host lookup are ordered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AddrOrder {
+    /// Keep the order from the DNS: IPv4 addresses before IPv6 addresses.
+    Unsorted,
+
+    /// Sort according to the destination address selection of RFC 6724.
+    Rfc6724,
+
+    /// Sort according to RFC 6724, then interleave address families.
+    ///
+    /// This is the order suggested by Happy Eyeballs, RFC 8305, for
+    /// making connection attempts.
+    HappyEyeballs,
+}
+
+impl Default for AddrOrder {
+    fn default() -> Self {
+        AddrOrder::Unsorted
+    }
+}
+
+
+//------------ SortlistEntry ------------------------------------------------
+
+/// The maximum number of sort list entries taken from `resolv.conf`.
+///
+/// Additional entries are ignored. This is the limit used by glibc.
+const MAX_SORTLIST: usize = 10;
+
+/// An entry of the sort list.
+///
+/// Each entry consists of an address and a netmask. An IP address matches
+/// the entry if it is equal to the entry’s address after applying the
+/// netmask. Both address and netmask are always of the same family.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SortlistEntry {
+    /// The network address.
+    addr: IpAddr,
+
+    /// The netmask.
+    mask: IpAddr,
+}
+
+impl SortlistEntry {
+    /// Creates a new entry from an address and a netmask.
+    ///
+    /// If `mask` is `None`, the natural netmask of the address’s class is
+    /// used for IPv4 and a host mask for IPv6, just like glibc does. Fails
+    /// if address and netmask are of different families. Host bits of
+    /// `addr` are cleared so that only the network address is kept.
+    pub fn new(addr: IpAddr, mask: Option<IpAddr>) -> Result<Self> {
+        match (addr, mask) {
+            (IpAddr::V4(addr), Some(IpAddr::V4(mask))) => {
+                Ok(Self::new_v4(addr, mask))
+            }
+            (IpAddr::V6(addr), Some(IpAddr::V6(mask))) => {
+                Ok(Self::new_v6(addr, mask))
+            }
+            (IpAddr::V4(addr), None) => {
+                Ok(Self::new_v4(addr, class_mask(addr)))
+            }
+            (IpAddr::V6(addr), None) => {
+                Ok(Self::new_v6(addr, Ipv6Addr::from([0xFF; 16])))
+            }
+            _ => Err(Error::ParseError)
+        }
+    }
+
+    /// Creates a new IPv4 entry, clearing the host bits of `addr`.
+    fn new_v4(addr: Ipv4Addr, mask: Ipv4Addr) -> Self {
+        let addr = Ipv4Addr::from(u32::from(addr) & u32::from(mask));
+        SortlistEntry { addr: IpAddr::V4(addr), mask: IpAddr::V4(mask) }
+    }
+
+    /// Creates a new IPv6 entry, clearing the host bits of `addr`.
+    fn new_v6(addr: Ipv6Addr, mask: Ipv6Addr) -> Self {
+        let (mut addr, mask) = (addr.octets(), mask.octets());
+        for i in 0..16 {
+            addr[i] &= mask[i]
+        }
+        SortlistEntry {
+            addr: IpAddr::V6(Ipv6Addr::from(addr)),
+            mask: IpAddr::V6(Ipv6Addr::from(mask))
+        }
+    }
+
+    /// Returns the network address of the entry.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns the netmask of the entry.
+    pub fn mask(&self) -> IpAddr {
+        self.mask
+    }
+
+    /// Returns whether `addr` matches the entry.
+    pub fn matches(&self, addr: &IpAddr) -> bool {
+        match (self.addr, self.mask, *addr) {
+            (IpAddr::V4(net), IpAddr::V4(mask), IpAddr::V4(addr)) => {
+                u32::from(addr) & u32::from(mask) == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(mask), IpAddr::V6(addr)) => {
+                let (net, mask, addr) = (net.octets(), mask.octets(),
+                                         addr.octets());
+                (0..16).all(|i| addr[i] & mask[i] == net[i])
+            }
+            _ => false
+        }
+    }
+}
+
+
+//--- FromStr
+
+impl FromStr for SortlistEntry {
+    type Err = Error;
+
+    /// Parses an entry in the form used by `resolv.conf`.
+    ///
+    /// This is an address optionally followed by either a slash or an
+    /// ampersand and the netmask in address form.
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, mask) = match s.find(|ch| ch == '/' || ch == '&') {
+            Some(pos) => {
+                (&s[..pos], Some(try!(IpAddr::from_str(&s[pos + 1..]))))
+            }
+            None => (s, None)
+        };
+        SortlistEntry::new(try!(IpAddr::from_str(addr)), mask)
+    }
+}
+
+
+//--- Display
+
+impl fmt::Display for SortlistEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.mask)
+    }
+}
+
+
+//------------ RecursionConf ------------------------------------------------
+
+/// Configuration for iterative resolution.
+///
+/// If the `recursion` field of the resolver configuration contains a
+/// value of this type, the resolver doesn’t send its queries to the
+/// configured servers but rather resolves them itself, starting at the
+/// root name servers given by the root hints and following referrals
+/// down to the name servers authoritative for the query name.
+#[derive(Clone, Debug)]
+pub struct RecursionConf {
+    /// The name servers to start with.
+    pub hints: RootHints,
+
+    /// The port to contact all name servers on.
+    ///
+    /// This is 53 by default and really only useful to change for testing.
+    pub port: u16,
+
+    /// Use QNAME minimisation as defined in RFC 9156.
+    ///
+    /// If this is `true`, which it is by default, the resolver only
+    /// reveals as much of the query name to a name server as is necessary
+    /// to find the next zone cut. Only the servers of the zone containing
+    /// the full query name get to see it and the actual record type.
+    pub qname_minimisation: bool,
+
+    /// The maximum number of requests sent while resolving a query.
+    ///
+    /// This includes the requests necessary to find the addresses of name
+    /// servers given without glue records.
+    pub max_queries: usize,
+
+    /// How deeply lookups of name server addresses may nest.
+    ///
+    /// Resolving the address of a name server may in turn require
+    /// resolving the address of another name server and so on. Beyond this
+    /// depth, name servers without glue are ignored.
+    pub max_depth: usize,
+}
+
+impl RecursionConf {
+    /// Creates a new configuration starting with the given root hints.
+    pub fn new(hints: RootHints) -> Self {
+        RecursionConf {
+            hints: hints,
+            port: 53,
+            qname_minimisation: true,
+            max_queries: 100,
+            max_depth: 4,
         }
     }
+}
+
+impl Default for RecursionConf {
+    /// Returns a configuration using the built-in root hints.
+    fn default() -> Self {
+        Self::new(RootHints::builtin())
+    }
+}
+
+
+//------------ RouteConf -----------------------------------------------------
+
+/// Servers and options for queries for names under a domain.
+///
+/// Routes allow sending queries for some domains to servers other than
+/// the default ones, a setup commonly called *split DNS.* For instance,
+/// names under `corp.example.` can be sent to the resolvers of a VPN
+/// while names under `consul.` go to a local agent. This is similar to the
+/// routing domains of systemd-resolved and the files in `/etc/resolver` on
+/// macOS.
+///
+/// A query uses the route whose domain is the longest suffix of the
+/// query name. Queries for names not covered by any route use the
+/// default servers and options of the resolver configuration.
+#[derive(Clone, Debug)]
+pub struct RouteConf {
+    /// The domain the route applies to.
+    ///
+    /// This has to be an absolute name. The route applies to the domain
+    /// itself and all names below it.
+    pub domain: DNameBuf,
+
+    /// The servers to query for names under the domain.
+    pub servers: Vec<ServerConf>,
 
+    /// The options for queries for names under the domain.
+    ///
+    /// These replace the options of the resolver configuration for those
+    /// queries.
+    pub options: ResolvOptions,
+
+    /// The request timeout for the route’s servers.
+    ///
+    /// If this is `None`, the timeout of the resolver configuration is
+    /// used when finalizing it.
+    pub timeout: Option<Duration>,
+}
+
+impl RouteConf {
+    /// Creates a new route for `domain` without any servers.
+    pub fn new(domain: DNameBuf) -> Self {
+        RouteConf {
+            domain: domain,
+            servers: Vec::new(),
+            options: ResolvOptions::default(),
+            timeout: None,
+        }
+    }
+
+    /// Creates a new route for `domain` using the given servers.
+    pub fn with_servers(domain: DNameBuf, servers: Vec<ServerConf>) -> Self {
+        let mut res = Self::new(domain);
+        res.servers = servers;
+        res
+    }
+
+    /// Returns whether the route applies to `name`.
+    pub fn matches(&self, name: &DNameSlice) -> bool {
+        name.ends_with(&self.domain)
+    }
+
+    /// Parses the route from a file.
+    pub fn parse_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut file = fs::File::open(path)?;
+        self.parse(&mut file)
+    }
+
+    /// Parses the route from a reader.
+    ///
+    /// The format is that of the files in `/etc/resolver` on macOS. The
+    /// `nameserver` lines give the addresses of the servers, all of which
+    /// are contacted on the port given by the `port` line or 53. The
+    /// `timeout` line gives the request timeout in seconds and `options`
+    /// lines are the same as in `/etc/resolv.conf`. The `domain`,
+    /// `search`, and `search_order` lines are ignored.
+    pub fn parse<R: Read>(&mut self, reader: &mut R) -> Result<()> {
+        use std::io::BufRead;
+
+        let mut addrs = Vec::new();
+        let mut port = 53;
+        for line in io::BufReader::new(reader).lines() {
+            let line = line?;
+            let line = line.trim_right();
+
+            if line.is_empty() || line.starts_with(';') ||
+                                  line.starts_with('#') {
+                continue
+            }
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    addrs.push(IpAddr::from_str(next_word(&mut words)?)?);
+                    no_more_words(words)?
+                }
+                Some("port") => {
+                    port = u16::from_str(next_word(&mut words)?)?;
+                    no_more_words(words)?
+                }
+                Some("timeout") => {
+                    let secs = u64::from_str(next_word(&mut words)?)?;
+                    self.timeout = Some(Duration::from_secs(secs));
+                    no_more_words(words)?
+                }
+                Some("options") => {
+                    let mut conf = ResolvConf::new();
+                    conf.options = self.options.clone();
+                    conf.parse_options(words)?;
+                    self.options = conf.options;
+                }
+                Some("domain") | Some("search") | Some("search_order") => { }
+                _ => return Err(Error::ParseError)
+            }
+        }
+        for addr in addrs {
+            self.servers.push(ServerConf::new(SocketAddr::new(addr, port)))
+        }
+        Ok(())
+    }
 }
 
 
@@ -271,8 +916,11 @@ pub struct ResolvConf {
     /// Search list for host-name lookup.
     pub search: Vec<DNameBuf>,
 
-    /// TODO Sortlist
-    /// sortlist: ??
+    /// Sort list for ordering the addresses of a host lookup.
+    ///
+    /// Addresses matching an earlier entry are placed before those
+    /// matching a later entry or none at all.
+    pub sortlist: Vec<SortlistEntry>,
 
     /// Number of dots before an initial absolute query is made.
     pub ndots: usize,
@@ -287,9 +935,138 @@ pub struct ResolvConf {
     pub options: ResolvOptions,
 
     //--- New config not in glibc
+
+    /// Routes sending queries for some domains to other servers.
+    ///
+    /// A query for a name under the domain of a route is sent to the
+    /// route’s servers using its options. If several routes match, the
+    /// one with the longest domain wins. Routes are ignored if the
+    /// resolver resolves iteratively.
+    pub routes: Vec<RouteConf>,
     
     /// Default idle timeout for stream oriented transports.
     pub idle_timeout: Duration,
+
+    /// The hosts file consulted by host and address lookups.
+    ///
+    /// This is empty for a new configuration. The default configuration
+    /// uses the system’s hosts file, normally `/etc/hosts`.
+    pub hosts: HostsFile,
+
+    /// The maximum number of responses kept in the resolver’s cache.
+    ///
+    /// If this is zero, which it is by default, responses are not cached
+    /// at all.
+    pub cache_size: usize,
+
+    /// The delay between starting requests to the servers in blast mode.
+    ///
+    /// If the `blast` option is set, a query is sent to the next server
+    /// after this delay even if the previous server hasn’t answered yet.
+    /// It is also sent right away if the previous server failed. If the
+    /// delay is zero, which is the default, all servers are asked at once.
+    pub blast_delay: Duration,
+
+    /// How to order the addresses found by a host lookup.
+    ///
+    /// This is applied before the sort list, so an entry in `sortlist`
+    /// always overrides the order determined here.
+    pub addr_order: AddrOrder,
+
+    /// Order servers by their smoothed round trip time.
+    ///
+    /// If this is `true`, each attempt starts with the server that has
+    /// been answering fastest. Servers not asked slowly have their round
+    /// trip time decreased so they will be tried again eventually. This
+    /// takes precedence over the `rotate` option. By default, servers are
+    /// asked in the order given.
+    pub srtt_order: bool,
+
+    /// The number of consecutive failures before a server is held down.
+    ///
+    /// A server that timed out or failed this many times in a row is
+    /// only asked after all other servers until `holddown_time` has passed
+    /// or it answers again. If this is zero, servers are never held down.
+    pub holddown_failures: usize,
+
+    /// For how long a server is held down after repeated failures.
+    pub holddown_time: Duration,
+
+    /// Use DNS cookies as defined in RFC 7873.
+    ///
+    /// If this is `true`, requests include a client cookie chosen for each
+    /// server and the last server cookie received from it. Responses that
+    /// contain a cookie not echoing our client cookie are dropped. Since
+    /// cookies are an EDNS option, they are only sent if EDNS is used. This
+    /// is off by default.
+    pub cookies: bool,
+
+    /// Randomize the case of query names as a defence against spoofing.
+    ///
+    /// If this is `true`, the letters of the query name are randomly
+    /// changed to upper or lower case for each request, a technique
+    /// known as 0x20 encoding, and responses have to echo the name in
+    /// exactly the same case. This is only used for UDP where the message
+    /// ID is otherwise the only defence against spoofed responses. A
+    /// response with the name in the wrong case makes the request be
+    /// repeated. If a server sends several of those in a row, it is
+    /// assumed not to preserve the case of the query name and
+    /// randomization is switched off for it for an hour. This is off by
+    /// default.
+    pub randomize_case: bool,
+
+    /// Remove out-of-bailiwick records from responses.
+    ///
+    /// If this is `true`, which it is by default, records in the authority
+    /// and additional sections of responses that don’t belong to the zone
+    /// of the answer are dropped. See the `scrub` module for details.
+    pub scrub: bool,
+
+    /// The policy deciding whether a response is acceptable.
+    ///
+    /// If this is `None`, which it is by default, the standard policy
+    /// derived from this configuration is used.
+    pub response_policy: Option<Arc<ResponsePolicy>>,
+
+    /// An observer to be told about the resolver’s traffic.
+    ///
+    /// If this is `Some(_)`, the observer learns about every request sent
+    /// to a server and its outcome. See the `observer` module for details.
+    /// This is `None` by default.
+    pub observer: Option<Arc<Observer>>,
+
+    /// The configuration file this configuration was parsed from.
+    ///
+    /// This is set by `parse_file()` and is used by `reparse()` and the
+    /// resolver when checking the file for changes.
+    pub path: Option<PathBuf>,
+
+    /// How often the resolver checks the configuration file for changes.
+    ///
+    /// If this is `Some(_)` and `path` is set, the resolver checks the
+    /// modification time and content of the file at this interval. If the
+    /// file has changed, the resolver replaces its configuration with the
+    /// one from `reparse()`. Queries already in flight finish using the
+    /// old configuration. This is `None` by default.
+    pub watch_interval: Option<Duration>,
+
+    /// Resolve queries iteratively instead of asking `servers`.
+    ///
+    /// If this is `None`, which it is by default, the resolver is a stub
+    /// resolver that sends its queries to the configured servers. Otherwise
+    /// it follows referrals starting at the root servers itself. The
+    /// timeout as well as the EDNS, cookie, and case randomization
+    /// settings apply to the requests sent to name servers.
+    pub recursion: Option<RecursionConf>,
+
+    /// Validate answers using DNSSEC starting at these trust anchors.
+    ///
+    /// If this is `None`, which it is by default, answers are accepted
+    /// as they are. Otherwise, requests are sent with the DO and CD bits
+    /// set and queries validate their answers. See the `dnssec` module
+    /// for details.
+    #[cfg(feature = "dnssec")]
+    pub trust_anchors: Option<TrustAnchors>,
 }
 
 
@@ -304,12 +1081,30 @@ impl ResolvConf {
         ResolvConf {
             servers: Vec::new(),
             search: Vec::new(),
-            //sortlist,
+            sortlist: Vec::new(),
             ndots: 1,
             timeout: Duration::new(5,0),
             attempts: 2,
             idle_timeout: Duration::new(3,0), // XXX Guesswork
-            options: ResolvOptions::default()
+            options: ResolvOptions::default(),
+            routes: Vec::new(),
+            hosts: HostsFile::new(),
+            cache_size: 0,
+            blast_delay: Duration::from_secs(0),
+            addr_order: AddrOrder::default(),
+            srtt_order: false,
+            holddown_failures: 3,
+            holddown_time: Duration::from_secs(60),
+            cookies: false,
+            randomize_case: false,
+            scrub: true,
+            response_policy: None,
+            observer: None,
+            path: None,
+            watch_interval: None,
+            recursion: None,
+            #[cfg(feature = "dnssec")]
+            trust_anchors: None,
         }
     }
 
@@ -333,6 +1128,37 @@ impl ResolvConf {
         for server in &mut self.servers {
             server.request_timeout = self.timeout
         }
+        for route in &mut self.routes {
+            let timeout = route.timeout.unwrap_or(self.timeout);
+            for server in &mut route.servers {
+                server.request_timeout = timeout
+            }
+        }
+    }
+
+    /// Returns the index of the route to use for `name`.
+    ///
+    /// This is the route with the longest domain that `name` is under or
+    /// `None` if there is no such route.
+    pub fn route(&self, name: &DNameSlice) -> Option<usize> {
+        self.routes.iter().enumerate()
+            .filter(|&(_, route)| route.matches(name))
+            .max_by_key(|&(_, route)| route.domain.len())
+            .map(|(index, _)| index)
+    }
+
+    /// Returns whether answers are to be validated using DNSSEC.
+    ///
+    /// This is only ever the case if the crate was built with the `dnssec`
+    /// feature and the `trust_anchors` field is set.
+    pub fn validates(&self) -> bool {
+        #[cfg(feature = "dnssec")]
+        {
+            if self.trust_anchors.is_some() {
+                return true
+            }
+        }
+        false
     }
 
     /// Creates a default configuration for this system.
@@ -341,6 +1167,11 @@ impl ResolvConf {
     pub fn default() -> Self {
         let mut res = ResolvConf::new();
         let _ = res.parse_file("/etc/resolv.conf");
+        #[cfg(target_os = "macos")]
+        {
+            let _ = res.parse_resolver_dir("/etc/resolver");
+        }
+        res.hosts = HostsFile::system();
         res.finalize();
         res
     }
@@ -351,11 +1182,46 @@ impl ResolvConf {
 ///
 impl ResolvConf {
     /// Parses the configuration from a file.
+    ///
+    /// The path of the file is kept in the `path` field, even if the file
+    /// cannot be opened, so it can be checked for changes later.
     pub fn parse_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.path = Some(path.as_ref().to_owned());
         let mut file = try!(fs::File::open(path));
         self.parse(&mut file)
     }
 
+    /// Creates a new configuration by parsing the configuration file again.
+    ///
+    /// The values that can be set in the file, i.e., the servers, search
+    /// and sort lists, and options, are reset and read from the file given
+    /// by `path`. All other values are kept. The new configuration is
+    /// finalized.
+    ///
+    /// Fails if `path` isn’t set or the file cannot be parsed.
+    pub fn reparse(&self) -> Result<Self> {
+        let path = match self.path {
+            Some(ref path) => path.clone(),
+            None => {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::NotFound, "no configuration file"
+                )))
+            }
+        };
+        let fresh = ResolvConf::new();
+        let mut res = self.clone();
+        res.servers = fresh.servers;
+        res.search = fresh.search;
+        res.sortlist = fresh.sortlist;
+        res.ndots = fresh.ndots;
+        res.timeout = fresh.timeout;
+        res.attempts = fresh.attempts;
+        res.options = fresh.options;
+        try!(res.parse_file(path));
+        res.finalize();
+        Ok(res)
+    }
+
     /// Parses the configuration from a reader.
     ///
     /// The format is that of the /etc/resolv.conf file.
@@ -385,6 +1251,39 @@ impl ResolvConf {
         Ok(())
     }
 
+    /// Adds routes from a directory of files.
+    ///
+    /// Each file in the directory describes the route for the domain given
+    /// by its name in the format described with `RouteConf::parse()`. This
+    /// is the layout of the `/etc/resolver` directory on macOS which is
+    /// read by `ResolvConf::default()` on that system. Files starting with
+    /// a dot and files without any servers are skipped.
+    pub fn parse_resolver_dir<P: AsRef<Path>>(&mut self, path: P)
+                                              -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if let Ok(name) = entry.file_name().into_string() {
+                if !name.starts_with('.') {
+                    entries.push((name, entry.path()))
+                }
+            }
+        }
+        entries.sort();
+        for (name, path) in entries {
+            let mut domain = DNameBuf::from_str(&name)?;
+            if domain.append_root().is_err() {
+                return Err(Error::ParseError)
+            }
+            let mut route = RouteConf::new(domain);
+            route.parse_file(path)?;
+            if !route.servers.is_empty() {
+                self.routes.push(route)
+            }
+        }
+        Ok(())
+    }
+
     fn parse_nameserver(&mut self, mut words: SplitWhitespace) -> Result<()> {
         use std::net::ToSocketAddrs;
         
@@ -414,9 +1313,16 @@ impl ResolvConf {
         Ok(())
     }
 
+    /// Parses a sortlist line.
+    ///
+    /// Like glibc, we skip entries we don’t understand rather than failing
+    /// the whole file.
     fn parse_sortlist(&mut self, words: SplitWhitespace) -> Result<()> {
-        // XXX TODO
-        let _ = words; 
+        self.sortlist = words.filter_map(|word| {
+                                 SortlistEntry::from_str(word).ok()
+                             })
+                             .take(MAX_SORTLIST)
+                             .collect();
         Ok(())
     }
  
@@ -467,6 +1373,9 @@ impl ResolvConf {
                 ("use-vc", None) => {
                     self.options.use_vc = true
                 }
+                ("blast", None) => {
+                    self.options.blast = true
+                }
                 // Ignore unknown or misformated options.
                 _ => { }
             }
@@ -507,6 +1416,13 @@ impl fmt::Display for ResolvConf {
             }
             try!("\n".fmt(f));
         }
+        if !self.sortlist.is_empty() {
+            try!("sortlist".fmt(f));
+            for entry in &self.sortlist {
+                try!(write!(f, " {}", entry));
+            }
+            try!("\n".fmt(f));
+        }
 
         // Collect options so we only print them if there are any non-default
         // ones.
@@ -581,6 +1497,14 @@ fn no_more_words(mut words: str::SplitWhitespace) -> Result<()> {
     }
 }
 
+/// Returns the natural netmask for the class of an IPv4 address.
+fn class_mask(addr: Ipv4Addr) -> Ipv4Addr {
+    let first = addr.octets()[0];
+    if first & 0x80 == 0 { Ipv4Addr::new(255, 0, 0, 0) }
+    else if first & 0xC0 == 0x80 { Ipv4Addr::new(255, 255, 0, 0) }
+    else { Ipv4Addr::new(255, 255, 255, 0) }
+}
+
 /// Splits the name and argument from an option with arguments.
 ///
 /// These options consist of a name followed by a colon followed by a
@@ -623,6 +1547,12 @@ impl convert::From<io::Error> for Error {
     }
 }
 
+impl convert::From<AddrParseError> for Error {
+    fn from(_: AddrParseError) -> Error {
+        Error::ParseError
+    }
+}
+
 impl convert::From<name::FromStrError> for Error {
     fn from(_: name::FromStrError) -> Error {
         Error::ParseError
@@ -664,4 +1594,106 @@ mod test {
         assert!(conf.options.use_vc);
         assert_eq!(conf.ndots, 122);
     }
+
+    #[test]
+    fn parse_sortlist() {
+        let mut conf = ResolvConf::new();
+        let data = "sortlist 130.155.160.0/255.255.240.0 130.155.0.0 \
+                    2001:db8::&ffff:ffff::\n".to_string();
+        assert!(conf.parse(&mut io::Cursor::new(data)).is_ok());
+        assert_eq!(conf.sortlist.len(), 3);
+        assert_eq!(format!("{}", conf),
+                   "sortlist 130.155.160.0/255.255.240.0 \
+                    130.155.0.0/255.255.0.0 \
+                    2001:db8::/ffff:ffff::\n");
+
+        let addr = |s| IpAddr::from_str(s).unwrap();
+        assert!(conf.sortlist[0].matches(&addr("130.155.175.1")));
+        assert!(!conf.sortlist[0].matches(&addr("130.155.176.1")));
+        assert!(conf.sortlist[1].matches(&addr("130.155.176.1")));
+        assert!(!conf.sortlist[1].matches(&addr("2001:db8::1")));
+        assert!(conf.sortlist[2].matches(&addr("2001:db8::1")));
+
+        // Host bits of the address are masked off.
+        let entry = SortlistEntry::from_str("192.0.2.17/255.255.255.0")
+                                  .unwrap();
+        assert_eq!(entry.addr(), addr("192.0.2.0"));
+        assert!(entry.matches(&addr("192.0.2.1")));
+        let entry = SortlistEntry::from_str("2001:db8::1/ffff:ffff::")
+                                  .unwrap();
+        assert_eq!(entry.addr(), addr("2001:db8::"));
+        assert!(entry.matches(&addr("2001:db8::2")));
+
+        // Broken entries are skipped and don’t stop the parsing of the
+        // following lines.
+        let mut conf = ResolvConf::new();
+        let data = "sortlist 192.0.2.0/ffff:: 10.0.0.0/8 198.51.100.0\n\
+                    nameserver 192.0.2.1\n\
+                    options ndots:3\n".to_string();
+        assert!(conf.parse(&mut io::Cursor::new(data)).is_ok());
+        assert_eq!(conf.sortlist.len(), 1);
+        assert!(conf.sortlist[0].matches(&addr("198.51.100.1")));
+        assert_eq!(conf.servers.len(), 1);
+        assert_eq!(conf.ndots, 3);
+    }
+
+    #[test]
+    fn routes() {
+        let name = |s| DNameBuf::from_str(s).unwrap();
+        let mut route = RouteConf::new(name("corp.example."));
+        let data = "# VPN resolvers\n\
+                    nameserver 192.0.2.53\n\
+                    nameserver 2001:db8::53\n\
+                    port 5353\n\
+                    timeout 3\n\
+                    search_order 1\n\
+                    options use-vc\n".to_string();
+        assert!(route.parse(&mut io::Cursor::new(data)).is_ok());
+        assert_eq!(route.servers.iter().map(|server| server.addr)
+                        .collect::<Vec<_>>(),
+                   vec![SocketAddr::from_str("192.0.2.53:5353").unwrap(),
+                        SocketAddr::from_str("[2001:db8::53]:5353").unwrap()]);
+        assert!(route.options.use_vc);
+        let data = "nameserver 192.0.2.53:53\n".to_string();
+        assert!(RouteConf::new(name("corp.example."))
+                          .parse(&mut io::Cursor::new(data)).is_err());
+
+        let mut conf = ResolvConf::new();
+        conf.routes.push(route);
+        conf.routes.push(RouteConf::with_servers(
+            name("example."),
+            vec![ServerConf::new(SocketAddr::from_str("192.0.2.1:53")
+                                            .unwrap())]
+        ));
+        conf.finalize();
+        assert_eq!(conf.routes[0].servers[0].request_timeout,
+                   Duration::from_secs(3));
+        assert_eq!(conf.routes[1].servers[0].request_timeout, conf.timeout);
+        assert_eq!(conf.route(&name("www.corp.example.")), Some(0));
+        assert_eq!(conf.route(&name("corp.example.")), Some(0));
+        assert_eq!(conf.route(&name("www.example.")), Some(1));
+        assert_eq!(conf.route(&name("xcorp.example.")), Some(1));
+        assert_eq!(conf.route(&name("example.com.")), None);
+    }
+
+    #[test]
+    fn resolver_dir() {
+        use std::{env, fs, process};
+
+        let dir = env::temp_dir().join(format!("domain-resolver-{}",
+                                               process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("consul"), "nameserver 127.0.0.1\nport 8600\n")
+           .unwrap();
+        fs::write(dir.join("empty.example"), "").unwrap();
+        fs::write(dir.join(".hidden"), "nameserver 192.0.2.1\n").unwrap();
+        let mut conf = ResolvConf::new();
+        let res = conf.parse_resolver_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(res.is_ok());
+        assert_eq!(conf.routes.len(), 1);
+        assert_eq!(conf.routes[0].domain.to_string(), "consul.");
+        assert_eq!(conf.routes[0].servers[0].addr,
+                   SocketAddr::from_str("127.0.0.1:8600").unwrap());
+    }
 }
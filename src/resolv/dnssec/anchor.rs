@@ -0,0 +1,141 @@
+//! Trust anchors.
+//!
+//! A trust anchor is a set of Ds records for a zone that are trusted
+//! without any further validation. Validation of an answer starts at the
+//! trust anchor closest to it. Normally, the only trust anchor is the one
+//! for the root zone which is available through [`TrustAnchors::builtin()`].
+//!
+//! [`TrustAnchors::builtin()`]: struct.TrustAnchors.html#method.builtin
+
+use std::slice;
+use ::bits::{DNameBuf, DNameSlice};
+use ::iana::{DigestAlg, SecAlg};
+use ::rdata::owned::Ds;
+
+
+//------------ TrustAnchors --------------------------------------------------
+
+/// The set of trust anchors used by a validating resolver.
+#[derive(Clone, Debug, Default)]
+pub struct TrustAnchors {
+    /// The anchors in the order they were added.
+    anchors: Vec<TrustAnchor>,
+}
+
+impl TrustAnchors {
+    /// Creates a new, empty set of trust anchors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the built-in trust anchors.
+    ///
+    /// These are the Ds records for the root zone’s key signing keys as
+    /// published by IANA: KSK-2017 with key tag 20326 and KSK-2024 with key
+    /// tag 38696.
+    pub fn builtin() -> Self {
+        let mut res = Self::new();
+        for &(key_tag, digest) in ROOT_ANCHORS {
+            res.push(DNameSlice::root().to_owned(),
+                     Ds::new(key_tag, SecAlg::RsaSha256, DigestAlg::Sha256,
+                             hex_decode(digest)))
+        }
+        res
+    }
+
+    /// Adds a Ds record for the zone `owner`.
+    ///
+    /// If there already is an anchor for the zone, the record is added to
+    /// it. Otherwise, a new anchor is appended.
+    pub fn push(&mut self, owner: DNameBuf, ds: Ds) {
+        if let Some(anchor) = self.anchors.iter_mut()
+                                  .find(|anchor| anchor.owner == owner) {
+            if !anchor.ds.contains(&ds) {
+                anchor.ds.push(ds)
+            }
+            return
+        }
+        self.anchors.push(TrustAnchor { owner: owner, ds: vec![ds] })
+    }
+
+    /// Returns the anchor for the closest zone enclosing `name`, if any.
+    pub fn closest(&self, name: &DNameSlice) -> Option<&TrustAnchor> {
+        self.anchors.iter().filter(|anchor| name.ends_with(&anchor.owner))
+                    .max_by_key(|anchor| anchor.owner.len())
+    }
+
+    /// Returns an iterator over the anchors.
+    pub fn iter(&self) -> slice::Iter<TrustAnchor> {
+        self.anchors.iter()
+    }
+
+    /// Returns whether there are no anchors.
+    pub fn is_empty(&self) -> bool {
+        self.anchors.is_empty()
+    }
+}
+
+
+//------------ TrustAnchor ---------------------------------------------------
+
+/// The trust anchor for a zone.
+#[derive(Clone, Debug)]
+pub struct TrustAnchor {
+    /// The apex of the zone.
+    pub owner: DNameBuf,
+
+    /// The Ds records identifying the zone’s trusted keys.
+    pub ds: Vec<Ds>,
+}
+
+
+//------------ ROOT_ANCHORS --------------------------------------------------
+
+/// The key tags and SHA-256 digests of the root zone’s key signing keys.
+const ROOT_ANCHORS: &[(u16, &str)] = &[
+    (20326,
+     "E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D"),
+    (38696,
+     "683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16"),
+];
+
+/// Decodes one of the digests above.
+fn hex_decode(s: &str) -> Vec<u8> {
+    (0..s.len()).step_by(2)
+                .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+                .collect()
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    #[test]
+    fn builtin() {
+        let anchors = TrustAnchors::builtin();
+        assert_eq!(anchors.iter().count(), 1);
+        let name = DNameBuf::from_str("www.example.com.").unwrap();
+        let anchor = anchors.closest(&name).unwrap();
+        assert_eq!(anchor.owner, DNameBuf::root());
+        assert_eq!(anchor.ds.len(), 2);
+        assert_eq!(anchor.ds[0].key_tag(), 20326);
+        assert_eq!(anchor.ds[0].digest().len(), 32);
+    }
+
+    #[test]
+    fn closest() {
+        let mut anchors = TrustAnchors::builtin();
+        let example = DNameBuf::from_str("example.com.").unwrap();
+        anchors.push(example.clone(),
+                     Ds::new(1, SecAlg::Ed25519, DigestAlg::Sha256,
+                             vec![0; 32]));
+        let name = DNameBuf::from_str("www.example.com.").unwrap();
+        assert_eq!(anchors.closest(&name).unwrap().owner, example);
+        let name = DNameBuf::from_str("example.org.").unwrap();
+        assert_eq!(anchors.closest(&name).unwrap().owner, DNameBuf::root());
+    }
+}
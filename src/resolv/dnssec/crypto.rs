@@ -0,0 +1,189 @@
+//! The cryptographic primitives needed for validation.
+//!
+//! All actual cryptography is left to the *ring* crate. This module only
+//! translates between the DNSSEC formats of keys, signatures, and digests
+//! and those expected by *ring*.
+
+use ring::digest;
+use ring::signature;
+use ::iana::{DigestAlg, SecAlg};
+
+
+//------------ Signatures ----------------------------------------------------
+
+/// Returns whether we can verify signatures made with `algorithm`.
+///
+/// These are the algorithms RFC 8624 says a validator must or should
+/// support except for Ed448 which *ring* doesn’t provide.
+pub fn is_supported(algorithm: SecAlg) -> bool {
+    match algorithm {
+        SecAlg::RsaSha1 | SecAlg::RsaSha1Nsec3Sha1 | SecAlg::RsaSha256
+        | SecAlg::RsaSha512 | SecAlg::EcdsaP256Sha256
+        | SecAlg::EcdsaP384Sha384 | SecAlg::Ed25519 => true,
+        _ => false
+    }
+}
+
+/// Verifies a signature.
+///
+/// The `public_key` is in the format of the public key field of a Dnskey
+/// record for `algorithm` and `signature` in the format of the signature
+/// field of a Rrsig record. Returns `false` if the signature is wrong, the
+/// key is malformed, or the algorithm isn’t supported.
+pub fn verify(algorithm: SecAlg, public_key: &[u8], message: &[u8],
+              signature: &[u8]) -> bool {
+    match algorithm {
+        SecAlg::RsaSha1 | SecAlg::RsaSha1Nsec3Sha1 => {
+            verify_rsa(
+                &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+                public_key, message, signature
+            )
+        }
+        SecAlg::RsaSha256 => {
+            verify_rsa(
+                &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                public_key, message, signature
+            )
+        }
+        SecAlg::RsaSha512 => {
+            verify_rsa(
+                &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+                public_key, message, signature
+            )
+        }
+        SecAlg::EcdsaP256Sha256 => {
+            verify_ecdsa(&signature::ECDSA_P256_SHA256_FIXED, 64,
+                         public_key, message, signature)
+        }
+        SecAlg::EcdsaP384Sha384 => {
+            verify_ecdsa(&signature::ECDSA_P384_SHA384_FIXED, 96,
+                         public_key, message, signature)
+        }
+        SecAlg::Ed25519 => {
+            signature::UnparsedPublicKey::new(&signature::ED25519,
+                                              public_key)
+                .verify(message, signature).is_ok()
+        }
+        _ => false
+    }
+}
+
+/// Verifies an RSA signature.
+///
+/// RSA public keys are encoded as described in RFC 3110: the length of
+/// the exponent in one octet or, if that is zero, in the next two octets,
+/// followed by the exponent and the modulus.
+fn verify_rsa(params: &signature::RsaParameters, public_key: &[u8],
+              message: &[u8], signature: &[u8]) -> bool {
+    let (exp_len, rest) = match public_key.split_first() {
+        Some((&0, rest)) if rest.len() >= 2 => {
+            ((rest[0] as usize) << 8 | rest[1] as usize, &rest[2..])
+        }
+        Some((&len, rest)) if len != 0 => (len as usize, rest),
+        _ => return false
+    };
+    if rest.len() <= exp_len {
+        return false
+    }
+    let (e, n) = rest.split_at(exp_len);
+    signature::RsaPublicKeyComponents {
+        n: strip_zeros(n),
+        e: strip_zeros(e),
+    }.verify(params, message, signature).is_ok()
+}
+
+/// Verifies an ECDSA signature.
+///
+/// The public key in DNSSEC is the concatenation of the two coordinates
+/// of the curve point, each `len / 2` octets long. *ring* expects an
+/// uncompressed point which has an additional octet 4 in front.
+fn verify_ecdsa(alg: &'static signature::EcdsaVerificationAlgorithm,
+                len: usize, public_key: &[u8], message: &[u8],
+                signature: &[u8]) -> bool {
+    if public_key.len() != len {
+        return false
+    }
+    let mut key = Vec::with_capacity(len + 1);
+    key.push(4);
+    key.extend_from_slice(public_key);
+    signature::UnparsedPublicKey::new(alg, key)
+        .verify(message, signature).is_ok()
+}
+
+/// Returns a slice without any leading zero octets.
+fn strip_zeros(slice: &[u8]) -> &[u8] {
+    let start = slice.iter().position(|&x| x != 0).unwrap_or(slice.len());
+    &slice[start..]
+}
+
+
+//------------ Digests -------------------------------------------------------
+
+/// Returns whether we can calculate digests of type `digest_type`.
+pub fn is_digest_supported(digest_type: DigestAlg) -> bool {
+    match digest_type {
+        DigestAlg::Sha1 | DigestAlg::Sha256 | DigestAlg::Sha384 => true,
+        _ => false
+    }
+}
+
+/// Calculates a digest for comparing with the digest field of a Ds record.
+///
+/// The digest is calculated over the concatenation of `owner`, which must
+/// be the owner name of the Dnskey record in canonical wire format, and
+/// `dnskey`, the record data of the Dnskey record. Returns `None` if the
+/// digest type isn’t supported.
+pub fn ds_digest(digest_type: DigestAlg, owner: &[u8], dnskey: &[u8])
+                 -> Option<Vec<u8>> {
+    let alg = match digest_type {
+        DigestAlg::Sha1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        DigestAlg::Sha256 => &digest::SHA256,
+        DigestAlg::Sha384 => &digest::SHA384,
+        _ => return None
+    };
+    let mut ctx = digest::Context::new(alg);
+    ctx.update(owner);
+    ctx.update(dnskey);
+    Some(ctx.finish().as_ref().to_vec())
+}
+
+/// Calculates the Nsec3 hash of a name.
+///
+/// The `name` must be in canonical wire format. The only hash algorithm
+/// defined for Nsec3 is SHA-1, so this is what we use.
+pub fn nsec3_hash(name: &[u8], salt: &[u8], iterations: u16) -> Vec<u8> {
+    let alg = &digest::SHA1_FOR_LEGACY_USE_ONLY;
+    let mut ctx = digest::Context::new(alg);
+    ctx.update(name);
+    ctx.update(salt);
+    let mut res = ctx.finish();
+    for _ in 0..iterations {
+        let mut ctx = digest::Context::new(alg);
+        ctx.update(res.as_ref());
+        ctx.update(salt);
+        res = ctx.finish();
+    }
+    res.as_ref().to_vec()
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use ::utils::base32;
+    use super::*;
+
+    #[test]
+    fn nsec3_hash_example() {
+        // The hashes from RFC 5155, appendix A: salt aabbccdd, 12
+        // iterations.
+        let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+        assert_eq!(base32::encode_hex(&nsec3_hash(b"\x07example\x00",
+                                                  &salt, 12)),
+                   "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom");
+        assert_eq!(base32::encode_hex(&nsec3_hash(b"\x01a\x07example\x00",
+                                                  &salt, 12)),
+                   "35mthgpgcu1qg68fab165klnsnk3dpvl");
+    }
+}
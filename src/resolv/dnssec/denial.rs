@@ -0,0 +1,556 @@
+//! Authenticated denial of existence.
+//!
+//! Negative answers are proven through Nsec records as defined in RFC 4035
+//! or Nsec3 records as defined in RFC 5155. Both chain the names of a zone
+//! in order, the former directly, the latter through their hashes, and
+//! state which record types exist for each name. A name that falls between
+//! two links of the chain doesn’t exist.
+//!
+//! The [`Proofs`] type collects the validated records of either kind from
+//! a response and answers the questions a validator has about names.
+//!
+//! [`Proofs`]: struct.Proofs.html
+
+use ::bits::{DNameBuf, DNameSlice};
+use ::iana::{Nsec3HashAlg, Rtype};
+use ::rdata::parsed;
+use ::utils::base32;
+use super::Security;
+use super::crypto;
+use super::rrset::{Rrset, canonical_name};
+
+
+/// The maximum number of Nsec3 iterations we are willing to calculate.
+///
+/// As suggested by RFC 9276, responses using more iterations are treated
+/// as insecure.
+const MAX_ITERATIONS: u16 = 150;
+
+
+//------------ Proofs --------------------------------------------------------
+
+/// The validated Nsec and Nsec3 records of a response.
+#[derive(Clone, Debug, Default)]
+pub struct Proofs {
+    /// The Nsec records.
+    nsecs: Vec<NsecLink>,
+
+    /// The Nsec3 records.
+    nsec3s: Vec<Nsec3Link>,
+}
+
+impl Proofs {
+    /// Creates a new, empty set of proofs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the records of a validated record set.
+    ///
+    /// Sets of other types than Nsec and Nsec3 are ignored.
+    pub fn add(&mut self, rrset: &Rrset) {
+        match rrset.rtype() {
+            Rtype::Nsec => {
+                for nsec in rrset.records::<parsed::Nsec>() {
+                    self.nsecs.push(NsecLink {
+                        owner: rrset.owner().to_owned(),
+                        next: canonical_name(&nsec.next_name().unpack()),
+                        types: nsec.types().as_bytes().to_vec(),
+                    })
+                }
+            }
+            Rtype::Nsec3 => {
+                let (hash, zone) = match rrset.owner().split_first() {
+                    Some((label, zone)) => {
+                        (label.as_bytes()[1..].to_ascii_lowercase(),
+                         zone.to_owned())
+                    }
+                    None => return
+                };
+                for nsec3 in rrset.records::<parsed::Nsec3>() {
+                    self.nsec3s.push(Nsec3Link {
+                        hash: hash.clone(),
+                        zone: zone.clone(),
+                        next: base32::encode_hex(nsec3.next_owner())
+                                     .into_bytes(),
+                        algorithm: nsec3.hash_algorithm(),
+                        iterations: nsec3.iterations(),
+                        salt: nsec3.salt().to_vec(),
+                        opt_out: nsec3.opt_out(),
+                        types: nsec3.types().as_bytes().to_vec(),
+                    })
+                }
+            }
+            _ => { }
+        }
+    }
+
+    /// Checks the proof that `qname` exists but has no `qtype` records.
+    ///
+    /// This includes the case where the name only exists through a
+    /// wildcard which doesn’t have records of the type either.
+    pub fn nodata(&self, qname: &DNameSlice, qtype: Rtype) -> Security {
+        if let Some(types) = self.nsec_match(qname) {
+            return check_types(types, qtype)
+        }
+        if self.nsec_empty_non_terminal(qname) {
+            return Security::Secure
+        }
+        if let Some(ce) = self.nsec_closest_encloser(qname) {
+            if let Some(types) = self.nsec_match(&wildcard(ce)) {
+                return check_types(types, qtype)
+            }
+        }
+        if self.nsec3s.is_empty() {
+            return Security::Bogus
+        }
+        if self.nsec3_unsupported() {
+            return Security::Insecure
+        }
+        if let Some(link) = self.nsec3_match(qname) {
+            return check_types(&link.types, qtype)
+        }
+        if let Some((ce, cover)) = self.nsec3_closest_encloser(qname) {
+            if let Some(link) = self.nsec3_match(&wildcard(ce)) {
+                return check_types(&link.types, qtype)
+            }
+            if qtype == Rtype::Ds && cover.opt_out {
+                return Security::Insecure
+            }
+        }
+        Security::Bogus
+    }
+
+    /// Checks the proof that `qname` doesn’t exist.
+    ///
+    /// This requires proof that neither the name itself nor a wildcard
+    /// that could have been expanded into it exist.
+    pub fn nxdomain(&self, qname: &DNameSlice) -> Security {
+        if self.nsec_cover(qname).is_some() {
+            if self.nsec_empty_non_terminal(qname) {
+                return Security::Bogus
+            }
+            return match self.nsec_closest_encloser(qname) {
+                Some(ce) if self.nsec_cover(&wildcard(ce)).is_some() => {
+                    Security::Secure
+                }
+                _ => Security::Bogus
+            }
+        }
+        if self.nsec3s.is_empty() {
+            return Security::Bogus
+        }
+        if self.nsec3_unsupported() {
+            return Security::Insecure
+        }
+        match self.nsec3_closest_encloser(qname) {
+            Some((ce, cover)) => {
+                if self.nsec3_cover(&wildcard(ce)).is_none() {
+                    Security::Bogus
+                }
+                else if cover.opt_out {
+                    Security::Insecure
+                }
+                else {
+                    Security::Secure
+                }
+            }
+            None => Security::Bogus
+        }
+    }
+
+    /// Checks the proof that an answer could be expanded from a wildcard.
+    ///
+    /// The answer’s owner is `qname` and its signature had `labels`
+    /// labels. In this case, there must be proof that the name one label
+    /// below the wildcard’s parent doesn’t exist.
+    pub fn wildcard(&self, qname: &DNameSlice, labels: u8) -> Security {
+        let mut next_closer = qname;
+        while next_closer.len() > labels as usize + 2 {
+            next_closer = match next_closer.parent() {
+                Some(parent) => parent,
+                None => return Security::Bogus
+            }
+        }
+        if self.nsec_cover(next_closer).is_some() {
+            return Security::Secure
+        }
+        if self.nsec3s.is_empty() {
+            return Security::Bogus
+        }
+        if self.nsec3_unsupported() {
+            return Security::Insecure
+        }
+        if self.nsec3_cover(next_closer).is_some() {
+            Security::Secure
+        }
+        else {
+            Security::Bogus
+        }
+    }
+
+    /// Determines what a negative answer for a Ds query says about a name.
+    pub fn delegation(&self, qname: &DNameSlice) -> Delegation {
+        if let Some(types) = self.nsec_match(qname) {
+            return Delegation::from_types(types)
+        }
+        if self.nsec_empty_non_terminal(qname) {
+            return Delegation::NoCut
+        }
+        if self.nsec3s.is_empty() {
+            return Delegation::Bogus
+        }
+        if self.nsec3_unsupported() {
+            return Delegation::Insecure
+        }
+        if let Some(link) = self.nsec3_match(qname) {
+            return Delegation::from_types(&link.types)
+        }
+        match self.nsec3_closest_encloser(qname) {
+            Some((_, cover)) if cover.opt_out => Delegation::Insecure,
+            _ => Delegation::Bogus
+        }
+    }
+}
+
+/// # Nsec
+///
+impl Proofs {
+    /// Returns the types of the Nsec record owned by `name`, if any.
+    fn nsec_match(&self, name: &DNameSlice) -> Option<&[u8]> {
+        self.nsecs.iter().find(|link| *link.owner == *name)
+                  .map(|link| link.types.as_ref())
+    }
+
+    /// Returns the Nsec record covering `name`, if any.
+    fn nsec_cover(&self, name: &DNameSlice) -> Option<&NsecLink> {
+        self.nsecs.iter().find(|link| {
+            covers(link.owner.as_slice(), link.next.as_slice(), name)
+        })
+    }
+
+    /// Returns whether `name` is proven to be an empty non-terminal.
+    ///
+    /// This is the case if the next name of the Nsec record covering it
+    /// is below it.
+    fn nsec_empty_non_terminal(&self, name: &DNameSlice) -> bool {
+        match self.nsec_cover(name) {
+            Some(link) => link.next.ends_with(&name) && *link.next != *name,
+            None => false
+        }
+    }
+
+    /// Returns the closest encloser of `name` according to Nsec records.
+    ///
+    /// This is the longest ancestor of `name` that is also an ancestor of
+    /// either end of the Nsec record covering `name`.
+    fn nsec_closest_encloser<'a>(&self, name: &'a DNameSlice)
+                                 -> Option<&'a DNameSlice> {
+        let link = self.nsec_cover(name)?;
+        let mut ce = name.parent()?;
+        loop {
+            if link.owner.ends_with(&ce) || link.next.ends_with(&ce) {
+                return Some(ce)
+            }
+            ce = ce.parent()?;
+        }
+    }
+}
+
+/// # Nsec3
+///
+impl Proofs {
+    /// Returns whether any Nsec3 record uses parameters we don’t support.
+    fn nsec3_unsupported(&self) -> bool {
+        self.nsec3s.iter().any(|link| {
+            link.algorithm != Nsec3HashAlg::Sha1
+                || link.iterations > MAX_ITERATIONS
+        })
+    }
+
+    /// Returns the encoded hash of `name` in the zone of an Nsec3 record.
+    fn nsec3_hash(&self, name: &DNameSlice) -> Option<Vec<u8>> {
+        let link = self.nsec3s.iter().find(|link| name.ends_with(&link.zone))?;
+        let hash = crypto::nsec3_hash(canonical_name(name).as_bytes(),
+                                      &link.salt, link.iterations);
+        Some(base32::encode_hex(&hash).into_bytes())
+    }
+
+    /// Returns the Nsec3 record matching `name`, if any.
+    fn nsec3_match(&self, name: &DNameSlice) -> Option<&Nsec3Link> {
+        let hash = self.nsec3_hash(name)?;
+        self.nsec3s.iter().find(|link| {
+            link.hash == hash && name.ends_with(&link.zone)
+        })
+    }
+
+    /// Returns the Nsec3 record covering `name`, if any.
+    fn nsec3_cover(&self, name: &DNameSlice) -> Option<&Nsec3Link> {
+        let hash = self.nsec3_hash(name)?;
+        self.nsec3s.iter().find(|link| {
+            name.ends_with(&link.zone)
+                && covers(link.hash.as_slice(), link.next.as_slice(),
+                          hash.as_slice())
+        })
+    }
+
+    /// Returns the closest encloser proof for `name`.
+    ///
+    /// This is the closest encloser, the longest ancestor of `name` with a
+    /// matching Nsec3 record, together with the Nsec3 record covering the
+    /// next closer name, the ancestor one label longer.
+    fn nsec3_closest_encloser<'a>(&self, name: &'a DNameSlice)
+                                  -> Option<(&'a DNameSlice, &Nsec3Link)> {
+        let mut next_closer = name;
+        let mut ce = name.parent()?;
+        loop {
+            if self.nsec3_match(ce).is_some() {
+                return self.nsec3_cover(next_closer).map(|link| (ce, link))
+            }
+            next_closer = ce;
+            ce = ce.parent()?;
+        }
+    }
+}
+
+
+//------------ Delegation ----------------------------------------------------
+
+/// What a negative answer to a Ds query says about the query name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Delegation {
+    /// The name is a zone cut without Ds records or may be one.
+    ///
+    /// The latter happens with Nsec3 opt-out.
+    Insecure,
+
+    /// The name is not a zone cut.
+    NoCut,
+
+    /// The answer doesn’t prove anything.
+    Bogus,
+}
+
+impl Delegation {
+    /// Determines the status from the types present at the name.
+    fn from_types(types: &[u8]) -> Self {
+        if bitmap_contains(types, Rtype::Ds) {
+            Delegation::Bogus
+        }
+        else if bitmap_contains(types, Rtype::Ns)
+                && !bitmap_contains(types, Rtype::Soa) {
+            Delegation::Insecure
+        }
+        else {
+            Delegation::NoCut
+        }
+    }
+}
+
+
+//------------ NsecLink and Nsec3Link ----------------------------------------
+
+/// An Nsec record.
+#[derive(Clone, Debug)]
+struct NsecLink {
+    /// The owner of the record.
+    owner: DNameBuf,
+
+    /// The next name in the zone.
+    next: DNameBuf,
+
+    /// The type bitmap in wire format.
+    types: Vec<u8>,
+}
+
+/// An Nsec3 record.
+///
+/// The hashes are kept in their lowercase Base 32 encoding. Since that
+/// encoding preserves the order of the hashes, they can be compared as is.
+#[derive(Clone, Debug)]
+struct Nsec3Link {
+    /// The hash of the owner.
+    hash: Vec<u8>,
+
+    /// The zone the record belongs to.
+    zone: DNameBuf,
+
+    /// The hash of the next owner.
+    next: Vec<u8>,
+
+    algorithm: Nsec3HashAlg,
+    iterations: u16,
+    salt: Vec<u8>,
+    opt_out: bool,
+
+    /// The type bitmap in wire format.
+    types: Vec<u8>,
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns whether `name` lies strictly between `owner` and `next`.
+///
+/// If `next` isn’t greater than `owner`, this is the last link of the
+/// chain which covers everything after `owner` and before `next`.
+fn covers<T: Ord + ?Sized>(owner: &T, next: &T, name: &T) -> bool {
+    if owner < next {
+        owner < name && name < next
+    }
+    else {
+        owner < name || name < next
+    }
+}
+
+/// Checks that a type bitmap contains neither `qtype` nor Cname.
+fn check_types(types: &[u8], qtype: Rtype) -> Security {
+    if bitmap_contains(types, qtype) || bitmap_contains(types, Rtype::Cname) {
+        Security::Bogus
+    }
+    else {
+        Security::Secure
+    }
+}
+
+/// Returns whether a type bitmap in wire format contains `rtype`.
+fn bitmap_contains(types: &[u8], rtype: Rtype) -> bool {
+    ::rdata::rfc4034::RtypeBitmap::from_bytes(types)
+        .map(|bitmap| bitmap.contains(rtype)).unwrap_or(false)
+}
+
+/// Returns the wildcard name with the given parent.
+fn wildcard(parent: &DNameSlice) -> DNameBuf {
+    let mut res = DNameBuf::new();
+    let _ = res.push_normal(b"*");
+    let _ = res.append(&parent);
+    res
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use ::rdata::rfc4034::RtypeBitmap;
+    use super::*;
+
+    fn name(s: &str) -> DNameBuf {
+        DNameBuf::from_str(s).unwrap()
+    }
+
+    fn types(types: &[Rtype]) -> Vec<u8> {
+        RtypeBitmap::from_types(types.iter().cloned()).as_bytes().to_vec()
+    }
+
+    fn nsec(owner: &str, next: &str, rtypes: &[Rtype]) -> NsecLink {
+        NsecLink { owner: name(owner), next: name(next),
+                   types: types(rtypes) }
+    }
+
+    /// Builds an Nsec3 chain for the zone `example.` from the given names.
+    fn nsec3_chain(names: &[(&str, &[Rtype])], iterations: u16,
+                   opt_out: bool) -> Proofs {
+        let salt = vec![0xaa, 0xbb];
+        let mut hashes: Vec<_> = names.iter().map(|&(owner, rtypes)| {
+            let hash = crypto::nsec3_hash(name(owner).as_bytes(), &salt,
+                                          iterations);
+            (base32::encode_hex(&hash).into_bytes(), types(rtypes))
+        }).collect();
+        hashes.sort();
+        let mut res = Proofs::new();
+        for i in 0..hashes.len() {
+            res.nsec3s.push(Nsec3Link {
+                hash: hashes[i].0.clone(),
+                zone: name("example."),
+                next: hashes[(i + 1) % hashes.len()].0.clone(),
+                algorithm: Nsec3HashAlg::Sha1,
+                iterations: iterations,
+                salt: salt.clone(),
+                opt_out: opt_out,
+                types: hashes[i].1.clone(),
+            })
+        }
+        res
+    }
+
+    #[test]
+    fn nsec_nodata() {
+        let mut proofs = Proofs::new();
+        proofs.nsecs.push(nsec("www.example.com.", "zzz.example.com.",
+                               &[Rtype::A, Rtype::Rrsig, Rtype::Nsec]));
+        let www = name("WWW.example.com.");
+        assert_eq!(proofs.nodata(&www, Rtype::Aaaa), Security::Secure);
+        assert_eq!(proofs.nodata(&www, Rtype::A), Security::Bogus);
+        assert_eq!(proofs.nodata(&name("ftp.example.com."), Rtype::A),
+                   Security::Bogus);
+    }
+
+    #[test]
+    fn nsec_nxdomain() {
+        let mut proofs = Proofs::new();
+        proofs.nsecs.push(nsec("a.example.com.", "c.example.com.",
+                               &[Rtype::A]));
+        let b = name("b.example.com.");
+        assert_eq!(proofs.nxdomain(&b), Security::Bogus);
+        proofs.nsecs.push(nsec("example.com.", "a.example.com.",
+                               &[Rtype::Soa, Rtype::Ns]));
+        assert_eq!(proofs.nxdomain(&b), Security::Secure);
+        assert_eq!(proofs.nxdomain(&name("a.example.com.")),
+                   Security::Bogus);
+        assert_eq!(proofs.wildcard(&b, 2), Security::Secure);
+    }
+
+    #[test]
+    fn nsec_empty_non_terminal() {
+        let mut proofs = Proofs::new();
+        proofs.nsecs.push(nsec("a.example.com.", "x.b.example.com.",
+                               &[Rtype::A]));
+        let b = name("b.example.com.");
+        assert_eq!(proofs.nodata(&b, Rtype::A), Security::Secure);
+        assert_eq!(proofs.nxdomain(&b), Security::Bogus);
+        assert_eq!(proofs.delegation(&b), Delegation::NoCut);
+    }
+
+    #[test]
+    fn nsec_delegation() {
+        let mut proofs = Proofs::new();
+        proofs.nsecs.push(nsec("sub.example.com.", "zzz.example.com.",
+                               &[Rtype::Ns, Rtype::Rrsig, Rtype::Nsec]));
+        proofs.nsecs.push(nsec("signed.example.com.", "sub.example.com.",
+                               &[Rtype::Ns, Rtype::Ds]));
+        proofs.nsecs.push(nsec("example.com.", "signed.example.com.",
+                               &[Rtype::Ns, Rtype::Soa]));
+        assert_eq!(proofs.delegation(&name("sub.example.com.")),
+                   Delegation::Insecure);
+        assert_eq!(proofs.delegation(&name("signed.example.com.")),
+                   Delegation::Bogus);
+        assert_eq!(proofs.delegation(&name("example.com.")),
+                   Delegation::NoCut);
+    }
+
+    #[test]
+    fn nsec3() {
+        let names: &[(&str, &[Rtype])] = &[
+            ("example.", &[Rtype::Soa, Rtype::Ns]),
+            ("a.example.", &[Rtype::A]),
+        ];
+        let proofs = nsec3_chain(names, 5, false);
+        let a = name("a.example.");
+        let b = name("b.example.");
+        assert_eq!(proofs.nodata(&a, Rtype::Aaaa), Security::Secure);
+        assert_eq!(proofs.nodata(&a, Rtype::A), Security::Bogus);
+        assert_eq!(proofs.nxdomain(&b), Security::Secure);
+        assert_eq!(proofs.nxdomain(&a), Security::Bogus);
+        assert_eq!(proofs.wildcard(&b, 1), Security::Secure);
+        assert_eq!(proofs.delegation(&b), Delegation::Bogus);
+
+        let proofs = nsec3_chain(names, 5, true);
+        assert_eq!(proofs.nxdomain(&b), Security::Insecure);
+        assert_eq!(proofs.delegation(&b), Delegation::Insecure);
+
+        let proofs = nsec3_chain(names, 200, false);
+        assert_eq!(proofs.nxdomain(&b), Security::Insecure);
+    }
+}
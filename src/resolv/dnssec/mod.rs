@@ -0,0 +1,139 @@
+//! DNSSEC validation.
+//!
+//! This module is only available if the crate is built with the `dnssec`
+//! feature.
+//!
+//! Normally, the resolver trusts whatever its upstream servers tell it.
+//! The upstream server may have validated the answer itself and indicate
+//! so through the AD bit but this is only of value if the link to the
+//! server can be trusted, too. If the resolver configuration contains
+//! [`TrustAnchors`], the resolver instead validates all answers itself.
+//!
+//! For this purpose, requests are sent with the DO bit set so servers
+//! include the Rrsig records with their answers and with the CD bit set so
+//! they don’t withhold answers they consider bogus. For each answer, the
+//! resolver then establishes a chain of trust from a trust anchor down to
+//! the zone that signed the answer by asking for the Ds and Dnskey records
+//! of all zones in between. Once it has the keys of the zone, it checks
+//! the signatures of the answer. Negative answers have to be proven
+//! through Nsec or Nsec3 records.
+//!
+//! The outcome of validation is expressed through the [`Security`] type.
+//! A [`Query`] started on a validating resolver sets the AD bit of the
+//! response if and only if the answer was found to be secure. If you
+//! need the exact outcome, use [`Resolver::validate()`] instead which
+//! resolves into a pair of the response and its security status. The
+//! [`lookup_secure()`] lookup finally fails if the answer is bogus.
+//!
+//! [`Query`]: ../struct.Query.html
+//! [`Resolver::validate()`]: ../struct.Resolver.html#method.validate
+//! [`Security`]: enum.Security.html
+//! [`TrustAnchors`]: anchor/struct.TrustAnchors.html
+//! [`lookup_secure()`]: ../lookup/secure/fn.lookup_secure.html
+
+use std::fmt;
+
+
+//------------ Re-exports ----------------------------------------------------
+
+pub use self::anchor::{TrustAnchor, TrustAnchors};
+pub use self::validate::Validate;
+
+
+//------------ Modules -------------------------------------------------------
+
+pub mod anchor;
+pub mod crypto;
+
+mod denial;
+mod rrset;
+mod validate;
+
+
+//------------ Security ------------------------------------------------------
+
+/// The security status of an answer.
+///
+/// The four values are those defined in RFC 4035, section 4.3.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Security {
+    /// There is a chain of trust from a trust anchor to all of the answer.
+    Secure,
+
+    /// Part of the answer is provably not signed.
+    ///
+    /// This happens if some zone along the chain of trust is known to be
+    /// unsigned or uses only algorithms we don’t support.
+    Insecure,
+
+    /// Part of the answer should be signed but we couldn’t verify it.
+    ///
+    /// Such an answer may well be the product of an attack.
+    Bogus,
+
+    /// There is no trust anchor for some part of the answer.
+    Indeterminate,
+}
+
+impl Security {
+    /// Combines the status of two parts of an answer.
+    ///
+    /// An answer is only as secure as its least secure part. Bogus parts
+    /// are considered worse than indeterminate ones which in turn are
+    /// worse than insecure parts.
+    pub fn and(self, other: Self) -> Self {
+        if self.rank() >= other.rank() { self }
+        else { other }
+    }
+
+    /// Returns whether the status is `Security::Secure`.
+    pub fn is_secure(self) -> bool {
+        self == Security::Secure
+    }
+
+    /// Returns whether the status is `Security::Bogus`.
+    pub fn is_bogus(self) -> bool {
+        self == Security::Bogus
+    }
+
+    /// Returns how bad the status is for combining statuses.
+    fn rank(self) -> u8 {
+        match self {
+            Security::Secure => 0,
+            Security::Insecure => 1,
+            Security::Indeterminate => 2,
+            Security::Bogus => 3,
+        }
+    }
+}
+
+
+//--- Display
+
+impl fmt::Display for Security {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Security::Secure => "secure",
+            Security::Insecure => "insecure",
+            Security::Bogus => "bogus",
+            Security::Indeterminate => "indeterminate",
+        })
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn and() {
+        use super::Security::*;
+
+        assert_eq!(Secure.and(Secure), Secure);
+        assert_eq!(Secure.and(Insecure), Insecure);
+        assert_eq!(Indeterminate.and(Insecure), Indeterminate);
+        assert_eq!(Insecure.and(Bogus), Bogus);
+        assert_eq!(Bogus.and(Indeterminate), Bogus);
+    }
+}
@@ -0,0 +1,437 @@
+//! Record sets in canonical form and their signatures.
+//!
+//! Signatures are calculated over record sets in the canonical form
+//! defined in RFC 4034, section 6: names are lowercased and never
+//! compressed and the records are sorted by their record data. This
+//! module collects the records of a message section into such sets and
+//! checks their signatures.
+
+use ::bits::{ComposeMode, Composer, DNameBuf, DNameSlice, ParsedRecordData,
+             Parser, ParseError, ParseResult, RecordData};
+use ::bits::message::RecordSection;
+use ::iana::{Class, Rtype};
+use ::rdata::owned::{Dnskey, Rrsig};
+use ::rdata::parsed;
+use super::crypto;
+
+
+//------------ Rrset ---------------------------------------------------------
+
+/// A record set in canonical form together with its signatures.
+#[derive(Clone, Debug)]
+pub struct Rrset {
+    /// The lowercased owner name.
+    owner: DNameBuf,
+
+    /// The class of the records.
+    class: Class,
+
+    /// The record type of the records.
+    rtype: Rtype,
+
+    /// The canonical record data of all records, sorted.
+    rdata: Vec<Vec<u8>>,
+
+    /// The signatures covering the set.
+    ///
+    /// Their signer names have been lowercased, too.
+    sigs: Vec<Rrsig>,
+}
+
+impl Rrset {
+    /// Collects all record sets of a message section.
+    ///
+    /// Rrsig records are attached to the sets they cover and dropped if
+    /// there is no such set. Opt records are skipped.
+    pub fn collect(section: RecordSection) -> ParseResult<Vec<Self>> {
+        let mut res: Vec<Self> = Vec::new();
+        for record in section.clone() {
+            let record = record?;
+            let rtype = record.rtype();
+            if rtype == Rtype::Rrsig || rtype == Rtype::Opt {
+                continue
+            }
+            let owner = canonical_name(&record.name().unpack());
+            let rdata = canonical_rdata(rtype, record.data())?;
+            if let Some(set) = res.iter_mut().find(|set| {
+                set.owner == owner && set.class == record.class()
+                    && set.rtype == rtype
+            }) {
+                set.rdata.push(rdata);
+                continue
+            }
+            res.push(Rrset {
+                owner: owner,
+                class: record.class(),
+                rtype: rtype,
+                rdata: vec![rdata],
+                sigs: Vec::new()
+            })
+        }
+        for record in section.limit_to::<parsed::Rrsig>() {
+            let record = record?;
+            let owner = canonical_name(&record.name().unpack());
+            let sig = record.data();
+            if let Some(set) = res.iter_mut().find(|set| {
+                set.owner == owner && set.class == record.class()
+                    && set.rtype == sig.type_covered()
+            }) {
+                set.sigs.push(Rrsig::new(
+                    sig.type_covered(), sig.algorithm(), sig.labels(),
+                    sig.original_ttl(), sig.expiration(), sig.inception(),
+                    sig.key_tag(),
+                    canonical_name(&sig.signer_name().unpack()),
+                    sig.signature().into()
+                ))
+            }
+        }
+        for set in &mut res {
+            set.rdata.sort();
+            set.rdata.dedup();
+        }
+        Ok(res)
+    }
+
+    /// Returns the lowercased owner name of the set.
+    pub fn owner(&self) -> &DNameSlice {
+        &self.owner
+    }
+
+    /// Returns the record type of the set.
+    pub fn rtype(&self) -> Rtype {
+        self.rtype
+    }
+
+    /// Returns the canonical record data of the records in the set.
+    pub fn rdata(&self) -> &[Vec<u8>] {
+        &self.rdata
+    }
+
+    /// Returns whether the set came with any signatures.
+    pub fn is_signed(&self) -> bool {
+        !self.sigs.is_empty()
+    }
+
+    /// Returns the signer name of the set.
+    ///
+    /// This is the signer name of the first signature whose signer is
+    /// the owner or one of its ancestors. Returns `None` if there is no
+    /// such signature.
+    pub fn signer(&self) -> Option<&DNameSlice> {
+        self.sigs.iter().map(|sig| sig.signer_name().as_slice())
+                 .find(|signer| self.owner.ends_with(signer))
+    }
+
+    /// Parses the record data of all records of the set as `D`.
+    ///
+    /// Records that fail to parse are skipped.
+    pub fn records<'a, D: ParsedRecordData<'a>>(&'a self) -> Vec<D> {
+        self.rdata.iter().filter_map(|rdata| {
+            D::parse(self.rtype, &mut Parser::new(rdata)).ok()
+                                                         .and_then(|x| x)
+        }).collect()
+    }
+
+    /// Returns the number of labels of the owner as counted by Rrsig.
+    ///
+    /// This excludes the root label as well as a leading asterisk label.
+    pub fn label_count(&self) -> u8 {
+        let mut count = self.owner.len() - 1;
+        if self.owner.as_bytes().starts_with(b"\x01*") {
+            count -= 1
+        }
+        count as u8
+    }
+
+    /// Checks the signatures of the set against the keys of a zone.
+    ///
+    /// Only signatures made by the zone with apex `apex` that are valid at
+    /// time `now`, given in seconds since the Unix epoch, are considered.
+    /// If one of them can be verified with one of `keys`, returns the
+    /// labels field of that signature. If it is smaller than the result
+    /// of `label_count()`, the set was synthesized from a wildcard.
+    pub fn verify(&self, apex: &DNameSlice, keys: &[Dnskey], now: u32)
+                  -> Option<u8> {
+        if !self.owner.ends_with(&apex) {
+            return None
+        }
+        for sig in &self.sigs {
+            if *sig.signer_name().as_slice() != *apex
+                    || sig.labels() > self.label_count()
+                    || !is_current(sig, now)
+                    || !crypto::is_supported(sig.algorithm()) {
+                continue
+            }
+            let data = match self.signed_data(sig) {
+                Some(data) => data,
+                None => continue
+            };
+            for key in keys {
+                if key.algorithm() == sig.algorithm()
+                        && key.key_tag() == sig.key_tag()
+                        && key.protocol() == 3
+                        && key.is_zone_key() && !key.is_revoked()
+                        && crypto::verify(sig.algorithm(), key.public_key(),
+                                          &data, sig.signature()) {
+                    return Some(sig.labels())
+                }
+            }
+        }
+        None
+    }
+
+    /// Assembles the data a signature has been calculated over.
+    ///
+    /// This is described in RFC 4034, section 3.1.8.1. If the signature
+    /// has fewer labels than the owner name, the owner is replaced with
+    /// the wildcard name it was expanded from.
+    fn signed_data(&self, sig: &Rrsig) -> Option<Vec<u8>> {
+        let mut composer = Composer::new(ComposeMode::Unlimited, false);
+        sig.compose_signed_data(&mut composer).ok()?;
+        let mut res = composer.finish();
+        let mut owner_bytes = Vec::new();
+        if sig.labels() < self.label_count() {
+            let mut owner = self.owner.as_slice();
+            while owner.len() - 1 > sig.labels() as usize {
+                owner = owner.parent()?;
+            }
+            owner_bytes.extend_from_slice(b"\x01*");
+            owner_bytes.extend_from_slice(owner.as_bytes());
+        }
+        else {
+            owner_bytes.extend_from_slice(self.owner.as_bytes());
+        }
+        for rdata in &self.rdata {
+            if rdata.len() > 0xFFFF {
+                return None
+            }
+            res.extend_from_slice(&owner_bytes);
+            push_u16(&mut res, self.rtype.to_int());
+            push_u16(&mut res, self.class.to_int());
+            push_u16(&mut res, (sig.original_ttl() >> 16) as u16);
+            push_u16(&mut res, sig.original_ttl() as u16);
+            push_u16(&mut res, rdata.len() as u16);
+            res.extend_from_slice(rdata);
+        }
+        Some(res)
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the canonical form of a domain name.
+///
+/// All ASCII letters in normal labels are lowercased.
+pub fn canonical_name(name: &DNameSlice) -> DNameBuf {
+    let mut bytes = name.as_bytes().to_vec();
+    lowercase_name(&mut bytes, 0);
+    DNameBuf::from_vec(bytes).unwrap_or_else(|| name.to_owned())
+}
+
+/// Returns the canonical form of record data.
+///
+/// Names embedded in the record data are decompressed and, for the record
+/// types listed in RFC 4034, section 6.2, as amended by RFC 6840, section
+/// 5.1, lowercased.
+fn canonical_rdata<D: RecordData>(rtype: Rtype, data: &D)
+                                  -> ParseResult<Vec<u8>> {
+    let mut composer = Composer::new(ComposeMode::Unlimited, false);
+    if data.compose(&mut composer).is_err() {
+        return Err(ParseError::FormErr)
+    }
+    let mut res = composer.finish();
+    let (skip, count) = match rtype {
+        Rtype::Ns | Rtype::Md | Rtype::Mf | Rtype::Cname | Rtype::Mb
+        | Rtype::Mg | Rtype::Mr | Rtype::Ptr | Rtype::Dname => (0, 1),
+        Rtype::Soa | Rtype::Minfo | Rtype::Rp => (0, 2),
+        Rtype::Mx | Rtype::Afsdb | Rtype::Rt | Rtype::Kx => (2, 1),
+        Rtype::Px => (2, 2),
+        Rtype::Srv => (6, 1),
+        _ => return Ok(res)
+    };
+    let mut pos = skip;
+    for _ in 0..count {
+        pos = match lowercase_name(&mut res, pos) {
+            Some(pos) => pos,
+            None => break
+        }
+    }
+    Ok(res)
+}
+
+/// Lowercases the uncompressed name starting at `pos` in `data`.
+///
+/// Returns the position after the name or `None` if there is no valid name
+/// of normal labels only at `pos`. In this case, the name may be partly
+/// lowercased.
+fn lowercase_name(data: &mut [u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *data.get(pos)? as usize;
+        let end = pos + 1 + len;
+        if len > 63 || end > data.len() {
+            return None
+        }
+        data[pos + 1..end].make_ascii_lowercase();
+        pos = end;
+        if len == 0 {
+            return Some(pos)
+        }
+    }
+}
+
+/// Returns whether the validity period of a signature includes `now`.
+///
+/// As per RFC 4034, section 3.1.5, the times are compared using serial
+/// number arithmetic.
+fn is_current(sig: &Rrsig, now: u32) -> bool {
+    (now.wrapping_sub(sig.inception()) as i32) >= 0
+        && (sig.expiration().wrapping_sub(now) as i32) >= 0
+}
+
+/// Appends a 16 bit integer in network byte order.
+fn push_u16(target: &mut Vec<u8>, value: u16) {
+    target.push((value >> 8) as u8);
+    target.push(value as u8);
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+pub mod test {
+    use std::str::FromStr;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use ::bits::{ComposeMode, DNameBuf, DNameSlice, MessageBuf,
+                 MessageBuilder, RecordData};
+    use ::iana::{Class, DigestAlg, Rtype, SecAlg};
+    use ::rdata::A;
+    use ::rdata::owned::{Dnskey, Ds, Rrsig};
+    use super::*;
+
+    /// The time all test signatures are valid at.
+    pub const NOW: u32 = 1_700_000_000;
+
+    /// A signing key for tests.
+    pub struct TestKey {
+        pair: Ed25519KeyPair,
+        dnskey: Dnskey,
+    }
+
+    impl TestKey {
+        /// Creates a key pair from a seed.
+        pub fn new(seed: u8, flags: u16) -> Self {
+            let pair = Ed25519KeyPair::from_seed_unchecked(&[seed; 32])
+                                      .unwrap();
+            let dnskey = Dnskey::new(flags, 3, SecAlg::Ed25519,
+                                     pair.public_key().as_ref().to_vec());
+            TestKey { pair: pair, dnskey: dnskey }
+        }
+
+        pub fn dnskey(&self) -> &Dnskey {
+            &self.dnskey
+        }
+
+        /// Returns a SHA-256 Ds record for the key as a key of `zone`.
+        pub fn ds(&self, zone: &DNameSlice) -> Ds {
+            let rdata = canonical_rdata(Rtype::Dnskey, &self.dnskey).unwrap();
+            let digest = crypto::ds_digest(DigestAlg::Sha256,
+                                           canonical_name(zone).as_bytes(),
+                                           &rdata).unwrap();
+            Ds::new(self.dnskey.key_tag(), SecAlg::Ed25519, DigestAlg::Sha256,
+                    digest)
+        }
+
+        /// Signs the given records all sharing owner, class, and type.
+        ///
+        /// The records are passed as pairs of owner and record data. The
+        /// labels field of the signature is taken from `labels`.
+        pub fn sign<D: RecordData>(&self, zone: &DNameSlice,
+                                   records: &[(DNameBuf, D)], labels: u8)
+                                   -> Rrsig {
+            let mut rrset = Rrset {
+                owner: canonical_name(&records[0].0),
+                class: Class::In,
+                rtype: records[0].1.rtype(),
+                rdata: records.iter().map(|&(_, ref data)| {
+                    canonical_rdata(data.rtype(), data).unwrap()
+                }).collect(),
+                sigs: Vec::new(),
+            };
+            rrset.rdata.sort();
+            let unsigned = Rrsig::new(rrset.rtype, SecAlg::Ed25519, labels,
+                                      3600, NOW + 86400, NOW - 86400,
+                                      self.dnskey.key_tag(),
+                                      canonical_name(zone), Vec::new());
+            let data = rrset.signed_data(&unsigned).unwrap();
+            let signature = self.pair.sign(&data).as_ref().to_vec();
+            Rrsig::new(rrset.rtype, SecAlg::Ed25519, labels, 3600,
+                       NOW + 86400, NOW - 86400, self.dnskey.key_tag(),
+                       canonical_name(zone), signature)
+        }
+    }
+
+    fn name(s: &str) -> DNameBuf {
+        DNameBuf::from_str(s).unwrap()
+    }
+
+    fn message(records: &[(DNameBuf, A)], sig: &Rrsig) -> MessageBuf {
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.push((name("www.example.com."), Rtype::A)).unwrap();
+        let mut msg = msg.answer();
+        for &(ref owner, ref data) in records {
+            msg.push((owner, 3600, data.clone())).unwrap();
+        }
+        msg.push((&records[0].0, 3600, sig.clone())).unwrap();
+        MessageBuf::from_vec(msg.finish()).unwrap()
+    }
+
+    #[test]
+    fn canonical() {
+        let data = canonical_rdata(Rtype::Mx,
+                                   &::rdata::Mx::new(10, name("Mail.Ex.")))
+                                  .unwrap();
+        assert_eq!(data, b"\x00\x0a\x04mail\x02ex\x00");
+        assert_eq!(canonical_name(&name("WWW.Example.")),
+                   name("www.example."));
+    }
+
+    #[test]
+    fn verify() {
+        let zone = name("example.com.");
+        let key = TestKey::new(1, 0x0101);
+        let records = vec![
+            (name("WWW.example.com."), A::from_octets(192, 0, 2, 2)),
+            (name("www.example.com."), A::from_octets(192, 0, 2, 1)),
+        ];
+        let sig = key.sign(&zone, &records, 3);
+        let msg = message(&records, &sig);
+        let sets = Rrset::collect(msg.answer().unwrap()).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].rdata().len(), 2);
+        assert_eq!(sets[0].signer(), Some(zone.as_slice()));
+        let keys = vec![key.dnskey().clone()];
+        assert_eq!(sets[0].verify(&zone, &keys, NOW), Some(3));
+        assert_eq!(sets[0].verify(&zone, &keys, NOW + 2 * 86400), None);
+        let other = vec![TestKey::new(2, 0x0101).dnskey().clone()];
+        assert_eq!(sets[0].verify(&zone, &other, NOW), None);
+        assert_eq!(sets[0].verify(&name("com."), &keys, NOW), None);
+    }
+
+    #[test]
+    fn wildcard() {
+        let zone = name("example.com.");
+        let key = TestKey::new(1, 0x0101);
+        let sig = key.sign(&zone, &[(name("*.example.com."),
+                                     A::from_octets(192, 0, 2, 1))],
+                           2);
+        let records = vec![(name("www.example.com."),
+                            A::from_octets(192, 0, 2, 1))];
+        let msg = message(&records, &sig);
+        let sets = Rrset::collect(msg.answer().unwrap()).unwrap();
+        assert_eq!(sets[0].label_count(), 3);
+        assert_eq!(sets[0].verify(&zone, &[key.dnskey().clone()], NOW),
+                   Some(2));
+    }
+}
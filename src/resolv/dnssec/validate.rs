@@ -0,0 +1,1257 @@
+//! Validating queries.
+//!
+//! Validation happens in two steps. First, the zones responsible for all
+//! parts of the answer are determined and the chain of trust down to each
+//! of them is established by a [`ChainWalk`]. Second, the signatures of the
+//! answer and, for negative answers and answers synthesized from
+//! wildcards, the proofs of non-existence are checked.
+//!
+//! [`ChainWalk`]: struct.ChainWalk.html
+
+use std::mem;
+use std::time::{SystemTime, UNIX_EPOCH};
+use futures::{Async, Future, Poll};
+use ::bits::{DName, DNameBuf, DNameSlice, MessageBuf, ParsedRecordData,
+             Parser, Question};
+use ::iana::{Class, Rcode, Rtype};
+use ::rdata::owned::{Dnskey, Ds};
+use ::rdata::parsed;
+use super::super::Resolver;
+use super::super::conf::ServerConf;
+use super::super::error::{Attempt, Error};
+use super::super::public::RawQuery;
+use super::Security;
+use super::crypto;
+use super::denial::{Delegation, Proofs};
+use super::rrset::{Rrset, canonical_name};
+
+
+//------------ Validate ------------------------------------------------------
+
+/// A query that validates its answer.
+///
+/// This future resolves into the response to the query and the security
+/// status of the answer. Start it through [`Resolver::validate()`].
+///
+/// Only errors that prevent a response to the query itself from being
+/// received are returned as errors. Failure to retrieve the records
+/// necessary for validation is reflected in the security status.
+///
+/// [`Resolver::validate()`]: ../struct.Resolver.html#method.validate
+pub struct Validate {
+    /// The resolver to ask for answer and keys.
+    resolver: Resolver,
+
+    /// What we are currently doing.
+    state: State,
+
+    /// The server that sent the response to the question.
+    server: Option<ServerConf>,
+
+    /// The requests for the question that failed before the response.
+    attempts: Vec<Attempt>,
+}
+
+/// The state of a validating query.
+enum State {
+    /// Waiting for the response to the question.
+    Query(RawQuery, Question<DNameBuf>),
+
+    /// Checking the response.
+    Check(Check),
+
+    /// All done.
+    Done,
+}
+
+impl Validate {
+    /// Starts a new validating query.
+    pub fn new<N, Q>(resolver: Resolver, question: Q) -> Self
+               where N: DName, Q: Into<Question<N>> {
+        let question = question.into();
+        let question = Question::new(question.qname().to_cow().into_owned(),
+                                     question.qtype(), question.qclass());
+        let query = RawQuery::new(resolver.clone(), question.clone());
+        Validate {
+            resolver: resolver,
+            state: State::Query(query, question),
+            server: None,
+            attempts: Vec::new(),
+        }
+    }
+
+    /// Returns the configuration of the server that sent the response.
+    ///
+    /// This is `None` before the response to the question has arrived or
+    /// if it didn’t come from one of the configured servers.
+    pub fn server(&self) -> Option<&ServerConf> {
+        self.server.as_ref()
+    }
+
+    /// Takes the requests for the question that failed before a response
+    /// arrived.
+    pub fn take_attempts(&mut self) -> Vec<Attempt> {
+        mem::replace(&mut self.attempts, Vec::new())
+    }
+}
+
+
+//--- Future
+
+impl Future for Validate {
+    type Item = (MessageBuf, Security);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.state {
+                State::Query(ref mut query, ref question) => {
+                    let response = try_ready!(query.poll());
+                    self.server = query.server().cloned();
+                    self.attempts = query.take_attempts();
+                    match Check::new(response, question) {
+                        Ok(check) => State::Check(check),
+                        Err(res) => {
+                            self.state = State::Done;
+                            return Ok(Async::Ready(res))
+                        }
+                    }
+                }
+                State::Check(ref mut check) => {
+                    let security = try_ready!(check.poll(&self.resolver));
+                    let response = check.response.clone();
+                    self.state = State::Done;
+                    return Ok(Async::Ready((response, security)))
+                }
+                State::Done => panic!("polling a resolved Validate")
+            };
+            self.state = next;
+        }
+    }
+}
+
+
+//------------ Check ---------------------------------------------------------
+
+/// The checking of a response.
+struct Check {
+    /// The response to check.
+    response: MessageBuf,
+
+    /// The question asked.
+    question: Question<DNameBuf>,
+
+    /// The record sets of the answer section.
+    answer: Vec<Rrset>,
+
+    /// The Nsec, Nsec3, and Soa record sets of the authority section.
+    authority: Vec<Rrset>,
+
+    /// The name at the end of the Cname chain.
+    final_name: DNameBuf,
+
+    /// Whether there are records of the requested type for `final_name`.
+    positive: bool,
+
+    /// The names we need to know the zone for and what we learned.
+    ///
+    /// These are the signer names of all signed sets and the owners of
+    /// all unsigned sets as well as `final_name` for negative answers.
+    targets: Vec<(DNameBuf, Option<Outcome>)>,
+
+    /// All zones we have validated keys for.
+    zones: Vec<SecureZone>,
+
+    /// The chain walk currently in progress.
+    walk: Option<ChainWalk>,
+
+    /// The time to check signatures against.
+    now: u32,
+}
+
+impl Check {
+    /// Prepares checking the response to a question.
+    ///
+    /// Returns the final result right away if the response can’t be
+    /// checked.
+    fn new(response: MessageBuf, question: &Question<DNameBuf>)
+           -> Result<Self, (MessageBuf, Security)> {
+        let rcode = response.header().rcode();
+        if rcode != Rcode::NoError && rcode != Rcode::NXDomain {
+            return Err((response, Security::Indeterminate))
+        }
+        let sets = response.answer().and_then(Rrset::collect)
+                           .and_then(|answer| {
+            Ok((answer, Rrset::collect(response.authority()?)?))
+        });
+        let (answer, mut authority) = match sets {
+            Ok(sets) => sets,
+            Err(_) => return Err((response, Security::Bogus))
+        };
+        authority.retain(|set| {
+            set.rtype() == Rtype::Nsec || set.rtype() == Rtype::Nsec3
+                || set.rtype() == Rtype::Soa
+        });
+        let mut res = Check {
+            response: response,
+            question: question.clone(),
+            answer: answer,
+            authority: authority,
+            final_name: canonical_name(question.qname()),
+            positive: false,
+            targets: Vec::new(),
+            zones: Vec::new(),
+            walk: None,
+            now: now(),
+        };
+        res.follow_cnames();
+        res.collect_targets();
+        Ok(res)
+    }
+
+    /// Follows the Cname chain in the answer to the final name.
+    fn follow_cnames(&mut self) {
+        let qtype = self.question.qtype();
+        for _ in 0..self.answer.len() + 1 {
+            if self.answer.iter().any(|set| {
+                set.owner() == self.final_name.as_slice()
+                    && (set.rtype() == qtype || qtype == Rtype::Any)
+            }) {
+                self.positive = true;
+                return
+            }
+            let next = self.answer.iter().find(|set| {
+                set.owner() == self.final_name.as_slice()
+                    && set.rtype() == Rtype::Cname
+            }).and_then(|set| set.rdata().first())
+              .and_then(|rdata| DNameSlice::from_bytes(rdata));
+            match next {
+                Some(name) => self.final_name = name.to_owned(),
+                None => return
+            }
+        }
+    }
+
+    /// Determines the names we need the zone status for.
+    fn collect_targets(&mut self) {
+        let mut targets = Vec::new();
+        for set in self.answer.iter().chain(self.authority.iter()) {
+            if !self.is_synthesized(set) {
+                if let Some(target) = target_of(set) {
+                    targets.push(target)
+                }
+            }
+        }
+        if !self.positive {
+            targets.push(self.final_name.clone())
+        }
+        for target in targets {
+            if !self.targets.iter().any(|&(ref name, _)| *name == target) {
+                self.targets.push((target, None))
+            }
+        }
+    }
+
+    /// Walks the chains of trust for all targets and evaluates the answer.
+    fn poll(&mut self, resolver: &Resolver) -> Poll<Security, Error> {
+        loop {
+            let outcome = match self.walk {
+                Some(ref mut walk) => Some(try_ready!(walk.poll())),
+                None => None
+            };
+            if let Some(outcome) = outcome {
+                let walk = self.walk.take().unwrap();
+                self.zones.extend(walk.zones);
+                self.set_outcome(&walk.target, outcome);
+            }
+            let target = self.targets.iter()
+                             .find(|&&(_, ref outcome)| outcome.is_none())
+                             .map(|&(ref name, _)| name.clone());
+            let target = match target {
+                Some(target) => target,
+                None => return Ok(Async::Ready(self.evaluate()))
+            };
+            match ChainWalk::new(resolver, target.clone(),
+                                 self.question.qclass(), &self.zones,
+                                 self.now) {
+                Ok(walk) => self.walk = Some(walk),
+                Err(outcome) => self.set_outcome(&target, outcome)
+            }
+        }
+    }
+
+    /// Records the outcome of the chain walk for `target`.
+    fn set_outcome(&mut self, target: &DNameSlice, outcome: Outcome) {
+        for &mut (ref name, ref mut item) in &mut self.targets {
+            if name.as_slice() == target {
+                *item = Some(outcome.clone())
+            }
+        }
+    }
+
+    /// Returns the outcome of the chain walk for `target`.
+    fn outcome(&self, target: &DNameSlice) -> Option<&Outcome> {
+        self.targets.iter().find(|&&(ref name, _)| name.as_slice() == target)
+                    .and_then(|&(_, ref outcome)| outcome.as_ref())
+    }
+
+    /// Returns the keys of the zone with the given apex.
+    fn keys(&self, apex: &DNameSlice) -> &[Dnskey] {
+        self.zones.iter().find(|zone| zone.apex.as_slice() == apex)
+                  .map(|zone| zone.keys.as_slice()).unwrap_or(&[])
+    }
+
+    /// Evaluates the security status of the response.
+    fn evaluate(&self) -> Security {
+        let mut res = Security::Secure;
+        let mut wildcards = Vec::new();
+        for set in self.answer.iter().chain(self.authority.iter()) {
+            if !self.is_synthesized(set) {
+                res = res.and(self.check_set(set, &mut wildcards))
+            }
+        }
+        for (owner, labels, apex) in wildcards {
+            res = res.and(self.proofs(&apex).wildcard(&owner, labels))
+        }
+        if !self.positive {
+            res = res.and(match self.outcome(&self.final_name) {
+                Some(&Outcome::Secure(ref apex)) => {
+                    let proofs = self.proofs(apex);
+                    if self.response.header().rcode() == Rcode::NXDomain {
+                        proofs.nxdomain(&self.final_name)
+                    }
+                    else {
+                        proofs.nodata(&self.final_name,
+                                      self.question.qtype())
+                    }
+                }
+                Some(outcome) => outcome.security(),
+                None => Security::Indeterminate
+            })
+        }
+        res
+    }
+
+    /// Checks the signatures of a record set.
+    ///
+    /// If the set was expanded from a wildcard, adds its owner, the labels
+    /// of the signature, and the signer to `wildcards`.
+    fn check_set(&self, set: &Rrset,
+                 wildcards: &mut Vec<(DNameBuf, u8, DNameBuf)>)
+                 -> Security {
+        let target = match target_of(set) {
+            Some(target) => target,
+            None => return Security::Bogus
+        };
+        let apex = match self.outcome(&target) {
+            Some(&Outcome::Secure(ref apex)) => apex,
+            Some(outcome) => return outcome.security(),
+            None => return Security::Indeterminate
+        };
+        if *apex != target {
+            return Security::Bogus
+        }
+        match set.verify(apex, self.keys(apex), self.now) {
+            Some(labels) => {
+                if labels < set.label_count() {
+                    wildcards.push((set.owner().to_owned(), labels,
+                                    apex.clone()))
+                }
+                Security::Secure
+            }
+            None => Security::Bogus
+        }
+    }
+
+    /// Returns the denial proofs signed by the zone with the given apex.
+    fn proofs(&self, apex: &DNameSlice) -> Proofs {
+        let mut res = Proofs::new();
+        for set in &self.authority {
+            if set.verify(apex, self.keys(apex), self.now).is_some() {
+                res.add(set)
+            }
+        }
+        res
+    }
+
+    /// Returns whether `set` is an unsigned Cname synthesized from a Dname.
+    ///
+    /// Servers synthesize these records on the fly and thus can’t sign
+    /// them. Instead, the signed Dname record in the answer covers them.
+    fn is_synthesized(&self, set: &Rrset) -> bool {
+        if set.rtype() != Rtype::Cname || set.is_signed()
+                || set.rdata().len() != 1 {
+            return false
+        }
+        let owner = set.owner().as_bytes();
+        self.answer.iter().any(|dname| {
+            if dname.rtype() != Rtype::Dname || dname.rdata().len() != 1 {
+                return false
+            }
+            let suffix = dname.owner().as_bytes();
+            if !set.owner().ends_with(&dname.owner())
+                    || owner.len() <= suffix.len() {
+                return false
+            }
+            let mut synth = owner[..owner.len() - suffix.len()].to_vec();
+            synth.extend_from_slice(&dname.rdata()[0]);
+            synth == set.rdata()[0]
+        })
+    }
+}
+
+
+//------------ ChainWalk -----------------------------------------------------
+
+/// Establishing the chain of trust down to the zone of a name.
+///
+/// The walk starts at the closest zone we already have validated keys for
+/// or, failing that, at the closest trust anchor. From there, it asks for
+/// the Ds records of each name on the way to the target name, one label
+/// at a time. If there are Ds records, the name is the apex of a signed
+/// zone and the walk continues with its keys. If the name provably is a
+/// zone cut without Ds records, the target is in an unsigned zone. If the
+/// name provably isn’t a cut, we just move on.
+struct ChainWalk {
+    /// The resolver to ask.
+    resolver: Resolver,
+
+    /// The class of the records we need.
+    class: Class,
+
+    /// The name we want to know the zone of.
+    target: DNameBuf,
+
+    /// The deepest zone we have validated keys for.
+    ///
+    /// This is `None` until we have the keys for the trust anchor.
+    zone: Option<SecureZone>,
+
+    /// The name we have made it to.
+    pos: DNameBuf,
+
+    /// The zones validated during the walk.
+    zones: Vec<SecureZone>,
+
+    /// The time to check signatures against.
+    now: u32,
+
+    /// The query currently in progress.
+    state: WalkState,
+}
+
+/// The query a chain walk is waiting for.
+enum WalkState {
+    /// The Dnskey records of `pos`, to be matched with the given Ds set.
+    Keys(RawQuery, Vec<Ds>),
+
+    /// The Ds records of the name one label below `pos`.
+    Ds(RawQuery),
+
+    /// Nothing.
+    Idle,
+}
+
+impl ChainWalk {
+    /// Starts a new chain walk.
+    ///
+    /// Returns the outcome right away if there is nothing to ask.
+    fn new(resolver: &Resolver, target: DNameBuf, class: Class,
+           zones: &[SecureZone], now: u32) -> Result<Self, Outcome> {
+        let zone = zones.iter().filter(|zone| target.ends_with(&zone.apex))
+                        .max_by_key(|zone| zone.apex.len());
+        let conf = resolver.conf();
+        let anchor = conf.trust_anchors.as_ref()
+                         .and_then(|anchors| anchors.closest(&target));
+        let mut res = ChainWalk {
+            resolver: resolver.clone(),
+            class: class,
+            target: target,
+            zone: None,
+            pos: DNameBuf::root(),
+            zones: Vec::new(),
+            now: now,
+            state: WalkState::Idle,
+        };
+        match (zone, anchor) {
+            (Some(zone), anchor) if anchor.map_or(true, |anchor| {
+                zone.apex.len() >= anchor.owner.len()
+            }) => {
+                res.pos = zone.apex.clone();
+                res.zone = Some(zone.clone());
+                match res.step() {
+                    Some(outcome) => Err(outcome),
+                    None => Ok(res)
+                }
+            }
+            (_, Some(anchor)) => {
+                res.pos = canonical_name(&anchor.owner);
+                let query = res.query(res.pos.clone(), Rtype::Dnskey);
+                res.state = WalkState::Keys(query, anchor.ds.clone());
+                Ok(res)
+            }
+            _ => Err(Outcome::Indeterminate)
+        }
+    }
+
+    /// Starts a query for the given name and record type.
+    fn query(&self, name: DNameBuf, rtype: Rtype) -> RawQuery {
+        RawQuery::new(self.resolver.clone(), (name, rtype, self.class))
+    }
+
+    /// Moves on to the next label or finishes the walk.
+    fn step(&mut self) -> Option<Outcome> {
+        let apex = match self.zone {
+            Some(ref zone) => zone.apex.clone(),
+            None => return Some(Outcome::Bogus)
+        };
+        if self.pos == self.target {
+            return Some(Outcome::Secure(apex))
+        }
+        let query = self.query(self.child(), Rtype::Ds);
+        self.state = WalkState::Ds(query);
+        None
+    }
+
+    /// Returns the name one label below `pos` on the way to the target.
+    fn child(&self) -> DNameBuf {
+        let mut name = self.target.as_slice();
+        while name.len() > self.pos.len() + 1 {
+            name = match name.parent() {
+                Some(parent) => parent,
+                None => break
+            }
+        }
+        name.to_owned()
+    }
+
+    /// Processes the response to a Dnskey query.
+    fn keys_response(&mut self, response: &MessageBuf, ds: &[Ds])
+                     -> Option<Outcome> {
+        match verify_keys(response, &self.pos, ds, self.now) {
+            Ok(keys) => {
+                let zone = SecureZone { apex: self.pos.clone(), keys: keys };
+                self.zones.push(zone.clone());
+                self.zone = Some(zone);
+                self.step()
+            }
+            Err(outcome) => Some(outcome)
+        }
+    }
+
+    /// Processes the response to a Ds query.
+    fn ds_response(&mut self, response: &MessageBuf) -> Option<Outcome> {
+        let child = self.child();
+        let answer = match self.zone {
+            Some(ref zone) => classify_ds(response, zone, &child, self.now),
+            None => return Some(Outcome::Bogus)
+        };
+        match answer {
+            DsAnswer::Secure(ds) => {
+                if !ds.iter().any(|ds| {
+                    crypto::is_supported(ds.algorithm())
+                        && crypto::is_digest_supported(ds.digest_type())
+                }) {
+                    return Some(Outcome::Insecure)
+                }
+                self.pos = child;
+                let query = self.query(self.pos.clone(), Rtype::Dnskey);
+                self.state = WalkState::Keys(query, ds);
+                None
+            }
+            DsAnswer::Insecure => Some(Outcome::Insecure),
+            DsAnswer::NoCut => {
+                self.pos = child;
+                self.step()
+            }
+            DsAnswer::Stop => {
+                self.zone.as_ref().map(|zone| {
+                    Outcome::Secure(zone.apex.clone())
+                })
+            }
+            DsAnswer::Bogus => Some(Outcome::Bogus),
+        }
+    }
+}
+
+
+//--- Future
+
+impl Future for ChainWalk {
+    type Item = Outcome;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let response = match self.state {
+                WalkState::Keys(ref mut query, _)
+                | WalkState::Ds(ref mut query) => try_ready!(query.poll()),
+                WalkState::Idle => panic!("polling a resolved ChainWalk")
+            };
+            let outcome = match mem::replace(&mut self.state,
+                                             WalkState::Idle) {
+                WalkState::Keys(_, ds) => self.keys_response(&response, &ds),
+                WalkState::Ds(_) => self.ds_response(&response),
+                WalkState::Idle => unreachable!()
+            };
+            if let Some(outcome) = outcome {
+                return Ok(Async::Ready(outcome))
+            }
+        }
+    }
+}
+
+
+//------------ SecureZone ----------------------------------------------------
+
+/// A zone we have validated keys for.
+#[derive(Clone, Debug)]
+struct SecureZone {
+    /// The apex of the zone.
+    apex: DNameBuf,
+
+    /// The zone’s keys.
+    keys: Vec<Dnskey>,
+}
+
+
+//------------ Outcome -------------------------------------------------------
+
+/// The outcome of a chain walk.
+#[derive(Clone, Debug)]
+enum Outcome {
+    /// The name is in the secure zone with the given apex.
+    Secure(DNameBuf),
+
+    /// The name is in an unsigned zone.
+    Insecure,
+
+    /// The chain of trust is broken.
+    Bogus,
+
+    /// There is no trust anchor for the name.
+    Indeterminate,
+}
+
+impl Outcome {
+    /// Returns the security status for a name with this outcome.
+    fn security(&self) -> Security {
+        match *self {
+            Outcome::Secure(_) => Security::Secure,
+            Outcome::Insecure => Security::Insecure,
+            Outcome::Bogus => Security::Bogus,
+            Outcome::Indeterminate => Security::Indeterminate,
+        }
+    }
+}
+
+
+//------------ DsAnswer ------------------------------------------------------
+
+/// What the response to a Ds query during a chain walk says.
+enum DsAnswer {
+    /// The name is the apex of a signed zone with these Ds records.
+    Secure(Vec<Ds>),
+
+    /// The name is the apex of an unsigned zone.
+    Insecure,
+
+    /// The name is not a zone cut.
+    NoCut,
+
+    /// The name doesn’t exist or is an alias, so there is nothing below.
+    Stop,
+
+    /// The response is broken.
+    Bogus,
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the time to check signatures against.
+fn now() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+                     .map(|now| now.as_secs() as u32).unwrap_or(0)
+}
+
+/// Returns the name whose zone status decides about a record set.
+///
+/// For signed sets this is the signer name, for unsigned sets the owner.
+/// Returns `None` if none of the signers is an ancestor of the owner.
+fn target_of(set: &Rrset) -> Option<DNameBuf> {
+    if set.is_signed() {
+        set.signer().map(DNameSlice::to_owned)
+    }
+    else {
+        Some(set.owner().to_owned())
+    }
+}
+
+/// Classifies the response to a Ds query for `child` below `zone`.
+fn classify_ds(response: &MessageBuf, zone: &SecureZone,
+               child: &DNameSlice, now: u32) -> DsAnswer {
+    let nxdomain = match response.header().rcode() {
+        Rcode::NoError => false,
+        Rcode::NXDomain => true,
+        _ => return DsAnswer::Bogus
+    };
+    let answer = match response.answer().and_then(Rrset::collect) {
+        Ok(answer) => answer,
+        Err(_) => return DsAnswer::Bogus
+    };
+    let authority = match response.authority().and_then(Rrset::collect) {
+        Ok(authority) => authority,
+        Err(_) => return DsAnswer::Bogus
+    };
+    let mut proofs = Proofs::new();
+    for set in &authority {
+        if set.verify(&zone.apex, &zone.keys, now).is_some() {
+            proofs.add(set)
+        }
+    }
+    if nxdomain {
+        return match proofs.nxdomain(child) {
+            Security::Secure => DsAnswer::Stop,
+            Security::Insecure => DsAnswer::Insecure,
+            _ => DsAnswer::Bogus
+        }
+    }
+    if let Some(set) = answer.iter().find(|set| {
+        set.rtype() == Rtype::Ds && set.owner() == child
+    }) {
+        if set.verify(&zone.apex, &zone.keys, now).is_none() {
+            return DsAnswer::Bogus
+        }
+        return DsAnswer::Secure(
+            set.records::<parsed::Ds>().iter().map(|ds| {
+                Ds::new(ds.key_tag(), ds.algorithm(), ds.digest_type(),
+                        ds.digest().to_vec())
+            }).collect()
+        )
+    }
+    let delegation = proofs.delegation(child);
+    if let Some(set) = answer.iter().find(|set| {
+        set.rtype() == Rtype::Cname && set.owner() == child
+    }) {
+        // An alias is no zone cut. Without a valid signature on the
+        // Cname, the proofs have to tell.
+        if set.verify(&zone.apex, &zone.keys, now).is_some() {
+            return DsAnswer::Stop
+        }
+        return match delegation {
+            Delegation::Insecure => DsAnswer::Insecure,
+            Delegation::NoCut => DsAnswer::Stop,
+            Delegation::Bogus => DsAnswer::Bogus,
+        }
+    }
+    match delegation {
+        Delegation::Insecure => DsAnswer::Insecure,
+        Delegation::NoCut => DsAnswer::NoCut,
+        Delegation::Bogus => DsAnswer::Bogus,
+    }
+}
+
+/// Validates the Dnskey records of the zone `apex` against its Ds records.
+///
+/// Returns all the keys of the zone if one of the keys matching a Ds
+/// record has signed them.
+fn verify_keys(response: &MessageBuf, apex: &DNameSlice, ds: &[Ds],
+               now: u32) -> Result<Vec<Dnskey>, Outcome> {
+    let supported: Vec<_> = ds.iter().filter(|ds| {
+        crypto::is_supported(ds.algorithm())
+            && crypto::is_digest_supported(ds.digest_type())
+    }).collect();
+    if supported.is_empty() {
+        return Err(Outcome::Insecure)
+    }
+    if response.header().rcode() != Rcode::NoError {
+        return Err(Outcome::Bogus)
+    }
+    let answer = match response.answer().and_then(Rrset::collect) {
+        Ok(answer) => answer,
+        Err(_) => return Err(Outcome::Bogus)
+    };
+    let set = match answer.iter().find(|set| {
+        set.rtype() == Rtype::Dnskey && set.owner() == apex
+    }) {
+        Some(set) => set,
+        None => return Err(Outcome::Bogus)
+    };
+    let owner = canonical_name(apex);
+    let mut keys = Vec::new();
+    let mut trusted = Vec::new();
+    for rdata in set.rdata() {
+        let key = match parsed::Dnskey::parse(Rtype::Dnskey,
+                                              &mut Parser::new(rdata)) {
+            Ok(Some(key)) => key,
+            _ => continue
+        };
+        let key = Dnskey::new(key.flags(), key.protocol(), key.algorithm(),
+                              key.public_key().to_vec());
+        if supported.iter().any(|ds| {
+            ds.key_tag() == key.key_tag() && ds.algorithm() == key.algorithm()
+                && crypto::ds_digest(ds.digest_type(), owner.as_bytes(),
+                                     rdata)
+                          .map_or(false, |digest| digest == ds.digest())
+        }) {
+            trusted.push(key.clone())
+        }
+        keys.push(key)
+    }
+    if trusted.is_empty() {
+        return Err(Outcome::Bogus)
+    }
+    match set.verify(apex, &trusted, now) {
+        Some(_) => Ok(keys),
+        None => Err(Outcome::Bogus)
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use ::bits::{ComposeMode, MessageBuilder, RecordData};
+    use ::iana::{DigestAlg, Nsec3HashAlg, SecAlg};
+    use ::rdata::A;
+    use ::rdata::owned::{Cname, Nsec, Nsec3};
+    use ::rdata::rfc4034::RtypeBitmap;
+    use ::utils::base32;
+    use super::super::rrset::test::{NOW, TestKey};
+    use super::*;
+
+    /// One day in seconds.
+    const DAY: u32 = 86400;
+
+    fn name(s: &str) -> DNameBuf {
+        DNameBuf::from_str(s).unwrap()
+    }
+
+    /// Creates a response with the Dnskey records of `zone`.
+    fn keys_response(zone: &DNameBuf, keys: &[&TestKey], signer: &TestKey)
+                     -> MessageBuf {
+        let records: Vec<_> = keys.iter().map(|key| {
+            (zone.clone(), key.dnskey().clone())
+        }).collect();
+        let sig = signer.sign(zone, &records, zone.labels().count() as u8 - 1);
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.push((zone, Rtype::Dnskey)).unwrap();
+        let mut msg = msg.answer();
+        for &(ref owner, ref data) in &records {
+            msg.push((owner, 3600, data.clone())).unwrap();
+        }
+        msg.push((zone, 3600, sig)).unwrap();
+        MessageBuf::from_vec(msg.finish()).unwrap()
+    }
+
+    /// Creates a response with the Ds record for `child` signed by `key`.
+    fn ds_response(zone: &DNameBuf, child: &DNameBuf, ds: Option<Ds>,
+                   key: &TestKey, rcode: Rcode) -> MessageBuf {
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.header_mut().set_rcode(rcode);
+        msg.push((child, Rtype::Ds)).unwrap();
+        let mut msg = msg.answer();
+        if let Some(ds) = ds {
+            let sig = key.sign(zone, &[(child.clone(), ds.clone())],
+                               child.labels().count() as u8 - 1);
+            msg.push((child, 3600, ds)).unwrap();
+            msg.push((child, 3600, sig)).unwrap();
+        }
+        MessageBuf::from_vec(msg.finish()).unwrap()
+    }
+
+    /// Creates a negative response with the given signed proofs.
+    ///
+    /// Each proof record is signed by `key` as a key of `zone`.
+    fn negative<D>(qname: &DNameBuf, qtype: Rtype, rcode: Rcode,
+                   zone: &DNameBuf, key: &TestKey, proofs: &[(DNameBuf, D)])
+                   -> MessageBuf
+                where D: RecordData + Clone {
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.header_mut().set_rcode(rcode);
+        msg.push((qname, qtype)).unwrap();
+        let mut msg = msg.answer().authority();
+        for &(ref owner, ref data) in proofs {
+            let sig = key.sign(zone, &[(owner.clone(), data.clone())],
+                               owner.labels().count() as u8 - 1);
+            msg.push((owner, 3600, data.clone())).unwrap();
+            msg.push((owner, 3600, sig)).unwrap();
+        }
+        MessageBuf::from_vec(msg.finish()).unwrap()
+    }
+
+    /// Creates Nsec record data.
+    fn nsec(next: &str, types: &[Rtype]) -> Nsec {
+        Nsec::new(name(next), RtypeBitmap::from_types(types.iter().cloned()))
+    }
+
+    /// Creates the Nsec3 chain of the zone `example.` from `names`.
+    fn nsec3_chain(names: &[(&str, &[Rtype])], opt_out: bool)
+                   -> Vec<(DNameBuf, Nsec3)> {
+        let salt = vec![0xaa, 0xbb];
+        let mut hashes: Vec<_> = names.iter().map(|&(owner, types)| {
+            (crypto::nsec3_hash(name(owner).as_bytes(), &salt, 1), types)
+        }).collect();
+        hashes.sort();
+        (0..hashes.len()).map(|i| {
+            let (ref hash, types) = hashes[i];
+            let owner = format!("{}.example.", base32::encode_hex(hash));
+            (name(&owner),
+             Nsec3::new(Nsec3HashAlg::Sha1, opt_out as u8, 1, salt.clone(),
+                        hashes[(i + 1) % hashes.len()].0.clone(),
+                        RtypeBitmap::from_types(types.iter().cloned())))
+        }).collect()
+    }
+
+    /// Evaluates a response as if the chain walks were already done.
+    ///
+    /// The zone `zone` is secure with `key` as its only key and the
+    /// signatures are checked at `now`. Targets other than `zone` get
+    /// `outcome`.
+    fn evaluate(response: MessageBuf, zone: &DNameBuf, key: &TestKey,
+                outcome: Outcome, now: u32) -> Security {
+        let question = {
+            let question = response.first_question().unwrap();
+            Question::new(question.qname().to_cow().into_owned(),
+                          question.qtype(), question.qclass())
+        };
+        let mut check = match Check::new(response, &question) {
+            Ok(check) => check,
+            Err((_, security)) => return security
+        };
+        check.now = now;
+        check.zones.push(SecureZone {
+            apex: zone.clone(),
+            keys: vec![key.dnskey().clone()]
+        });
+        for &mut (ref target, ref mut item) in &mut check.targets {
+            *item = Some(if target == zone {
+                Outcome::Secure(zone.clone())
+            }
+            else {
+                outcome.clone()
+            });
+        }
+        check.evaluate()
+    }
+
+    #[test]
+    fn keys() {
+        let zone = name("example.com.");
+        let ksk = TestKey::new(1, 0x0101);
+        let zsk = TestKey::new(2, 0x0100);
+        let response = keys_response(&zone, &[&ksk, &zsk], &ksk);
+
+        let keys = verify_keys(&response, &zone, &[ksk.ds(&zone)], NOW)
+                              .unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(zsk.dnskey()));
+
+        // The ZSK is known but hasn’t signed the key set.
+        match verify_keys(&response, &zone, &[zsk.ds(&zone)], NOW) {
+            Err(Outcome::Bogus) => { }
+            other => panic!("{:?}", other)
+        }
+
+        // A Ds for the wrong zone doesn’t match.
+        match verify_keys(&response, &zone, &[ksk.ds(&name("com."))], NOW) {
+            Err(Outcome::Bogus) => { }
+            other => panic!("{:?}", other)
+        }
+
+        // Only unsupported algorithms make the zone insecure.
+        let ds = Ds::new(1, SecAlg::RsaMd5, DigestAlg::Sha256, vec![0; 32]);
+        match verify_keys(&response, &zone, &[ds], NOW) {
+            Err(Outcome::Insecure) => { }
+            other => panic!("{:?}", other)
+        }
+    }
+
+    #[test]
+    fn ds() {
+        let zone = name("com.");
+        let child = name("example.com.");
+        let key = TestKey::new(1, 0x0101);
+        let secure = SecureZone {
+            apex: zone.clone(),
+            keys: vec![key.dnskey().clone()]
+        };
+        let child_ds = TestKey::new(2, 0x0101).ds(&child);
+
+        let response = ds_response(&zone, &child, Some(child_ds.clone()),
+                                   &key, Rcode::NoError);
+        match classify_ds(&response, &secure, &child, NOW) {
+            DsAnswer::Secure(ds) => assert_eq!(ds, vec![child_ds.clone()]),
+            _ => panic!("not secure")
+        }
+
+        // Signed by a key we don’t trust.
+        let response = ds_response(&zone, &child, Some(child_ds),
+                                   &TestKey::new(3, 0x0101), Rcode::NoError);
+        match classify_ds(&response, &secure, &child, NOW) {
+            DsAnswer::Bogus => { }
+            _ => panic!("not bogus")
+        }
+
+        // An unproven negative answer is bogus, too.
+        let response = ds_response(&zone, &child, None, &key,
+                                   Rcode::NoError);
+        match classify_ds(&response, &secure, &child, NOW) {
+            DsAnswer::Bogus => { }
+            _ => panic!("not bogus")
+        }
+
+        // A name that provably doesn’t exist ends the walk.
+        let proofs = vec![
+            (zone.clone(), nsec("f.com.", &[Rtype::Soa, Rtype::Ns])),
+        ];
+        let response = negative(&child, Rtype::Ds, Rcode::NXDomain, &zone,
+                                &key, &proofs);
+        match classify_ds(&response, &secure, &child, NOW) {
+            DsAnswer::Stop => { }
+            _ => panic!("not stop")
+        }
+
+        // But only with proof.
+        let response = ds_response(&zone, &child, None, &key,
+                                   Rcode::NXDomain);
+        match classify_ds(&response, &secure, &child, NOW) {
+            DsAnswer::Bogus => { }
+            _ => panic!("not bogus")
+        }
+    }
+
+    #[test]
+    fn ds_cname() {
+        let zone = name("com.");
+        let child = name("example.com.");
+        let key = TestKey::new(1, 0x0101);
+        let secure = SecureZone {
+            apex: zone.clone(),
+            keys: vec![key.dnskey().clone()]
+        };
+        let cname = Cname::new(name("example.net."));
+        let response = |signed: bool, proof: Option<Nsec>| {
+            let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                         .unwrap();
+            msg.push((&child, Rtype::Ds)).unwrap();
+            let mut msg = msg.answer();
+            msg.push((&child, 3600, cname.clone())).unwrap();
+            if signed {
+                msg.push((&child, 3600,
+                          key.sign(&zone, &[(child.clone(), cname.clone())],
+                                   2))).unwrap();
+            }
+            let mut msg = msg.authority();
+            if let Some(proof) = proof {
+                let sig = key.sign(&zone, &[(child.clone(), proof.clone())],
+                                   2);
+                msg.push((&child, 3600, proof)).unwrap();
+                msg.push((&child, 3600, sig)).unwrap();
+            }
+            MessageBuf::from_vec(msg.finish()).unwrap()
+        };
+
+        // A signed alias ends the walk.
+        match classify_ds(&response(true, None), &secure, &child, NOW) {
+            DsAnswer::Stop => { }
+            _ => panic!("not stop")
+        }
+
+        // An unsigned one needs proof that there is no Ds.
+        let proof = nsec("f.com.", &[Rtype::Cname, Rtype::Rrsig,
+                                     Rtype::Nsec]);
+        match classify_ds(&response(false, Some(proof)), &secure, &child,
+                          NOW) {
+            DsAnswer::Stop => { }
+            _ => panic!("not stop")
+        }
+        match classify_ds(&response(false, None), &secure, &child, NOW) {
+            DsAnswer::Bogus => { }
+            _ => panic!("not bogus")
+        }
+    }
+
+    #[test]
+    fn nsec_denial() {
+        let zone = name("example.");
+        let key = TestKey::new(1, 0x0101);
+        let secure = Outcome::Secure(zone.clone());
+        let qname = name("b.example.");
+        let proofs = vec![
+            (name("a.example."), nsec("c.example.", &[Rtype::A])),
+            (zone.clone(), nsec("a.example.", &[Rtype::Soa, Rtype::Ns])),
+        ];
+
+        let response = negative(&qname, Rtype::A, Rcode::NXDomain, &zone,
+                                &key, &proofs);
+        assert_eq!(evaluate(response, &zone, &key, secure.clone(), NOW),
+                   Security::Secure);
+
+        // Without the proof that there is no wildcard.
+        let response = negative(&qname, Rtype::A, Rcode::NXDomain, &zone,
+                                &key, &proofs[..1]);
+        assert_eq!(evaluate(response, &zone, &key, secure.clone(), NOW),
+                   Security::Bogus);
+
+        // Proofs signed by a key we don’t trust.
+        let response = negative(&qname, Rtype::A, Rcode::NXDomain, &zone,
+                                &TestKey::new(2, 0x0101), &proofs);
+        assert_eq!(evaluate(response, &zone, &key, secure.clone(), NOW),
+                   Security::Bogus);
+
+        let qname = name("a.example.");
+        let response = negative(&qname, Rtype::Aaaa, Rcode::NoError, &zone,
+                                &key, &proofs[..1]);
+        assert_eq!(evaluate(response, &zone, &key, secure.clone(), NOW),
+                   Security::Secure);
+
+        // The Nsec record says the type exists.
+        let response = negative(&qname, Rtype::A, Rcode::NoError, &zone,
+                                &key, &proofs[..1]);
+        assert_eq!(evaluate(response, &zone, &key, secure, NOW),
+                   Security::Bogus);
+    }
+
+    #[test]
+    fn nsec3_denial() {
+        let zone = name("example.");
+        let key = TestKey::new(1, 0x0101);
+        let secure = Outcome::Secure(zone.clone());
+        let names: &[(&str, &[Rtype])] = &[
+            ("example.", &[Rtype::Soa, Rtype::Ns]),
+            ("a.example.", &[Rtype::A]),
+        ];
+        let proofs = nsec3_chain(names, false);
+
+        let qname = name("b.example.");
+        let response = negative(&qname, Rtype::A, Rcode::NXDomain, &zone,
+                                &key, &proofs);
+        assert_eq!(evaluate(response, &zone, &key, secure.clone(), NOW),
+                   Security::Secure);
+
+        let qname = name("a.example.");
+        let response = negative(&qname, Rtype::Aaaa, Rcode::NoError, &zone,
+                                &key, &proofs);
+        assert_eq!(evaluate(response, &zone, &key, secure.clone(), NOW),
+                   Security::Secure);
+        let response = negative(&qname, Rtype::A, Rcode::NoError, &zone,
+                                &key, &proofs);
+        assert_eq!(evaluate(response, &zone, &key, secure.clone(), NOW),
+                   Security::Bogus);
+
+        // With opt-out, the name may be an unsigned delegation.
+        let qname = name("b.example.");
+        let response = negative(&qname, Rtype::A, Rcode::NXDomain, &zone,
+                                &key, &nsec3_chain(names, true));
+        assert_eq!(evaluate(response, &zone, &key, secure, NOW),
+                   Security::Insecure);
+    }
+
+    #[test]
+    fn signature_validity() {
+        let zone = name("example.");
+        let key = TestKey::new(1, 0x0101);
+        let owner = name("www.example.");
+        let data = A::new("192.0.2.1".parse().unwrap());
+        let sig = key.sign(&zone, &[(owner.clone(), data.clone())], 2);
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.push((&owner, Rtype::A)).unwrap();
+        let mut msg = msg.answer();
+        msg.push((&owner, 3600, data)).unwrap();
+        msg.push((&owner, 3600, sig)).unwrap();
+        let response = MessageBuf::from_vec(msg.finish()).unwrap();
+
+        let outcome = Outcome::Secure(zone.clone());
+        assert_eq!(evaluate(response.clone(), &zone, &key, outcome.clone(),
+                            NOW),
+                   Security::Secure);
+
+        // Expired.
+        assert_eq!(evaluate(response.clone(), &zone, &key, outcome.clone(),
+                            NOW + 2 * DAY),
+                   Security::Bogus);
+
+        // Not yet valid.
+        assert_eq!(evaluate(response, &zone, &key, outcome.clone(),
+                            NOW - 2 * DAY),
+                   Security::Bogus);
+
+        // Expired proofs don’t prove anything.
+        let proofs = vec![
+            (owner.clone(), nsec("z.example.", &[Rtype::A])),
+        ];
+        let response = negative(&owner, Rtype::Aaaa, Rcode::NoError, &zone,
+                                &key, &proofs);
+        assert_eq!(evaluate(response, &zone, &key, outcome, NOW + 2 * DAY),
+                   Security::Bogus);
+    }
+
+    #[test]
+    fn insecure_delegation() {
+        let zone = name("com.");
+        let child = name("example.com.");
+        let key = TestKey::new(1, 0x0101);
+        let secure = SecureZone {
+            apex: zone.clone(),
+            keys: vec![key.dnskey().clone()]
+        };
+
+        // A delegation without Ds records.
+        let proofs = vec![
+            (child.clone(), nsec("z.com.", &[Rtype::Ns])),
+        ];
+        let response = negative(&child, Rtype::Ds, Rcode::NoError, &zone,
+                                &key, &proofs);
+        match classify_ds(&response, &secure, &child, NOW) {
+            DsAnswer::Insecure => { }
+            _ => panic!("not insecure")
+        }
+
+        // The Nsec record claims there are Ds records.
+        let proofs = vec![
+            (child.clone(), nsec("z.com.", &[Rtype::Ns, Rtype::Ds])),
+        ];
+        let response = negative(&child, Rtype::Ds, Rcode::NoError, &zone,
+                                &key, &proofs);
+        match classify_ds(&response, &secure, &child, NOW) {
+            DsAnswer::Bogus => { }
+            _ => panic!("not bogus")
+        }
+
+        // The name exists but isn’t a zone cut.
+        let proofs = vec![
+            (child.clone(), nsec("z.com.", &[Rtype::A])),
+        ];
+        let response = negative(&child, Rtype::Ds, Rcode::NoError, &zone,
+                                &key, &proofs);
+        match classify_ds(&response, &secure, &child, NOW) {
+            DsAnswer::NoCut => { }
+            _ => panic!("not no cut")
+        }
+
+        // An unsigned answer from the insecure zone.
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.push((name("www.example.com."), Rtype::A)).unwrap();
+        let mut msg = msg.answer();
+        msg.push((name("www.example.com."), 3600,
+                  A::new("192.0.2.1".parse().unwrap()))).unwrap();
+        let response = MessageBuf::from_vec(msg.finish()).unwrap();
+        assert_eq!(evaluate(response.clone(), &zone, &key, Outcome::Insecure,
+                            NOW),
+                   Security::Insecure);
+
+        // The same answer in a secure zone is bogus.
+        assert_eq!(evaluate(response, &zone, &key,
+                            Outcome::Secure(child.clone()), NOW),
+                   Security::Bogus);
+    }
+}
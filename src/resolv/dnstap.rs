@@ -0,0 +1,651 @@
+//! Writing the resolver’s traffic as dnstap.
+//!
+//! [dnstap] is a format for logging DNS traffic. Each message sent or
+//! received is encoded as a protocol buffer message and written as a
+//! data frame using the Frame Streams protocol, either into a file or to
+//! a Unix socket a dnstap reader is listening on.
+//!
+//! The [`DnstapWriter`] is an observer that does exactly this. Since the
+//! resolver is a stub resolver talking to upstream servers, requests are
+//! written as `STUB_QUERY` and responses as `STUB_RESPONSE` messages. All
+//! other events are ignored. The actual writing happens in a thread of its
+//! own so that the resolver isn’t held up by it.
+//!
+//! To use the writer, create it and put it into the `observer` field of
+//! the resolver configuration:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use domain::resolv::{Resolver, ResolvConf};
+//! use domain::resolv::dnstap::DnstapWriter;
+//!
+//! let mut conf = ResolvConf::default();
+//! conf.observer = Some(Arc::new(DnstapWriter::create("resolver.tap")
+//!                                            .unwrap()));
+//! # let _ = conf;
+//! ```
+//!
+//! [dnstap]: http://dnstap.info/
+//! [`DnstapWriter`]: struct.DnstapWriter.html
+
+use std::{fmt, io, thread};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+#[cfg(unix)] use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::observer::{Event, EventKind, Observer, Protocol};
+
+
+//------------ DnstapWriter --------------------------------------------------
+
+/// The maximum number of frames waiting for the writing thread.
+pub const MAX_QUEUED: usize = 1024;
+
+/// An observer writing requests and responses as dnstap frames.
+///
+/// The writer can either write to a file via [`create()`], to a Unix
+/// socket via [`connect()`], or to anything that implements `Write` via
+/// [`from_writer()`]. Files and writers are written to using the
+/// unidirectional variant of the Frame Streams protocol, sockets use the
+/// bidirectional variant with its handshake.
+///
+/// Frames are handed to a thread that does the writing. If writing
+/// fails, the thread ends and all later frames are dropped. At most
+/// [`MAX_QUEUED`] frames wait for the thread. If it falls behind, new
+/// frames are dropped rather than holding up the resolver and the
+/// [`dropped()`] method tells how many. When the writer is dropped, the
+/// stream is finished properly and the thread is joined.
+///
+/// [`create()`]: #method.create
+/// [`connect()`]: #method.connect
+/// [`from_writer()`]: #method.from_writer
+/// [`MAX_QUEUED`]: constant.MAX_QUEUED.html
+/// [`dropped()`]: #method.dropped
+pub struct DnstapWriter {
+    /// The identity of the resolver to include in each frame.
+    identity: Option<Vec<u8>>,
+
+    /// The version of the resolver to include in each frame.
+    version: Option<Vec<u8>>,
+
+    /// The sending end of the channel to the writing thread.
+    ///
+    /// This is `None` only while shutting down.
+    frames: Mutex<Option<mpsc::SyncSender<Vec<u8>>>>,
+
+    /// The number of frames dropped because the queue was full.
+    dropped: AtomicUsize,
+
+    /// The writing thread.
+    ///
+    /// This is `None` only while shutting down.
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl DnstapWriter {
+    /// Creates a writer writing to a new file at `path`.
+    ///
+    /// If the file exists, it is truncated.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_writer(File::create(path)?)
+    }
+
+    /// Creates a writer connected to the Unix socket at `path`.
+    ///
+    /// A dnstap reader has to listen on the socket. The function performs
+    /// the Frame Streams handshake with the reader and fails if the
+    /// reader doesn’t accept the stream.
+    #[cfg(unix)]
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut sock = UnixStream::connect(path)?;
+        write_control(&mut sock, CONTROL_READY, true)?;
+        if read_control(&mut sock)? != CONTROL_ACCEPT {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "dnstap reader didn’t accept"))
+        }
+        write_control(&mut sock, CONTROL_START, true)?;
+        Self::spawn(sock, |sock| {
+            // The reader confirms the end of the stream. We don’t really
+            // care what it says, though.
+            read_control(sock).ok();
+        })
+    }
+
+    /// Creates a writer writing to `writer`.
+    pub fn from_writer<W: Write + Send + 'static>(writer: W)
+                                                   -> io::Result<Self> {
+        let mut writer = writer;
+        write_control(&mut writer, CONTROL_START, true)?;
+        Self::spawn(writer, |_| { })
+    }
+
+    /// Spawns the writing thread.
+    ///
+    /// Once the channel closes, the thread writes the STOP frame and then
+    /// runs `finish` with the writer.
+    fn spawn<W, F>(writer: W, finish: F) -> io::Result<Self>
+             where W: Write + Send + 'static,
+                   F: FnOnce(&mut W) + Send + 'static {
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(MAX_QUEUED);
+        let thread = thread::Builder::new()
+                                    .name("domain-dnstap".into())
+                                    .spawn(move || {
+            let mut writer = writer;
+            let res = write_frames(&mut writer, rx).and_then(|_| {
+                write_control(&mut writer, CONTROL_STOP, false)
+            });
+            if res.is_ok() {
+                finish(&mut writer)
+            }
+        })?;
+        Ok(DnstapWriter {
+            identity: None,
+            version: Some(format!("domain {}", env!("CARGO_PKG_VERSION"))
+                              .into_bytes()),
+            frames: Mutex::new(Some(tx)),
+            dropped: AtomicUsize::new(0),
+            thread: Some(thread),
+        })
+    }
+
+    /// Sets the identity of the resolver included in each frame.
+    ///
+    /// By default, there is no identity.
+    pub fn set_identity<T: Into<Vec<u8>>>(&mut self, identity: T) {
+        self.identity = Some(identity.into())
+    }
+
+    /// Sets the version of the resolver included in each frame.
+    ///
+    /// By default, this is the name and version of this crate.
+    pub fn set_version<T: Into<Vec<u8>>>(&mut self, version: T) {
+        self.version = Some(version.into())
+    }
+
+    /// Returns the number of frames dropped because the queue was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Encodes an event as a data frame.
+    fn encode(&self, event: &Event, message_type: u64) -> Vec<u8> {
+        let mut message = Vec::new();
+        put_varint_field(&mut message, MESSAGE_TYPE, message_type);
+        let addr = match event.server().ip() {
+            IpAddr::V4(addr) => {
+                put_varint_field(&mut message, MESSAGE_SOCKET_FAMILY,
+                                 SOCKET_FAMILY_INET);
+                addr.octets().to_vec()
+            }
+            IpAddr::V6(addr) => {
+                put_varint_field(&mut message, MESSAGE_SOCKET_FAMILY,
+                                 SOCKET_FAMILY_INET6);
+                addr.octets().to_vec()
+            }
+        };
+        let protocol = match event.protocol() {
+            Protocol::Udp => SOCKET_PROTOCOL_UDP,
+            Protocol::Tcp => SOCKET_PROTOCOL_TCP,
+            Protocol::Tls => SOCKET_PROTOCOL_DOT,
+            Protocol::Https => SOCKET_PROTOCOL_DOH,
+        };
+        put_varint_field(&mut message, MESSAGE_SOCKET_PROTOCOL, protocol);
+        put_bytes_field(&mut message, MESSAGE_RESPONSE_ADDRESS, &addr);
+        put_varint_field(&mut message, MESSAGE_RESPONSE_PORT,
+                         u64::from(event.server().port()));
+        let (sec, nsec) = timestamp(event.query_time());
+        put_varint_field(&mut message, MESSAGE_QUERY_TIME_SEC, sec);
+        put_fixed32_field(&mut message, MESSAGE_QUERY_TIME_NSEC, nsec);
+        if message_type == MESSAGE_TYPE_STUB_QUERY {
+            put_bytes_field(&mut message, MESSAGE_QUERY_MESSAGE,
+                            event.query());
+        }
+        else {
+            let (sec, nsec) = timestamp(event.time());
+            put_varint_field(&mut message, MESSAGE_RESPONSE_TIME_SEC, sec);
+            put_fixed32_field(&mut message, MESSAGE_RESPONSE_TIME_NSEC,
+                              nsec);
+            if let Some(response) = event.response() {
+                put_bytes_field(&mut message, MESSAGE_RESPONSE_MESSAGE,
+                                response);
+            }
+        }
+
+        let mut dnstap = Vec::new();
+        if let Some(ref identity) = self.identity {
+            put_bytes_field(&mut dnstap, DNSTAP_IDENTITY, identity);
+        }
+        if let Some(ref version) = self.version {
+            put_bytes_field(&mut dnstap, DNSTAP_VERSION, version);
+        }
+        put_bytes_field(&mut dnstap, DNSTAP_MESSAGE, &message);
+        put_varint_field(&mut dnstap, DNSTAP_TYPE, DNSTAP_TYPE_MESSAGE);
+
+        let mut frame = Vec::with_capacity(dnstap.len() + 4);
+        put_u32(&mut frame, dnstap.len() as u32);
+        frame.extend_from_slice(&dnstap);
+        frame
+    }
+}
+
+
+//--- Observer
+
+impl Observer for DnstapWriter {
+    fn observe(&self, event: &Event) {
+        let message_type = match event.kind() {
+            EventKind::Query => MESSAGE_TYPE_STUB_QUERY,
+            EventKind::Response => MESSAGE_TYPE_STUB_RESPONSE,
+            _ => return
+        };
+        let frame = self.encode(event, message_type);
+        if let Ok(frames) = self.frames.lock() {
+            if let Some(ref frames) = *frames {
+                if let Err(mpsc::TrySendError::Full(_))
+                        = frames.try_send(frame) {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+
+//--- Drop
+
+impl Drop for DnstapWriter {
+    fn drop(&mut self) {
+        // Dropping the sender ends the writing thread’s loop.
+        if let Ok(mut frames) = self.frames.lock() {
+            *frames = None;
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+
+//--- Debug
+
+impl fmt::Debug for DnstapWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DnstapWriter")
+         .field("identity", &self.identity)
+         .field("version", &self.version)
+         .finish()
+    }
+}
+
+
+//------------ Frame Streams -------------------------------------------------
+
+/// The content type of dnstap data frames.
+const CONTENT_TYPE: &'static [u8] = b"protobuf:dnstap.Dnstap";
+
+/// The control frame types we need.
+const CONTROL_ACCEPT: u32 = 0x01;
+const CONTROL_START: u32 = 0x02;
+const CONTROL_STOP: u32 = 0x03;
+const CONTROL_READY: u32 = 0x04;
+
+/// The control field type for the content type.
+const CONTROL_FIELD_CONTENT_TYPE: u32 = 0x01;
+
+/// The maximum length of a control frame we are willing to read.
+const MAX_CONTROL_LEN: usize = 512;
+
+/// Writes all frames received from `rx` to `writer`.
+///
+/// Returns once the channel is closed or writing fails.
+fn write_frames<W: Write>(writer: &mut W, rx: mpsc::Receiver<Vec<u8>>)
+                          -> io::Result<()> {
+    for frame in rx {
+        writer.write_all(&frame)?;
+    }
+    writer.flush()
+}
+
+/// Writes a control frame, optionally with the dnstap content type.
+fn write_control<W: Write>(writer: &mut W, control_type: u32,
+                           content_type: bool) -> io::Result<()> {
+    let mut payload = Vec::new();
+    put_u32(&mut payload, control_type);
+    if content_type {
+        put_u32(&mut payload, CONTROL_FIELD_CONTENT_TYPE);
+        put_u32(&mut payload, CONTENT_TYPE.len() as u32);
+        payload.extend_from_slice(CONTENT_TYPE);
+    }
+    let mut frame = Vec::with_capacity(payload.len() + 8);
+    put_u32(&mut frame, 0);
+    put_u32(&mut frame, payload.len() as u32);
+    frame.extend_from_slice(&payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/// Reads a control frame and returns its type.
+#[cfg(unix)]
+fn read_control<R: Read>(reader: &mut R) -> io::Result<u32> {
+    if read_u32(reader)? != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "expected dnstap control frame"))
+    }
+    let len = read_u32(reader)? as usize;
+    if len < 4 || len > MAX_CONTROL_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "invalid dnstap control frame"))
+    }
+    let control_type = read_u32(reader)?;
+    let mut fields = vec![0; len - 4];
+    reader.read_exact(&mut fields)?;
+    Ok(control_type)
+}
+
+/// Reads a big-endian 32 bit integer.
+#[cfg(unix)]
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok((u32::from(buf[0]) << 24) | (u32::from(buf[1]) << 16)
+       | (u32::from(buf[2]) << 8) | u32::from(buf[3]))
+}
+
+/// Appends a big-endian 32 bit integer.
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8,
+                            (value >> 8) as u8, value as u8]);
+}
+
+
+//------------ Protocol Buffers ----------------------------------------------
+
+/// Field numbers of the `Dnstap` message.
+const DNSTAP_IDENTITY: u32 = 1;
+const DNSTAP_VERSION: u32 = 2;
+const DNSTAP_MESSAGE: u32 = 14;
+const DNSTAP_TYPE: u32 = 15;
+
+/// The `Dnstap.Type` for messages.
+const DNSTAP_TYPE_MESSAGE: u64 = 1;
+
+/// Field numbers of the `Message` message.
+const MESSAGE_TYPE: u32 = 1;
+const MESSAGE_SOCKET_FAMILY: u32 = 2;
+const MESSAGE_SOCKET_PROTOCOL: u32 = 3;
+const MESSAGE_RESPONSE_ADDRESS: u32 = 5;
+const MESSAGE_RESPONSE_PORT: u32 = 7;
+const MESSAGE_QUERY_TIME_SEC: u32 = 8;
+const MESSAGE_QUERY_TIME_NSEC: u32 = 9;
+const MESSAGE_QUERY_MESSAGE: u32 = 10;
+const MESSAGE_RESPONSE_TIME_SEC: u32 = 12;
+const MESSAGE_RESPONSE_TIME_NSEC: u32 = 13;
+const MESSAGE_RESPONSE_MESSAGE: u32 = 14;
+
+/// The `Message.Type` values we use.
+const MESSAGE_TYPE_STUB_QUERY: u64 = 9;
+const MESSAGE_TYPE_STUB_RESPONSE: u64 = 10;
+
+/// The `SocketFamily` values.
+const SOCKET_FAMILY_INET: u64 = 1;
+const SOCKET_FAMILY_INET6: u64 = 2;
+
+/// The `SocketProtocol` values.
+const SOCKET_PROTOCOL_UDP: u64 = 1;
+const SOCKET_PROTOCOL_TCP: u64 = 2;
+const SOCKET_PROTOCOL_DOT: u64 = 3;
+const SOCKET_PROTOCOL_DOH: u64 = 4;
+
+/// Appends a varint.
+fn put_varint(buf: &mut Vec<u8>, value: u64) {
+    let mut value = value;
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Appends a varint field.
+fn put_varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    put_varint(buf, u64::from(field) << 3);
+    put_varint(buf, value);
+}
+
+/// Appends a fixed32 field.
+fn put_fixed32_field(buf: &mut Vec<u8>, field: u32, value: u32) {
+    put_varint(buf, (u64::from(field) << 3) | 5);
+    buf.extend_from_slice(&[value as u8, (value >> 8) as u8,
+                            (value >> 16) as u8, (value >> 24) as u8]);
+}
+
+/// Appends a length-delimited field.
+fn put_bytes_field(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+    put_varint(buf, (u64::from(field) << 3) | 2);
+    put_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+/// Splits a time into seconds and nanoseconds since the Unix epoch.
+fn timestamp(time: SystemTime) -> (u64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => (duration.as_secs(), duration.subsec_nanos()),
+        Err(_) => (0, 0)
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::*;
+
+    /// A writer into a shared buffer.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Splits a frame stream into its frames.
+    ///
+    /// Control frames are returned as their type, data frames as `None`
+    /// and their payload.
+    fn frames(mut data: &[u8]) -> Vec<(Option<u32>, Vec<u8>)> {
+        let mut res = Vec::new();
+        while !data.is_empty() {
+            let len = read_u32(&mut data).unwrap() as usize;
+            if len == 0 {
+                let len = read_u32(&mut data).unwrap() as usize;
+                let control_type = read_u32(&mut data).unwrap();
+                res.push((Some(control_type), data[..len - 4].to_vec()));
+                data = &data[len - 4..];
+            }
+            else {
+                res.push((None, data[..len].to_vec()));
+                data = &data[len..];
+            }
+        }
+        res
+    }
+
+    /// Decodes the fields of a protocol buffer message.
+    ///
+    /// Varints and fixed32 values are returned as numbers, everything
+    /// else as bytes.
+    fn fields(mut data: &[u8]) -> Vec<(u32, u64, Vec<u8>)> {
+        fn varint(data: &mut &[u8]) -> u64 {
+            let mut res = 0;
+            let mut shift = 0;
+            loop {
+                let byte = data[0];
+                *data = &data[1..];
+                res |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return res
+                }
+                shift += 7;
+            }
+        }
+
+        let mut res = Vec::new();
+        while !data.is_empty() {
+            let key = varint(&mut data);
+            match key & 7 {
+                0 => res.push(((key >> 3) as u32, varint(&mut data),
+                               Vec::new())),
+                2 => {
+                    let len = varint(&mut data) as usize;
+                    res.push(((key >> 3) as u32, 0, data[..len].to_vec()));
+                    data = &data[len..];
+                }
+                5 => {
+                    let value = u64::from(data[0])
+                              | u64::from(data[1]) << 8
+                              | u64::from(data[2]) << 16
+                              | u64::from(data[3]) << 24;
+                    res.push(((key >> 3) as u32, value, Vec::new()));
+                    data = &data[4..];
+                }
+                _ => panic!("unexpected wire type")
+            }
+        }
+        res
+    }
+
+    fn event(kind: EventKind, response: Option<&'static [u8]>)
+             -> Event<'static> {
+        Event::new(kind, "192.0.2.1:53".parse::<SocketAddr>().unwrap(),
+                   Protocol::Udp,
+                   UNIX_EPOCH + Duration::new(1000, 5),
+                   UNIX_EPOCH + Duration::new(1001, 7),
+                   b"query", response)
+    }
+
+    #[test]
+    fn file_frames() {
+        let buf = Buffer::default();
+        let mut writer = DnstapWriter::from_writer(buf.clone()).unwrap();
+        writer.set_identity("test");
+        writer.observe(&event(EventKind::Query, None));
+        writer.observe(&event(EventKind::Timeout, None));
+        writer.observe(&event(EventKind::Response, Some(b"response")));
+        drop(writer);
+
+        let data = buf.0.lock().unwrap().clone();
+        let frames = frames(&data);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].0, Some(CONTROL_START));
+        assert!(frames[0].1.ends_with(CONTENT_TYPE));
+        assert_eq!(frames[3], (Some(CONTROL_STOP), Vec::new()));
+
+        let query = fields(&frames[1].1);
+        assert_eq!(query[0], (DNSTAP_IDENTITY, 0, b"test".to_vec()));
+        assert_eq!(query[1].0, DNSTAP_VERSION);
+        assert_eq!(query[3], (DNSTAP_TYPE, DNSTAP_TYPE_MESSAGE, Vec::new()));
+        assert_eq!(fields(&query[2].2), vec![
+            (MESSAGE_TYPE, MESSAGE_TYPE_STUB_QUERY, Vec::new()),
+            (MESSAGE_SOCKET_FAMILY, SOCKET_FAMILY_INET, Vec::new()),
+            (MESSAGE_SOCKET_PROTOCOL, SOCKET_PROTOCOL_UDP, Vec::new()),
+            (MESSAGE_RESPONSE_ADDRESS, 0, vec![192, 0, 2, 1]),
+            (MESSAGE_RESPONSE_PORT, 53, Vec::new()),
+            (MESSAGE_QUERY_TIME_SEC, 1000, Vec::new()),
+            (MESSAGE_QUERY_TIME_NSEC, 5, Vec::new()),
+            (MESSAGE_QUERY_MESSAGE, 0, b"query".to_vec()),
+        ]);
+
+        let response = fields(&frames[2].1);
+        assert_eq!(&fields(&response[2].2)[5..], &[
+            (MESSAGE_QUERY_TIME_SEC, 1000, Vec::new()),
+            (MESSAGE_QUERY_TIME_NSEC, 5, Vec::new()),
+            (MESSAGE_RESPONSE_TIME_SEC, 1001, Vec::new()),
+            (MESSAGE_RESPONSE_TIME_NSEC, 7, Vec::new()),
+            (MESSAGE_RESPONSE_MESSAGE, 0, b"response".to_vec()),
+        ][..]);
+    }
+
+    #[test]
+    fn full_queue() {
+        /// A writer that blocks while the gate is locked.
+        struct Stalled(Buffer, Arc<Mutex<()>>);
+
+        impl Write for Stalled {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let _gate = self.1.lock().unwrap();
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buf = Buffer::default();
+        let gate = Arc::new(Mutex::new(()));
+        let writer = DnstapWriter::from_writer(
+            Stalled(buf.clone(), gate.clone())
+        ).unwrap();
+        let total = MAX_QUEUED + 10;
+        {
+            let _gate = gate.lock().unwrap();
+            for _ in 0..total {
+                writer.observe(&event(EventKind::Query, None));
+            }
+            assert!(writer.dropped() > 0);
+        }
+        let dropped = writer.dropped();
+        drop(writer);
+
+        let data = buf.0.lock().unwrap().clone();
+        assert_eq!(frames(&data).len() - 2 + dropped, total);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_handshake() {
+        use std::{env, fs, process};
+        use std::os::unix::net::UnixListener;
+
+        let path = env::temp_dir().join(format!("domain-dnstap-{}",
+                                                process::id()));
+        fs::remove_file(&path).ok();
+        let listener = UnixListener::bind(&path).unwrap();
+        let reader = thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            assert_eq!(read_control(&mut sock).unwrap(), CONTROL_READY);
+            write_control(&mut sock, CONTROL_ACCEPT, true).unwrap();
+            assert_eq!(read_control(&mut sock).unwrap(), CONTROL_START);
+            let len = read_u32(&mut sock).unwrap() as usize;
+            let mut frame = vec![0; len];
+            sock.read_exact(&mut frame).unwrap();
+            assert_eq!(read_control(&mut sock).unwrap(), CONTROL_STOP);
+            write_control(&mut sock, 0x05, false).unwrap();
+            frame
+        });
+        let writer = DnstapWriter::connect(&path).unwrap();
+        writer.observe(&event(EventKind::Query, None));
+        drop(writer);
+        let frame = reader.join().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(fields(&frame).last().unwrap(),
+                   &(DNSTAP_TYPE, DNSTAP_TYPE_MESSAGE, Vec::new()));
+    }
+}
@@ -0,0 +1,524 @@
+//! DNS-over-HTTPS channel and transport.
+//!
+//! This implements RFC 8484. Each request becomes an HTTP/2 request of its
+//! own on a connection shared by all requests. Since HTTP already takes
+//! care of matching responses to requests, the message ID is set to zero
+//! on the wire as recommended by the RFC. The channel restores the ID in
+//! the response so that the transport can do its usual matching.
+
+use std::{io, mem};
+use std::sync::Arc;
+use std::net::SocketAddr;
+use base64;
+use bytes::Bytes;
+use futures::{Async, AsyncSink, Future, Poll, StartSend, Stream};
+use h2;
+use h2::client::{ResponseFuture, SendRequest};
+use h2::RecvStream;
+use http::{Method, Request, StatusCode};
+use http::header::{ACCEPT, CONTENT_TYPE};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor;
+use ::bits::MessageBuf;
+use super::channel::Channel;
+use super::conf::{DohConf, TlsConf};
+use super::observer::Protocol;
+use super::request::{TransportHandle, TransportRequest};
+use super::server::ServerInfo;
+use super::tls::connect_tls;
+use super::transport::{TransportMode, spawn_transport};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The media type for DNS messages.
+const DNS_MESSAGE: &'static str = "application/dns-message";
+
+
+//------------ doh_transport -------------------------------------------------
+
+/// Spawns a new DNS-over-HTTPS transport for the given server config.
+///
+/// Returns the transport handle for the transport or `None` if the server
+/// doesn’t have a DoH config or its TCP mode is `None`. Unless stated
+/// otherwise, requests are multiplexed over a single connection.
+pub fn doh_transport(reactor: &reactor::Handle, info: &Arc<ServerInfo>)
+                     -> Option<TransportHandle> {
+    let conf = info.conf();
+    let doh = match conf.doh {
+        Some(ref doh) => doh.clone(),
+        None => return None
+    };
+    let mode = match TransportMode::resolve(conf.tcp,
+                                            Some(TransportMode::Multiplex)) {
+        Some(mode) => mode,
+        None => return None,
+    };
+    let channel = DohChannel::new(conf.addr, doh, conf.tls.clone(),
+                                  reactor.clone());
+    Some(spawn_transport(reactor, channel, mode, info, Protocol::Https))
+}
+
+
+//------------ DohChannel ----------------------------------------------------
+
+/// A channel sending DNS messages over HTTP/2.
+struct DohChannel {
+    /// The address of the server.
+    addr: SocketAddr,
+
+    /// The URI template and method.
+    doh: DohConf,
+
+    /// How to authenticate the server or `None` for plain HTTP.
+    tls: Option<TlsConf>,
+
+    /// A handle to the reactor for connecting.
+    handle: reactor::Handle,
+
+    /// The state of the HTTP/2 connection.
+    conn: ConnState,
+
+    /// The transport request waiting to be sent, if any.
+    wr: Option<TransportRequest>,
+
+    /// The HTTP requests we are waiting for responses to.
+    streams: Vec<DohStream>,
+
+    /// The message IDs and errors of requests whose streams failed.
+    failed: Vec<(u16, io::Error)>,
+}
+
+impl DohChannel {
+    /// Creates a new channel.
+    fn new(addr: SocketAddr, doh: DohConf, tls: Option<TlsConf>,
+           handle: reactor::Handle) -> Self {
+        DohChannel {
+            addr: addr,
+            doh: doh,
+            tls: tls,
+            handle: handle,
+            conn: ConnState::Idle,
+            wr: None,
+            streams: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Starts establishing an HTTP/2 connection.
+    ///
+    /// The connection itself is spawned into the reactor. We only keep the
+    /// handle for sending requests.
+    fn connect(&self) -> ConnState {
+        let handle = self.handle.clone();
+        let client = match self.tls {
+            Some(ref tls) => {
+                Box::new(
+                    connect_tls(&self.addr, tls, &["h2"], &self.handle)
+                    .and_then(|sock| {
+                        h2::client::handshake(sock).map_err(h2_error)
+                    })
+                    .map(move |(client, conn)| {
+                        handle.spawn(conn.map_err(|_| ()));
+                        client
+                    })
+                ) as ConnectFuture
+            }
+            None => {
+                Box::new(
+                    TcpStream::connect(&self.addr, &self.handle)
+                    .and_then(|sock| {
+                        h2::client::handshake(sock).map_err(h2_error)
+                    })
+                    .map(move |(client, conn)| {
+                        handle.spawn(conn.map_err(|_| ()));
+                        client
+                    })
+                )
+            }
+        };
+        ConnState::Connecting(client)
+    }
+
+    /// Tries to make sure there is a connection ready for sending.
+    ///
+    /// If an established connection turns out to have been closed, for
+    /// instance because the server shut it down, a new one is started.
+    fn poll_conn(&mut self) -> Poll<(), io::Error> {
+        loop {
+            let next = match self.conn {
+                ConnState::Idle => return Ok(Async::NotReady),
+                ConnState::Connecting(ref mut fut) => {
+                    ConnState::Active(try_ready!(fut.poll()))
+                }
+                ConnState::Active(ref mut client) => {
+                    match client.poll_ready() {
+                        Ok(res) => return Ok(res),
+                        Err(_) => {
+                            // The streams died with the connection.
+                            for stream in self.streams.drain(..) {
+                                self.failed.push((stream.id, io::Error::new(
+                                    io::ErrorKind::ConnectionAborted,
+                                    "connection closed"
+                                )));
+                            }
+                            ConnState::Idle
+                        }
+                    }
+                }
+            };
+            self.conn = match next {
+                ConnState::Idle => self.connect(),
+                next => next
+            };
+        }
+    }
+
+    /// Turns a DNS request into an HTTP request and sends it.
+    ///
+    /// Returns the message ID of the DNS request and the future for the
+    /// HTTP response.
+    fn send_request(&mut self, request: &TransportRequest)
+                    -> Result<(u16, ResponseFuture), io::Error> {
+        let mut msg = request.message().dgram_bytes().to_vec();
+        let id = (msg[0] as u16) << 8 | msg[1] as u16;
+        msg[0] = 0;
+        msg[1] = 0;
+
+        let builder = if self.doh.use_get {
+            let dns = base64::encode_config(&msg, base64::URL_SAFE_NO_PAD);
+            Request::builder().method(Method::GET)
+                              .uri(self.doh.uri(Some(&dns)))
+                              .header(ACCEPT, DNS_MESSAGE)
+                              .body(())
+        }
+        else {
+            Request::builder().method(Method::POST)
+                              .uri(self.doh.uri(None))
+                              .header(ACCEPT, DNS_MESSAGE)
+                              .header(CONTENT_TYPE, DNS_MESSAGE)
+                              .body(())
+        };
+        let http_request = try!(builder.map_err(|err| {
+            io::Error::new(io::ErrorKind::Other, err)
+        }));
+        let client = match self.conn {
+            ConnState::Active(ref mut client) => client,
+            _ => panic!("sending without a connection")
+        };
+        let (response, mut stream)
+            = try!(client.send_request(http_request, self.doh.use_get)
+                         .map_err(h2_error));
+        if !self.doh.use_get {
+            try!(stream.send_data(Bytes::from(msg), true).map_err(h2_error));
+        }
+        Ok((id, response))
+    }
+}
+
+
+//--- Channel
+
+impl Channel for DohChannel {
+    fn start_send(&mut self, request: TransportRequest)
+                  -> StartSend<TransportRequest, io::Error> {
+        if self.wr.is_some() {
+            return Ok(AsyncSink::NotReady(request))
+        }
+        self.wr = Some(request);
+        if let ConnState::Idle = self.conn {
+            self.conn = self.connect()
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_send(&mut self) -> Poll<Option<TransportRequest>, io::Error> {
+        let request = match self.wr.take() {
+            Some(request) => request,
+            None => return Ok(Async::Ready(None))
+        };
+        match self.poll_conn() {
+            Ok(Async::Ready(())) => { }
+            Ok(Async::NotReady) => {
+                self.wr = Some(request);
+                return Ok(Async::NotReady)
+            }
+            Err(err) => {
+                self.wr = Some(request);
+                return Err(err)
+            }
+        }
+        let (id, response) = match self.send_request(&request) {
+            Ok(res) => res,
+            Err(err) => {
+                let kind = err.kind();
+                request.fail(err.into());
+                return Err(io::Error::new(kind, "sending request failed"))
+            }
+        };
+        self.streams.push(DohStream {
+            id: id,
+            state: StreamState::Head(response)
+        });
+        Ok(Async::Ready(Some(request)))
+    }
+
+    fn poll_recv(&mut self) -> Poll<MessageBuf, io::Error> {
+        let mut i = 0;
+        while i < self.streams.len() {
+            match self.streams[i].poll() {
+                Ok(Async::NotReady) => i += 1,
+                Ok(Async::Ready(response)) => {
+                    self.streams.swap_remove(i);
+                    return Ok(Async::Ready(response))
+                }
+                Err(err) => {
+                    // Only this request is lost. Remember it so the
+                    // transport can fail it via `poll_failed()`.
+                    let stream = self.streams.swap_remove(i);
+                    self.failed.push((stream.id, err));
+                }
+            }
+        }
+        Ok(Async::NotReady)
+    }
+
+    fn poll_failed(&mut self) -> Option<(u16, io::Error)> {
+        self.failed.pop()
+    }
+
+    fn sleep(&mut self) -> Result<(), io::Error> {
+        self.conn = ConnState::Idle;
+        self.wr = None;
+        self.streams.clear();
+        self.failed.clear();
+        Ok(())
+    }
+}
+
+
+//------------ ConnState -----------------------------------------------------
+
+/// The future resolving into a new HTTP/2 connection.
+type ConnectFuture = Box<Future<Item=SendRequest<Bytes>, Error=io::Error>>;
+
+/// The state of the HTTP/2 connection of a channel.
+enum ConnState {
+    /// There is no connection.
+    Idle,
+
+    /// The connection is currently being established.
+    Connecting(ConnectFuture),
+
+    /// The connection is established.
+    Active(SendRequest<Bytes>),
+}
+
+
+//------------ DohStream -----------------------------------------------------
+
+/// An HTTP request waiting for its response.
+struct DohStream {
+    /// The message ID of the DNS request.
+    id: u16,
+
+    /// How far along the response is.
+    state: StreamState,
+}
+
+/// The state of an HTTP response.
+enum StreamState {
+    /// Waiting for the response head.
+    Head(ResponseFuture),
+
+    /// Collecting the response body.
+    Body(RecvStream, Vec<u8>),
+}
+
+impl DohStream {
+    /// Polls for the complete response message.
+    fn poll(&mut self) -> Poll<MessageBuf, io::Error> {
+        loop {
+            let next = match self.state {
+                StreamState::Head(ref mut response) => {
+                    let response = try_ready!(response.poll()
+                                                      .map_err(h2_error));
+                    if response.status() != StatusCode::OK {
+                        return Err(io::Error::new(io::ErrorKind::Other,
+                                                  "unexpected HTTP status"))
+                    }
+                    let is_dns = match response.headers().get(CONTENT_TYPE) {
+                        Some(value) => value == DNS_MESSAGE,
+                        None => false
+                    };
+                    if !is_dns {
+                        return Err(io::Error::new(io::ErrorKind::Other,
+                                                  "unexpected content type"))
+                    }
+                    StreamState::Body(response.into_body(), Vec::new())
+                }
+                StreamState::Body(ref mut body, ref mut buf) => {
+                    match try_ready!(body.poll().map_err(h2_error)) {
+                        Some(data) => {
+                            try!(body.release_capacity()
+                                     .release_capacity(data.len())
+                                     .map_err(h2_error));
+                            if buf.len() + data.len() > 0xFFFF {
+                                return Err(io::Error::new(
+                                    io::ErrorKind::Other,
+                                    "response too long"
+                                ))
+                            }
+                            buf.extend_from_slice(&data);
+                            continue
+                        }
+                        None => {
+                            let buf = mem::replace(buf, Vec::new());
+                            let mut msg = try!(MessageBuf::from_vec(buf)
+                                                    .map_err(io::Error::from));
+                            msg.header_mut().set_id(self.id);
+                            return Ok(Async::Ready(msg))
+                        }
+                    }
+                }
+            };
+            self.state = next;
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Converts an HTTP/2 error into an IO error.
+fn h2_error(err: h2::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+    use base64;
+    use futures::{Future, Stream};
+    use futures::future;
+    use h2;
+    use http::{Method, Response, StatusCode};
+    use http::header::CONTENT_TYPE;
+    use tokio_core::net::TcpListener;
+    use tokio_core::reactor::Core;
+    use ::bits::DNameBuf;
+    use ::iana::Rtype;
+    use super::super::{ResolvConf, Resolver};
+    use super::super::conf::{DohConf, ServerConf};
+    use super::super::mock::answer_a;
+    use super::*;
+
+    #[test]
+    fn template() {
+        let doh = DohConf::new("https://dns.example.com/dns-query{?dns}")
+                          .unwrap();
+        assert!(doh.is_https());
+        assert_eq!(doh.host(), "dns.example.com");
+        assert_eq!(doh.uri(None), "https://dns.example.com/dns-query");
+        assert_eq!(doh.uri(Some("AAAB")),
+                   "https://dns.example.com/dns-query?dns=AAAB");
+
+        let doh = DohConf::new("http://[::1]:8053/q?ct{&dns}").unwrap();
+        assert!(!doh.is_https());
+        assert_eq!(doh.host(), "::1");
+        assert_eq!(doh.uri(Some("AAAB")), "http://[::1]:8053/q?ct&dns=AAAB");
+
+        assert!(DohConf::new("dns.example.com/dns-query").is_err());
+        assert!(DohConf::new("https://dns.example.com").is_err());
+        assert!(DohConf::new("https://example.com/q{?foo}").is_err());
+    }
+
+    /// Runs a query against a local plain HTTP/2 server.
+    ///
+    /// The server answers a single request with an A record after
+    /// checking the request. The HTTP response carries `status`.
+    fn query_local(use_get: bool, status: StatusCode) -> bool {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                                   0);
+        let listener = TcpListener::bind(&addr, &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_handle = handle.clone();
+        let server = listener.incoming().into_future()
+            .map_err(|_| ())
+            .and_then(|(sock, _)| {
+                h2::server::handshake(sock.unwrap().0).map_err(|_| ())
+            })
+            .and_then(move |conn| {
+                conn.map_err(|_| ()).for_each(move |(request, mut respond)| {
+                    let (parts, body) = request.into_parts();
+                    let message = if use_get {
+                        assert_eq!(parts.method, Method::GET);
+                        let query = parts.uri.query().unwrap();
+                        assert!(query.starts_with("dns="));
+                        let msg = base64::decode_config(
+                            &query[4..], base64::URL_SAFE_NO_PAD
+                        ).unwrap();
+                        Box::new(future::ok(msg))
+                            as Box<Future<Item=Vec<u8>, Error=()>>
+                    }
+                    else {
+                        assert_eq!(parts.method, Method::POST);
+                        assert_eq!(parts.headers[CONTENT_TYPE], DNS_MESSAGE);
+                        Box::new(body.concat2().map(|body| body.to_vec())
+                                     .map_err(|_| ()))
+                    };
+                    server_handle.spawn(message.map(move |msg| {
+                        assert_eq!(parts.uri.path(), "/dns-query");
+                        assert_eq!(&msg[..2], b"\0\0");
+                        let msg = answer_a(msg);
+                        let response = Response::builder()
+                                           .status(status)
+                                           .header(CONTENT_TYPE, DNS_MESSAGE)
+                                           .body(()).unwrap();
+                        let mut stream = respond.send_response(response,
+                                                               false)
+                                                .unwrap();
+                        stream.send_data(msg.into(), true).unwrap();
+                    }));
+                    Ok(())
+                })
+            });
+        handle.spawn(server);
+
+        let template = format!("http://localhost:{}/dns-query{{?dns}}",
+                               addr.port());
+        let mut doh = DohConf::new(&template).unwrap();
+        doh.use_get = use_get;
+        let mut conf = ResolvConf::new();
+        conf.servers.push(ServerConf::new_doh(addr, doh));
+        conf.attempts = 1;
+        conf.timeout = Duration::from_secs(10);
+        conf.finalize();
+        let resolver = Resolver::from_conf(&handle, conf);
+        let query = resolver.query(
+            (DNameBuf::from_str("example.com.").unwrap(), Rtype::A)
+        );
+        core.run(query).is_ok()
+    }
+
+    #[test]
+    fn local_server() {
+        assert!(query_local(false, StatusCode::OK));
+        assert!(query_local(true, StatusCode::OK));
+    }
+
+    #[test]
+    fn failed_stream() {
+        // The request fails right away rather than timing out.
+        let start = Instant::now();
+        assert!(!query_local(false, StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
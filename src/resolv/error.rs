@@ -4,63 +4,153 @@ use std::error;
 use std::io;
 use std::fmt;
 use std::result;
-use ::bits::{ComposeError, ParseError};
-use ::iana::Rcode;
+use ::bits::{ComposeError, MessageBuf, ParseError, ParsedDName, Record};
+use ::iana::{Rcode, Rtype};
+use ::rdata::parsed::Soa;
+use super::conf::ServerConf;
 
 
 //------------ Error ---------------------------------------------------------
 
 /// An error happened during a query.
 ///
+/// What went wrong is described by the error’s [`kind()`]. If the error
+/// was caused by a negative response, such as NXDOMAIN or NODATA, the
+/// response itself, including the SOA record from its authority section
+/// and the server that sent it, is available via [`response()`]. All
+/// requests to servers that failed on the way there are available via
+/// [`attempts()`].
+///
+/// When two errors are merged, for instance for the A and AAAA queries
+/// of a host lookup, the resulting error has the kind of the more
+/// relevant of the two but keeps the responses and attempts of both.
+///
+/// [`kind()`]: #method.kind
+/// [`response()`]: #method.response
+/// [`attempts()`]: #method.attempts
+//
 //  XXX While this type is currently used all over the resolver, it really
 //      is the error that is to be produced by lookups. We need to refactor
 //      this a bit and create specific error types for the various stages
 //      of processing.
 #[derive(Debug)]
-pub enum Error {
-    /// The question was broken.
-    Question(ComposeError),
-
-    /// All queries timed out.
-    Timeout,
+pub struct Error {
+    /// What kind of error this is.
+    kind: ErrorKind,
 
-    /// All responses for a query were negative.
-    NoName,
+    /// The underlying error for `Question` and `Io` kinds, if available.
+    cause: Option<Cause>,
 
-    /// At least one response was received but none was secure.
-    NoSecureAnswers,
+    /// The negative responses received.
+    ///
+    /// The response that caused the error’s kind comes first.
+    responses: Vec<NegativeResponse>,
 
-    /// At least one response was received but all were bogus.
-    AllBogusAnswers,
+    /// The failed requests to servers.
+    attempts: Vec<Attempt>,
+}
 
-    /// An IO error stopped queries from succeeding at all.
+/// The underlying error of an `Error`.
+#[derive(Debug)]
+enum Cause {
+    Question(ComposeError),
     Io(io::Error),
 }
 
-
 impl Error {
-    /// Finds the most appropriate error for two failed queries.
-    #[allow(match_same_arms)]
-    pub fn merge(self, other: Self) -> Self {
-        use self::Error::*;
+    /// Creates a new error of the given kind.
+    pub fn new(kind: ErrorKind) -> Self {
+        Error {
+            kind: kind,
+            cause: None,
+            responses: Vec::new(),
+            attempts: Vec::new(),
+        }
+    }
 
-        match (self, other) {
-            (Question(err), _) => Question(err),
+    /// Creates an error from a negative response.
+    ///
+    /// The kind of the error is determined from the response by
+    /// [`ErrorKind::of_response()`]. If the response isn’t negative at all,
+    /// it is given back.
+    ///
+    /// [`ErrorKind::of_response()`]: enum.ErrorKind.html#method.of_response
+    pub fn from_response(response: MessageBuf, server: Option<ServerConf>)
+                         -> result::Result<MessageBuf, Self> {
+        match ErrorKind::of_response(&response) {
+            Some(kind) => {
+                let mut res = Self::new(kind);
+                res.responses.push(NegativeResponse {
+                    response: response,
+                    server: server
+                });
+                Err(res)
+            }
+            None => Ok(response)
+        }
+    }
 
-            (Timeout, Io(_)) => Timeout,
-            (Timeout, other) => other,
+    /// Creates an error for a failed request to a server.
+    ///
+    /// The error will have the kind of `error` and a single attempt.
+    pub fn from_attempt(server: ServerConf, error: Error) -> Self {
+        let mut res = Self::new(error.kind);
+        res.attempts.push(Attempt { server: server, error: error });
+        res
+    }
 
-            (NoName, NoSecureAnswers) => NoSecureAnswers,
-            (NoName, AllBogusAnswers) => AllBogusAnswers,
-            (NoName, _) => NoName,
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
 
-            (NoSecureAnswers, _) => NoSecureAnswers,
+    /// Returns the negative response that caused the error if there is one.
+    pub fn response(&self) -> Option<&NegativeResponse> {
+        self.responses.first()
+    }
+
+    /// Returns all negative responses collected by the error.
+    ///
+    /// Errors merged from several queries may have more than one.
+    pub fn responses(&self) -> &[NegativeResponse] {
+        &self.responses
+    }
+
+    /// Returns the failed requests to servers.
+    pub fn attempts(&self) -> &[Attempt] {
+        &self.attempts
+    }
+
+    /// Adds failed requests to the error.
+    ///
+    /// The attempts are added in front of those already present since
+    /// they are assumed to have happened earlier.
+    pub fn add_attempts(&mut self, attempts: Vec<Attempt>) {
+        let mut attempts = attempts;
+        attempts.append(&mut self.attempts);
+        self.attempts = attempts;
+    }
 
-            (AllBogusAnswers, NoSecureAnswers) => NoSecureAnswers,
-            (AllBogusAnswers, _) => AllBogusAnswers,
+    /// Trades the error for its failed requests to servers.
+    pub fn into_attempts(self) -> Vec<Attempt> {
+        self.attempts
+    }
 
-            (Io(_), other) => other
+    /// Finds the most appropriate error for two failed queries.
+    ///
+    /// The resulting error has the kind and underlying error of the more
+    /// relevant error but keeps the negative responses and attempts of
+    /// both. If both are equally relevant, `self` wins.
+    pub fn merge(self, other: Self) -> Self {
+        let (mut first, mut second) = if other.kind.rank() > self.kind.rank() {
+            (other, self)
         }
+        else {
+            (self, other)
+        };
+        first.responses.append(&mut second.responses);
+        first.attempts.append(&mut second.attempts);
+        first
     }
 }
 
@@ -69,15 +159,10 @@ impl Error {
 
 impl error::Error for Error {
     fn description(&self) -> &str {
-        use self::Error::*;
-
-        match *self {
-            Question(ref error) => error.description(),
-            NoName => "all responses were negative",
-            Timeout => "all queries timed out",
-            NoSecureAnswers => "no received response was secure",
-            AllBogusAnswers => "all received responses were bogus",
-            Io(ref error) => error.description()
+        match self.cause {
+            Some(Cause::Question(ref error)) => error.description(),
+            Some(Cause::Io(ref error)) => error.description(),
+            None => self.kind.description()
         }
     }
 }
@@ -85,30 +170,31 @@ impl error::Error for Error {
 
 //--- From
 
-impl From<Rcode> for Error {
-    fn from(rcode: Rcode) -> Error {
-        match rcode {
-            Rcode::NXDomain => Error::NoName,
-            _ => Error::Timeout,
-        }
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind)
     }
 }
 
 impl From<ComposeError> for Error {
     fn from(error: ComposeError) -> Error {
-        Error::Question(error)
+        let mut res = Error::new(ErrorKind::Question);
+        res.cause = Some(Cause::Question(error));
+        res
     }
 }
 
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Error {
-        Error::Io(error)
+        let mut res = Error::new(ErrorKind::Io);
+        res.cause = Some(Cause::Io(error));
+        res
     }
 }
 
 impl From<ParseError> for Error {
     fn from(error: ParseError) -> Error {
-        Error::Io(io::Error::from(error))
+        io::Error::from(error).into()
     }
 }
 
@@ -132,8 +218,195 @@ impl fmt::Display for Error {
 }
 
 
+//------------ ErrorKind -----------------------------------------------------
+
+/// The kind of an error.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// The question was broken.
+    Question,
+
+    /// All queries timed out or no server gave a usable answer.
+    Timeout,
+
+    /// The queried name does not exist.
+    ///
+    /// This is the NXDOMAIN response code.
+    NoName,
+
+    /// The queried name exists but has no records of the requested type.
+    ///
+    /// This is a NOERROR response without any such records in its answer
+    /// section, commonly called NODATA.
+    NoData,
+
+    /// The server answered with an error response code.
+    ///
+    /// This is any response code other than NOERROR or NXDOMAIN, for
+    /// instance SERVFAIL or REFUSED.
+    Rcode,
+
+    /// At least one response was received but none was secure.
+    NoSecureAnswers,
+
+    /// At least one response was received but all were bogus.
+    AllBogusAnswers,
+
+    /// An IO error stopped queries from succeeding at all.
+    Io,
+}
+
+impl ErrorKind {
+    /// Determines the error kind of a final response.
+    ///
+    /// Returns `None` if the response is positive, i.e., if it has the
+    /// NOERROR response code and contains at least one record of the
+    /// type asked for in its answer section. CNAME records only count if
+    /// CNAME records were asked for. A response to an ANY query is
+    /// positive if it has any answer at all.
+    pub fn of_response(response: &MessageBuf) -> Option<Self> {
+        match response.header().rcode() {
+            Rcode::NoError => { }
+            Rcode::NXDomain => return Some(ErrorKind::NoName),
+            _ => return Some(ErrorKind::Rcode)
+        }
+        let qtype = match response.first_question() {
+            Some(question) => question.qtype(),
+            None => return None
+        };
+        let answer = match response.answer() {
+            Ok(answer) => answer,
+            Err(_) => return None
+        };
+        for record in answer {
+            match record {
+                Ok(record) => {
+                    if qtype == Rtype::Any || record.rtype() == qtype {
+                        return None
+                    }
+                }
+                Err(_) => return None
+            }
+        }
+        Some(ErrorKind::NoData)
+    }
+
+    /// Returns how relevant an error of this kind is.
+    ///
+    /// When merging errors, the kind with the higher rank wins.
+    fn rank(self) -> u8 {
+        match self {
+            ErrorKind::Io => 0,
+            ErrorKind::Timeout => 1,
+            ErrorKind::Rcode => 2,
+            ErrorKind::NoName => 3,
+            ErrorKind::NoData => 4,
+            ErrorKind::AllBogusAnswers => 5,
+            ErrorKind::NoSecureAnswers => 6,
+            ErrorKind::Question => 7,
+        }
+    }
+
+    /// Returns a description of the error kind.
+    fn description(self) -> &'static str {
+        match self {
+            ErrorKind::Question => "the question was broken",
+            ErrorKind::Timeout => "all queries timed out",
+            ErrorKind::NoName => "the name does not exist",
+            ErrorKind::NoData => "no records of the requested type",
+            ErrorKind::Rcode => "the server answered with an error",
+            ErrorKind::NoSecureAnswers => "no received response was secure",
+            ErrorKind::AllBogusAnswers => "all received responses were bogus",
+            ErrorKind::Io => "an IO error occurred",
+        }
+    }
+}
+
+
+//------------ NegativeResponse ----------------------------------------------
+
+/// A negative response that caused an error.
+#[derive(Clone, Debug)]
+pub struct NegativeResponse {
+    /// The response as received.
+    response: MessageBuf,
+
+    /// The server that sent the response.
+    server: Option<ServerConf>,
+}
+
+impl NegativeResponse {
+    /// Returns the response message.
+    pub fn message(&self) -> &MessageBuf {
+        &self.response
+    }
+
+    /// Trades the value for the response message.
+    pub fn into_message(self) -> MessageBuf {
+        self.response
+    }
+
+    /// Returns the response code of the response.
+    pub fn rcode(&self) -> Rcode {
+        self.response.header().rcode()
+    }
+
+    /// Returns the SOA record from the authority section of the response.
+    ///
+    /// Per RFC 2308, negative responses should contain the SOA record of
+    /// the zone, its minimum field giving the time the negative answer
+    /// may be cached for. Returns `None` if there is no such record or
+    /// the section is broken.
+    pub fn soa(&self) -> Option<Record<ParsedDName, Soa>> {
+        let authority = match self.response.authority() {
+            Ok(authority) => authority,
+            Err(_) => return None
+        };
+        for record in authority.limit_to::<Soa>() {
+            if let Ok(record) = record {
+                return Some(record)
+            }
+        }
+        None
+    }
+
+    /// Returns the configuration of the server that sent the response.
+    ///
+    /// This is `None` if the response wasn’t received from one of the
+    /// configured servers, for instance because it was taken from the
+    /// cache or found through iterative resolution.
+    pub fn server(&self) -> Option<&ServerConf> {
+        self.server.as_ref()
+    }
+}
+
+
+//------------ Attempt -------------------------------------------------------
+
+/// A failed request to a server.
+#[derive(Debug)]
+pub struct Attempt {
+    /// The configuration of the server the request was sent to.
+    server: ServerConf,
+
+    /// What went wrong.
+    error: Error,
+}
+
+impl Attempt {
+    /// Returns the configuration of the server asked.
+    pub fn server(&self) -> &ServerConf {
+        &self.server
+    }
+
+    /// Returns the error that ended the request.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+}
+
+
 //------------ Result --------------------------------------------------------
 
 /// The result type of a query.
 pub type Result<T> = result::Result<T, Error>;
-
@@ -0,0 +1,199 @@
+//! Root hints for iterative resolution.
+//!
+//! When resolving iteratively, the resolver starts out by asking one of
+//! the name servers of the root zone. Which servers these are is given by
+//! the *root hints,* represented by the [`RootHints`] type. The hints can
+//! either be the built-in set of root servers or be read from a zone file
+//! such as the `named.root` file published by IANA.
+//!
+//! [`RootHints`]: struct.RootHints.html
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::slice;
+use std::str::FromStr;
+use ::bits::{DNameBuf, DNameSlice};
+use ::master::{ScanResult, Scanner};
+use ::master::reader::{Reader, ReaderItem};
+use ::rdata::MasterRecordData;
+
+
+//------------ RootHints -----------------------------------------------------
+
+/// The name servers to start iterative resolution with.
+///
+/// A value of this type is a list of [`HintServer`]s, each of which has
+/// a name and a number of addresses. Only servers with at least one address
+/// are useful, so servers without addresses are skipped when reading hints
+/// from a zone file.
+///
+/// [`HintServer`]: struct.HintServer.html
+#[derive(Clone, Debug, Default)]
+pub struct RootHints {
+    /// The servers in the order they were added.
+    servers: Vec<HintServer>,
+}
+
+impl RootHints {
+    /// Creates a new, empty set of hints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the built-in root hints.
+    ///
+    /// These are the thirteen root servers and their IPv4 and IPv6
+    /// addresses.
+    pub fn builtin() -> Self {
+        let mut res = Self::new();
+        for &(name, v4, v6) in ROOT_SERVERS {
+            let name = DNameBuf::from_str(name).unwrap();
+            let v4 = Ipv4Addr::from_str(v4).unwrap();
+            let v6 = Ipv6Addr::from_str(v6).unwrap();
+            res.push(name.clone(), IpAddr::V4(v4));
+            res.push(name, IpAddr::V6(v6));
+        }
+        res
+    }
+
+    /// Reads root hints from the zone file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> ScanResult<Self> {
+        Self::from_reader(Reader::open(path)?)
+    }
+
+    /// Reads root hints from a master file reader.
+    ///
+    /// The name servers are taken from the NS records of the root zone in
+    /// the order they appear. Their addresses are taken from A and AAAA
+    /// records. All other records are ignored as are include directives.
+    pub fn from_reader<S: Scanner>(reader: Reader<S>) -> ScanResult<Self> {
+        let mut names = Vec::new();
+        let mut addrs = Vec::new();
+        for item in reader {
+            let record = match item? {
+                ReaderItem::Record(record) => record,
+                ReaderItem::Include { .. } => continue
+            };
+            match record.rdata {
+                MasterRecordData::Ns(ref ns) => {
+                    if **record.owner == *DNameSlice::root() {
+                        names.push(ns.nsdname().clone())
+                    }
+                }
+                MasterRecordData::A(ref a) => {
+                    addrs.push((record.owner.clone(), IpAddr::V4(a.addr())))
+                }
+                MasterRecordData::Aaaa(ref aaaa) => {
+                    addrs.push((record.owner.clone(), IpAddr::V6(aaaa.addr())))
+                }
+                _ => { }
+            }
+        }
+        let mut res = Self::new();
+        for name in names {
+            for &(ref owner, addr) in &addrs {
+                if **owner == name {
+                    res.push(name.clone(), addr)
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Adds an address for the server `name`.
+    ///
+    /// If the server is already known, the address is added to its list of
+    /// addresses. Otherwise, a new server is appended.
+    pub fn push(&mut self, name: DNameBuf, addr: IpAddr) {
+        if let Some(server) = self.servers.iter_mut()
+                                          .find(|server| server.name == name) {
+            if !server.addrs.contains(&addr) {
+                server.addrs.push(addr)
+            }
+            return
+        }
+        self.servers.push(HintServer { name: name, addrs: vec![addr] })
+    }
+
+    /// Returns an iterator over the servers.
+    pub fn iter(&self) -> slice::Iter<HintServer> {
+        self.servers.iter()
+    }
+
+    /// Returns whether there are no servers.
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+}
+
+
+//------------ HintServer ----------------------------------------------------
+
+/// A name server from the root hints.
+#[derive(Clone, Debug)]
+pub struct HintServer {
+    /// The name of the server.
+    pub name: DNameBuf,
+
+    /// The addresses of the server.
+    pub addrs: Vec<IpAddr>,
+}
+
+
+//------------ ROOT_SERVERS --------------------------------------------------
+
+/// The built-in root servers with their IPv4 and IPv6 addresses.
+const ROOT_SERVERS: &[(&str, &str, &str)] = &[
+    ("a.root-servers.net.", "198.41.0.4", "2001:503:ba3e::2:30"),
+    ("b.root-servers.net.", "170.247.170.2", "2801:1b8:10::b"),
+    ("c.root-servers.net.", "192.33.4.12", "2001:500:2::c"),
+    ("d.root-servers.net.", "199.7.91.13", "2001:500:2d::d"),
+    ("e.root-servers.net.", "192.203.230.10", "2001:500:a8::e"),
+    ("f.root-servers.net.", "192.5.5.241", "2001:500:2f::f"),
+    ("g.root-servers.net.", "192.112.36.4", "2001:500:12::d0d"),
+    ("h.root-servers.net.", "198.97.190.53", "2001:500:1::53"),
+    ("i.root-servers.net.", "192.36.148.17", "2001:7fe::53"),
+    ("j.root-servers.net.", "192.58.128.30", "2001:503:c27::2:30"),
+    ("k.root-servers.net.", "193.0.14.129", "2001:7fd::1"),
+    ("l.root-servers.net.", "199.7.83.42", "2001:500:9f::42"),
+    ("m.root-servers.net.", "202.12.27.33", "2001:dc3::35"),
+];
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use ::master::reader::Reader;
+    use super::*;
+
+    #[test]
+    fn builtin() {
+        let hints = RootHints::builtin();
+        assert_eq!(hints.iter().count(), 13);
+        assert!(hints.iter().all(|server| server.addrs.len() == 2));
+    }
+
+    #[test]
+    fn from_reader() {
+        let reader = Reader::create(&b".  3600000  IN  NS  A.ROOT-SERVERS.NET.
+A.ROOT-SERVERS.NET.  3600000  IN  A  198.41.0.4
+A.ROOT-SERVERS.NET.  3600000  IN  AAAA  2001:503:ba3e::2:30
+.  3600000  IN  NS  B.ROOT-SERVERS.NET.
+.  3600000  IN  NS  NOADDR.EXAMPLE.
+B.ROOT-SERVERS.NET.  3600000  IN  A  170.247.170.2
+"[..]);
+        let hints = RootHints::from_reader(reader).unwrap();
+        let servers: Vec<_> = hints.iter().collect();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].name,
+                   DNameBuf::from_str("a.root-servers.net.").unwrap());
+        assert_eq!(servers[0].addrs,
+                   vec![IpAddr::from_str("198.41.0.4").unwrap(),
+                        IpAddr::from_str("2001:503:ba3e::2:30").unwrap()]);
+        assert_eq!(servers[1].addrs,
+                   vec![IpAddr::from_str("170.247.170.2").unwrap()]);
+    }
+}
@@ -0,0 +1,402 @@
+//! The hosts file.
+//!
+//! Before asking the DNS, host name and address lookups consult a static
+//! table mapping addresses to host names. On Unix-y systems, this table
+//! lives in `/etc/hosts`, hence the name. This module contains the type
+//! [`Hosts`] for such a table as well as [`HostsFile`], the reloadable
+//! variant that is part of the resolver configuration.
+//!
+//! The format of the file is that of glibc’s `files` source: each line
+//! starts with an IP address followed by the canonical host name and any
+//! number of aliases, all separated by white space. Everything after a
+//! hash sign is a comment. Lines that can’t be parsed are silently
+//! ignored.
+//!
+//! [`Hosts`]: struct.Hosts.html
+//! [`HostsFile`]: struct.HostsFile.html
+
+use std::{fmt, fs, io};
+use std::io::Read;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use ::bits::{DNameBuf, DNameSlice};
+use super::lookup::addr::FoundAddrs;
+use super::lookup::host::FoundHosts;
+
+
+//------------ Hosts ---------------------------------------------------------
+
+/// The content of a hosts file.
+///
+/// A value of this type is an ordered list of [`HostEntry`]s. You can add
+/// entries by hand via `push()` or parse them from a reader or file. Host
+/// names and addresses are then looked up via the `lookup_host()` and
+/// `lookup_addr()` methods.
+///
+/// [`HostEntry`]: struct.HostEntry.html
+#[derive(Clone, Debug, Default)]
+pub struct Hosts {
+    /// The entries in the order they appeared in.
+    entries: Vec<HostEntry>,
+}
+
+impl Hosts {
+    /// Creates a new, empty hosts table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the entries of the file at `path` to the table.
+    pub fn parse_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let mut file = fs::File::open(path)?;
+        self.parse(&mut file)
+    }
+
+    /// Adds the entries read from `reader` to the table.
+    ///
+    /// Only reading can fail. Lines that aren’t valid entries are skipped.
+    pub fn parse<R: Read>(&mut self, reader: &mut R) -> io::Result<()> {
+        use std::io::BufRead;
+
+        for line in io::BufReader::new(reader).lines() {
+            if let Some(entry) = HostEntry::from_line(&line?) {
+                self.push(entry)
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends an entry to the table.
+    pub fn push(&mut self, entry: HostEntry) {
+        self.entries.push(entry)
+    }
+
+    /// Returns an iterator over the entries of the table.
+    pub fn iter(&self) -> ::std::slice::Iter<HostEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns whether the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Looks up the addresses for a host name.
+    ///
+    /// A relative `name` is treated as if it were absolute. The canonical
+    /// name and aliases are taken from the first entry that has `name` as
+    /// either its canonical name or one of its aliases. The addresses are
+    /// collected from all such entries. Returns `None` if there aren’t any.
+    pub fn lookup_host(&self, name: &DNameSlice) -> Option<FoundHosts> {
+        let name = match absolute_name(name) {
+            Some(name) => name,
+            None => return None
+        };
+        let mut found: Option<(&HostEntry, Vec<IpAddr>)> = None;
+        for entry in self.entries.iter().filter(|e| e.has_name(&name)) {
+            match found {
+                Some((_, ref mut addrs)) => {
+                    if !addrs.contains(&entry.addr) {
+                        addrs.push(entry.addr)
+                    }
+                }
+                None => found = Some((entry, vec![entry.addr]))
+            }
+        }
+        found.map(|(entry, addrs)| {
+            FoundHosts::with_aliases(entry.canonical.clone(),
+                                     entry.aliases.clone(), addrs)
+        })
+    }
+
+    /// Looks up the host names for an address.
+    ///
+    /// Returns the canonical name and aliases of the first entry for
+    /// `addr` or `None` if there is no such entry.
+    pub fn lookup_addr(&self, addr: IpAddr) -> Option<FoundAddrs> {
+        self.entries.iter().find(|entry| entry.addr == addr).map(|entry| {
+            let mut names = vec![entry.canonical.clone()];
+            names.extend(entry.aliases.iter().cloned());
+            FoundAddrs::from_names(names)
+        })
+    }
+}
+
+
+//------------ HostEntry -----------------------------------------------------
+
+/// A single entry of a hosts table.
+#[derive(Clone, Debug)]
+pub struct HostEntry {
+    /// The address of the host.
+    addr: IpAddr,
+
+    /// The canonical name of the host.
+    canonical: DNameBuf,
+
+    /// Other names of the host.
+    aliases: Vec<DNameBuf>,
+}
+
+impl HostEntry {
+    /// Creates a new entry.
+    ///
+    /// Relative names will be made absolute by appending the root label.
+    /// This fails if any of the names is too long for that.
+    pub fn new(addr: IpAddr, canonical: DNameBuf, aliases: Vec<DNameBuf>)
+               -> Option<Self> {
+        let canonical = match absolute_name(&canonical) {
+            Some(name) => name,
+            None => return None
+        };
+        let mut abs_aliases = Vec::with_capacity(aliases.len());
+        for alias in &aliases {
+            match absolute_name(alias) {
+                Some(alias) => abs_aliases.push(alias),
+                None => return None
+            }
+        }
+        Some(HostEntry {
+            addr: addr,
+            canonical: canonical,
+            aliases: abs_aliases
+        })
+    }
+
+    /// Parses an entry from a line of a hosts file.
+    ///
+    /// Returns `None` if the line is empty, a comment, or broken.
+    fn from_line(line: &str) -> Option<Self> {
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => line
+        };
+        let mut words = line.split_whitespace();
+        let addr = match words.next().map(IpAddr::from_str) {
+            Some(Ok(addr)) => addr,
+            _ => return None
+        };
+        let canonical = match words.next().map(DNameBuf::from_str) {
+            Some(Ok(name)) => name,
+            _ => return None
+        };
+        let mut aliases = Vec::new();
+        for word in words {
+            match DNameBuf::from_str(word) {
+                Ok(name) => aliases.push(name),
+                Err(_) => return None
+            }
+        }
+        Self::new(addr, canonical, aliases)
+    }
+
+    /// Returns the address of the entry.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns a reference to the canonical name of the entry.
+    pub fn canonical_name(&self) -> &DNameSlice {
+        &self.canonical
+    }
+
+    /// Returns a reference to the aliases of the entry.
+    pub fn aliases(&self) -> &[DNameBuf] {
+        &self.aliases
+    }
+
+    /// Returns whether the entry has `name` as one of its names.
+    fn has_name(&self, name: &DNameSlice) -> bool {
+        self.canonical.as_slice() == name
+            || self.aliases.iter().any(|alias| alias.as_slice() == name)
+    }
+}
+
+
+//------------ HostsFile -----------------------------------------------------
+
+/// A hosts table loaded from a file.
+///
+/// This is the hosts table used by the resolver. It is created with the
+/// path to a file which is loaded right away. If loading fails, the table
+/// is simply empty. Through `reload()` or `reload_if_modified()` the file
+/// can be loaded again at a later time.
+///
+/// The table is kept behind an arc, so all clones of a value—such as the
+/// ones held by the resolvers created from a configuration—share the same
+/// table and all see the result of a reload.
+#[derive(Clone)]
+pub struct HostsFile {
+    /// The path of the hosts file or `None` if there isn’t one.
+    path: Option<PathBuf>,
+
+    /// The table and the modification time of the file when loaded.
+    inner: Arc<RwLock<(Hosts, Option<SystemTime>)>>,
+}
+
+impl HostsFile {
+    /// Creates a new value without a file.
+    ///
+    /// The table will be empty and reloading will do nothing.
+    pub fn new() -> Self {
+        HostsFile {
+            path: None,
+            inner: Arc::new(RwLock::new((Hosts::new(), None)))
+        }
+    }
+
+    /// Creates a new value for the file at `path` and loads it.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let res = HostsFile {
+            path: Some(path.as_ref().into()),
+            inner: Arc::new(RwLock::new((Hosts::new(), None)))
+        };
+        let _ = res.reload();
+        res
+    }
+
+    /// Creates a new value for the system’s hosts file.
+    ///
+    /// XXX This currently only works for Unix-y systems.
+    pub fn system() -> Self {
+        Self::from_path("/etc/hosts")
+    }
+
+    /// Returns the path of the hosts file if there is one.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(AsRef::as_ref)
+    }
+
+    /// Loads the file again.
+    ///
+    /// If reading the file fails, the table is left unchanged and the error
+    /// is returned.
+    pub fn reload(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(())
+        };
+        let modified = fs::metadata(path)?.modified().ok();
+        let mut hosts = Hosts::new();
+        hosts.parse_file(path)?;
+        *self.inner.write().unwrap() = (hosts, modified);
+        Ok(())
+    }
+
+    /// Loads the file again if it has been modified since the last load.
+    ///
+    /// Returns whether the file was reloaded.
+    pub fn reload_if_modified(&self) -> io::Result<bool> {
+        let modified = match self.path {
+            Some(ref path) => fs::metadata(path)?.modified().ok(),
+            None => return Ok(false)
+        };
+        if modified.is_some() && self.inner.read().unwrap().1 == modified {
+            return Ok(false)
+        }
+        self.reload().map(|_| true)
+    }
+
+    /// Looks up the addresses for a host name.
+    ///
+    /// See [`Hosts::lookup_host()`] for details.
+    ///
+    /// [`Hosts::lookup_host()`]: struct.Hosts.html#method.lookup_host
+    pub fn lookup_host(&self, name: &DNameSlice) -> Option<FoundHosts> {
+        self.inner.read().unwrap().0.lookup_host(name)
+    }
+
+    /// Looks up the host names for an address.
+    ///
+    /// See [`Hosts::lookup_addr()`] for details.
+    ///
+    /// [`Hosts::lookup_addr()`]: struct.Hosts.html#method.lookup_addr
+    pub fn lookup_addr(&self, addr: IpAddr) -> Option<FoundAddrs> {
+        self.inner.read().unwrap().0.lookup_addr(addr)
+    }
+}
+
+
+//--- Default
+
+impl Default for HostsFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+//--- Debug
+
+impl fmt::Debug for HostsFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HostsFile{{path: {:?}, ...}}", self.path)
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns an absolute version of `name`.
+///
+/// Returns `None` if the name is relative and too long to be made absolute.
+fn absolute_name(name: &DNameSlice) -> Option<DNameBuf> {
+    let mut name = name.to_owned();
+    if name.is_relative() && name.append_root().is_err() {
+        return None
+    }
+    Some(name)
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use ::bits::DNameBuf;
+    use super::*;
+
+    #[test]
+    fn parse_and_lookup() {
+        let mut hosts = Hosts::new();
+        let data = "# A comment\n\
+                    127.0.0.1 localhost\n\
+                    ::1       localhost ip6-localhost # trailing\n\
+                    192.0.2.1 host.example.com host\n\
+                    garbage   line\n".to_string();
+        hosts.parse(&mut io::Cursor::new(data)).unwrap();
+        assert_eq!(hosts.iter().count(), 3);
+
+        let found = hosts.lookup_host(&DNameBuf::from_str("LocalHost")
+                                                .unwrap()).unwrap();
+        assert_eq!(found.canonical_name(),
+                   DNameBuf::from_str("localhost.").unwrap().as_slice());
+        assert_eq!(found.iter().collect::<Vec<_>>(),
+                   vec![IpAddr::from_str("127.0.0.1").unwrap(),
+                        IpAddr::from_str("::1").unwrap()]);
+
+        let found = hosts.lookup_host(&DNameBuf::from_str("host.").unwrap())
+                         .unwrap();
+        assert_eq!(found.canonical_name(),
+                   DNameBuf::from_str("host.example.com.").unwrap()
+                                                          .as_slice());
+        assert_eq!(found.aliases(),
+                   &[DNameBuf::from_str("host.").unwrap()]);
+        assert!(hosts.lookup_host(&DNameBuf::from_str("other.").unwrap())
+                     .is_none());
+
+        let found = hosts.lookup_addr(IpAddr::from_str("::1").unwrap())
+                         .unwrap();
+        let names = found.iter().map(|name| name.to_string())
+                         .collect::<Vec<_>>();
+        assert_eq!(names, vec!["localhost.", "ip6-localhost."]);
+        assert!(hosts.lookup_addr(IpAddr::from_str("192.0.2.2").unwrap())
+                     .is_none());
+    }
+}
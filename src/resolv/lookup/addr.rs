@@ -1,6 +1,6 @@
 //! Looking up host names for addresses.
 
-use std::mem;
+use std::{mem, slice};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 use futures::{Async, Future, Poll};
@@ -18,15 +18,19 @@ use super::super::Resolver;
 
 /// Creates a future that resolves into the host names for an IP address. 
 ///
-/// The future will query DNS using the resolver represented by `resolv`.
-/// It will query DNS only and not consider any other database the system
-/// may have.
+/// The future will first consult the hosts file of the resolver
+/// configuration. If the address is found there, the names from the file
+/// are returned. Otherwise, the future will query DNS using the resolver
+/// represented by `resolv`.
 /// 
 /// The value returned upon success can be turned into an iterator over
 /// host names via its `iter()` method. This is due to lifetime issues.
 pub fn lookup_addr(resolv: Resolver, addr: IpAddr) -> LookupAddr {
-    let name = dname_from_addr(addr, resolv.options());
-    LookupAddr(Query::new(resolv, (name, Rtype::Ptr, Class::In)))
+    if let Some(found) = resolv.conf().hosts.lookup_addr(addr) {
+        return LookupAddr(Err(Some(found)))
+    }
+    let name = dname_from_addr(addr, &resolv.options());
+    LookupAddr(Ok(Query::new(resolv, (name, Rtype::Ptr, Class::In))))
 }
 
 
@@ -35,14 +39,30 @@ pub fn lookup_addr(resolv: Resolver, addr: IpAddr) -> LookupAddr {
 /// The future for [`lookup_addr()`].
 ///
 /// [`lookup_addr()`]: fn.lookup_addr.html
-pub struct LookupAddr(Query);
+//
+//  If the address was found in the hosts file, there is nothing to query
+//  and the value contains the result right away. Much like with `Query`,
+//  this is the `Err` variant holding an option so we can take the result
+//  out when polled.
+pub struct LookupAddr(Result<Query, Option<FoundAddrs>>);
 
 impl Future for LookupAddr {
     type Item = FoundAddrs;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        Ok(Async::Ready(FoundAddrs(try_ready!(self.0.poll()))))
+        match self.0 {
+            Ok(ref mut query) => {
+                let response = try_ready!(query.poll());
+                Ok(Async::Ready(FoundAddrs(AddrsSource::Message(response))))
+            }
+            Err(ref mut found) => {
+                match found.take() {
+                    Some(found) => Ok(Async::Ready(found)),
+                    None => panic!("polling a resolved LookupAddr")
+                }
+            }
+        }
     }
 }
 
@@ -53,14 +73,45 @@ impl Future for LookupAddr {
 ///
 /// The only purpose of this type is to return an iterator over host names
 /// via its `iter()` method.
-pub struct FoundAddrs(MessageBuf);
+pub struct FoundAddrs(AddrsSource);
+
+/// Where the host names of a `FoundAddrs` come from.
+enum AddrsSource {
+    /// The response to a PTR query.
+    Message(MessageBuf),
+
+    /// A list of absolute names, such as from the hosts file.
+    Names(Vec<DNameBuf>),
+}
 
 impl FoundAddrs {
+    /// Creates a new value from a list of host names.
+    ///
+    /// The first name should be the canonical name of the host, any
+    /// further names are its aliases. Relative names are dropped.
+    pub fn from_names(mut names: Vec<DNameBuf>) -> Self {
+        names.retain(|name| name.is_absolute());
+        FoundAddrs(AddrsSource::Names(names))
+    }
+
     /// Returns an iterator over the host names.
     pub fn iter(&self) -> FoundAddrsIter {
-        FoundAddrsIter {
-            name: self.0.canonical_name(),
-            answer: self.0.answer().ok().map(|sec| sec.limit_to::<Ptr>())
+        match self.0 {
+            AddrsSource::Message(ref msg) => {
+                FoundAddrsIter {
+                    name: msg.canonical_name(),
+                    answer: msg.answer().ok()
+                               .map(|sec| sec.limit_to::<Ptr>()),
+                    names: None
+                }
+            }
+            AddrsSource::Names(ref names) => {
+                FoundAddrsIter {
+                    name: None,
+                    answer: None,
+                    names: Some(names.iter())
+                }
+            }
         }
     }
 }
@@ -71,7 +122,8 @@ impl FoundAddrs {
 /// An iterator over host names returned by address lookup.
 pub struct FoundAddrsIter<'a> {
     name: Option<ParsedDName<'a>>,
-    answer: Option<RecordIter<'a, Ptr<'a>>>
+    answer: Option<RecordIter<'a, Ptr<'a>>>,
+    names: Option<slice::Iter<'a, DNameBuf>>
 }
 
 impl<'a> Iterator for FoundAddrsIter<'a> {
@@ -79,6 +131,9 @@ impl<'a> Iterator for FoundAddrsIter<'a> {
 
     #[allow(while_let_on_iterator)]
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(ref mut names) = self.names {
+            return names.next().map(|name| ParsedDName::from_slice(name))
+        }
         let name = if let Some(ref name) = self.name { name }
                    else { return None };
         let answer = if let Some(ref mut answer) = self.answer { answer }
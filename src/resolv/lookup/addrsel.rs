@@ -0,0 +1,377 @@
+//! Ordering of destination addresses.
+//!
+//! This module implements the destination address selection of RFC 6724
+//! and the interleaving of address families suggested by Happy Eyeballs,
+//! RFC 8305.
+//!
+//! RFC 6724 relies on knowing which source address would be used for each
+//! destination. We learn this by connecting a UDP socket to the
+//! destination which doesn’t send any packets but makes the system pick
+//! the source address. Since we don’t know about deprecated or home
+//! addresses or the kind of transport used, rules 3, 4, and 7 are skipped.
+
+use std::cmp::Ordering;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+
+//------------ sort_rfc6724 --------------------------------------------------
+
+/// Sorts a list of destination addresses according to RFC 6724.
+///
+/// The source address for each destination is determined by probing the
+/// system via a connected UDP socket.
+pub fn sort_rfc6724(addrs: &mut Vec<IpAddr>) {
+    sort_with_sources(addrs, probe_source)
+}
+
+/// Sorts destination addresses using the given source address lookup.
+///
+/// The closure `source` is called once for each destination and returns
+/// the source address that would be used for it or `None` if the
+/// destination is unreachable.
+fn sort_with_sources<F>(addrs: &mut Vec<IpAddr>, source: F)
+                     where F: Fn(IpAddr) -> Option<IpAddr> {
+    if addrs.len() < 2 {
+        return
+    }
+    let mut dests: Vec<_> = addrs.iter().map(|&addr| {
+        Destination::new(addr, source(addr))
+    }).collect();
+    dests.sort_by(Destination::compare);
+    for (addr, dest) in addrs.iter_mut().zip(dests) {
+        *addr = dest.addr
+    }
+}
+
+
+//------------ interleave ----------------------------------------------------
+
+/// Interleaves the address families of a list of addresses.
+///
+/// The first address stays in place. After it, addresses of the other
+/// family and of its own family alternate for as long as there are both.
+/// Within each family, the order is kept.
+pub fn interleave(addrs: &mut Vec<IpAddr>) {
+    let first_v4 = match addrs.first() {
+        Some(addr) => addr.is_ipv4(),
+        None => return
+    };
+    let (mut first, mut second): (Vec<_>, Vec<_>)
+        = addrs.drain(..).partition(|addr| addr.is_ipv4() == first_v4);
+    let mut first = first.drain(..);
+    let mut second = second.drain(..);
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (left, right) => {
+                addrs.extend(left);
+                addrs.extend(right);
+            }
+        }
+    }
+}
+
+
+//------------ probe_source --------------------------------------------------
+
+/// Returns the source address the system would use for `dest`.
+///
+/// Returns `None` if there is no route to `dest`.
+fn probe_source(dest: IpAddr) -> Option<IpAddr> {
+    let local = match dest {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+    };
+    let sock = match UdpSocket::bind(SocketAddr::new(local, 0)) {
+        Ok(sock) => sock,
+        Err(_) => return None
+    };
+    // The port doesn’t matter since nothing is being sent.
+    if sock.connect(SocketAddr::new(dest, 53)).is_err() {
+        return None
+    }
+    sock.local_addr().ok().map(|addr| addr.ip())
+}
+
+
+//------------ Destination ---------------------------------------------------
+
+/// A destination address with everything needed for comparing it.
+struct Destination {
+    /// The destination address.
+    addr: IpAddr,
+
+    /// The source address used for the destination if it is reachable.
+    source: Option<IpAddr>,
+
+    /// The destination address as an IPv6 address.
+    mapped: Ipv6Addr,
+
+    /// The scope of the destination address.
+    scope: u8,
+
+    /// The precedence and label of the destination address.
+    policy: Policy,
+
+    /// The scope and label of the source address.
+    source_attrs: Option<(u8, u8)>,
+}
+
+impl Destination {
+    /// Creates a new destination from a destination and source address.
+    fn new(addr: IpAddr, source: Option<IpAddr>) -> Self {
+        let mapped = to_ipv6(addr);
+        Destination {
+            addr: addr,
+            source: source,
+            mapped: mapped,
+            scope: scope(&mapped),
+            policy: Policy::lookup(&mapped),
+            source_attrs: source.map(|source| {
+                let source = to_ipv6(source);
+                (scope(&source), Policy::lookup(&source).label)
+            }),
+        }
+    }
+
+    /// Compares two destinations.
+    ///
+    /// Returns `Ordering::Less` if `self` is to be preferred over `other`.
+    fn compare(&self, other: &Self) -> Ordering {
+        // Rule 1: Avoid unusable destinations.
+        match (self.source_attrs, other.source_attrs) {
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => return Ordering::Equal,
+            _ => { }
+        }
+        let (self_scope, self_label) = self.source_attrs.unwrap();
+        let (other_scope, other_label) = other.source_attrs.unwrap();
+
+        // Rule 2: Prefer matching scope.
+        let self_match = self.scope == self_scope;
+        let other_match = other.scope == other_scope;
+        if self_match != other_match {
+            return if self_match { Ordering::Less } else { Ordering::Greater }
+        }
+
+        // Rule 5: Prefer matching label.
+        let self_match = self.policy.label == self_label;
+        let other_match = other.policy.label == other_label;
+        if self_match != other_match {
+            return if self_match { Ordering::Less } else { Ordering::Greater }
+        }
+
+        // Rule 6: Prefer higher precedence.
+        match other.policy.precedence.cmp(&self.policy.precedence) {
+            Ordering::Equal => { }
+            res => return res
+        }
+
+        // Rule 8: Prefer smaller scope.
+        match self.scope.cmp(&other.scope) {
+            Ordering::Equal => { }
+            res => return res
+        }
+
+        // Rule 9: Use longest matching prefix. We only do this for IPv6
+        // as it isn’t very meaningful for IPv4.
+        if let (IpAddr::V6(_), IpAddr::V6(_), Some(IpAddr::V6(self_src)),
+                Some(IpAddr::V6(other_src)))
+                = (self.addr, other.addr, self.source, other.source) {
+            let self_len = common_prefix_len(&self.mapped, &self_src);
+            let other_len = common_prefix_len(&other.mapped, &other_src);
+            return other_len.cmp(&self_len)
+        }
+
+        // Rule 10: Otherwise, leave the order unchanged.
+        Ordering::Equal
+    }
+}
+
+
+//------------ Policy --------------------------------------------------------
+
+/// An entry of the policy table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Policy {
+    /// The precedence of addresses matching the entry.
+    precedence: u8,
+
+    /// The label of addresses matching the entry.
+    label: u8,
+}
+
+/// The default policy table from section 2.1 of RFC 6724.
+///
+/// Each entry consists of the prefix, the prefix length, precedence, and
+/// label. The table is ordered by decreasing prefix length so the first
+/// match is the longest.
+const POLICY_TABLE: &'static [([u8; 16], u8, u8, u8)] = &[
+    // ::1/128
+    ([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1], 128, 50, 0),
+    // ::ffff:0:0/96
+    ([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 0], 96, 35, 4),
+    // ::/96
+    ([0; 16], 96, 1, 3),
+    // 2001::/32
+    ([0x20, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 32, 5, 5),
+    // 2002::/16
+    ([0x20, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 16, 30, 2),
+    // 3ffe::/16
+    ([0x3f, 0xfe, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 16, 1, 12),
+    // fec0::/10
+    ([0xfe, 0xc0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 10, 1, 11),
+    // fc00::/7
+    ([0xfc, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 7, 3, 13),
+    // ::/0
+    ([0; 16], 0, 40, 1),
+];
+
+impl Policy {
+    /// Returns the policy for an address.
+    fn lookup(addr: &Ipv6Addr) -> Self {
+        for &(ref prefix, len, precedence, label) in POLICY_TABLE {
+            if common_prefix_len(addr, &Ipv6Addr::from(*prefix)) >= len {
+                return Policy { precedence: precedence, label: label }
+            }
+        }
+        unreachable!()
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Scope value for link-local addresses.
+const SCOPE_LINK_LOCAL: u8 = 0x02;
+
+/// Scope value for site-local addresses.
+const SCOPE_SITE_LOCAL: u8 = 0x05;
+
+/// Scope value for global addresses.
+const SCOPE_GLOBAL: u8 = 0x0e;
+
+/// Converts an address into an IPv6 address, mapping IPv4 addresses.
+fn to_ipv6(addr: IpAddr) -> Ipv6Addr {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+        IpAddr::V6(addr) => addr
+    }
+}
+
+/// Returns the scope of an address.
+///
+/// IPv4 addresses have to be mapped. Following section 3.2 of RFC 6724,
+/// IPv4 loopback and auto-configuration addresses have link-local scope,
+/// all others global scope.
+fn scope(addr: &Ipv6Addr) -> u8 {
+    let octets = addr.octets();
+    if octets[0] == 0xff {
+        // Multicast addresses carry their scope.
+        return octets[1] & 0x0f
+    }
+    if *addr == Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)
+            || (octets[0] == 0xfe && octets[1] & 0xc0 == 0x80) {
+        return SCOPE_LINK_LOCAL
+    }
+    if octets[0] == 0xfe && octets[1] & 0xc0 == 0xc0 {
+        return SCOPE_SITE_LOCAL
+    }
+    if octets[..12] == [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff] {
+        if octets[12] == 127 || (octets[12] == 169 && octets[13] == 254) {
+            return SCOPE_LINK_LOCAL
+        }
+    }
+    SCOPE_GLOBAL
+}
+
+/// Returns the length of the common prefix of two addresses in bits.
+fn common_prefix_len(left: &Ipv6Addr, right: &Ipv6Addr) -> u8 {
+    let mut res = 0;
+    for (left, right) in left.octets().iter().zip(right.octets().iter()) {
+        let diff = left ^ right;
+        if diff == 0 {
+            res += 8
+        }
+        else {
+            return res + diff.leading_zeros() as u8
+        }
+    }
+    res
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use super::*;
+
+    fn addrs(list: &[&str]) -> Vec<IpAddr> {
+        list.iter().map(|s| IpAddr::from_str(s).unwrap()).collect()
+    }
+
+    /// Sorts `dests` with a fixed set of source addresses.
+    ///
+    /// A destination is reachable if there is a source of the same family.
+    /// The source used is the one with the longest common prefix.
+    fn sort(dests: &[&str], sources: &[&str]) -> Vec<IpAddr> {
+        let sources = addrs(sources);
+        let mut dests = addrs(dests);
+        sort_with_sources(&mut dests, |dest| {
+            sources.iter().filter(|src| src.is_ipv4() == dest.is_ipv4())
+                   .max_by_key(|src| {
+                       common_prefix_len(&to_ipv6(dest), &to_ipv6(**src))
+                   }).cloned()
+        });
+        dests
+    }
+
+    // The examples from section 10.2 of RFC 6724.
+    #[test]
+    fn rfc_examples() {
+        assert_eq!(sort(&["2001:db8:1::1", "198.51.100.121"],
+                        &["2001:db8:1::2", "fe80::1", "169.254.13.78"]),
+                   addrs(&["2001:db8:1::1", "198.51.100.121"]));
+        assert_eq!(sort(&["2001:db8:1::1", "198.51.100.121"],
+                        &["fe80::1", "198.51.100.117"]),
+                   addrs(&["198.51.100.121", "2001:db8:1::1"]));
+        assert_eq!(sort(&["2001:db8:1::1", "10.1.2.3"],
+                        &["2001:db8:1::2", "fe80::1", "10.1.2.4"]),
+                   addrs(&["2001:db8:1::1", "10.1.2.3"]));
+        assert_eq!(sort(&["2001:db8:1::1", "fe80::1"],
+                        &["2001:db8:1::2", "fe80::2"]),
+                   addrs(&["fe80::1", "2001:db8:1::1"]));
+        assert_eq!(sort(&["2001:db8:1::1", "2001:db8:3ffe::1"],
+                        &["2001:db8:1::2", "2001:db8:3f44::2", "fe80::2"]),
+                   addrs(&["2001:db8:1::1", "2001:db8:3ffe::1"]));
+        assert_eq!(sort(&["2002:c633:6401::1", "2001:db8:1::1"],
+                        &["2002:c633:6401::2", "fe80::2"]),
+                   addrs(&["2002:c633:6401::1", "2001:db8:1::1"]));
+        assert_eq!(sort(&["2002:c633:6401::1", "2001:db8:1::1"],
+                        &["2002:c633:6401::2", "2001:db8:1::2", "fe80::2"]),
+                   addrs(&["2001:db8:1::1", "2002:c633:6401::1"]));
+    }
+
+    #[test]
+    fn unreachable() {
+        assert_eq!(sort(&["2001:db8:1::1", "198.51.100.121"],
+                        &["198.51.100.117"]),
+                   addrs(&["198.51.100.121", "2001:db8:1::1"]));
+    }
+
+    #[test]
+    fn interleave_families() {
+        let mut list = addrs(&["2001:db8::1", "2001:db8::2", "2001:db8::3",
+                               "192.0.2.1", "192.0.2.2"]);
+        interleave(&mut list);
+        assert_eq!(list, addrs(&["2001:db8::1", "192.0.2.1", "2001:db8::2",
+                                 "192.0.2.2", "2001:db8::3"]));
+        let mut list = addrs(&["192.0.2.1", "192.0.2.2", "2001:db8::1"]);
+        interleave(&mut list);
+        assert_eq!(list, addrs(&["192.0.2.1", "2001:db8::1", "192.0.2.2"]));
+    }
+}
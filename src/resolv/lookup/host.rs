@@ -6,9 +6,11 @@ use std::slice;
 use futures::{Async, Future, Poll};
 use ::bits::{DName, DNameBuf, DNameSlice, MessageBuf, ParsedDName, ParseResult};
 use ::iana::{Rtype, Class};
-use ::rdata::{A, Aaaa};
+use ::rdata::{A, Aaaa, Cname};
 use super::super::{Query, Resolver};
+use super::super::conf::{AddrOrder, SortlistEntry};
 use super::super::error::{Error, Result};
+use super::addrsel;
 use super::search::SearchIter;
 
 
@@ -21,6 +23,15 @@ use super::search::SearchIter;
 /// is a relative domain name, it is being translated into a series of
 /// absolute names according to the resolver’s configuration.
 ///
+/// Before asking the DNS, the future consults the hosts file of the
+/// resolver configuration. If `name` is found there, the addresses given
+/// in the file are returned and no query is made.
+///
+/// Addresses received from the DNS are ordered according to the
+/// `addr_order` and then the sort list of the resolver configuration. As
+/// with glibc, addresses found in the hosts file are returned in the order
+/// given in the file.
+///
 /// The value returned upon success can be turned into an iterator over
 /// IP addresses or even socket addresses. Since the lookup may determine that
 /// the host name is in fact an alias for another name, the value will also
@@ -28,6 +39,15 @@ use super::search::SearchIter;
 pub fn lookup_host<N>(resolver: Resolver, name: N) -> LookupHost
                    where N: AsRef<DNameSlice> {
     let name = name.as_ref();
+    if let Some(found) = resolver.conf().hosts.lookup_host(name) {
+        return LookupHost {
+            resolver: resolver,
+            a: MaybeDone::Gone,
+            aaaa: MaybeDone::Gone,
+            search: None,
+            hosts: Some(found)
+        }
+    }
     let mut search = SearchIter::new(resolver.clone(), name);
     let search_name = search.as_mut().map(|s| s.next().unwrap());
     let query_name = match search_name {
@@ -40,7 +60,8 @@ pub fn lookup_host<N>(resolver: Resolver, name: N) -> LookupHost
         resolver: resolver,
         a: MaybeDone::NotYet(a),
         aaaa: MaybeDone::NotYet(aaaa),
-        search: search
+        search: search,
+        hosts: None
     }
 }
 
@@ -63,6 +84,9 @@ pub struct LookupHost {
 
     /// An optional search list iterator for searching a name.
     search: Option<SearchIter>,
+
+    /// The result from the hosts file if the name was found there.
+    hosts: Option<FoundHosts>,
 }
 
 
@@ -73,11 +97,26 @@ impl Future for LookupHost {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Some(found) = self.hosts.take() {
+            return Ok(Async::Ready(found))
+        }
         if (self.a.poll(), self.aaaa.poll()) != (true, true) {
             return Ok(Async::NotReady)
         }
         let err = match FoundHosts::from_messages(self.a.take(), self.aaaa.take()) {
-            Ok(some) => return Ok(Async::Ready(some)),
+            Ok(mut some) => {
+                let conf = self.resolver.conf();
+                match conf.addr_order {
+                    AddrOrder::Unsorted => { }
+                    AddrOrder::Rfc6724 => some.sort_rfc6724(),
+                    AddrOrder::HappyEyeballs => {
+                        some.sort_rfc6724();
+                        some.interleave();
+                    }
+                }
+                some.sort(&conf.sortlist);
+                return Ok(Async::Ready(some))
+            }
             Err(err) => err,
         };
         let name = match self.search {
@@ -172,19 +211,30 @@ impl<A: Future> MaybeDone<A> {
 /// port.
 ///
 /// The `canonical_name()` method returns the canonical name of the host for
-/// which the addresses were found.
+/// which the addresses were found. Other names of the host, such as the
+/// names of CNAME records leading to the canonical name, are available
+/// through `aliases()`.
 #[derive(Clone, Debug)]
 pub struct FoundHosts {
     /// The canonical domain name for the host.
     canonical: DNameBuf,
 
+    /// Other names of the host.
+    aliases: Vec<DNameBuf>,
+
     /// All the IP addresses we’ve got.
     addrs: Vec<IpAddr>
 }
 
 impl FoundHosts {
     pub fn new(canonical: DNameBuf, addrs: Vec<IpAddr>) -> Self {
-        FoundHosts{canonical: canonical, addrs: addrs}
+        Self::with_aliases(canonical, Vec::new(), addrs)
+    }
+
+    /// Creates a new value with a canonical name, aliases, and addresses.
+    pub fn with_aliases(canonical: DNameBuf, aliases: Vec<DNameBuf>,
+                        addrs: Vec<IpAddr>) -> Self {
+        FoundHosts{canonical: canonical, aliases: aliases, addrs: addrs}
     }
 
     /// Creates a new value from the results of the A and AAAA queries.
@@ -202,7 +252,27 @@ impl FoundHosts {
         if let Ok(b) = b {
             Self::process_records(&mut addrs, &b, &name).ok();
         }
-        Ok(FoundHosts{canonical: name.to_cow().into_owned(), addrs: addrs})
+        let mut aliases = Vec::new();
+        Self::process_aliases(&mut aliases, &a).ok();
+        Ok(FoundHosts{canonical: name.to_cow().into_owned(),
+                      aliases: aliases, addrs: addrs})
+    }
+
+    /// Collects the aliases from a response message.
+    ///
+    /// These are the owner names of all the CNAME records in `msg`’s
+    /// answer.
+    fn process_aliases(aliases: &mut Vec<DNameBuf>, msg: &MessageBuf)
+                       -> ParseResult<()> {
+        for record in try!(msg.answer()).limit_to::<Cname<ParsedDName>>() {
+            if let Ok(record) = record {
+                let name = record.name().to_cow().into_owned();
+                if !aliases.contains(&name) {
+                    aliases.push(name)
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Processes the records of a response message.
@@ -228,11 +298,51 @@ impl FoundHosts {
         Ok(())
     }
 
+    /// Reorders the addresses according to a sort list.
+    ///
+    /// Addresses are ordered by the index of the first entry of `sortlist`
+    /// they match. Addresses that don’t match any entry go last. Otherwise,
+    /// the order is kept.
+    fn sort(&mut self, sortlist: &[SortlistEntry]) {
+        if sortlist.is_empty() {
+            return
+        }
+        self.addrs.sort_by_key(|addr| {
+            sortlist.iter().position(|entry| entry.matches(addr))
+                           .unwrap_or(sortlist.len())
+        })
+    }
+
+    /// Sorts the addresses according to RFC 6724.
+    ///
+    /// This implements the destination address selection algorithm of
+    /// RFC 6724 using the default policy table. The source addresses
+    /// needed by the algorithm are learned from the system by connecting
+    /// a UDP socket to each address. No packets are sent in the process.
+    pub fn sort_rfc6724(&mut self) {
+        addrsel::sort_rfc6724(&mut self.addrs)
+    }
+
+    /// Interleaves IPv6 and IPv4 addresses.
+    ///
+    /// The first address stays first. After that, addresses alternate
+    /// between the two families while keeping their relative order. Used
+    /// after `sort_rfc6724()`, this results in the order Happy Eyeballs
+    /// (RFC 8305) recommends for connection attempts.
+    pub fn interleave(&mut self) {
+        addrsel::interleave(&mut self.addrs)
+    }
+
     /// Returns a reference to the canonical name for the host.
     pub fn canonical_name(&self) -> &DNameSlice {
         &self.canonical
     }
 
+    /// Returns a reference to the other names of the host.
+    pub fn aliases(&self) -> &[DNameBuf] {
+        &self.aliases
+    }
+
     /// Returns an iterator over the IP addresses returned by the lookup.
     pub fn iter(&self) -> FoundHostsIter {
         FoundHostsIter(self.addrs.iter())
@@ -285,3 +395,30 @@ impl<'a> ToSocketAddrs for FoundHostsSocketIter<'a> {
         Ok(self.clone())
     }
 }
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use ::bits::DNameSlice;
+    use super::*;
+
+    #[test]
+    fn sortlist() {
+        let addrs = ["192.0.2.1", "2001:db8::1", "198.51.100.1",
+                     "203.0.113.1", "198.51.100.2"];
+        let addrs = addrs.iter().map(|s| IpAddr::from_str(s).unwrap())
+                                .collect();
+        let mut found = FoundHosts::new(DNameSlice::root().to_owned(), addrs);
+        found.sort(&[SortlistEntry::from_str("198.51.100.0").unwrap(),
+                     SortlistEntry::from_str("2001:db8::/ffff:ffff::").unwrap()]);
+        let sorted = ["198.51.100.1", "198.51.100.2", "2001:db8::1",
+                      "192.0.2.1", "203.0.113.1"];
+        let sorted: Vec<_> = sorted.iter().map(|s| IpAddr::from_str(s).unwrap())
+                                   .collect();
+        assert_eq!(found.iter().collect::<Vec<_>>(), sorted);
+    }
+}
@@ -6,10 +6,14 @@
 pub use self::addr::lookup_addr;
 pub use self::host::lookup_host;
 pub use self::records::lookup_records;
+#[cfg(feature = "dnssec")] pub use self::secure::lookup_secure;
 pub use self::srv::lookup_srv;
 
 pub mod addr;
 pub mod host;
 pub mod records;
 pub mod search;
+#[cfg(feature = "dnssec")] pub mod secure;
 pub mod srv;
+
+mod addrsel;
@@ -19,6 +19,12 @@ use super::search::SearchIter;
 /// to translate the name into a series of absolute names. If you want to
 /// find out the name that resulted in a successful answer, you can look at
 /// the query in the resulting message.
+///
+/// A name that doesn’t exist fails with an error of kind `NoName`, a name
+/// without records of the requested type with one of kind `NoData`. Either
+/// way, the next name of the search list is tried. If all names fail, the
+/// errors for all of them are merged, so the negative responses can be
+/// found in the resulting error.
 pub fn lookup_records<N>(resolver: Resolver, name: N, rtype: Rtype,
                          class: Class) -> LookupRecords
                       where N: AsRef<DNameSlice> {
@@ -35,7 +41,8 @@ pub fn lookup_records<N>(resolver: Resolver, name: N, rtype: Rtype,
         query: query,
         search: search,
         rtype: rtype,
-        class: class
+        class: class,
+        error: None
     }
 }
 
@@ -60,6 +67,9 @@ pub struct LookupRecords {
 
     /// The class to search for.
     class: Class,
+
+    /// The merged errors of the names of the search list tried so far.
+    error: Option<Error>,
 }
 
 
@@ -75,6 +85,10 @@ impl Future for LookupRecords {
             Ok(Async::Ready(item)) => return Ok(Async::Ready(item)),
             Err(err) => err
         };
+        let err = match self.error.take() {
+            Some(error) => error.merge(err),
+            None => err
+        };
         let name = match self.search {
             None => return Err(err),
             Some(ref mut search) => {
@@ -84,6 +98,7 @@ impl Future for LookupRecords {
                 }
             }
         };
+        self.error = Some(err);
         self.query = self.resolver.clone()
                          .query((name, self.rtype, self.class));
         self.poll()
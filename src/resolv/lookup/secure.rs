@@ -0,0 +1,114 @@
+//! Looking up records with validation.
+//!
+//! This module is only available if the crate is built with the `dnssec`
+//! feature.
+
+use futures::{Async, Future, Poll};
+use ::bits::{DNameSlice, MessageBuf};
+use ::iana::{Class, Rcode, Rtype};
+use super::super::Resolver;
+use super::super::dnssec::{Security, Validate};
+use super::super::error::{Error, ErrorKind};
+use super::search::SearchIter;
+
+
+//------------ lookup_secure -------------------------------------------------
+
+/// Creates a future that looks up DNS records and validates them.
+///
+/// This works like [`lookup_records()`] but always validates the response
+/// against the trust anchors of the resolver’s configuration. The future
+/// resolves into the response and its security status. If the response
+/// turns out to be bogus, the lookup fails with an error of kind
+/// `ErrorKind::AllBogusAnswers` rather than trying the next name of the
+/// search list.
+///
+/// If the resolver configuration doesn’t contain any trust anchors, all
+/// responses are considered indeterminate.
+///
+/// [`lookup_records()`]: ../records/fn.lookup_records.html
+pub fn lookup_secure<N>(resolver: Resolver, name: N, rtype: Rtype,
+                        class: Class) -> LookupSecure
+                     where N: AsRef<DNameSlice> {
+    let name = name.as_ref();
+    let mut search = SearchIter::new(resolver.clone(), name);
+    let search_name = search.as_mut().map(|s| s.next().unwrap());
+    let query_name = match search_name {
+        Some(ref name) => name,
+        None => name
+    };
+    let query = resolver.clone().validate((query_name, rtype, class));
+    LookupSecure {
+        resolver: resolver,
+        query: query,
+        search: search,
+        rtype: rtype,
+        class: class
+    }
+}
+
+
+//------------ LookupSecure --------------------------------------------------
+
+/// The future returned by [`lookup_secure()`].
+///
+/// [`lookup_secure()`]: fn.lookup_secure.html
+pub struct LookupSecure {
+    /// The resolver to run queries on.
+    resolver: Resolver,
+
+    /// The current query.
+    query: Validate,
+
+    /// An optional search list iterator for searching a name.
+    search: Option<SearchIter>,
+
+    /// The resource record type to search for.
+    rtype: Rtype,
+
+    /// The class to search for.
+    class: Class,
+}
+
+
+//--- Future
+
+impl Future for LookupSecure {
+    type Item = (MessageBuf, Security);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let err = match self.query.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready((_, Security::Bogus))) => {
+                return Err(ErrorKind::AllBogusAnswers.into())
+            }
+            Ok(Async::Ready((response, security))) => {
+                // A NODATA response is still an answer here since it
+                // comes with its security status.
+                if response.header().rcode() == Rcode::NoError {
+                    return Ok(Async::Ready((response, security)))
+                }
+                match Error::from_response(response, None) {
+                    Ok(response) => {
+                        return Ok(Async::Ready((response, security)))
+                    }
+                    Err(err) => err
+                }
+            }
+            Err(err) => err
+        };
+        let name = match self.search {
+            None => return Err(err),
+            Some(ref mut search) => {
+                match search.next() {
+                    None => return Err(err),
+                    Some(name) => name,
+                }
+            }
+        };
+        self.query = self.resolver.clone()
+                         .validate((name, self.rtype, self.class));
+        self.poll()
+    }
+}
@@ -7,7 +7,7 @@ use ::iana::{Rtype, Class};
 use ::rdata::{Srv, A, Aaaa};
 use super::host::{lookup_host, LookupHost, FoundHosts, FoundHostsSocketIter};
 use super::super::{Query, Resolver};
-use super::super::error::{Error, Result};
+use super::super::error::{Error, ErrorKind, Result};
 use super::search::SearchIter;
 use rand;
 use rand::distributions::{IndependentSample, Range};
@@ -102,7 +102,7 @@ impl LookupSrv {
             }
         }
         else {
-            Error::Question(ComposeError::LongName)
+            ComposeError::LongName.into()
         };
         let name = match self.search {
             None => return Err(err),
@@ -161,39 +161,39 @@ impl Stream for LookupSrvStream {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        let res = if let Some(ref mut query) = self.lookup {
-            match query.poll() {
-                Ok(Async::NotReady) =>
-                    return Ok(Async::NotReady),
-                Ok(Async::Ready(found)) => {
-                    let ref item = self.results[self.current_idx];
-                    self.current_idx += 1;
-                    Ok(Async::Ready(Some(SrvItem{state: SrvItemState::Resolved(found),
-                                                 port: item.port,
-                                                 txt_service: item.txt_service.clone(),
-                                                 priority: item.priority,
-                                                 weight: item.weight})))
-                },
-                Err(_) =>
-                    Ok(Async::NotReady)
-            }
-        }
-        else {
-            if let Some(item) = self.results.get(self.current_idx).cloned() {
-                if let SrvItemState::Unresolved(host) = item.state {
-                    self.lookup = Some(lookup_host(self.resolver.clone(), &host));
-                    return self.poll()
-                }
-                else {
-                    Ok(Async::Ready(Some(item)))
+        loop {
+            if let Some(mut lookup) = self.lookup.take() {
+                let found = match lookup.poll() {
+                    Ok(Async::NotReady) => {
+                        self.lookup = Some(lookup);
+                        return Ok(Async::NotReady)
+                    }
+                    Ok(Async::Ready(found)) => Some(found),
+                    Err(_) => None
+                };
+                let item = &self.results[self.current_idx];
+                self.current_idx += 1;
+                if let Some(found) = found {
+                    return Ok(Async::Ready(Some(SrvItem{state: SrvItemState::Resolved(found),
+                                                        port: item.port,
+                                                        txt_service: item.txt_service.clone(),
+                                                        priority: item.priority,
+                                                        weight: item.weight})))
                 }
+                // Skip items we failed to resolve.
+                continue
             }
-            else {
-                Ok(Async::Ready(None))
+            let item = match self.results.get(self.current_idx) {
+                Some(item) => item.clone(),
+                None => return Ok(Async::Ready(None))
+            };
+            if let SrvItemState::Unresolved(ref host) = item.state {
+                self.lookup = Some(lookup_host(self.resolver.clone(), host));
+                continue
             }
-        };
-        self.lookup = None;
-        res
+            self.current_idx += 1;
+            return Ok(Async::Ready(Some(item)))
+        }
     }
 }
 
@@ -281,7 +281,7 @@ impl FoundSrvs {
 
         if rrs.len() == 0 {
             // Return an error, falling back to resolving the base host
-            return Err(Error::NoName);
+            return Err(ErrorKind::NoData.into());
         };
         if rrs.len() == 1 && rrs[0].target().eq(&DNameBuf::root()) {
             // Abort if there is exactly one record and its target is "."
@@ -0,0 +1,168 @@
+//! Memory channel and transports.
+//!
+//! These replace the network transports of a resolver created via
+//! `Resolver::with_responder()`. See the `mock` module for details.
+
+use std::io;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::sync::Arc;
+use futures::{Async, AsyncSink, Poll, StartSend};
+use futures::task;
+use tokio_core::reactor;
+use ::bits::MessageBuf;
+use super::channel::Channel;
+use super::mock::{Reply, Request, Responder};
+use super::observer::Protocol;
+use super::request::{TransportHandle, TransportRequest};
+use super::server::ServerInfo;
+use super::transport::{TransportMode, spawn_pool_transport,
+                       spawn_transport};
+
+
+//------------ memory_transports ---------------------------------------------
+
+/// Spawns the memory transports for a server into a reactor.
+///
+/// Returns the handles for the UDP and TCP transports. Which of them are
+/// present and what mode they operate in is decided the same way as for
+/// real servers.
+pub fn memory_transports(reactor: &reactor::Handle, info: &Arc<ServerInfo>,
+                         responder: &Rc<Responder>)
+                         -> (Option<TransportHandle>,
+                             Option<TransportHandle>) {
+    let conf = info.conf();
+    let udp = if conf.is_tls() || conf.is_doh() {
+        None
+    }
+    else {
+        TransportMode::resolve(conf.udp, Some(TransportMode::Multiplex))
+    };
+    let tcp = TransportMode::resolve(conf.tcp,
+                                     Some(TransportMode::SingleRequest));
+    let udp = udp.map(|mode| {
+        let channel = || {
+            MemoryChannel::new(responder.clone(), conf.addr, false)
+        };
+        match mode {
+            TransportMode::Multiplex if conf.is_udp_pool() => {
+                spawn_pool_transport(reactor, channel, info, Protocol::Udp)
+            }
+            _ => spawn_transport(reactor, channel(), mode, info,
+                                 Protocol::Udp)
+        }
+    });
+    let protocol = if conf.is_doh() { Protocol::Https }
+                   else if conf.is_tls() { Protocol::Tls }
+                   else { Protocol::Tcp };
+    let tcp = tcp.map(|mode| {
+        let channel = MemoryChannel::new(responder.clone(), conf.addr,
+                                         true);
+        spawn_transport(reactor, channel, mode, info, protocol)
+    });
+    (udp, tcp)
+}
+
+
+//------------ MemoryChannel -------------------------------------------------
+
+/// A channel handing requests to a responder instead of sending them.
+struct MemoryChannel {
+    /// The responder.
+    responder: Rc<Responder>,
+
+    /// The address of the server we pretend to be talking to.
+    server: SocketAddr,
+
+    /// Do we pretend to be a stream channel?
+    stream: bool,
+
+    /// The request we are currently sending, if any.
+    wr: Option<TransportRequest>,
+
+    /// The responses waiting to be received.
+    responses: VecDeque<MessageBuf>,
+}
+
+impl MemoryChannel {
+    /// Creates a new memory channel.
+    fn new(responder: Rc<Responder>, server: SocketAddr, stream: bool)
+           -> Self {
+        MemoryChannel {
+            responder: responder,
+            server: server,
+            stream: stream,
+            wr: None,
+            responses: VecDeque::new(),
+        }
+    }
+
+    /// Creates a truncated response for `request`.
+    fn truncated(request: &Request) -> MessageBuf {
+        // The builder sets the TC bit itself when finishing, so we can
+        // only set it afterwards.
+        let mut msg = MessageBuf::from_vec(request.answer().finish())
+                                 .unwrap();
+        msg.header_mut().set_tc(true);
+        msg
+    }
+}
+
+
+//--- Channel
+
+impl Channel for MemoryChannel {
+    fn start_send(&mut self, request: TransportRequest)
+                  -> StartSend<TransportRequest, io::Error> {
+        if self.wr.is_some() {
+            return Ok(AsyncSink::NotReady(request))
+        }
+        self.wr = Some(request);
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_send(&mut self) -> Poll<Option<TransportRequest>, io::Error> {
+        let wr = match self.wr.take() {
+            Some(wr) => wr,
+            None => return Ok(Async::Ready(None))
+        };
+        let message = wr.message().dgram_bytes().to_vec();
+        let message = match MessageBuf::from_vec(message) {
+            Ok(message) => message,
+            Err(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "unparseable request"))
+            }
+        };
+        let request = Request::new(&message, self.server, self.stream);
+        let mut response = match self.responder.respond(&request) {
+            Reply::Response(response) => response,
+            Reply::Truncated(response) => {
+                if self.stream { response }
+                else { Self::truncated(&request) }
+            }
+            Reply::Timeout => return Ok(Async::Ready(Some(wr))),
+            Reply::Error(kind) => {
+                wr.fail(io::Error::new(kind, "mock error").into());
+                return Err(io::Error::new(kind, "mock error"))
+            }
+        };
+        response.header_mut().set_id(message.header().id());
+        self.responses.push_back(response);
+        task::current().notify();
+        Ok(Async::Ready(Some(wr)))
+    }
+
+    fn poll_recv(&mut self) -> Poll<MessageBuf, io::Error> {
+        match self.responses.pop_front() {
+            Some(response) => Ok(Async::Ready(response)),
+            None => Ok(Async::NotReady)
+        }
+    }
+
+    fn sleep(&mut self) -> Result<(), io::Error> {
+        self.wr = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,843 @@
+//! A resolver answering from memory for testing.
+//!
+//! Code using the resolver, including the lookups of this crate, is hard
+//! to test if it needs a real name server. This module allows to create a
+//! [`Resolver`] whose servers are replaced by a [`Responder`] via
+//! [`Resolver::with_responder()`]. Requests still pass through the real
+//! transports, so message IDs, EDNS, timeouts, and the fallback from UDP
+//! to TCP work just as with real servers, but instead of being sent over
+//! the network they are handed to the responder which decides what to
+//! reply.
+//!
+//! The [`Reply`] returned by the responder can be a response message but
+//! it can also cause the request to time out, the response to be
+//! truncated if the request was sent over UDP, or the transport to fail
+//! with an error.
+//!
+//! There are three kinds of responders: any closure taking a [`Request`]
+//! and returning a reply, the [`Answers`] type which is a fixed map from
+//! questions to replies, and [`Zone`] which answers authoritatively from
+//! the records of a zone that can be read from a master file.
+//!
+//! Only the configured upstream servers are replaced. Iterative
+//! resolution still asks the real name servers.
+//!
+//! [`Answers`]: struct.Answers.html
+//! [`Reply`]: enum.Reply.html
+//! [`Request`]: struct.Request.html
+//! [`Resolver`]: ../struct.Resolver.html
+//! [`Resolver::with_responder()`]:
+//!     ../struct.Resolver.html#method.with_responder
+//! [`Responder`]: trait.Responder.html
+//! [`Zone`]: struct.Zone.html
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use ::bits::{AnswerBuilder, ComposeMode, DName, DNameBuf, DNameSlice,
+             Message, MessageBuf, MessageBuilder, ParsedDName, Question,
+             Record};
+use ::iana::{Class, Rcode, Rtype};
+use ::master::{ScanResult, Scanner};
+use ::master::reader::{Reader, ReaderItem};
+use ::rdata::MasterRecordData;
+
+
+//------------ Responder -----------------------------------------------------
+
+/// A type that can reply to requests in place of a name server.
+///
+/// The trait is implemented for all closures taking a reference to a
+/// [`Request`] and returning a [`Reply`]. Since all transports run on the
+/// same reactor, the responder doesn’t need to be `Send` or `Sync` and can
+/// keep state in cells for scripting a sequence of replies.
+///
+/// [`Reply`]: enum.Reply.html
+/// [`Request`]: struct.Request.html
+pub trait Responder {
+    /// Returns the reply to a request.
+    fn respond(&self, request: &Request) -> Reply;
+}
+
+impl<F: Fn(&Request) -> Reply> Responder for F {
+    fn respond(&self, request: &Request) -> Reply {
+        (*self)(request)
+    }
+}
+
+
+//------------ Request -------------------------------------------------------
+
+/// A request sent to a mock server.
+pub struct Request<'a> {
+    /// The request message.
+    message: &'a Message,
+
+    /// The address of the server the request was sent to.
+    server: SocketAddr,
+
+    /// Was the request sent over a stream transport?
+    stream: bool,
+}
+
+impl<'a> Request<'a> {
+    /// Creates a new request.
+    ///
+    /// Normally, requests are created by the resolver. Creating them
+    /// yourself is useful for testing responders directly.
+    pub fn new(message: &'a Message, server: SocketAddr, stream: bool)
+               -> Self {
+        Request { message: message, server: server, stream: stream }
+    }
+
+    /// Returns a reference to the request message.
+    ///
+    /// The message is exactly what would have been sent to the server,
+    /// including the message ID and any EDNS information.
+    pub fn message(&self) -> &Message {
+        self.message
+    }
+
+    /// Returns the question of the request.
+    pub fn question(&self) -> Option<Question<ParsedDName<'a>>> {
+        self.message.first_question()
+    }
+
+    /// Returns the address of the server the request was sent to.
+    ///
+    /// This is the address of the server in the resolver configuration,
+    /// so a responder can behave differently for different servers.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Returns whether the request was sent over a stream transport.
+    ///
+    /// This is `false` for UDP and `true` for TCP.
+    pub fn is_stream(&self) -> bool {
+        self.stream
+    }
+
+    /// Starts building a response to the request.
+    ///
+    /// The response contains the question of the request. Its header has
+    /// the message ID and the RD bit of the request as well as the QR and
+    /// RA bits set. It has been advanced to the answer section.
+    pub fn answer(&self) -> AnswerBuilder {
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        {
+            let header = msg.header_mut();
+            header.set_id(self.message.header().id());
+            header.set_qr(true);
+            header.set_rd(self.message.header().rd());
+            header.set_ra(true);
+        }
+        if let Some(question) = self.question() {
+            msg.push(question).unwrap();
+        }
+        msg.answer()
+    }
+
+    /// Returns a reply with an empty response with the given rcode.
+    pub fn rcode(&self, rcode: Rcode) -> Reply {
+        let mut msg = self.answer();
+        msg.header_mut().set_rcode(rcode);
+        Reply::Response(MessageBuf::from_vec(msg.finish()).unwrap())
+    }
+}
+
+
+//------------ Reply ---------------------------------------------------------
+
+/// What a mock server does with a request.
+#[derive(Clone, Debug)]
+pub enum Reply {
+    /// Respond with the given message.
+    ///
+    /// The message ID of the response is set to that of the request.
+    /// Nothing else is changed, so the response still has to pass the
+    /// resolver’s response policy.
+    Response(MessageBuf),
+
+    /// Respond with the given message only over stream transports.
+    ///
+    /// If the request was sent over UDP, an empty response with the TC
+    /// bit set is sent instead, causing the resolver to repeat the request
+    /// over TCP.
+    Truncated(MessageBuf),
+
+    /// Don’t respond at all.
+    ///
+    /// The request will time out after the configured timeout.
+    Timeout,
+
+    /// Fail the transport with an IO error of the given kind.
+    ///
+    /// Just like with a socket error, the request and all other requests
+    /// currently in flight on the transport fail. The transport then shuts
+    /// down, so all later requests sent to it fail, too.
+    Error(io::ErrorKind),
+}
+
+
+//------------ Answers -------------------------------------------------------
+
+/// A responder with a fixed reply for each question.
+///
+/// Requests for questions without a reply are refused.
+#[derive(Clone, Debug, Default)]
+pub struct Answers {
+    /// The questions and their replies in the order they were added.
+    answers: Vec<(Question<DNameBuf>, Reply)>,
+}
+
+impl Answers {
+    /// Creates a new, empty set of answers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the reply for a question.
+    ///
+    /// If there already is a reply for the question, it is replaced. The
+    /// QR, RD, and RA bits of response messages are adjusted to fit the
+    /// request, so they don’t need to be set.
+    pub fn push<N, Q>(&mut self, question: Q, reply: Reply)
+                where N: DName, Q: Into<Question<N>> {
+        let question = question.into();
+        let question = Question::new(question.qname().to_cow().into_owned(),
+                                     question.qtype(), question.qclass());
+        self.answers.retain(|item| item.0 != question);
+        self.answers.push((question, reply))
+    }
+}
+
+impl Responder for Answers {
+    fn respond(&self, request: &Request) -> Reply {
+        let question = match request.question() {
+            Some(question) => question,
+            None => return request.rcode(Rcode::FormErr)
+        };
+        let reply = self.answers.iter().find(|item| {
+            item.0.qname() == question.qname()
+                && item.0.qtype() == question.qtype()
+                && item.0.qclass() == question.qclass()
+        });
+        let reply = match reply {
+            Some(&(_, ref reply)) => reply,
+            None => return request.rcode(Rcode::Refused)
+        };
+        let fix = |msg: &MessageBuf| {
+            let mut msg = msg.clone();
+            {
+                let header = msg.header_mut();
+                header.set_qr(true);
+                header.set_rd(request.message().header().rd());
+                header.set_ra(true);
+            }
+            msg
+        };
+        match *reply {
+            Reply::Response(ref msg) => Reply::Response(fix(msg)),
+            Reply::Truncated(ref msg) => Reply::Truncated(fix(msg)),
+            ref other => other.clone()
+        }
+    }
+}
+
+
+//------------ Zone ----------------------------------------------------------
+
+/// A responder answering authoritatively from the records of a zone.
+///
+/// The apex of the zone is the owner of its SOA record. Questions for
+/// names outside the zone are refused, as are all questions if the zone
+/// doesn’t have a SOA record. Names delegated via NS records below the
+/// apex result in referrals. CNAME records are followed as long as their
+/// targets are within the zone. Wildcards are not supported.
+#[derive(Clone, Debug, Default)]
+pub struct Zone {
+    /// The apex of the zone if we have seen its SOA record.
+    apex: Option<DNameBuf>,
+
+    /// The records of the zone.
+    records: Vec<Record<DNameBuf, MasterRecordData>>,
+}
+
+impl Zone {
+    /// Creates a new, empty zone.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a zone from the master file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> ScanResult<Self> {
+        Self::from_reader(Reader::open(path)?)
+    }
+
+    /// Reads a zone from a master file reader.
+    ///
+    /// Include directives are ignored.
+    pub fn from_reader<S: Scanner>(reader: Reader<S>) -> ScanResult<Self> {
+        let mut res = Self::new();
+        for item in reader {
+            if let ReaderItem::Record(record) = item? {
+                res.push(Record::new((*record.owner).clone(), record.class,
+                                     record.ttl, record.rdata))
+            }
+        }
+        Ok(res)
+    }
+
+    /// Adds a record to the zone.
+    ///
+    /// If the record is a SOA record, its owner becomes the apex of the
+    /// zone.
+    pub fn push(&mut self, record: Record<DNameBuf, MasterRecordData>) {
+        if let MasterRecordData::Soa(_) = *record.data() {
+            self.apex = Some(record.name().clone())
+        }
+        self.records.push(record)
+    }
+
+    /// Returns an iterator over the records owned by `name`.
+    fn owned<'a, 'b>(&'a self, name: &'b DNameSlice, class: Class)
+                     -> Box<Iterator<Item=&'a Record<DNameBuf,
+                                                     MasterRecordData>> + 'b>
+             where 'a: 'b {
+        Box::new(self.records.iter().filter(move |record| {
+            record.class() == class && **record.name() == *name
+        }))
+    }
+
+    /// Returns the zone cut that `name` is at or below, if any.
+    ///
+    /// If `ds` is `true`, a cut at `name` itself is ignored since the Ds
+    /// records for it are in the parent zone.
+    fn cut(&self, apex: &DNameSlice, name: &DNameSlice, class: Class,
+           ds: bool) -> Option<&DNameBuf> {
+        self.records.iter().filter(|record| {
+            record.class() == class && record.rtype() == Rtype::Ns
+                && **record.name() != *apex
+                && record.name().ends_with(&apex)
+                && name.ends_with(record.name())
+                && !(ds && **record.name() == *name)
+        }).map(|record| record.name()).min_by_key(|name| name.len())
+    }
+
+    /// Returns whether `name` exists, possibly as an empty non-terminal.
+    fn exists(&self, name: &DNameSlice, class: Class) -> bool {
+        self.records.iter().any(|record| {
+            record.class() == class && record.name().ends_with(&name)
+        })
+    }
+}
+
+impl Responder for Zone {
+    fn respond(&self, request: &Request) -> Reply {
+        let question = match request.question() {
+            Some(question) => question,
+            None => return request.rcode(Rcode::FormErr)
+        };
+        let (qtype, class) = (question.qtype(), question.qclass());
+        let qname = question.qname().to_cow().into_owned();
+        let apex = match self.apex {
+            Some(ref apex) if qname.ends_with(apex) => apex,
+            _ => return request.rcode(Rcode::Refused)
+        };
+
+        let mut answer = Vec::new();
+        let mut authority = Vec::new();
+        let mut additional = Vec::new();
+        let mut rcode = Rcode::NoError;
+        let mut aa = true;
+        let mut name = qname;
+        loop {
+            if let Some(cut) = self.cut(apex, &name, class,
+                                        qtype == Rtype::Ds) {
+                for record in self.owned(cut, class) {
+                    if let MasterRecordData::Ns(ref ns) = *record.data() {
+                        authority.push(record);
+                        additional.extend(
+                            self.owned(ns.nsdname(), class).filter(|rec| {
+                                rec.rtype() == Rtype::A
+                                    || rec.rtype() == Rtype::Aaaa
+                            })
+                        );
+                    }
+                }
+                aa = answer.len() > 0;
+                break
+            }
+            if !self.exists(&name, class) {
+                rcode = Rcode::NXDomain;
+                break
+            }
+            let len = answer.len();
+            answer.extend(self.owned(&name, class).filter(|record| {
+                record.rtype() == qtype || qtype == Rtype::Any
+            }));
+            if answer.len() > len {
+                break
+            }
+            let cname = self.owned(&name, class).filter_map(|record| {
+                match *record.data() {
+                    MasterRecordData::Cname(ref cname) => {
+                        Some((record, cname.cname().clone()))
+                    }
+                    _ => None
+                }
+            }).next();
+            match cname {
+                Some((record, target)) => {
+                    if answer.iter().any(|item| {
+                        item.name() == record.name()
+                    }) {
+                        break
+                    }
+                    answer.push(record);
+                    if !target.ends_with(apex) {
+                        break
+                    }
+                    name = target;
+                }
+                None => break
+            }
+        }
+        if authority.is_empty() && (rcode == Rcode::NXDomain
+                                    || answer.iter().all(|record| {
+                                           record.rtype() == Rtype::Cname
+                                       })) {
+            authority.extend(self.owned(apex, class).filter(|record| {
+                record.rtype() == Rtype::Soa
+            }));
+        }
+
+        let mut msg = request.answer();
+        msg.header_mut().set_aa(aa);
+        msg.header_mut().set_rcode(rcode);
+        for record in answer {
+            msg.push(record.clone()).unwrap();
+        }
+        let mut msg = msg.authority();
+        for record in authority {
+            msg.push(record.clone()).unwrap();
+        }
+        let mut msg = msg.additional();
+        for record in additional {
+            msg.push(record.clone()).unwrap();
+        }
+        Reply::Response(MessageBuf::from_vec(msg.finish()).unwrap())
+    }
+}
+
+
+//------------ answer_a ------------------------------------------------------
+
+/// Answers a request in wire format with an A record for 192.0.2.1.
+///
+/// This is used by the test servers of the network transports which
+/// receive and send raw messages.
+#[cfg(test)]
+pub fn answer_a(request: Vec<u8>) -> Vec<u8> {
+    use std::net::Ipv4Addr;
+    use ::rdata::A;
+
+    let request = MessageBuf::from_vec(request).unwrap();
+    let request = Request::new(&request, "127.0.0.1:53".parse().unwrap(),
+                               true);
+    let mut msg = request.answer();
+    msg.push((request.question().unwrap().qname().clone(), 3600,
+              A::new(Ipv4Addr::new(192, 0, 2, 1)))).unwrap();
+    msg.finish()
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::cell::{Cell, RefCell};
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::rc::Rc;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use futures::future;
+    use tokio_core::reactor::Core;
+    use ::bits::{DNameBuf, MessageBuf};
+    use ::iana::{Rcode, Rtype};
+    use ::master::reader::Reader;
+    use ::rdata::A;
+    use super::super::conf::{ResolvConf, RouteConf, ServerConf};
+    use super::super::error::ErrorKind;
+    use super::super::lookup::lookup_host;
+    use super::super::observer::{Event, EventKind, Observer, Protocol};
+    use super::super::Resolver;
+    use super::*;
+
+    const ZONE: &'static str = "\
+        example.com. 3600 IN SOA ns.example.com. hostmaster.example.com. \
+                                 1 3600 600 86400 300\n\
+        example.com. 3600 IN NS ns.example.com.\n\
+        ns.example.com. 3600 IN A 192.0.2.53\n\
+        www.example.com. 3600 IN CNAME host.example.com.\n\
+        host.example.com. 3600 IN A 192.0.2.1\n\
+        sub.example.com. 3600 IN NS ns.sub.example.com.\n\
+        ns.sub.example.com. 3600 IN A 192.0.2.54\n";
+
+    fn name(s: &str) -> DNameBuf {
+        DNameBuf::from_str(s).unwrap()
+    }
+
+    fn zone() -> Zone {
+        Zone::from_reader(Reader::create(ZONE)).unwrap()
+    }
+
+    fn conf() -> ResolvConf {
+        let mut conf = ResolvConf::new();
+        conf.servers.push(ServerConf::new("192.0.2.10:53".parse().unwrap()));
+        conf.finalize();
+        conf
+    }
+
+    fn respond<R: Responder>(responder: &R, qname: &str, qtype: Rtype)
+                             -> MessageBuf {
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.header_mut().set_id(12);
+        msg.header_mut().set_rd(true);
+        msg.push((name(qname), qtype)).unwrap();
+        let msg = MessageBuf::from_vec(msg.finish()).unwrap();
+        let request = Request::new(&msg, "192.0.2.10:53".parse().unwrap(),
+                                   false);
+        match responder.respond(&request) {
+            Reply::Response(response) => response,
+            other => panic!("unexpected reply {:?}", other)
+        }
+    }
+
+    #[test]
+    fn zone_answer() {
+        let response = respond(&zone(), "www.example.com.", Rtype::A);
+        assert!(response.header().aa());
+        assert_eq!(response.header().id(), 12);
+        assert_eq!(response.header().rcode(), Rcode::NoError);
+        let types: Vec<_> = response.answer().unwrap().map(|record| {
+            record.unwrap().rtype()
+        }).collect();
+        assert_eq!(types, vec![Rtype::Cname, Rtype::A]);
+    }
+
+    #[test]
+    fn zone_negative() {
+        let zone = zone();
+        let response = respond(&zone, "nope.example.com.", Rtype::A);
+        assert_eq!(response.header().rcode(), Rcode::NXDomain);
+        assert_eq!(response.authority().unwrap().count(), 1);
+
+        let response = respond(&zone, "host.example.com.", Rtype::Aaaa);
+        assert_eq!(response.header().rcode(), Rcode::NoError);
+        assert_eq!(response.answer().unwrap().count(), 0);
+        assert_eq!(response.authority().unwrap().count(), 1);
+
+        let response = respond(&zone, "www.example.org.", Rtype::A);
+        assert_eq!(response.header().rcode(), Rcode::Refused);
+    }
+
+    #[test]
+    fn zone_referral() {
+        let response = respond(&zone(), "www.sub.example.com.", Rtype::A);
+        assert!(!response.header().aa());
+        assert_eq!(response.answer().unwrap().count(), 0);
+        assert_eq!(response.authority().unwrap().count(), 1);
+        assert_eq!(response.additional().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn answers() {
+        let mut answers = Answers::new();
+        answers.push((name("example.com."), Rtype::A), Reply::Timeout);
+        let response = respond(&answers, "example.com.", Rtype::Aaaa);
+        assert_eq!(response.header().rcode(), Rcode::Refused);
+    }
+
+    #[test]
+    fn resolver_lookup() {
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf(), zone());
+        let found = core.run(lookup_host(resolv,
+                                         name("www.example.com."))).unwrap();
+        assert_eq!(found.canonical_name(), &*name("host.example.com."));
+        assert_eq!(found.iter().collect::<Vec<_>>(),
+                   vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+    }
+
+    #[test]
+    fn resolver_truncated() {
+        let stream = Rc::new(Cell::new(false));
+        let seen = stream.clone();
+        let responder = move |request: &Request| {
+            seen.set(request.is_stream());
+            let mut msg = request.answer();
+            msg.push((name("example.com."), 3600,
+                      A::new(Ipv4Addr::new(192, 0, 2, 1)))).unwrap();
+            Reply::Truncated(MessageBuf::from_vec(msg.finish()).unwrap())
+        };
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf(),
+                                              responder);
+        let response = core.run(resolv.query((name("example.com."),
+                                              Rtype::A))).unwrap();
+        assert!(stream.get());
+        assert_eq!(response.answer().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn resolver_edns_fallback() {
+        for &rcode in &[Rcode::FormErr, Rcode::NotImp] {
+            // The server chokes on requests with an OPT record.
+            let seen = Rc::new(RefCell::new(Vec::new()));
+            let log = seen.clone();
+            let responder = move |request: &Request| {
+                let edns = request.message().opt().is_some();
+                log.borrow_mut().push(edns);
+                if edns {
+                    return request.rcode(rcode)
+                }
+                let mut msg = request.answer();
+                msg.push((request.question().unwrap().qname().clone(), 3600,
+                          A::new(Ipv4Addr::new(192, 0, 2, 1)))).unwrap();
+                Reply::Response(MessageBuf::from_vec(msg.finish()).unwrap())
+            };
+            let mut conf = conf();
+            conf.options.use_edns0 = true;
+            let mut core = Core::new().unwrap();
+            let resolv = Resolver::with_responder(&core.handle(), conf,
+                                                  responder);
+
+            // The request is repeated right away without EDNS.
+            let response = core.run(resolv.clone().query((
+                name("www.example.com."), Rtype::A
+            ))).unwrap();
+            assert_eq!(response.answer().unwrap().count(), 1);
+            assert_eq!(*seen.borrow(), vec![true, false]);
+
+            // The server is remembered as not supporting EDNS.
+            let response = core.run(resolv.query((
+                name("host.example.com."), Rtype::A
+            ))).unwrap();
+            assert_eq!(response.answer().unwrap().count(), 1);
+            assert_eq!(*seen.borrow(), vec![true, false, false]);
+        }
+    }
+
+    #[test]
+    fn resolver_randomize_case() {
+        // The server always answers with the name in lower case.
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        let responder = move |request: &Request| {
+            let qname = request.question().unwrap().qname().to_string();
+            log.borrow_mut().push(qname);
+            let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                         .unwrap();
+            msg.header_mut().set_qr(true);
+            msg.header_mut().set_rd(true);
+            msg.header_mut().set_ra(true);
+            msg.push((name("www.example.com."), Rtype::A)).unwrap();
+            let mut msg = msg.answer();
+            msg.push((name("www.example.com."), 3600,
+                      A::new(Ipv4Addr::new(192, 0, 2, 1)))).unwrap();
+            Reply::Response(MessageBuf::from_vec(msg.finish()).unwrap())
+        };
+        let mut conf = conf();
+        conf.randomize_case = true;
+        conf.finalize();
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf,
+                                              responder);
+
+        // Randomization is only switched off after three mismatches.
+        core.run(resolv.clone().query((name("www.example.com."),
+                                       Rtype::A))).unwrap();
+        assert_eq!(seen.borrow().len(), 4);
+        assert_eq!(seen.borrow()[3], "www.example.com.");
+
+        // And stays off for the next query.
+        core.run(resolv.query((name("www.example.com."),
+                               Rtype::A))).unwrap();
+        assert_eq!(seen.borrow().len(), 5);
+    }
+
+    #[test]
+    fn resolver_randomize_case_udp_only() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        let responder = move |request: &Request| {
+            let qname = request.question().unwrap().qname().to_string();
+            log.borrow_mut().push((qname, request.is_stream()));
+            let mut msg = request.answer();
+            msg.push((name("www.example.com."), 3600,
+                      A::new(Ipv4Addr::new(192, 0, 2, 1)))).unwrap();
+            Reply::Truncated(MessageBuf::from_vec(msg.finish()).unwrap())
+        };
+        let mut conf = conf();
+        conf.randomize_case = true;
+        conf.finalize();
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf,
+                                              responder);
+        core.run(resolv.query((name("www.example.com."),
+                               Rtype::A))).unwrap();
+        let seen = seen.borrow();
+        assert_eq!(seen.len(), 2);
+        assert!(!seen[0].1);
+        assert_eq!(seen[1], ("www.example.com.".into(), true));
+    }
+
+    #[test]
+    fn resolver_udp_pool() {
+        let mut conf = ResolvConf::new();
+        let mut server = ServerConf::new("192.0.2.10:53".parse().unwrap());
+        server.udp_sockets = 3;
+        server.udp_socket_uses = 2;
+        conf.servers.push(server);
+        conf.finalize();
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf, zone());
+        let queries = (0..20).map(|_| {
+            resolv.clone().query((name("host.example.com."), Rtype::A))
+        }).collect::<Vec<_>>();
+        let responses = core.run(future::join_all(queries)).unwrap();
+        assert_eq!(responses.len(), 20);
+        for response in responses {
+            assert_eq!(response.answer().unwrap().count(), 1);
+        }
+    }
+
+    #[test]
+    fn resolver_routes() {
+        let mut conf = conf();
+        let server = |addr: &str| ServerConf::new(addr.parse().unwrap());
+        conf.routes.push(RouteConf::with_servers(
+            name("corp.example."), vec![server("192.0.2.20:53")]
+        ));
+        conf.routes.push(RouteConf::with_servers(
+            name("eng.corp.example."), vec![server("192.0.2.30:53")]
+        ));
+        conf.finalize();
+        let responder = |request: &Request| {
+            let mut msg = request.answer();
+            let addr = match request.server().ip() {
+                IpAddr::V4(addr) => addr,
+                IpAddr::V6(_) => unreachable!()
+            };
+            msg.push((request.question().unwrap().qname().clone(), 3600,
+                      A::new(addr))).unwrap();
+            Reply::Response(MessageBuf::from_vec(msg.finish()).unwrap())
+        };
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf,
+                                              responder);
+        for &(qname, addr) in &[("www.example.com.", [192, 0, 2, 10]),
+                                ("host.corp.example.", [192, 0, 2, 20]),
+                                ("host.eng.corp.example.", [192, 0, 2, 30])] {
+            let query = resolv.clone().query((name(qname), Rtype::A));
+            let response = core.run(query).unwrap();
+            let record = response.answer().unwrap().limit_to::<A>()
+                                 .next().unwrap().unwrap();
+            assert_eq!(record.data().addr(), Ipv4Addr::from(addr));
+        }
+        assert_eq!(resolv.server_stats().len(), 3);
+    }
+
+    #[test]
+    fn resolver_timeout() {
+        let mut conf = conf();
+        conf.timeout = ::std::time::Duration::from_millis(10);
+        conf.finalize();
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf,
+                                              |_: &Request| Reply::Timeout);
+        match core.run(resolv.query((name("example.com."), Rtype::A))) {
+            Err(ref err) if err.kind() == ErrorKind::Timeout => { }
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[test]
+    fn resolver_negative() {
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf(), zone());
+
+        let query = resolv.clone().query((name("missing.example.com."),
+                                          Rtype::A));
+        let err = core.run(query).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoName);
+        let response = err.response().unwrap();
+        assert_eq!(response.rcode(), Rcode::NXDomain);
+        assert_eq!(response.server().unwrap().addr,
+                   "192.0.2.10:53".parse().unwrap());
+        let soa = response.soa().unwrap();
+        assert_eq!(soa.name().to_string(), "example.com.");
+        assert_eq!(soa.data().minimum(), 300);
+
+        let query = resolv.clone().query((name("host.example.com."),
+                                          Rtype::Mx));
+        let err = core.run(query).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoData);
+        let response = err.response().unwrap();
+        assert_eq!(response.rcode(), Rcode::NoError);
+        assert!(response.soa().is_some());
+
+        let query = resolv.clone().query((name("www.example.com."),
+                                          Rtype::Mx));
+        let err = core.run(query).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoData);
+        assert_eq!(err.response().unwrap().message().answer().unwrap()
+                      .count(), 1);
+
+        let err = core.run(lookup_host(resolv, name("missing.example.com.")))
+                      .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoName);
+        assert_eq!(err.responses().len(), 2);
+    }
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<(EventKind, Protocol, bool)>>);
+
+    impl Observer for Recorder {
+        fn observe(&self, event: &Event) {
+            assert_eq!(event.server(), "192.0.2.10:53".parse().unwrap());
+            assert!(!event.query().is_empty());
+            self.0.lock().unwrap().push((event.kind(), event.protocol(),
+                                         event.response().is_some()));
+        }
+    }
+
+    #[test]
+    fn resolver_observer() {
+        let recorder = Arc::new(Recorder::default());
+        let mut conf = conf();
+        conf.observer = Some(recorder.clone());
+        let responder = move |request: &Request| {
+            let msg = request.answer();
+            let msg = MessageBuf::from_vec(msg.finish()).unwrap();
+            if request.is_stream() { Reply::Response(msg) }
+            else { Reply::Truncated(msg) }
+        };
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf,
+                                              responder);
+        core.run(resolv.query((name("example.com."), Rtype::A)))
+            .unwrap_err();
+        assert_eq!(*recorder.0.lock().unwrap(), vec![
+            (EventKind::Query, Protocol::Udp, false),
+            (EventKind::Response, Protocol::Udp, true),
+            (EventKind::SwitchToStream, Protocol::Udp, true),
+            (EventKind::Query, Protocol::Tcp, false),
+            (EventKind::Response, Protocol::Tcp, true),
+        ]);
+    }
+}
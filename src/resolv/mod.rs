@@ -6,7 +6,9 @@
 //! applications use DNS.
 //!
 //! This module implements a modern, asynchronous stub resolver built on
-//! top of [futures] and [tokio].
+//! top of [futures] and [tokio]. Optionally, the resolver can also find
+//! answers itself by iteratively asking the authoritative name servers,
+//! starting at the root servers given in the [hints] sub-module.
 //!
 //! The module provides ways to create a *resolver* that knows how to
 //! process DNS *queries*. A query asks for all the resource records
@@ -167,6 +169,16 @@
 //! currently available.
 //!
 //!
+//! # Validating Answers
+//!
+//! If the crate is built with the `dnssec` feature, the resolver can
+//! validate answers itself using DNSSEC. To do so, set the `trust_anchors`
+//! field of the [`ResolvConf`] to the trust anchors to start from, normally
+//! the root zone’s anchors available through `TrustAnchors::builtin()`.
+//! Queries will then set the AD bit of responses only if they were found
+//! to be secure. The [dnssec] sub-module explains the details.
+//!
+//!
 //! # The Run Shortcut
 //!
 //! If you only want to do a DNS lookup and don’t otherwise use tokio, there
@@ -194,11 +206,36 @@
 //! }
 //! ```
 //!
+//! Since `run()` creates a new reactor and resolver every time, nothing
+//! learned about the servers survives between calls. If you make many
+//! lookups from synchronous code, use a [`BlockingResolver`] instead. It
+//! keeps a resolver running on a reactor in a thread of its own and can
+//! be shared between threads:
+//!
+//! ```rust,no_run
+//! extern crate domain;
+//!
+//! use std::str::FromStr;
+//! use domain::bits::DNameBuf;
+//! use domain::resolv::BlockingResolver;
+//!
+//! fn main() {
+//!     let resolv = BlockingResolver::new().unwrap();
+//!     let name = DNameBuf::from_str("www.rust-lang.org").unwrap();
+//!     for addr in resolv.lookup_host(&name).unwrap().iter() {
+//!         println!("{}", addr);
+//!     }
+//! }
+//! ```
+//!
 //!
 //! [futures]: https://github.com/alexcrichton/futures-rs
 //! [tokio]: https://tokio.rs/
+//! [dnssec]: dnssec/index.html
+//! [hints]: hints/index.html
 //! [intro]: intro/index.html
 //! [lookup]: lookup/index.html
+//! [`BlockingResolver`]: struct.BlockingResolver.html
 //! [`Error`]: error/enum.Error.html
 //! [`MessageBuf`]: ../bits/message/struct.MessageBuf.html
 //! [`ResolvConf`]: conf/struct.ResolvConf.html
@@ -212,15 +249,25 @@
 
 //------------ Re-exports ----------------------------------------------------
 
+pub use self::blocking::BlockingResolver;
 pub use self::conf::ResolvConf;
-pub use self::public::{Query, Resolver};
+pub use self::public::{ConfRef, Query, QueryBuilder, Reload, Resolver};
+pub use self::server::ServerStats;
 
 
 //------------ Public Modules ------------------------------------------------
 
 pub mod conf;
+pub mod dnstap;
+#[cfg(feature = "dnssec")] pub mod dnssec;
 pub mod error;
+pub mod hints;
+pub mod hosts;
 pub mod lookup;
+pub mod mock;
+pub mod observer;
+pub mod policy;
+pub mod scrub;
 
 
 //------------ Meta-modules for Documentation --------------------------------
@@ -230,9 +277,17 @@ pub mod intro;
 
 //------------ Private Modules -----------------------------------------------
 
+mod blocking;
+mod cache;
+mod case;
 mod channel;
+#[cfg(feature = "doh")] mod doh;
+mod memory;
 mod public;
+mod recursion;
 mod request;
+mod server;
 mod tcp;
+#[cfg(feature = "tls")] mod tls;
 mod transport;
 mod udp;
@@ -0,0 +1,172 @@
+//! Observing what a resolver sends and receives.
+//!
+//! If the resolver configuration contains an [`Observer`] in its
+//! `observer` field, the observer is told about every request sent to a
+//! server and its outcome as well as about the decisions queries make
+//! along the way. Each such [`Event`] carries the address of the server,
+//! the [`Protocol`] used to reach it, the relevant timestamps, and the
+//! messages in wire format.
+//!
+//! The `dnstap` module contains an observer that writes these events as
+//! dnstap frames.
+//!
+//! [`Observer`]: trait.Observer.html
+//! [`Event`]: struct.Event.html
+//! [`Protocol`]: enum.Protocol.html
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+
+//------------ Observer ------------------------------------------------------
+
+/// A type that wants to know what a resolver is doing.
+///
+/// The observer is shared by all transports and queries of a resolver and
+/// may be called from different threads, hence the trait bounds. It is
+/// called synchronously from within the reactor, so it should return
+/// quickly and leave anything expensive, such as writing to a file, to
+/// some other thread.
+pub trait Observer: fmt::Debug + Send + Sync {
+    /// Processes an event.
+    fn observe(&self, event: &Event);
+}
+
+
+//------------ Event ---------------------------------------------------------
+
+/// Something that happened in the resolver.
+#[derive(Clone, Debug)]
+pub struct Event<'a> {
+    /// What happened.
+    kind: EventKind,
+
+    /// The address of the server concerned.
+    server: SocketAddr,
+
+    /// The protocol used for talking to the server.
+    protocol: Protocol,
+
+    /// When the request was sent.
+    query_time: SystemTime,
+
+    /// When the event happened.
+    time: SystemTime,
+
+    /// The request message.
+    query: &'a [u8],
+
+    /// The response message if there is one.
+    response: Option<&'a [u8]>,
+}
+
+impl<'a> Event<'a> {
+    /// Creates a new event.
+    pub fn new(kind: EventKind, server: SocketAddr, protocol: Protocol,
+               query_time: SystemTime, time: SystemTime, query: &'a [u8],
+               response: Option<&'a [u8]>) -> Self {
+        Event {
+            kind: kind,
+            server: server,
+            protocol: protocol,
+            query_time: query_time,
+            time: time,
+            query: query,
+            response: response,
+        }
+    }
+
+    /// Returns what happened.
+    pub fn kind(&self) -> EventKind {
+        self.kind
+    }
+
+    /// Returns the address of the server.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Returns the protocol used for talking to the server.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Returns when the request was sent.
+    ///
+    /// For `Query` events, this is the same as `time()`.
+    pub fn query_time(&self) -> SystemTime {
+        self.query_time
+    }
+
+    /// Returns when the event happened.
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+
+    /// Returns the request message in wire format.
+    ///
+    /// For events reported by transports, this is the message exactly as
+    /// sent, minus the length prefix of stream transports. For events
+    /// reported by queries, it is the message before the transport added
+    /// its message ID and OPT record.
+    pub fn query(&self) -> &'a [u8] {
+        self.query
+    }
+
+    /// Returns the response message in wire format if there is one.
+    ///
+    /// There is a response for `Response` events and for `SwitchToStream`
+    /// events where it is the truncated response.
+    pub fn response(&self) -> Option<&'a [u8]> {
+        self.response
+    }
+}
+
+
+//------------ EventKind -----------------------------------------------------
+
+/// The kind of an event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EventKind {
+    /// A transport has sent a request to a server.
+    Query,
+
+    /// A transport has received the response to a request.
+    Response,
+
+    /// A request has timed out.
+    Timeout,
+
+    /// A query is done with a server and moves on to the next one.
+    ///
+    /// This happens if the request failed or the response wasn’t
+    /// acceptable. The server of the event is the one the query is done
+    /// with. If there are no servers left, the query fails after this
+    /// event. Queries in blast mode don’t report this event since they ask
+    /// all servers at once.
+    NextServer,
+
+    /// A query received a truncated response and switches to stream
+    /// transports.
+    SwitchToStream,
+}
+
+
+//------------ Protocol ------------------------------------------------------
+
+/// The protocol used for talking to a server.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Protocol {
+    /// Plain DNS over UDP.
+    Udp,
+
+    /// Plain DNS over TCP.
+    Tcp,
+
+    /// DNS-over-TLS.
+    Tls,
+
+    /// DNS-over-HTTPS.
+    Https,
+}
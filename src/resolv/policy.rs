@@ -0,0 +1,202 @@
+//! Deciding whether to accept a response.
+//!
+//! When a query receives a response from an upstream server, it asks a
+//! [`ResponsePolicy`] what to do with it. The policy returns a [`Verdict`]
+//! that says whether the response is the final answer, whether it should
+//! only be used if no other server has anything better, or whether it is
+//! to be thrown away.
+//!
+//! Unless the resolver configuration provides its own policy, the
+//! [`StandardPolicy`] is used. It is built from the resolver configuration
+//! and implements, among other things, the `aa_only` option.
+//!
+//! [`ResponsePolicy`]: trait.ResponsePolicy.html
+//! [`Verdict`]: enum.Verdict.html
+//! [`StandardPolicy`]: struct.StandardPolicy.html
+
+use std::fmt;
+use ::bits::Message;
+use ::iana::Rcode;
+use super::conf::ResolvConf;
+
+
+//------------ Verdict -------------------------------------------------------
+
+/// What to do with a response.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    /// The response is the answer to the query.
+    Accept,
+
+    /// Try the next server but use the response if there is nothing better.
+    ///
+    /// If several servers give such responses, the last one is used.
+    Fallback,
+
+    /// Ignore the response and try the next server.
+    Reject,
+}
+
+
+//------------ ResponsePolicy ------------------------------------------------
+
+/// A policy for accepting responses.
+///
+/// The policy is shared by all queries of a resolver and may be called
+/// from different threads, hence the trait bounds.
+pub trait ResponsePolicy: fmt::Debug + Send + Sync {
+    /// Decides what to do with `response` received for `request`.
+    ///
+    /// The request message is the one sent to the server minus any
+    /// EDNS information added by the transport.
+    fn check(&self, request: &Message, response: &Message) -> Verdict;
+}
+
+
+//------------ StandardPolicy ------------------------------------------------
+
+/// The response policy used by default.
+///
+/// Each of the checks can be switched on or off individually. The checks
+/// happen in the order of the fields.
+#[derive(Clone, Debug)]
+pub struct StandardPolicy {
+    /// Reject responses that don’t answer the question asked.
+    ///
+    /// A response is an answer if it has the QR bit set and has the same
+    /// question as the request. This is on by default.
+    pub check_question: bool,
+
+    /// Check the recursion flags of the response.
+    ///
+    /// If this is on, which it is by default, responses that don’t echo
+    /// the RD bit of the request are rejected. Responses that claim
+    /// recursion isn’t available when it was desired are only used as a
+    /// fallback unless they are authoritative.
+    pub check_recursion: bool,
+
+    /// Reject responses that aren’t authoritative.
+    ///
+    /// This is taken from the `aa_only` option.
+    pub aa_only: bool,
+
+    /// Only use SERVFAIL and REFUSED responses as a fallback.
+    ///
+    /// Since another server may well be able to answer, these responses
+    /// only end the query if no server had anything better. This is on
+    /// by default.
+    pub retry_failures: bool,
+}
+
+impl StandardPolicy {
+    /// Creates the standard policy for a resolver configuration.
+    pub fn from_conf(conf: &ResolvConf) -> Self {
+        StandardPolicy {
+            check_question: true,
+            check_recursion: true,
+            aa_only: conf.options.aa_only,
+            retry_failures: true,
+        }
+    }
+}
+
+
+//--- ResponsePolicy
+
+impl ResponsePolicy for StandardPolicy {
+    fn check(&self, request: &Message, response: &Message) -> Verdict {
+        if self.check_question && !response.is_answer(request) {
+            return Verdict::Reject
+        }
+        let (req, resp) = (request.header(), response.header());
+        if self.check_recursion {
+            if req.rd() != resp.rd() {
+                return Verdict::Reject
+            }
+            if req.rd() && !resp.ra() && !resp.aa() {
+                return Verdict::Fallback
+            }
+        }
+        if self.aa_only && !resp.aa() {
+            return Verdict::Reject
+        }
+        if self.retry_failures {
+            match resp.rcode() {
+                Rcode::ServFail | Rcode::Refused => return Verdict::Fallback,
+                _ => { }
+            }
+        }
+        Verdict::Accept
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use ::bits::{ComposeMode, DNameBuf, MessageBuf, MessageBuilder};
+    use ::iana::{Rcode, Rtype};
+    use super::super::conf::ResolvConf;
+    use super::*;
+
+    fn request(name: &str) -> MessageBuf {
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, false)
+                                     .unwrap();
+        msg.header_mut().set_rd(true);
+        msg.push((DNameBuf::from_str(name).unwrap(), Rtype::A)).unwrap();
+        MessageBuf::from_vec(msg.finish()).unwrap()
+    }
+
+    fn response(request: &MessageBuf, edit: fn(&mut MessageBuf))
+                -> MessageBuf {
+        let mut response = request.clone();
+        response.header_mut().set_qr(true);
+        response.header_mut().set_ra(true);
+        edit(&mut response);
+        response
+    }
+
+    #[test]
+    fn standard() {
+        let mut conf = ResolvConf::new();
+        let policy = StandardPolicy::from_conf(&conf);
+        let req = request("example.com.");
+
+        assert_eq!(policy.check(&req, &response(&req, |_| { })),
+                   Verdict::Accept);
+        assert_eq!(policy.check(&req, &response(&request("example.net."),
+                                                 |_| { })),
+                   Verdict::Reject);
+        assert_eq!(policy.check(&req, &response(&req, |resp| {
+                       resp.header_mut().set_qr(false)
+                   })),
+                   Verdict::Reject);
+        assert_eq!(policy.check(&req, &response(&req, |resp| {
+                       resp.header_mut().set_rd(false)
+                   })),
+                   Verdict::Reject);
+        assert_eq!(policy.check(&req, &response(&req, |resp| {
+                       resp.header_mut().set_ra(false)
+                   })),
+                   Verdict::Fallback);
+        assert_eq!(policy.check(&req, &response(&req, |resp| {
+                       resp.header_mut().set_rcode(Rcode::Refused)
+                   })),
+                   Verdict::Fallback);
+        assert_eq!(policy.check(&req, &response(&req, |resp| {
+                       resp.header_mut().set_rcode(Rcode::NXDomain)
+                   })),
+                   Verdict::Accept);
+
+        conf.options.aa_only = true;
+        let policy = StandardPolicy::from_conf(&conf);
+        assert_eq!(policy.check(&req, &response(&req, |_| { })),
+                   Verdict::Reject);
+        assert_eq!(policy.check(&req, &response(&req, |resp| {
+                       resp.header_mut().set_aa(true)
+                   })),
+                   Verdict::Accept);
+    }
+}
@@ -3,16 +3,32 @@
 //! These two are here together because `Query` needs to be able to access 
 //! `Resolver`’s transport handles yet I don’t want to expose them publicly.
 
-use std::io;
-use std::sync::Arc;
-use futures::{Async, Future, Poll};
+use std::{fs, io, mem, ops};
+use std::io::Read;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use futures::{Async, Future, Poll, Stream};
+use futures::sync::{mpsc, oneshot};
 use rand::random;
 use tokio_core::reactor;
-use ::bits::{DName, MessageBuf, Question};
-use ::iana::Rcode;
-use super::conf::{ResolvConf, ResolvOptions};
-use super::error::Error;
-use super::request::{QueryRequest, RequestMessage, TransportHandle};
+use ::bits::{ComposeError, DName, DNameBuf, MessageBuf, Question};
+use ::bits::opt::OptData;
+use super::cache::{Cache, cache_key};
+use super::conf::{ResolvConf, ResolvOptions, ServerConf};
+#[cfg(feature = "dnssec")] use super::dnssec::Validate;
+use super::error::{Attempt, Error, ErrorKind};
+use super::observer::EventKind;
+use super::memory::memory_transports;
+use super::mock::Responder;
+use super::policy::{ResponsePolicy, StandardPolicy, Verdict};
+use super::recursion::{NameServers, Recursion};
+use super::scrub::scrub;
+use super::request::{BlastRequest, QueryRequest, RequestMessage,
+                     RequestOptions, TransportHandle, unacceptable_response};
+use super::server::{ServerInfo, ServerStats};
 use super::tcp::tcp_transport;
 use super::udp::udp_transport;
 
@@ -25,7 +41,8 @@ use super::udp::udp_transport;
 /// queries. You can create a new resoler using the system’s configuration
 /// using the [`new()`] associate function or using your own configuration
 /// with [`from_conf()`]. Either function will spawn everything necessary
-/// into a `tokio_core` reactor core represented by a handle.
+/// into a `tokio_core` reactor core represented by a handle. For testing,
+/// [`with_responder()`] creates a resolver that never touches the network.
 ///
 /// Resolver values can be cloned relatively cheaply as they keep all
 /// information behind an arc. This is may already be useful when starting
@@ -38,25 +55,91 @@ use super::udp::udp_transport;
 /// (even if this is nowhere near as dangerous as it sounds) and resolver by
 /// using the [`run()`] or [`run_with_conf()`] associated functions.
 ///
+/// The configuration of a resolver can be replaced while it is running
+/// via the [`reload()`] method. If the configuration was read from a file,
+/// [`check_conf()`] re-reads the file if it has changed and the
+/// configuration’s `watch_interval` field makes the resolver do so
+/// periodically. Queries already started finish with the old
+/// configuration and its servers while new ones, even if started from
+/// the same resolver value, use the new configuration.
+///
 /// [`new()`]: #method.new
 /// [`from_conf()`]: #method.from_conf
 /// [`query()`]: #method.query
+/// [`with_responder()`]: #method.with_responder
 /// [`run()`]: #method.run
 /// [`run_with_conf()`]: #method.run_with_conf
-#[derive(Clone, Debug)]
-pub struct Resolver(Arc<ResolverInner>);
+/// [`reload()`]: #method.reload
+/// [`check_conf()`]: #method.check_conf
+#[derive(Debug)]
+pub struct Resolver(Arc<ResolverInner>, Arc<Shared>);
 
 /// The actual resolver.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 struct ResolverInner {
-    /// Handles to all UDP transports.
-    udp: Vec<TransportHandle>,
+    /// Information about all servers.
+    ///
+    /// The default servers come first, followed by the servers of each
+    /// route.
+    servers: Vec<Arc<ServerInfo>>,
 
-    /// Handles to all TCP transports.
-    tcp: Vec<TransportHandle>,
+    /// The transports of the default servers.
+    transports: Transports,
+
+    /// The transports of the servers of each route.
+    ///
+    /// The routes are in the same order as in the configuration.
+    routes: Vec<Transports>,
 
     /// Our resolver configuration.
     conf: ResolvConf,
+
+    /// The response cache if caching is enabled.
+    cache: Option<Cache>,
+
+    /// The policy for accepting responses.
+    policy: Arc<ResponsePolicy>,
+
+    /// A remote for the reactor we are running on.
+    ///
+    /// We need this for starting timers in blast mode.
+    reactor: reactor::Remote,
+
+    /// The name servers for iterative resolution.
+    ///
+    /// This is `None` unless the configuration asks for iterative
+    /// resolution.
+    name_servers: Option<Arc<NameServers>>,
+}
+
+/// The part shared by all resolver values.
+#[derive(Debug)]
+struct Shared {
+    /// The current resolver.
+    ///
+    /// This is replaced by the reloader whenever the configuration
+    /// changes.
+    current: Arc<RwLock<Arc<ResolverInner>>>,
+
+    /// The sending end of the command channel to the reloader.
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Shared {
+    /// Returns the current resolver.
+    fn current(&self) -> Arc<ResolverInner> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Sends a command to the reloader.
+    fn command<F>(&self, command: F) -> Reload
+               where F: FnOnce(oneshot::Sender<bool>) -> Command {
+        let (tx, rx) = oneshot::channel();
+        match self.commands.unbounded_send(command(tx)) {
+            Ok(()) => Reload(Some(rx)),
+            Err(_) => Reload(None)
+        }
+    }
 }
 
 impl Resolver {
@@ -74,22 +157,52 @@ impl Resolver {
     /// referenced by `reactor`.
     pub fn from_conf(reactor: &reactor::Handle, conf: ResolvConf)
                      -> Self {
-        let mut udp = Vec::new();
-        let mut tcp = Vec::new();
+        let handle = reactor.clone();
+        Self::from_transports(reactor, conf, move |info| {
+            (udp_transport(&handle, info), tcp_transport(&handle, info))
+        })
+    }
 
-        for server in &conf.servers {
-            if let Some(transport) = udp_transport(reactor, server) {
-                udp.push(transport)
-            }
-            if let Some(transport) = tcp_transport(reactor, server) {
-                tcp.push(transport)
-            }
-        }
+    /// Creates a new resolver whose servers are answered by a responder.
+    ///
+    /// The resolver behaves exactly like one created via `from_conf()`
+    /// except that requests to the servers given in `conf` are not sent
+    /// over the network but handed to `responder` instead. This is
+    /// useful for testing code that uses the resolver. See the [mock]
+    /// module for details.
+    ///
+    /// [mock]: mock/index.html
+    pub fn with_responder<R>(reactor: &reactor::Handle, conf: ResolvConf,
+                             responder: R) -> Self
+                          where R: Responder + 'static {
+        let responder: Rc<Responder> = Rc::new(responder);
+        let handle = reactor.clone();
+        Self::from_transports(reactor, conf, move |info| {
+            memory_transports(&handle, info, &responder)
+        })
+    }
 
-        Resolver(Arc::new(ResolverInner {
-            udp: udp,
-            tcp: tcp,
-            conf: conf
+    /// Creates a new resolver using `transports` to create transports.
+    ///
+    /// The closure is called once for every server of the configuration
+    /// and returns the handles of the server’s UDP and TCP transports. It
+    /// is kept for creating the transports anew whenever the
+    /// configuration is reloaded.
+    fn from_transports<F>(reactor: &reactor::Handle, conf: ResolvConf,
+                          mut transports: F) -> Self
+                       where F: FnMut(&Arc<ServerInfo>)
+                                      -> (Option<TransportHandle>,
+                                          Option<TransportHandle>)
+                                + 'static {
+        let inner = Arc::new(ResolverInner::new(reactor, conf,
+                                                &mut transports));
+        let current = Arc::new(RwLock::new(inner.clone()));
+        let (tx, rx) = mpsc::unbounded();
+        reactor.spawn(Reloader::new(rx, current.clone(), reactor.clone(),
+                                    transports));
+        Resolver(inner, Arc::new(Shared {
+            current: current,
+            commands: tx,
         }))
     }
 
@@ -106,24 +219,146 @@ impl Resolver {
         Query::new(self, question)
     }
 
-    /// Returns a reference to the list of UDP service handles.
-    fn udp(&self) -> &[TransportHandle] {
-        &self.0.udp
+    /// Trades the resolver for a builder for a DNS query with options.
+    ///
+    /// The builder allows changing the flags of the request, the servers
+    /// to ask, the timeout and number of attempts, and adding EDNS options
+    /// for this one query. See [`QueryBuilder`] for details.
+    ///
+    /// [`QueryBuilder`]: struct.QueryBuilder.html
+    pub fn query_builder<N, Q>(self, question: Q) -> QueryBuilder
+                         where N: DName, Q: Into<Question<N>> {
+        QueryBuilder::new(self, question)
+    }
+
+    /// Trades the resolver for a validating DNS query.
+    ///
+    /// The returned future resolves into the response and the security
+    /// status of its answer. The response is validated against the trust
+    /// anchors of the resolver configuration. If there are none, the
+    /// status will always be indeterminate.
+    ///
+    /// Unlike `query()`, the future doesn’t turn negative responses into
+    /// errors since their security status is of interest, too.
+    #[cfg(feature = "dnssec")]
+    pub fn validate<N, Q>(self, question: Q) -> Validate
+                    where N: DName, Q: Into<Question<N>> {
+        Validate::new(self, question)
+    }
+
+    /// Returns the transports of the default servers or a route.
+    fn transports(&self, route: Option<usize>) -> &Transports {
+        match route {
+            Some(index) => &self.0.routes[index],
+            None => &self.0.transports
+        }
+    }
+
+    /// Returns the options to use for the default servers or a route.
+    fn route_options(&self, route: Option<usize>) -> &ResolvOptions {
+        match route {
+            Some(index) => &self.0.conf.routes[index].options,
+            None => &self.0.conf.options
+        }
+    }
+
+    /// Returns a reference to the response policy.
+    fn policy(&self) -> &Arc<ResponsePolicy> {
+        &self.0.policy
+    }
+
+    /// Returns a reference to the response cache if there is one.
+    fn cache(&self) -> Option<&Cache> {
+        self.0.cache.as_ref()
+    }
+
+    /// Removes all responses from the resolver’s cache.
+    ///
+    /// Does nothing if the resolver doesn’t have a cache.
+    pub fn clear_cache(&self) {
+        if let Some(ref cache) = self.1.current().cache {
+            cache.clear()
+        }
+    }
+
+    /// Returns the current statistics for all servers.
+    ///
+    /// The statistics are returned in the order of the servers in the
+    /// resolver configuration, starting with the default servers followed
+    /// by the servers of each route. They are for the current
+    /// configuration and start afresh whenever it is reloaded.
+    pub fn server_stats(&self) -> Vec<ServerStats> {
+        self.1.current().servers.iter().map(|info| info.stats()).collect()
     }
 
-    /// Returns a reference to the list of TCP service handles.
-    fn tcp(&self) -> &[TransportHandle] {
-        &self.0.tcp
+    /// Returns a reference to the current configuration of the resolver.
+    ///
+    /// The returned value keeps this configuration around even if it is
+    /// replaced in the meantime.
+    pub fn conf(&self) -> ConfRef {
+        ConfRef(self.1.current())
     }
 
-    /// Returns a reference to the configuration of this resolver.
-    pub fn conf(&self) -> &ResolvConf {
+    /// Returns the current configuration options of the resolver.
+    pub fn options(&self) -> ResolvOptions {
+        self.1.current().conf.options.clone()
+    }
+
+    /// Returns the resolver with the current configuration.
+    ///
+    /// Queries keep using the configuration of the resolver value they
+    /// were started with, so they call this first.
+    fn refresh(self) -> Self {
+        Resolver(self.1.current(), self.1)
+    }
+
+    /// Returns the configuration of this resolver value.
+    ///
+    /// Unlike `conf()`, this is the configuration that goes with the
+    /// value’s transports.
+    fn query_conf(&self) -> &ResolvConf {
         &self.0.conf
     }
 
-    /// Returns a reference to the configuration options of this resolver.
-    pub fn options(&self) -> &ResolvOptions {
-        &self.0.conf.options
+    /// Replaces the configuration of the resolver.
+    ///
+    /// New transports are created for the servers of `conf` and all
+    /// queries started after the returned future has resolved use them.
+    /// Queries already in flight finish with the old servers whose
+    /// transports are closed once the last of them is done. This affects
+    /// all clones of the resolver.
+    ///
+    /// The future resolves into `true` once the configuration has been
+    /// replaced. It fails if the resolver’s reactor has gone away.
+    pub fn reload(&self, conf: ResolvConf) -> Reload {
+        self.1.command(|tx| Command::Reload(conf, tx))
+    }
+
+    /// Re-reads the configuration file if it has changed.
+    ///
+    /// If the current configuration was read from a file via
+    /// `ResolvConf::parse_file()` and the file has been modified since,
+    /// the configuration is parsed again and replaces the current one as
+    /// if given to [`reload()`]. Only the settings read from the file
+    /// change, everything else is kept. If the file cannot be read, the
+    /// current configuration stays in place.
+    ///
+    /// The returned future resolves into whether the configuration was
+    /// replaced. If the configuration’s `watch_interval` field is set,
+    /// the resolver does this by itself at that interval.
+    ///
+    /// [`reload()`]: #method.reload
+    pub fn check_conf(&self) -> Reload {
+        self.1.command(Command::Check)
+    }
+}
+
+
+//--- Clone
+
+impl Clone for Resolver {
+    fn clone(&self) -> Self {
+        Resolver(self.1.current(), self.1.clone())
     }
 }
 
@@ -164,6 +399,354 @@ impl Resolver {
 }
 
 
+//------------ ConfRef -------------------------------------------------------
+
+/// A reference to the configuration of a resolver.
+///
+/// A value of this type is returned by [`Resolver::conf()`]. It
+/// dereferences into the configuration that was current at that time.
+///
+/// [`Resolver::conf()`]: struct.Resolver.html#method.conf
+#[derive(Clone, Debug)]
+pub struct ConfRef(Arc<ResolverInner>);
+
+
+//--- Deref
+
+impl ops::Deref for ConfRef {
+    type Target = ResolvConf;
+
+    fn deref(&self) -> &ResolvConf {
+        &self.0.conf
+    }
+}
+
+
+//------------ Transports ----------------------------------------------------
+
+/// The transports for a list of servers.
+#[derive(Debug)]
+struct Transports {
+    /// Handles to all UDP transports.
+    udp: Vec<TransportHandle>,
+
+    /// Handles to all TCP transports.
+    tcp: Vec<TransportHandle>,
+}
+
+impl Transports {
+    /// Creates the transports for `servers`.
+    ///
+    /// The information for each server is created from `conf` and added
+    /// to `infos`. The closure `transports` creates the server’s UDP and
+    /// TCP transports.
+    fn new<F>(servers: &[ServerConf], conf: &ResolvConf,
+              infos: &mut Vec<Arc<ServerInfo>>, transports: &mut F) -> Self
+           where F: FnMut(&Arc<ServerInfo>)
+                          -> (Option<TransportHandle>,
+                              Option<TransportHandle>) {
+        let mut udp = Vec::new();
+        let mut tcp = Vec::new();
+        for server in servers {
+            let info = Arc::new(ServerInfo::new(server.clone(), conf));
+            let (udp_handle, tcp_handle) = transports(&info);
+            if let Some(transport) = udp_handle {
+                udp.push(transport)
+            }
+            if let Some(transport) = tcp_handle {
+                tcp.push(transport)
+            }
+            infos.push(info)
+        }
+        Transports {
+            udp: udp,
+            tcp: tcp,
+        }
+    }
+}
+
+
+//------------ ResolverInner -------------------------------------------------
+
+impl ResolverInner {
+    /// Creates a new resolver for a configuration.
+    ///
+    /// The closure `transports` creates the transports for each server.
+    fn new<F>(reactor: &reactor::Handle, conf: ResolvConf,
+              transports: &mut F) -> Self
+           where F: FnMut(&Arc<ServerInfo>)
+                          -> (Option<TransportHandle>,
+                              Option<TransportHandle>) {
+        let mut servers = Vec::new();
+        let default = Transports::new(&conf.servers, &conf, &mut servers,
+                                      transports);
+        let routes = conf.routes.iter().map(|route| {
+            // The servers of a route use the route’s options.
+            let mut route_conf = conf.clone();
+            route_conf.options = route.options.clone();
+            Transports::new(&route.servers, &route_conf, &mut servers,
+                            transports)
+        }).collect();
+
+        let cache = if conf.cache_size > 0 {
+            Some(Cache::new(conf.cache_size))
+        }
+        else {
+            None
+        };
+
+        let policy = match conf.response_policy {
+            Some(ref policy) => policy.clone(),
+            None => Arc::new(StandardPolicy::from_conf(&conf))
+        };
+
+        let name_servers = NameServers::new(&conf, reactor).map(Arc::new);
+
+        ResolverInner {
+            servers: servers,
+            transports: default,
+            routes: routes,
+            conf: conf,
+            cache: cache,
+            policy: policy,
+            reactor: reactor.remote().clone(),
+            name_servers: name_servers,
+        }
+    }
+}
+
+
+//------------ Command -------------------------------------------------------
+
+/// A command for the reloader.
+#[derive(Debug)]
+enum Command {
+    /// Replace the configuration with the given one.
+    Reload(ResolvConf, oneshot::Sender<bool>),
+
+    /// Re-read the configuration file if it has changed.
+    Check(oneshot::Sender<bool>),
+}
+
+
+//------------ Reloader ------------------------------------------------------
+
+/// The future that replaces the configuration of a resolver.
+///
+/// The reloader is spawned into the reactor when the resolver is created.
+/// It processes the commands sent by the resolver values and, if the
+/// configuration asks for it, checks the configuration file for changes
+/// periodically. It ends when the last resolver value is dropped.
+struct Reloader<F> {
+    /// The receiving end of the command channel.
+    commands: mpsc::UnboundedReceiver<Command>,
+
+    /// The current resolver shared with all resolver values.
+    current: Arc<RwLock<Arc<ResolverInner>>>,
+
+    /// A handle to the reactor for creating transports and the interval.
+    reactor: reactor::Handle,
+
+    /// The closure creating the transports for a server.
+    transports: F,
+
+    /// The state of the configuration file when last read.
+    file: FileState,
+
+    /// The interval for checking the configuration file.
+    interval: Option<reactor::Interval>,
+}
+
+impl<F> Reloader<F>
+     where F: FnMut(&Arc<ServerInfo>)
+                    -> (Option<TransportHandle>, Option<TransportHandle>) {
+    /// Creates a new reloader.
+    fn new(commands: mpsc::UnboundedReceiver<Command>,
+           current: Arc<RwLock<Arc<ResolverInner>>>,
+           reactor: reactor::Handle, transports: F) -> Self {
+        let mut res = Reloader {
+            commands: commands,
+            current: current,
+            reactor: reactor,
+            transports: transports,
+            file: FileState::default(),
+            interval: None,
+        };
+        res.watch();
+        res
+    }
+
+    /// Returns the current configuration.
+    fn conf(&self) -> ResolvConf {
+        self.current.read().unwrap().conf.clone()
+    }
+
+    /// Prepares watching the configuration file of the current
+    /// configuration.
+    fn watch(&mut self) {
+        let conf = self.conf();
+        self.file = FileState::default();
+        if let Some(ref path) = conf.path {
+            self.file.update(path);
+        }
+        self.interval = conf.watch_interval.and_then(|interval| {
+            reactor::Interval::new(interval, &self.reactor).ok()
+        });
+    }
+
+    /// Replaces the current configuration.
+    fn replace(&mut self, conf: ResolvConf) {
+        let inner = ResolverInner::new(&self.reactor, conf,
+                                       &mut self.transports);
+        *self.current.write().unwrap() = Arc::new(inner);
+        self.watch();
+    }
+
+    /// Re-reads the configuration file if it has changed.
+    ///
+    /// Returns whether the configuration was replaced.
+    fn check(&mut self) -> bool {
+        let conf = self.conf();
+        let changed = match conf.path {
+            Some(ref path) => self.file.update(path),
+            None => false
+        };
+        if !changed {
+            return false
+        }
+        match conf.reparse() {
+            Ok(conf) => {
+                self.replace(conf);
+                true
+            }
+            Err(_) => false
+        }
+    }
+}
+
+
+//--- Future
+
+impl<F> Future for Reloader<F>
+     where F: FnMut(&Arc<ServerInfo>)
+                    -> (Option<TransportHandle>, Option<TransportHandle>) {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.commands.poll()? {
+                Async::Ready(Some(Command::Reload(conf, tx))) => {
+                    self.replace(conf);
+                    tx.send(true).ok();
+                }
+                Async::Ready(Some(Command::Check(tx))) => {
+                    let res = self.check();
+                    tx.send(res).ok();
+                }
+                Async::Ready(None) => return Ok(Async::Ready(())),
+                Async::NotReady => break
+            }
+        }
+        loop {
+            let tick = match self.interval {
+                Some(ref mut interval) => interval.poll(),
+                None => break
+            };
+            match tick {
+                Ok(Async::Ready(Some(()))) => {
+                    self.check();
+                }
+                Ok(Async::NotReady) => break,
+                Ok(Async::Ready(None)) | Err(_) => {
+                    self.interval = None;
+                    break
+                }
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+
+//------------ FileState -----------------------------------------------------
+
+/// What we know about a configuration file.
+#[derive(Clone, Debug, Default)]
+struct FileState {
+    /// The modification time of the file.
+    modified: Option<SystemTime>,
+
+    /// The length of the file.
+    len: u64,
+
+    /// The content of the file.
+    ///
+    /// This is `None` if the file couldn’t be read.
+    content: Option<Vec<u8>>,
+}
+
+impl FileState {
+    /// Updates the state from the file at `path`.
+    ///
+    /// Returns whether the file has changed. Since modification times may
+    /// be too coarse to notice quick changes, the content is compared,
+    /// too, unless both the modification time and the length are
+    /// unchanged.
+    fn update(&mut self, path: &Path) -> bool {
+        let (modified, len) = match fs::metadata(path) {
+            Ok(metadata) => (metadata.modified().ok(), metadata.len()),
+            Err(_) => (None, 0)
+        };
+        if self.content.is_some() && modified.is_some()
+                && modified == self.modified && len == self.len {
+            return false
+        }
+        let mut content = Vec::new();
+        let content = match fs::File::open(path)
+                                    .and_then(|mut file| {
+                                        file.read_to_end(&mut content)
+                                    }) {
+            Ok(_) => Some(content),
+            Err(_) => None
+        };
+        let changed = content != self.content;
+        self.modified = modified;
+        self.len = len;
+        self.content = content;
+        changed
+    }
+}
+
+
+//------------ Reload --------------------------------------------------------
+
+/// The future returned by `Resolver::reload()` and `Resolver::check_conf()`.
+///
+/// It resolves into whether the configuration of the resolver was
+/// replaced. It fails if the resolver has gone away, for instance because
+/// the reactor it was running on has been dropped.
+pub struct Reload(Option<oneshot::Receiver<bool>>);
+
+
+//--- Future
+
+impl Future for Reload {
+    type Item = bool;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<bool, io::Error> {
+        let res = match self.0 {
+            Some(ref mut rx) => rx.poll(),
+            None => Err(oneshot::Canceled)
+        };
+        res.map_err(|_| {
+            io::Error::new(io::ErrorKind::Other, "resolver has gone away")
+        })
+    }
+}
+
+
 //------------ Query ---------------------------------------------------------
 
 /// A DNS query.
@@ -176,41 +759,370 @@ impl Resolver {
 ///
 /// While you can start a query directly by calling the `Query::new()`
 /// function, the resolver’s `query()` method may be more convenient.
+///
+/// If the resolver has a cache and it contains a response for the
+/// question, the query resolves right away with that response. If the
+/// resolver is configured for iterative resolution, the query asks the
+/// authoritative name servers itself rather than the configured servers.
+///
+/// If the resolver configuration contains trust anchors, the response is
+/// validated before the query resolves. The AD bit of the response is
+/// set if the answer was found to be secure and cleared otherwise.
+///
+/// Negative responses make the query fail. NXDOMAIN becomes an error of
+/// kind `NoName`, a response without records of the requested type one
+/// of kind `NoData`. The error keeps the response and the server that
+/// sent it.
+pub struct Query(Kind);
+
+/// How a query treats its response.
+enum Kind {
+    /// The response is taken as it is.
+    Plain(RawQuery),
+
+    /// The response is validated.
+    #[cfg(feature = "dnssec")]
+    Validating(Validate),
+}
+
+impl Query {
+    /// Starts a new query.
+    pub fn new<N, Q>(resolv: Resolver, question: Q) -> Self
+               where N: DName, Q: Into<Question<N>> {
+        #[cfg(feature = "dnssec")]
+        {
+            if resolv.conf().trust_anchors.is_some() {
+                return Query(Kind::Validating(Validate::new(resolv,
+                                                            question)))
+            }
+        }
+        Query(Kind::Plain(RawQuery::new(resolv, question)))
+    }
+}
+
+
+//--- Future
+
+impl Future for Query {
+    type Item = MessageBuf;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (response, server, attempts) = match self.0 {
+            Kind::Plain(ref mut query) => {
+                let response = try_ready!(query.poll());
+                (response, query.server().cloned(), query.take_attempts())
+            }
+            #[cfg(feature = "dnssec")]
+            Kind::Validating(ref mut query) => {
+                let (mut response, security) = try_ready!(query.poll());
+                response.header_mut().set_ad(security.is_secure());
+                (response, query.server().cloned(), query.take_attempts())
+            }
+        };
+        finish(response, server, attempts).map(Async::Ready)
+    }
+}
+
+/// Turns a final response into the query’s result.
+///
+/// Negative responses, i.e., NXDOMAIN, NODATA, and other error response
+/// codes, become errors that keep the response, the server that sent it,
+/// and the requests that failed before.
+fn finish(response: MessageBuf, server: Option<ServerConf>,
+          attempts: Vec<Attempt>) -> Result<MessageBuf, Error> {
+    Error::from_response(response, server).map_err(|mut err| {
+        err.add_attempts(attempts);
+        err
+    })
+}
+
+
+//------------ QueryBuilder --------------------------------------------------
+
+/// A builder for a DNS query with options of its own.
+///
+/// Normally, everything about a query is determined by the resolver
+/// configuration. A query builder, created via the resolver’s
+/// `query_builder()` method, allows deviating from it for a single query
+/// and then starting the query through `start()`.
+///
+/// The recursion desired (RD) and checking disabled (CD) bits of the
+/// request can be set or cleared, as can the DNSSEC OK (DO) bit of its OPT
+/// record. Arbitrary EDNS options, such as `ClientSubnet` or `Nsid`, can be
+/// added. If the DO bit is set or there are EDNS options, requests always
+/// include an OPT record, even to servers not believed to support EDNS.
+///
+/// The query can be sent over TCP right away and can be limited to some of
+/// the resolver’s servers. The timeout for each request and the number of
+/// attempts can be changed, too.
+///
+/// Queries with changed flags, EDNS options, or servers bypass the cache
+/// since their responses may differ from what the resolver would normally
+/// get. They are also never validated by the resolver itself, so the AD
+/// bit is whatever the server said. If the resolver resolves iteratively,
+/// the options are ignored.
+pub struct QueryBuilder {
+    /// The resolver to use.
+    resolver: Resolver,
+
+    /// The question to ask.
+    question: Question<DNameBuf>,
+
+    /// The options for the query.
+    options: QueryOptions,
+}
+
+impl QueryBuilder {
+    /// Creates a new query builder without any options.
+    pub fn new<N, Q>(resolver: Resolver, question: Q) -> Self
+               where N: DName, Q: Into<Question<N>> {
+        let question = question.into();
+        QueryBuilder {
+            resolver: resolver,
+            question: Question::new(question.qname().to_cow().into_owned(),
+                                    question.qtype(), question.qclass()),
+            options: QueryOptions::default(),
+        }
+    }
+
+    /// Sets or clears the recursion desired bit.
+    ///
+    /// By default, the bit is set unless the `recurse` option is cleared.
+    pub fn rd(mut self, rd: bool) -> Self {
+        self.options.rd = Some(rd);
+        self
+    }
+
+    /// Sets or clears the checking disabled bit.
+    ///
+    /// By default, the bit is only set if the resolver validates answers.
+    pub fn cd(mut self, cd: bool) -> Self {
+        self.options.cd = Some(cd);
+        self
+    }
+
+    /// Sets or clears the DNSSEC OK bit.
+    ///
+    /// By default, the bit is only set if the resolver validates answers.
+    pub fn dnssec_ok(mut self, dnssec_ok: bool) -> Self {
+        self.options.request.dnssec_ok = Some(dnssec_ok);
+        self
+    }
+
+    /// Sends the query over TCP only.
+    ///
+    /// This is the same as the `use_vc` option but for this query only.
+    pub fn use_vc(mut self) -> Self {
+        self.options.use_vc = true;
+        self
+    }
+
+    /// Limits the query to the server with the given address.
+    ///
+    /// This can be called multiple times to ask a subset of the
+    /// resolver’s servers. Only servers from the resolver configuration
+    /// can be used. If none of them has any of the given addresses, the
+    /// query fails.
+    pub fn server(mut self, addr: SocketAddr) -> Self {
+        self.options.servers.push(addr);
+        self
+    }
+
+    /// Sets the timeout for each request of the query.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.request.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the number of attempts to ask each server.
+    ///
+    /// If `attempts` is zero, each server is still asked once.
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.options.attempts = Some(attempts);
+        self
+    }
+
+    /// Adds an EDNS option to the OPT record of the requests.
+    ///
+    /// If the option cannot be assembled, the query will fail with a
+    /// question error.
+    pub fn edns_option<O: OptData>(mut self, option: O) -> Self {
+        if let Err(err) = self.options.request.push_edns_option(option) {
+            self.options.error = Some(err)
+        }
+        self
+    }
+
+    /// Starts the query.
+    pub fn start(self) -> Query {
+        Query(Kind::Plain(RawQuery::with_options(self.resolver,
+                                                 self.question,
+                                                 self.options)))
+    }
+}
+
+
+//------------ QueryOptions --------------------------------------------------
+
+/// The options of a query deviating from the resolver configuration.
+#[derive(Clone, Debug, Default)]
+struct QueryOptions {
+    /// The value of the RD bit if it should be changed.
+    rd: Option<bool>,
+
+    /// The value of the CD bit if it should be changed.
+    cd: Option<bool>,
+
+    /// Should we only use TCP?
+    use_vc: bool,
+
+    /// The addresses of the servers to ask or empty for all servers.
+    servers: Vec<SocketAddr>,
+
+    /// The number of attempts if it should be changed.
+    attempts: Option<usize>,
+
+    /// The options for each request.
+    request: RequestOptions,
+
+    /// An error that happened while building the options.
+    error: Option<ComposeError>,
+}
+
+impl QueryOptions {
+    /// Returns whether a query with these options may use the cache.
+    fn cacheable(&self) -> bool {
+        self.rd.is_none() && self.cd.is_none() && self.servers.is_empty()
+            && self.request.dnssec_ok.is_none()
+            && self.request.edns_options.is_empty()
+    }
+
+    /// Returns whether the service at `index` in `track` may be asked.
+    fn allows(&self, track: &[TransportHandle], index: usize) -> bool {
+        self.servers.is_empty()
+            || self.servers.contains(&track[index].info().conf().addr)
+    }
+}
+
+
+//------------ RawQuery ------------------------------------------------------
+
+/// A query resolving into the response as received.
+///
+/// This is what a `Query` does minus converting negative responses into
+/// errors and validation. It is used by validation for retrieving the
+/// records of the chain of trust.
 //
-//  Since a query can fail very early on when building the request message,
-//  but we don’t really want to complicate things by `Query::new()` returning
-//  a result, it internally consists of that `Result`. If it is `Ok()`, we
-//  really do have a query to poll, otherwise we have an error to take out
-//  and return.
+//  Since a query can fail very early on when building the request message
+//  or be answered from the cache, but we don’t really want to complicate
+//  things by `RawQuery::new()` returning a result, it internally consists
+//  of a `Result`. If it is `Ok()`, we really do have a query to poll,
+//  otherwise we have the final outcome to take out and return.
 //
 //  This is okay because the early failure should be rather unlikely.
-pub struct Query(Result<QueryInner, Option<Error>>);
+pub struct RawQuery(Result<Mode, Option<Result<MessageBuf, Error>>>);
 
-impl Query {
+impl RawQuery {
     /// Starts a new query.
     pub fn new<N, Q>(resolv: Resolver, question: Q) -> Self
                where N: DName, Q: Into<Question<N>> {
-        let message = match RequestMessage::new(question, resolv.conf()) {
+        Self::with_options(resolv, question, QueryOptions::default())
+    }
+
+    /// Starts a new query with the given options.
+    fn with_options<N, Q>(resolv: Resolver, question: Q,
+                          options: QueryOptions) -> Self
+                    where N: DName, Q: Into<Question<N>> {
+        let resolv = resolv.refresh();
+        let question = question.into();
+        if let Some(err) = options.error {
+            return RawQuery(Err(Some(Err(err.into()))))
+        }
+        if !options.servers.is_empty() && !resolv.0.servers.iter().any(|info| {
+            options.servers.contains(&info.conf().addr)
+        }) {
+            return RawQuery(Err(Some(Err(
+                io::Error::new(io::ErrorKind::NotFound,
+                               "no server with the given address").into()
+            ))))
+        }
+        let cache = if options.cacheable() { resolv.cache() }
+                    else { None };
+        let key = match cache {
+            Some(cache) => {
+                let key = cache_key(&question);
+                if let Some(response) = cache.get(&key) {
+                    return RawQuery(Err(Some(Ok(response))))
+                }
+                Some(key)
+            }
+            None => None
+        };
+        let recursion = resolv.0.name_servers.as_ref().map(|servers| {
+            Recursion::new(servers.clone(), &question)
+        });
+        if let Some(recursion) = recursion {
+            return RawQuery(Ok(Mode::Recursive(RecursiveQuery {
+                resolver: resolv,
+                recursion: recursion,
+                cache_key: key,
+            })))
+        }
+        let route = resolv.query_conf().route(&question.qname().to_cow());
+        let mut message = match RequestMessage::new(question,
+                                                    resolv.query_conf()) {
             Ok(message) => message,
-            Err(err) => return Query(Err(Some(err.into())))
+            Err(err) => return RawQuery(Err(Some(Err(err.into()))))
         };
-        Query(Ok(QueryInner::new(resolv, message)))
+        message.set_rd(options.rd.unwrap_or(
+            resolv.route_options(route).recurse
+        ));
+        if let Some(cd) = options.cd {
+            message.set_cd(cd)
+        }
+        message.set_options(options.request.clone());
+        RawQuery(Ok(Mode::Stub(QueryInner::new(resolv, message, key,
+                                               options, route))))
+    }
+
+    /// Returns the configuration of the server that sent the response.
+    ///
+    /// This is `None` before the query has resolved or if the response
+    /// didn’t come from one of the configured servers.
+    pub fn server(&self) -> Option<&ServerConf> {
+        match self.0 {
+            Ok(Mode::Stub(ref inner)) => inner.answered.as_ref(),
+            _ => None
+        }
+    }
+
+    /// Takes the requests that failed before the query resolved.
+    pub fn take_attempts(&mut self) -> Vec<Attempt> {
+        match self.0 {
+            Ok(Mode::Stub(ref mut inner)) => {
+                mem::replace(&mut inner.attempts, Vec::new())
+            }
+            _ => Vec::new()
+        }
     }
 }
 
 
 //--- Future
 
-impl Future for Query {
+impl Future for RawQuery {
     type Item = MessageBuf;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         match self.0 {
-            Ok(ref mut inner) => inner.poll(),
-            Err(ref mut err) => {
-                match err.take() {
-                    Some(err) => Err(err),
+            Ok(Mode::Stub(ref mut inner)) => inner.poll(),
+            Ok(Mode::Recursive(ref mut inner)) => inner.poll(),
+            Err(ref mut res) => {
+                match res.take() {
+                    Some(Ok(response)) => Ok(Async::Ready(response)),
+                    Some(Err(err)) => Err(err),
                     None => panic!("polling a resolved Query")
                 }
             }
@@ -219,6 +1131,18 @@ impl Future for Query {
 }
 
 
+//------------ Mode ----------------------------------------------------------
+
+/// How a query is being resolved.
+enum Mode {
+    /// By asking the configured servers.
+    Stub(QueryInner),
+
+    /// By asking the authoritative name servers.
+    Recursive(RecursiveQuery),
+}
+
+
 //------------ QueryInner ----------------------------------------------------
 
 struct QueryInner {
@@ -226,7 +1150,7 @@ struct QueryInner {
     resolver: Resolver,
 
     /// The request we are currently processing.
-    request: QueryRequest,
+    request: Request,
 
     /// Are we on datagram track?
     ///
@@ -236,115 +1160,280 @@ struct QueryInner {
     /// the stream track if we receive a truncated response.
     dgram: bool,
 
-    /// The index of the service we started at.
+    /// The order in which to ask the services during this attempt.
     ///
-    /// The index references either `resolver.udp` or `resolver.tcp`,
-    /// depending on the value of `dgram`. We need to keep the index we
-    /// started at because of the rotate option which makes us start at a
-    /// random service.
-    start_index: usize,
+    /// The indexes reference either `resolver.udp` or `resolver.tcp`,
+    /// depending on the value of `dgram`. The order depends on the rotate
+    /// option, the servers’ round trip times, and whether they are held
+    /// down.
+    order: Vec<usize>,
 
-    /// The index of the service we currently are using.
-    curr_index: usize,
+    /// The index in `order` of the service we currently are using.
+    pos: usize,
 
     /// The how-many-th attempt this is, starting at attempt 0.
     attempt: usize,
+
+    /// The question to store the response under in the cache.
+    ///
+    /// This is `None` if the resolver doesn’t have a cache.
+    cache_key: Option<Question<DNameBuf>>,
+
+    /// The last response the response policy deemed a fallback.
+    ///
+    /// If we run out of servers, this response becomes the result. It is
+    /// kept together with the server that sent it.
+    fallback: Option<(MessageBuf, Option<ServerConf>)>,
+
+    /// The server that sent the response the query resolved into.
+    answered: Option<ServerConf>,
+
+    /// The requests that failed so far.
+    attempts: Vec<Attempt>,
+
+    /// The options of the query.
+    options: QueryOptions,
+
+    /// The index of the route for the query name.
+    ///
+    /// If this is `None`, the default servers are used.
+    route: Option<usize>,
 }
 
 
 impl QueryInner {
-    fn new(resolver: Resolver, message: RequestMessage) -> Self {
-        let dgram = !resolver.options().use_vc;
-        let (index, request) = Self::start(&resolver, dgram, message);
+    fn new(resolver: Resolver, message: RequestMessage,
+           cache_key: Option<Question<DNameBuf>>, options: QueryOptions,
+           route: Option<usize>) -> Self {
+        // Servers reached via TLS don’t have a datagram transport, so if
+        // they are all we have, we start out in stream track right away.
+        let dgram = {
+            let udp = &resolver.transports(route).udp;
+            !resolver.route_options(route).use_vc && !options.use_vc
+                && (0..udp.len()).any(|i| options.allows(udp, i))
+        };
+        let (order, request) = Self::start(&resolver, &options, route,
+                                           dgram, message);
         QueryInner {
             resolver: resolver,
             request: request,
             dgram: dgram,
-            start_index: index,
-            curr_index: index,
-            attempt: 0
+            order: order,
+            pos: 0,
+            attempt: 0,
+            cache_key: cache_key,
+            fallback: None,
+            answered: None,
+            attempts: Vec::new(),
+            options: options,
+            route: route,
         }
     }
 
     /// Processes a response received from a request.
     ///
-    /// This will either resolve the future or switch to stream mode and
-    /// continue.
+    /// This will either resolve the future, switch to stream mode, or move
+    /// on to the next server if the response policy doesn’t accept the
+    /// response.
     fn response(&mut self, response: MessageBuf, message: RequestMessage)
                 -> Poll<MessageBuf, Error> {
+        let server = self.request.server().cloned();
+        let verdict = self.resolver.policy().check(message.as_ref(),
+                                                   &response);
+        let response = match verdict {
+            Verdict::Reject => None,
+            _ => self.scrub(response)
+        };
+        let response = match response {
+            Some(response) => response,
+            None => return self.error(unacceptable_response(), message)
+        };
+        if verdict == Verdict::Fallback {
+            self.fallback = Some((response, server));
+            return self.error(unacceptable_response(), message)
+        }
         if response.header().tc() && self.dgram
-                && !self.resolver.options().ign_tc {
+                && !self.resolv_options().ign_tc {
+            self.observe(EventKind::SwitchToStream, &message,
+                         Some(&response));
             self.start_stream(message)
         }
-        else if response.header().rcode() != Rcode::NoError {
-            Err(response.header().rcode().into())
-        }
         else {
+            if let Some(key) = self.cache_key.take() {
+                if let Some(cache) = self.resolver.cache() {
+                    cache.insert(key, &response)
+                }
+            }
+            self.answered = server;
             Ok(Async::Ready(response))
         }
     }
 
+    /// Removes out-of-bailiwick records if the resolver is configured so.
+    ///
+    /// Returns `None` if the response turns out to be broken.
+    fn scrub(&self, response: MessageBuf) -> Option<MessageBuf> {
+        if self.resolver.query_conf().scrub {
+            scrub(&response).ok()
+        }
+        else {
+            Some(response)
+        }
+    }
+
     /// Processes an error received from a request.
     ///
-    /// Proceeds to the next request or errors out.
-    fn error(&mut self, _error: Error, message: RequestMessage)
+    /// Records the failed request and proceeds to the next request or
+    /// errors out. If we have a fallback response when running out of
+    /// servers, it becomes the result.
+    fn error(&mut self, error: Error, message: RequestMessage)
              -> Poll<MessageBuf, Error> {
-        self.curr_index = (self.curr_index + 1) % self.track().len();
-        if self.curr_index == self.start_index {
+        self.observe(EventKind::NextServer, &message, None);
+        // A blast request has already recorded its attempts.
+        let error = match self.request {
+            Request::Single(ref request) => {
+                Error::from_attempt(request.server().clone(), error)
+            }
+            Request::Blast(_) => error
+        };
+        self.attempts.append(&mut error.into_attempts());
+        // In blast mode, the request already went to all services of the
+        // track, so we are through with this attempt.
+        self.pos = if self.resolv_options().blast { self.order.len() }
+                   else { self.pos + 1 };
+        if self.pos >= self.order.len() {
             self.attempt += 1;
-            if self.attempt == self.resolver.conf().attempts {
-                return Err(Error::Timeout)
+            let attempts = self.options.attempts
+                               .unwrap_or(self.resolver.query_conf().attempts);
+            if self.attempt >= attempts {
+                return match self.fallback.take() {
+                    Some((response, server)) => {
+                        self.answered = server;
+                        Ok(Async::Ready(response))
+                    }
+                    None => {
+                        let mut error = Error::new(ErrorKind::Timeout);
+                        error.add_attempts(mem::replace(&mut self.attempts,
+                                                        Vec::new()));
+                        Err(error)
+                    }
+                }
             }
-            let (index, request) = Self::start(&self.resolver,
+            let (order, request) = Self::start(&self.resolver,
+                                               &self.options, self.route,
                                                self.dgram, message);
-            self.start_index = index;
-            self.curr_index = index;
+            self.order = order;
+            self.pos = 0;
             self.request = request;
         }
         else {
-            self.request = QueryRequest::new(message,
-                                             &self.track()[self.curr_index]);
+            let index = self.order[self.pos];
+            self.request = Request::Single(
+                QueryRequest::new(message, &self.track()[index])
+            );
         }
         self.poll()
     }
 
+    /// Reports an event for the current request to the observer.
+    ///
+    /// Since a blast request asks several servers at once, events are
+    /// only reported for single requests.
+    fn observe(&self, kind: EventKind, message: &RequestMessage,
+               response: Option<&MessageBuf>) {
+        if let Request::Single(ref request) = self.request {
+            request.observe(kind, message, response)
+        }
+    }
+
     /// Switches to stream mode and starts the first request.
     fn start_stream(&mut self, message: RequestMessage)
                     -> Poll<MessageBuf, Error> {
         self.dgram = false;
-        let (index, request) = Self::start(&self.resolver, false,
-                                           message);
-        self.start_index = index;
-        self.curr_index = index;
+        let (order, request) = Self::start(&self.resolver, &self.options,
+                                           self.route, false, message);
+        self.order = order;
+        self.pos = 0;
         self.request = request;
         self.poll()
     }
 
-    /// Determines the start index and request for a new attempt.
-    fn start(resolver: &Resolver, dgram: bool, message: RequestMessage)
-             -> (usize, QueryRequest) {
-        let track = if dgram { resolver.udp() }
-                    else { resolver.tcp() };
-        let index = if resolver.options().rotate {
-            random::<usize>() % track.len()
+    /// Determines the order of services and the request for a new attempt.
+    ///
+    /// In blast mode, the request is sent to all services of the track
+    /// in that order. Only the services of the route and, of those, only
+    /// the ones allowed by the query options are used. If there are none,
+    /// a blast request without any services takes care of failing the
+    /// attempt.
+    fn start(resolver: &Resolver, options: &QueryOptions,
+             route: Option<usize>, dgram: bool, message: RequestMessage)
+             -> (Vec<usize>, Request) {
+        let transports = resolver.transports(route);
+        let track = if dgram { &transports.udp }
+                    else { &transports.tcp };
+        let mut order = Self::order(resolver, route, track);
+        order.retain(|&i| options.allows(track, i));
+        let request = if resolver.route_options(route).blast
+                         || order.is_empty() {
+            let track = order.iter().map(|&i| track[i].clone()).collect();
+            let delay = resolver.query_conf().blast_delay;
+            let delay = if delay == Duration::from_secs(0) { None }
+                        else { Some((delay, resolver.0.reactor.clone())) };
+            Request::Blast(BlastRequest::new(message, track,
+                                             resolver.policy().clone(),
+                                             delay))
+        }
+        else {
+            Request::Single(QueryRequest::new(message, &track[order[0]]))
+        };
+        (order, request)
+    }
+
+    /// Determines the order in which to ask the services of a track.
+    ///
+    /// Services are ordered by their smoothed round trip time if the
+    /// `srtt_order` config option is set. Otherwise they are asked in
+    /// the order given, starting at a random service if the `rotate`
+    /// option is set. Either way, services currently held down go last.
+    fn order(resolver: &Resolver, route: Option<usize>,
+             track: &[TransportHandle]) -> Vec<usize> {
+        if track.is_empty() {
+            return Vec::new()
         }
-        else { 0 };
-        let request = QueryRequest::new(message, &track[index]);
-        (index, request)
+        let mut order: Vec<usize> = (0..track.len()).collect();
+        if resolver.query_conf().srtt_order {
+            order.sort_by_key(|&i| track[i].info().srtt());
+            for &i in order.iter().skip(1) {
+                track[i].info().decay_srtt()
+            }
+        }
+        else if resolver.route_options(route).rotate {
+            let start = random::<usize>() % track.len();
+            order.rotate_left(start);
+        }
+        order.sort_by_key(|&i| track[i].info().is_held_down());
+        order
     }
 
     /// Returns the current track.
     ///
-    /// This is either the UDP or TCP service handles of the resolver,
-    /// depending on whether we are in datagram or stream mode.
+    /// This is either the UDP or TCP service handles of the query’s route
+    /// or the default servers, depending on whether we are in datagram or
+    /// stream mode.
     fn track(&self) -> &[TransportHandle] {
+        let transports = self.resolver.transports(self.route);
         if self.dgram {
-            self.resolver.udp()
+            &transports.udp
         }
         else {
-            self.resolver.tcp()
+            &transports.tcp
         }
     }
+
+    /// Returns the options for the query’s route or the default servers.
+    fn resolv_options(&self) -> &ResolvOptions {
+        self.resolver.route_options(self.route)
+    }
 }
 
 
@@ -358,6 +1447,9 @@ impl Future for QueryInner {
         match self.request.poll() {
             Ok(Async::NotReady) => Ok(Async::NotReady),
             Ok(Async::Ready((response, message))) => {
+                if let Request::Blast(ref mut request) = self.request {
+                    self.attempts.append(&mut request.take_attempts())
+                }
                 self.response(response, message)
             }
             Err((error, message)) => {
@@ -367,3 +1459,318 @@ impl Future for QueryInner {
     }
 }
 
+
+//------------ RecursiveQuery ------------------------------------------------
+
+/// A query resolved iteratively.
+///
+/// This wraps the actual resolution in order to add its result to the
+/// cache.
+struct RecursiveQuery {
+    /// The resolver we are working with.
+    resolver: Resolver,
+
+    /// The iterative resolution.
+    recursion: Recursion,
+
+    /// The question to store the response under in the cache.
+    ///
+    /// This is `None` if the resolver doesn’t have a cache.
+    cache_key: Option<Question<DNameBuf>>,
+}
+
+
+//--- Future
+
+impl Future for RecursiveQuery {
+    type Item = MessageBuf;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.recursion.poll());
+        if let Some(key) = self.cache_key.take() {
+            if let Some(cache) = self.resolver.cache() {
+                cache.insert(key, &response)
+            }
+        }
+        Ok(Async::Ready(response))
+    }
+}
+
+
+//------------ Request -------------------------------------------------------
+
+/// The request currently processed by a query.
+///
+/// Normally, a query asks one service at a time. In blast mode, it asks
+/// all services of a track at once.
+enum Request {
+    Single(QueryRequest),
+    Blast(BlastRequest),
+}
+
+impl Request {
+    /// Returns the configuration of the server that sent the response.
+    fn server(&self) -> Option<&ServerConf> {
+        match *self {
+            Request::Single(ref request) => Some(request.server()),
+            Request::Blast(ref request) => request.server(),
+        }
+    }
+}
+
+
+//--- Future
+
+impl Future for Request {
+    type Item = (MessageBuf, RequestMessage);
+    type Error = (Error, RequestMessage);
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            Request::Single(ref mut request) => request.poll(),
+            Request::Blast(ref mut request) => request.poll(),
+        }
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::cell::{Cell, RefCell};
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+    use std::str::FromStr;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+    use ::bits::DNameBuf;
+    use ::bits::opt::rfc5001::Nsid;
+    use ::iana::{Rcode, Rtype};
+    use super::super::conf::{ResolvConf, ServerConf, ServerMode};
+    use super::super::error::ErrorKind;
+    use super::super::lookup::lookup_host;
+    use super::super::mock::{Reply, Request};
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        SocketAddr::from_str(s).unwrap()
+    }
+
+    fn conf() -> ResolvConf {
+        let mut conf = ResolvConf::new();
+        conf.servers.push(ServerConf::new(addr("192.0.2.10:53")));
+        conf.servers.push(ServerConf::new(addr("192.0.2.11:53")));
+        conf.finalize();
+        conf
+    }
+
+    fn question() -> (DNameBuf, Rtype) {
+        (DNameBuf::from_str("example.com.").unwrap(), Rtype::A)
+    }
+
+    #[test]
+    fn options() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        let responder = move |request: &Request| {
+            let header = request.message().header();
+            let (dnssec_ok, nsid) = match request.message().opt() {
+                Some(opt) => {
+                    (opt.ttl() & 0x8000 != 0,
+                     opt.data().iter::<Nsid<&[u8]>>().next().is_some())
+                }
+                None => (false, false)
+            };
+            log.borrow_mut().push((header.rd(), header.cd(), dnssec_ok,
+                                   nsid, request.server(),
+                                   request.is_stream()));
+            request.rcode(Rcode::NoError)
+        };
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf(),
+                                              responder);
+
+        let err = core.run(resolv.clone().query(question())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoData);
+        let err = core.run(resolv.query_builder(question())
+                                 .rd(false).cd(true).dnssec_ok(true)
+                                 .edns_option(Nsid::new(b""))
+                                 .use_vc().server(addr("192.0.2.11:53"))
+                                 .start()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoData);
+        assert_eq!(*seen.borrow(),
+                   vec![(true, false, false, false, addr("192.0.2.10:53"),
+                         false),
+                        (false, true, true, true, addr("192.0.2.11:53"),
+                         true)]);
+    }
+
+    #[test]
+    fn timeout_and_attempts() {
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+        let responder = move |_: &Request| {
+            counter.set(counter.get() + 1);
+            Reply::Timeout
+        };
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf(),
+                                              responder);
+        let res = core.run(resolv.clone().query_builder(question())
+                                 .timeout(Duration::from_millis(10))
+                                 .attempts(1)
+                                 .start());
+        let err = match res {
+            Err(err) => err,
+            other => panic!("unexpected result {:?}", other)
+        };
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert_eq!(count.get(), 2);
+        assert_eq!(err.attempts().iter().map(|attempt| {
+                       (attempt.server().addr, attempt.error().kind())
+                   }).collect::<Vec<_>>(),
+                   vec![(addr("192.0.2.10:53"), ErrorKind::Timeout),
+                        (addr("192.0.2.11:53"), ErrorKind::Timeout)]);
+
+        // The query’s timeout is used for the round trip time.
+        for stats in resolv.server_stats() {
+            assert_eq!(stats.srtt, Duration::from_millis(10) / 10 * 3);
+        }
+    }
+
+    #[test]
+    fn unknown_server() {
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf(),
+                                              |_: &Request| Reply::Timeout);
+        let res = core.run(resolv.query_builder(question())
+                                 .server(addr("192.0.2.12:53"))
+                                 .start());
+        match res {
+            Err(ref err) if err.kind() == ErrorKind::Io => { }
+            other => panic!("unexpected result {:?}", other)
+        }
+    }
+
+    #[cfg(feature = "dnssec")]
+    #[test]
+    fn validating_negative() {
+        use super::super::dnssec::TrustAnchors;
+
+        let mut conf = conf();
+        conf.timeout = Duration::from_millis(10);
+        conf.attempts = 1;
+        conf.trust_anchors = Some(TrustAnchors::new());
+        conf.finalize();
+        let responder = |request: &Request| {
+            if request.server() == addr("192.0.2.10:53") {
+                Reply::Timeout
+            }
+            else {
+                request.rcode(Rcode::NoError)
+            }
+        };
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf,
+                                              responder);
+        let err = core.run(resolv.query(question())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoData);
+        assert_eq!(err.response().unwrap().server().unwrap().addr,
+                   addr("192.0.2.11:53"));
+        assert_eq!(err.attempts().iter().map(|attempt| {
+                       (attempt.server().addr, attempt.error().kind())
+                   }).collect::<Vec<_>>(),
+                   vec![(addr("192.0.2.10:53"), ErrorKind::Timeout)]);
+    }
+
+    #[test]
+    fn rotate_without_tcp() {
+        // A truncated answer from a server without TCP switches to an
+        // empty stream track which must not upset picking a random
+        // starting server.
+        let mut conf = ResolvConf::new();
+        let mut server = ServerConf::new(addr("192.0.2.10:53"));
+        server.tcp = ServerMode::None;
+        conf.servers.push(server);
+        conf.options.rotate = true;
+        conf.finalize();
+        let mut core = Core::new().unwrap();
+        let responder = |request: &Request| {
+            let msg = request.answer();
+            Reply::Truncated(MessageBuf::from_vec(msg.finish()).unwrap())
+        };
+        let resolv = Resolver::with_responder(&core.handle(), conf,
+                                              responder);
+        assert!(core.run(resolv.query(question())).is_err());
+    }
+
+    /// Returns a responder recording the servers asked.
+    fn recorder() -> (Rc<RefCell<Vec<SocketAddr>>>,
+                      Box<Fn(&Request) -> Reply>) {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        (seen, Box::new(move |request: &Request| {
+            log.borrow_mut().push(request.server());
+            request.rcode(Rcode::NoError)
+        }))
+    }
+
+    #[test]
+    fn reload() {
+        let (seen, responder) = recorder();
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf(),
+                                              responder);
+        let clone = resolv.clone();
+
+        let mut conf = ResolvConf::new();
+        conf.servers.push(ServerConf::new(addr("192.0.2.20:53")));
+        conf.finalize();
+        assert!(core.run(resolv.reload(conf)).unwrap());
+        assert_eq!(resolv.server_stats().len(), 1);
+
+        // Both the value itself and its earlier clones use the new
+        // configuration.
+        assert_eq!(resolv.conf().servers.len(), 1);
+        assert_eq!(clone.conf().servers.len(), 1);
+        core.run(clone.query(question())).unwrap_err();
+        assert_eq!(*seen.borrow(), vec![addr("192.0.2.20:53")]);
+
+        seen.borrow_mut().clear();
+        let name = DNameBuf::from_str("example.com.").unwrap();
+        core.run(lookup_host(resolv, &name)).unwrap_err();
+        assert!(!seen.borrow().is_empty());
+        assert!(seen.borrow().iter().all(|&server| {
+            server == addr("192.0.2.20:53")
+        }));
+    }
+
+    #[test]
+    fn check_conf() {
+        use std::{env, fs, process};
+
+        let path = env::temp_dir().join(format!("domain-resolv-conf-{}",
+                                                process::id()));
+        fs::write(&path, "nameserver 192.0.2.10\n").unwrap();
+        let mut conf = ResolvConf::new();
+        conf.parse_file(&path).unwrap();
+        conf.finalize();
+
+        let (seen, responder) = recorder();
+        let mut core = Core::new().unwrap();
+        let resolv = Resolver::with_responder(&core.handle(), conf,
+                                              responder);
+        assert!(!core.run(resolv.check_conf()).unwrap());
+        fs::write(&path, "nameserver 192.0.2.20\noptions attempts:1\n")
+           .unwrap();
+        let changed = core.run(resolv.check_conf()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(changed);
+
+        assert_eq!(resolv.conf().attempts, 1);
+        core.run(resolv.query(question())).unwrap_err();
+        assert_eq!(*seen.borrow(), vec![addr("192.0.2.20:53")]);
+    }
+}
@@ -0,0 +1,1185 @@
+//! Iterative resolution.
+//!
+//! If the resolver configuration contains a recursion config, queries are
+//! not sent to the configured servers. Instead, the resolver finds the
+//! answer itself by walking down the tree of zones. It starts by asking
+//! one of the root servers given by the root hints. These will either
+//! answer the question or refer the resolver to the name servers of a
+//! child zone. The resolver then asks those and keeps following referrals
+//! until it reaches a server that can answer.
+//!
+//! Referrals normally contain the addresses of the name servers as glue
+//! records. Glue is only accepted if it lies within the zone of the server
+//! giving the referral. For name servers without acceptable glue, the
+//! resolver looks up the addresses itself via a nested resolution.
+//!
+//! If the answer turns out to be an alias, the resolver follows the chain
+//! of CNAME records. DNAME records are followed via the CNAME records
+//! synthesized for them. Where the chain leaves the response, resolution
+//! restarts for the target name.
+//!
+//! Unless disabled, the resolver uses QNAME minimisation as described in
+//! RFC 9156. Instead of the full question, it asks each zone’s servers for
+//! the A records of a name only one label longer than the zone. This is
+//! enough to learn about the next zone cut. Only once the name is
+//! complete, the actual question is asked.
+//!
+//! The final response is assembled from the responses received along the
+//! way: it contains the original question, the records of the CNAME chain
+//! and the answer in the answer section, and the authority section of the
+//! last response.
+
+use std::mem;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use futures::{Async, Future, Poll};
+use tokio_core::reactor;
+use ::bits::{ComposeMode, DName, DNameBuf, DNameSlice, Message, MessageBuf,
+             MessageBuilder, ParseResult, Question};
+use ::iana::{Class, Rcode, Rtype};
+use ::rdata::parsed::{A, Aaaa, Cname, Ns};
+use super::conf::{RecursionConf, ResolvConf, ServerConf};
+use super::error::{Error, ErrorKind};
+use super::request::{QueryRequest, RequestMessage, TransportHandle};
+use super::server::ServerInfo;
+use super::tcp::tcp_transport;
+use super::udp::udp_transport;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The maximum number of CNAME records followed for a single query.
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// The maximum number of minimised queries sent to the servers of a zone.
+///
+/// This is the value of `MAX_MINIMISE_COUNT` suggested by RFC 9156. Once
+/// it is reached, the full query name is sent.
+const MAX_MINIMISE_COUNT: usize = 10;
+
+/// The number of name servers to keep transports for.
+///
+/// Once there are more, all transports are dropped and we start over.
+const MAX_NAME_SERVERS: usize = 1000;
+
+
+//------------ NameServers ---------------------------------------------------
+
+/// The name servers asked during iterative resolution.
+///
+/// Since we don’t know the name servers beforehand, transports for them
+/// are spawned when a server is asked for the first time. They are kept
+/// for later use, so the server info learned about them, such as whether
+/// they support EDNS, survives for later queries.
+#[derive(Debug)]
+pub struct NameServers {
+    /// The configuration for iterative resolution.
+    conf: RecursionConf,
+
+    /// The resolver configuration for creating server infos.
+    resolv: ResolvConf,
+
+    /// A remote for the reactor to spawn transports into.
+    reactor: reactor::Remote,
+
+    /// The transports for each name server we have asked so far.
+    transports: Mutex<HashMap<SocketAddr, Transports>>,
+}
+
+impl NameServers {
+    /// Creates a new value from a resolver configuration.
+    ///
+    /// Returns `None` if the configuration doesn’t ask for iterative
+    /// resolution.
+    pub fn new(conf: &ResolvConf, reactor: &reactor::Handle)
+               -> Option<Self> {
+        conf.recursion.as_ref().map(|recursion| {
+            NameServers {
+                conf: recursion.clone(),
+                resolv: conf.clone(),
+                reactor: reactor.remote().clone(),
+                transports: Mutex::new(HashMap::new()),
+            }
+        })
+    }
+
+    /// Returns a transport for the name server at `addr`.
+    ///
+    /// Returns a UDP transport if `dgram` is `true` or a TCP transport
+    /// otherwise. Returns `None` if the transport isn’t available or if
+    /// we are not running on the reactor’s thread and thus can’t spawn it.
+    fn transport(&self, addr: IpAddr, dgram: bool)
+                 -> Option<TransportHandle> {
+        let addr = SocketAddr::new(addr, self.conf.port);
+        let mut transports = self.transports.lock().unwrap();
+        if !transports.contains_key(&addr) {
+            let handle = self.reactor.handle()?;
+            if transports.len() >= MAX_NAME_SERVERS {
+                transports.clear()
+            }
+            let mut conf = ServerConf::new(addr);
+            conf.request_timeout = self.resolv.timeout;
+            let info = Arc::new(ServerInfo::new(conf, &self.resolv));
+            transports.insert(addr, Transports {
+                udp: udp_transport(&handle, &info),
+                tcp: tcp_transport(&handle, &info),
+            });
+        }
+        let transports = &transports[&addr];
+        if dgram { transports.udp.clone() }
+        else { transports.tcp.clone() }
+    }
+}
+
+
+/// The transports for a single name server.
+#[derive(Debug)]
+struct Transports {
+    udp: Option<TransportHandle>,
+    tcp: Option<TransportHandle>,
+}
+
+
+//------------ Recursion -----------------------------------------------------
+
+/// A future resolving a question iteratively.
+///
+/// The future resolves into a response assembled from the responses of
+/// the name servers along the way. Negative responses are returned, too,
+/// and left for the caller to turn into errors. If no name server could
+/// be found that answers the question, the future fails with a timeout.
+pub struct Recursion {
+    /// The name servers and the configuration.
+    servers: Arc<NameServers>,
+
+    /// The question to answer.
+    question: Question<DNameBuf>,
+
+    /// The name we are currently looking for.
+    ///
+    /// This starts out as the query name and changes whenever we have to
+    /// follow a CNAME record out of a response.
+    qname: DNameBuf,
+
+    /// The query name and all the names in the CNAME chain so far.
+    names: Vec<DNameBuf>,
+
+    /// The responses that make up the final response.
+    ///
+    /// These are the responses containing the CNAME chain and, at the very
+    /// end, the final response.
+    chain: Vec<MessageBuf>,
+
+    /// The zone whose name servers we are currently asking.
+    zone: DNameBuf,
+
+    /// The addresses of the zone’s name servers not yet asked.
+    addrs: VecDeque<IpAddr>,
+
+    /// The names of the zone’s name servers we need to find addresses for.
+    glueless: VecDeque<DNameBuf>,
+
+    /// The number of labels of the minimised query name.
+    ///
+    /// If this is `None`, we send the full query.
+    minimise: Option<usize>,
+
+    /// The number of minimised queries sent to the current zone.
+    minimise_count: usize,
+
+    /// The number of requests we may still send.
+    ///
+    /// This is shared with all nested resolutions.
+    budget: Arc<AtomicUsize>,
+
+    /// How deeply this resolution is nested.
+    depth: usize,
+
+    /// What we are currently doing.
+    state: State,
+}
+
+/// What a recursion is currently doing.
+enum State {
+    /// We haven’t started yet.
+    Start,
+
+    /// We are waiting for a response from a name server.
+    Request {
+        request: QueryRequest,
+        addr: IpAddr,
+        dgram: bool,
+    },
+
+    /// We are looking up the address of a name server.
+    Lookup {
+        name: DNameBuf,
+        rtype: Rtype,
+        lookup: Box<Recursion>,
+    },
+}
+
+impl Recursion {
+    /// Creates a new iterative resolution for the given question.
+    pub fn new<N: DName>(servers: Arc<NameServers>, question: &Question<N>)
+                         -> Self {
+        let budget = Arc::new(AtomicUsize::new(servers.conf.max_queries));
+        let question = Question::new(question.qname().to_cow().into_owned(),
+                                     question.qtype(), question.qclass());
+        Self::nested(servers, question, budget, 0)
+    }
+
+    /// Creates a new resolution at the given nesting depth.
+    fn nested(servers: Arc<NameServers>, question: Question<DNameBuf>,
+              budget: Arc<AtomicUsize>, depth: usize) -> Self {
+        let qname = question.qname().clone();
+        let mut res = Recursion {
+            servers: servers,
+            question: question,
+            qname: qname.clone(),
+            names: vec![qname],
+            chain: Vec::new(),
+            zone: DNameSlice::root().to_owned(),
+            addrs: VecDeque::new(),
+            glueless: VecDeque::new(),
+            minimise: None,
+            minimise_count: 0,
+            budget: budget,
+            depth: depth,
+            state: State::Start,
+        };
+        res.start_at_root();
+        res
+    }
+
+    /// Starts over with the root servers from the hints.
+    ///
+    /// IPv4 addresses are tried first since IPv6 may not be available.
+    fn start_at_root(&mut self) {
+        let mut v6 = Vec::new();
+        self.addrs.clear();
+        self.glueless.clear();
+        for server in self.servers.conf.hints.iter() {
+            for &addr in &server.addrs {
+                if addr.is_ipv4() { self.addrs.push_back(addr) }
+                else { v6.push(addr) }
+            }
+        }
+        self.addrs.extend(v6);
+        self.zone = DNameSlice::root().to_owned();
+        self.start_zone();
+    }
+
+    /// Resets QNAME minimisation for a new zone.
+    fn start_zone(&mut self) {
+        self.minimise_count = 0;
+        let len = self.zone.len() + 1;
+        self.minimise = if self.servers.conf.qname_minimisation
+                                && len < self.qname.len() {
+            Some(len)
+        }
+        else {
+            None
+        };
+    }
+
+    /// Returns the name and type to ask the current zone for.
+    fn query(&self) -> (DNameBuf, Rtype) {
+        match self.minimise {
+            Some(len) => (suffix(&self.qname, len).to_owned(), Rtype::A),
+            None => (self.qname.clone(), self.question.qtype())
+        }
+    }
+
+    /// Moves on to the next name server of the current zone.
+    ///
+    /// Fails if we have run out of servers.
+    fn next_server(&mut self) -> Result<(), Error> {
+        loop {
+            if let Some(addr) = self.addrs.pop_front() {
+                if self.ask(addr, !self.servers.resolv.options.use_vc)? {
+                    return Ok(())
+                }
+            }
+            else if let Some(name) = self.glueless.pop_front() {
+                if self.lookup(name, Rtype::A) {
+                    return Ok(())
+                }
+            }
+            else {
+                return Err(ErrorKind::Timeout.into())
+            }
+        }
+    }
+
+    /// Asks the name server at `addr` again.
+    fn retry(&mut self, addr: IpAddr) -> Result<(), Error> {
+        self.addrs.push_front(addr);
+        self.next_server()
+    }
+
+    /// Sends the current query to the name server at `addr`.
+    ///
+    /// Returns whether a request was started. Fails if we have used up
+    /// our budget of requests.
+    fn ask(&mut self, addr: IpAddr, dgram: bool) -> Result<bool, Error> {
+        let transport = match self.servers.transport(addr, dgram) {
+            Some(transport) => transport,
+            None => return Ok(false)
+        };
+        let budget = self.budget.load(Ordering::Relaxed);
+        if budget == 0 {
+            return Err(ErrorKind::Timeout.into())
+        }
+        self.budget.store(budget - 1, Ordering::Relaxed);
+        let (qname, qtype) = self.query();
+        let message = RequestMessage::iterative(
+            (qname, qtype, self.question.qclass())
+        )?;
+        self.state = State::Request {
+            request: QueryRequest::new(message, &transport),
+            addr: addr,
+            dgram: dgram,
+        };
+        Ok(true)
+    }
+
+    /// Starts looking up the addresses of the name server `name`.
+    ///
+    /// Returns `false` if nesting has become too deep.
+    fn lookup(&mut self, name: DNameBuf, rtype: Rtype) -> bool {
+        if self.depth >= self.servers.conf.max_depth {
+            return false
+        }
+        let lookup = Recursion::nested(
+            self.servers.clone(),
+            Question::new(name.clone(), rtype, Class::In),
+            self.budget.clone(), self.depth + 1
+        );
+        self.state = State::Lookup {
+            name: name,
+            rtype: rtype,
+            lookup: Box::new(lookup),
+        };
+        true
+    }
+
+    /// Processes the result of looking up a name server’s addresses.
+    ///
+    /// If there are no IPv4 addresses, we try IPv6 next.
+    fn lookup_done(&mut self, name: DNameBuf, rtype: Rtype,
+                   response: Option<MessageBuf>) -> Result<(), Error> {
+        let addrs = match response {
+            Some(response) => answer_addrs(&response).unwrap_or_default(),
+            None => Vec::new()
+        };
+        if addrs.is_empty() && rtype == Rtype::A
+                && self.lookup(name, Rtype::Aaaa) {
+            return Ok(())
+        }
+        self.addrs.extend(addrs);
+        self.next_server()
+    }
+
+    /// Processes a response from the name server at `addr`.
+    ///
+    /// Returns the final response if we are done.
+    fn response(&mut self, response: MessageBuf, message: RequestMessage,
+                addr: IpAddr, dgram: bool)
+                -> Result<Option<MessageBuf>, Error> {
+        if !response.is_answer(&message) {
+            return self.next_server().map(|_| None)
+        }
+        if response.header().tc() && dgram {
+            if self.ask(addr, false)? {
+                return Ok(None)
+            }
+            return self.next_server().map(|_| None)
+        }
+        let (qname, qtype) = self.query();
+        let (aliases, outcome) = match classify(&response, &qname, qtype,
+                                                &self.zone) {
+            Ok(res) => res,
+            Err(_) => return self.next_server().map(|_| None)
+        };
+        match outcome {
+            Outcome::Lame => self.next_server().map(|_| None),
+            Outcome::Referral(referral) => {
+                self.referral(referral);
+                self.next_server().map(|_| None)
+            }
+            Outcome::NxDomain if self.minimise.is_some() => {
+                // Some servers answer NXDOMAIN for empty non-terminals.
+                // Play it safe and ask for the full name.
+                self.minimise = None;
+                self.retry(addr).map(|_| None)
+            }
+            _ if self.minimise.is_some() => {
+                // There is no zone cut at the minimised name, so we add
+                // another label.
+                self.minimise_count += 1;
+                self.minimise = match self.minimise {
+                    Some(len) if len + 1 < self.qname.len()
+                            && self.minimise_count < MAX_MINIMISE_COUNT => {
+                        Some(len + 1)
+                    }
+                    _ => None
+                };
+                self.retry(addr).map(|_| None)
+            }
+            Outcome::Alias => {
+                if self.names.len() + aliases.len() > MAX_CNAME_CHAIN + 1 {
+                    return Err(ErrorKind::Timeout.into())
+                }
+                self.chain.push(response);
+                self.names.extend(aliases);
+                self.qname = self.names[self.names.len() - 1].clone();
+                if self.qname.ends_with(&self.zone) {
+                    self.start_zone();
+                    self.retry(addr).map(|_| None)
+                }
+                else {
+                    self.start_at_root();
+                    self.next_server().map(|_| None)
+                }
+            }
+            Outcome::Answer | Outcome::NoData | Outcome::NxDomain => {
+                self.chain.push(response);
+                self.names.extend(aliases);
+                self.finish().map(Some)
+            }
+        }
+    }
+
+    /// Descends into the zone given by a referral.
+    fn referral(&mut self, referral: Referral) {
+        let mut v6 = Vec::new();
+        self.addrs.clear();
+        self.glueless.clear();
+        for name in referral.servers {
+            let mut found = false;
+            for &(ref owner, addr) in &referral.glue {
+                if *owner == name {
+                    found = true;
+                    if addr.is_ipv4() { self.addrs.push_back(addr) }
+                    else { v6.push(addr) }
+                }
+            }
+            if !found {
+                self.glueless.push_back(name)
+            }
+        }
+        self.addrs.extend(v6);
+        self.zone = referral.zone;
+        self.start_zone();
+    }
+
+    /// Assembles the final response.
+    fn finish(&self) -> Result<MessageBuf, Error> {
+        let last = &self.chain[self.chain.len() - 1];
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)?;
+        msg.header_mut().set_qr(true);
+        msg.header_mut().set_rd(true);
+        msg.header_mut().set_ra(true);
+        msg.header_mut().set_rcode(last.header().rcode());
+        msg.push(self.question.clone())?;
+        let mut msg = msg.answer();
+        for response in &self.chain {
+            for record in response.answer()? {
+                let record = record?;
+                if self.names.iter().any(|name| *name == *record.name()) {
+                    msg.push(record)?;
+                }
+            }
+        }
+        let mut msg = msg.authority();
+        for record in last.authority()? {
+            msg.push(record?)?;
+        }
+        Ok(MessageBuf::from_vec(msg.finish())?)
+    }
+}
+
+
+//--- Future
+
+impl Future for Recursion {
+    type Item = MessageBuf;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Start) {
+                State::Start => self.next_server()?,
+                State::Request { mut request, addr, dgram } => {
+                    match request.poll() {
+                        Ok(Async::NotReady) => {
+                            self.state = State::Request {
+                                request: request, addr: addr, dgram: dgram
+                            };
+                            return Ok(Async::NotReady)
+                        }
+                        Ok(Async::Ready((response, message))) => {
+                            let res = self.response(response, message,
+                                                    addr, dgram)?;
+                            if let Some(response) = res {
+                                return Ok(Async::Ready(response))
+                            }
+                        }
+                        Err(_) => self.next_server()?
+                    }
+                }
+                State::Lookup { name, rtype, mut lookup } => {
+                    match lookup.poll() {
+                        Ok(Async::NotReady) => {
+                            self.state = State::Lookup {
+                                name: name, rtype: rtype, lookup: lookup
+                            };
+                            return Ok(Async::NotReady)
+                        }
+                        Ok(Async::Ready(response)) => {
+                            self.lookup_done(name, rtype, Some(response))?
+                        }
+                        Err(_) => self.lookup_done(name, rtype, None)?
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+//------------ Outcome -------------------------------------------------------
+
+/// What a response from a name server tells us.
+enum Outcome {
+    /// The response contains the answer.
+    Answer,
+
+    /// The response ends in a CNAME pointing outside of it.
+    Alias,
+
+    /// The response refers us to the servers of a child zone.
+    Referral(Referral),
+
+    /// The name exists but doesn’t have records of the requested type.
+    NoData,
+
+    /// The name doesn’t exist.
+    NxDomain,
+
+    /// The server can’t help us.
+    Lame,
+}
+
+/// The content of a referral.
+struct Referral {
+    /// The child zone we are referred to.
+    zone: DNameBuf,
+
+    /// The names of the child zone’s name servers.
+    servers: Vec<DNameBuf>,
+
+    /// The acceptable glue records.
+    glue: Vec<(DNameBuf, IpAddr)>,
+}
+
+
+/// Determines what a response to a question for `qname` and `qtype` means.
+///
+/// The response came from a server of `zone`. Also returns the names of
+/// the CNAME chain found in the response’s answer section, not including
+/// `qname` itself.
+fn classify(response: &Message, qname: &DNameBuf, qtype: Rtype,
+            zone: &DNameBuf) -> ParseResult<(Vec<DNameBuf>, Outcome)> {
+    let rcode = response.header().rcode();
+    if rcode != Rcode::NoError && rcode != Rcode::NXDomain {
+        return Ok((Vec::new(), Outcome::Lame))
+    }
+
+    let mut aliases = Vec::new();
+    let mut target = qname.clone();
+    loop {
+        if has_record(response, &target, qtype)? {
+            return Ok((aliases, Outcome::Answer))
+        }
+        match cname_target(response, &target)? {
+            Some(next) => {
+                if next == *qname || aliases.contains(&next)
+                        || aliases.len() == MAX_CNAME_CHAIN {
+                    return Ok((Vec::new(), Outcome::Lame))
+                }
+                aliases.push(next.clone());
+                target = next;
+            }
+            None => break
+        }
+    }
+    if rcode == Rcode::NXDomain {
+        return Ok((aliases, Outcome::NxDomain))
+    }
+    if !aliases.is_empty() {
+        return Ok((aliases, Outcome::Alias))
+    }
+    if let Some(referral) = referral(response, qname, zone)? {
+        return Ok((aliases, Outcome::Referral(referral)))
+    }
+    // A response without an answer that has NS records in its authority
+    // section but isn’t authoritative is an upwards referral.
+    if !response.header().aa() && !has_authority(response, Rtype::Soa)?
+            && has_authority(response, Rtype::Ns)? {
+        return Ok((aliases, Outcome::Lame))
+    }
+    Ok((aliases, Outcome::NoData))
+}
+
+/// Returns whether the answer section has a record for `name` and `rtype`.
+fn has_record(response: &Message, name: &DNameBuf, rtype: Rtype)
+              -> ParseResult<bool> {
+    for record in response.answer()? {
+        let record = record?;
+        if *record.name() == *name
+                && (record.rtype() == rtype || rtype == Rtype::Any) {
+            return Ok(true)
+        }
+    }
+    Ok(false)
+}
+
+/// Returns whether the authority section has a record of type `rtype`.
+fn has_authority(response: &Message, rtype: Rtype) -> ParseResult<bool> {
+    for record in response.authority()? {
+        if record?.rtype() == rtype {
+            return Ok(true)
+        }
+    }
+    Ok(false)
+}
+
+/// Returns the target of a CNAME record for `name` in the answer section.
+fn cname_target(response: &Message, name: &DNameBuf)
+                -> ParseResult<Option<DNameBuf>> {
+    for record in response.answer()?.limit_to::<Cname>() {
+        let record = record?;
+        if *record.name() == *name {
+            return Ok(Some(record.data().cname().to_cow().into_owned()))
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the referral contained in a response if there is one.
+///
+/// A referral has NS records in its authority section for a zone strictly
+/// below `zone` that contains `qname`. Glue records are only accepted if
+/// they are within `zone`.
+fn referral(response: &Message, qname: &DNameBuf, zone: &DNameBuf)
+            -> ParseResult<Option<Referral>> {
+    let mut cut: Option<DNameBuf> = None;
+    let mut servers = Vec::new();
+    for record in response.authority()?.limit_to::<Ns>() {
+        let record = record?;
+        let owner = record.name().to_cow().into_owned();
+        if owner == *zone || !owner.ends_with(zone)
+                || !qname.ends_with(&owner) {
+            continue
+        }
+        if let Some(ref cut) = cut {
+            if *cut != owner {
+                continue
+            }
+        }
+        cut = Some(owner);
+        servers.push(record.data().nsdname().to_cow().into_owned());
+    }
+    let cut = match cut {
+        Some(cut) => cut,
+        None => return Ok(None)
+    };
+
+    let mut glue = Vec::new();
+    for record in response.additional()?.limit_to::<A>() {
+        let record = record?;
+        let owner = record.name().to_cow().into_owned();
+        if servers.contains(&owner) && owner.ends_with(zone) {
+            glue.push((owner, IpAddr::V4(record.data().addr())))
+        }
+    }
+    for record in response.additional()?.limit_to::<Aaaa>() {
+        let record = record?;
+        let owner = record.name().to_cow().into_owned();
+        if servers.contains(&owner) && owner.ends_with(zone) {
+            glue.push((owner, IpAddr::V6(record.data().addr())))
+        }
+    }
+    Ok(Some(Referral { zone: cut, servers: servers, glue: glue }))
+}
+
+/// Returns all the addresses in the answer section of a response.
+fn answer_addrs(response: &Message) -> ParseResult<Vec<IpAddr>> {
+    let mut res = Vec::new();
+    for record in response.answer()?.limit_to::<A>() {
+        res.push(IpAddr::V4(record?.data().addr()))
+    }
+    for record in response.answer()?.limit_to::<Aaaa>() {
+        res.push(IpAddr::V6(record?.data().addr()))
+    }
+    Ok(res)
+}
+
+/// Returns the suffix of `name` with `len` labels.
+fn suffix(name: &DNameSlice, len: usize) -> &DNameSlice {
+    let mut name = name;
+    let mut count = name.len();
+    while count > len {
+        name = match name.parent() {
+            Some(parent) => parent,
+            None => break
+        };
+        count -= 1;
+    }
+    name
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use ::bits::{ComposeMode, DName, DNameBuf, Message, MessageBuf,
+                 MessageBuilder};
+    use ::iana::{Rcode, Rtype};
+    use ::rdata::{A, Cname, Ns};
+    use ::resolv::{ResolvConf, Resolver};
+    use ::resolv::conf::RecursionConf;
+    use ::resolv::error::{Error, ErrorKind};
+    use ::resolv::hints::RootHints;
+    use super::answer_addrs;
+
+    //--- Stand-in authoritative servers
+
+    enum Data {
+        A([u8; 4]),
+        Ns(&'static str),
+        Cname(&'static str),
+    }
+
+    struct Zone {
+        origin: &'static str,
+        records: &'static [(&'static str, Data)],
+    }
+
+    const ROOT: &[Zone] = &[Zone { origin: ".", records: &[
+        ("com.", Data::Ns("ns.com.")),
+        ("ns.com.", Data::A([127, 0, 0, 2])),
+        ("net.", Data::Ns("ns.example.com.")),
+    ]}];
+
+    const COM: &[Zone] = &[Zone { origin: "com.", records: &[
+        ("com.", Data::Ns("ns.com.")),
+        ("example.com.", Data::Ns("ns.example.com.")),
+        ("ns.example.com.", Data::A([127, 0, 0, 3])),
+    ]}];
+
+    const EXAMPLE: &[Zone] = &[
+        Zone { origin: "example.com.", records: &[
+            ("example.com.", Data::Ns("ns.example.com.")),
+            ("ns.example.com.", Data::A([127, 0, 0, 3])),
+            ("www.example.com.", Data::A([192, 0, 2, 1])),
+            ("alias.example.com.", Data::Cname("www.example.net.")),
+            ("deep.sub.example.com.", Data::A([192, 0, 2, 3])),
+        ]},
+        Zone { origin: "example.net.", records: &[
+            ("example.net.", Data::Ns("ns.example.com.")),
+            ("www.example.net.", Data::A([192, 0, 2, 2])),
+        ]},
+    ];
+
+    fn name(s: &str) -> DNameBuf {
+        DNameBuf::from_str(s).unwrap()
+    }
+
+    /// Answers a request from the given zones.
+    fn respond(zones: &[Zone], request: &Message) -> Vec<u8> {
+        let question = request.first_question().unwrap();
+        let qname = question.qname().to_cow().into_owned();
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.header_mut().set_id(request.header().id());
+        msg.header_mut().set_qr(true);
+        msg.push(question.clone()).unwrap();
+        let zone = zones.iter().filter(|zone| {
+            qname.ends_with(&name(zone.origin))
+        }).max_by_key(|zone| name(zone.origin).len());
+        let zone = match zone {
+            Some(zone) => zone,
+            None => {
+                msg.header_mut().set_rcode(Rcode::Refused);
+                return msg.finish()
+            }
+        };
+        let cut = zone.records.iter().filter_map(|&(owner, ref data)| {
+            match *data {
+                Data::Ns(_) if owner != zone.origin
+                            && qname.ends_with(&name(owner)) => Some(owner),
+                _ => None
+            }
+        }).next();
+        if let Some(cut) = cut {
+            let mut msg = msg.authority();
+            let mut targets = Vec::new();
+            for &(owner, ref data) in zone.records {
+                if let Data::Ns(target) = *data {
+                    if owner == cut {
+                        msg.push((name(owner), 3600, Ns::new(name(target))))
+                           .unwrap();
+                        targets.push(target);
+                    }
+                }
+            }
+            let mut msg = msg.additional();
+            for &(owner, ref data) in zone.records {
+                if let Data::A(addr) = *data {
+                    if targets.contains(&owner) {
+                        msg.push((name(owner), 3600, A::new(addr.into())))
+                           .unwrap();
+                    }
+                }
+            }
+            return msg.finish()
+        }
+        msg.header_mut().set_aa(true);
+        if !zone.records.iter().any(|&(owner, _)| {
+            name(owner).ends_with(&qname)
+        }) {
+            msg.header_mut().set_rcode(Rcode::NXDomain);
+            return msg.finish()
+        }
+        let mut msg = msg.answer();
+        for &(owner, ref data) in zone.records {
+            if name(owner) != qname {
+                continue
+            }
+            match *data {
+                Data::A(addr) if question.qtype() == Rtype::A => {
+                    msg.push((name(owner), 3600, A::new(addr.into())))
+                       .unwrap()
+                }
+                Data::Ns(target) if question.qtype() == Rtype::Ns => {
+                    msg.push((name(owner), 3600, Ns::new(name(target))))
+                       .unwrap()
+                }
+                Data::Cname(target) => {
+                    msg.push((name(owner), 3600, Cname::new(name(target))))
+                       .unwrap()
+                }
+                _ => { }
+            }
+        }
+        msg.finish()
+    }
+
+    /// Runs a server for `zones` on `sock` and returns its query log.
+    fn serve(sock: UdpSocket, zones: &'static [Zone])
+             -> Arc<Mutex<Vec<String>>> {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let res = log.clone();
+        sock.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok((len, peer)) = sock.recv_from(&mut buf) {
+                let request = MessageBuf::from_bytes(&buf[..len]).unwrap();
+                let question = request.first_question().unwrap();
+                log.lock().unwrap().push(format!("{} {}", question.qname(),
+                                                 question.qtype()));
+                let response = respond(zones, &request);
+                sock.send_to(&response, peer).unwrap();
+            }
+        });
+        res
+    }
+
+    /// Binds `count` sockets on 127.0.0.1 and up, all on the same port.
+    ///
+    /// Returns a configuration using the first socket as the only root
+    /// server and the sockets.
+    fn bind(count: u8) -> (ResolvConf, Vec<UdpSocket>) {
+        let root = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = root.local_addr().unwrap().port();
+        let mut socks = vec![root];
+        for i in 2..(count + 1) {
+            socks.push(UdpSocket::bind((Ipv4Addr::new(127, 0, 0, i), port))
+                                 .unwrap());
+        }
+
+        let mut hints = RootHints::new();
+        hints.push(name("a.root."), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        let mut recursion = RecursionConf::new(hints);
+        recursion.port = port;
+        let mut conf = ResolvConf::new();
+        conf.timeout = Duration::from_secs(1);
+        conf.recursion = Some(recursion);
+        (conf, socks)
+    }
+
+    /// Starts the root, com, and example servers on 127.0.0.1 to .3.
+    ///
+    /// Returns a configuration for using them and the query log of the
+    /// root server.
+    fn start_servers() -> (ResolvConf, Arc<Mutex<Vec<String>>>) {
+        let (conf, mut socks) = bind(3);
+        serve(socks.pop().unwrap(), EXAMPLE);
+        serve(socks.pop().unwrap(), COM);
+        let log = serve(socks.pop().unwrap(), ROOT);
+        (conf, log)
+    }
+
+    /// The recursion configuration of `conf`.
+    fn recursion(conf: &mut ResolvConf) -> &mut RecursionConf {
+        conf.recursion.as_mut().unwrap()
+    }
+
+    fn lookup(conf: ResolvConf, qname: &str)
+              -> Result<Vec<IpAddr>, Error> {
+        let response = Resolver::run_with_conf(conf, |resolv| {
+            resolv.query((name(qname), Rtype::A))
+        })?;
+        Ok(answer_addrs(&response).unwrap())
+    }
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
+
+    //--- Tests
+
+    #[test]
+    fn referrals() {
+        let (conf, log) = start_servers();
+        assert_eq!(lookup(conf.clone(), "www.example.com.").unwrap(),
+                   vec![v4(192, 0, 2, 1)]);
+        assert_eq!(*log.lock().unwrap(), vec!["com. A".to_string()]);
+        assert_eq!(lookup(conf, "deep.sub.example.com.").unwrap(),
+                   vec![v4(192, 0, 2, 3)]);
+    }
+
+    #[test]
+    fn glueless_and_cname() {
+        let (conf, _) = start_servers();
+        let response = Resolver::run_with_conf(conf, |resolv| {
+            resolv.query((name("alias.example.com."), Rtype::A))
+        }).unwrap();
+        let question = response.first_question().unwrap();
+        assert_eq!(*question.qname(), name("alias.example.com."));
+        assert_eq!(response.answer().unwrap().count(), 2);
+        assert_eq!(answer_addrs(&response).unwrap(), vec![v4(192, 0, 2, 2)]);
+    }
+
+    #[test]
+    fn nxdomain() {
+        let (conf, _) = start_servers();
+        match lookup(conf, "a.missing.example.com.") {
+            Err(ref err) if err.kind() == ErrorKind::NoName => { }
+            res => panic!("unexpected result {:?}", res)
+        }
+    }
+
+    #[test]
+    fn no_minimisation() {
+        let (mut conf, log) = start_servers();
+        conf.recursion.as_mut().unwrap().qname_minimisation = false;
+        assert_eq!(lookup(conf, "www.example.com.").unwrap(),
+                   vec![v4(192, 0, 2, 1)]);
+        assert_eq!(*log.lock().unwrap(),
+                   vec!["www.example.com. A".to_string()]);
+    }
+
+    #[test]
+    fn query_budget() {
+        let (mut conf, log) = start_servers();
+        recursion(&mut conf).max_queries = 2;
+        match lookup(conf.clone(), "www.example.com.") {
+            Err(ref err) if err.kind() == ErrorKind::Timeout => { }
+            res => panic!("unexpected result {:?}", res)
+        }
+        assert_eq!(log.lock().unwrap().len(), 1);
+
+        recursion(&mut conf).max_queries = 3;
+        assert_eq!(lookup(conf, "www.example.com.").unwrap(),
+                   vec![v4(192, 0, 2, 1)]);
+    }
+
+    #[test]
+    fn referral_loop() {
+        // The server for example. refers sub.example. back to itself.
+        const ROOT: &[Zone] = &[Zone { origin: ".", records: &[
+            ("example.", Data::Ns("ns.example.")),
+            ("ns.example.", Data::A([127, 0, 0, 2])),
+        ]}];
+        const EXAMPLE: &[Zone] = &[Zone { origin: "example.", records: &[
+            ("example.", Data::Ns("ns.example.")),
+            ("ns.example.", Data::A([127, 0, 0, 2])),
+            ("sub.example.", Data::Ns("ns.sub.example.")),
+            ("ns.sub.example.", Data::A([127, 0, 0, 2])),
+        ]}];
+
+        let (conf, mut socks) = bind(2);
+        let log = serve(socks.pop().unwrap(), EXAMPLE);
+        serve(socks.pop().unwrap(), ROOT);
+        match lookup(conf, "www.sub.example.") {
+            Err(ref err) if err.kind() == ErrorKind::Timeout => { }
+            res => panic!("unexpected result {:?}", res)
+        }
+        assert_eq!(*log.lock().unwrap(),
+                   vec!["sub.example. A".to_string(),
+                        "www.sub.example. A".to_string()]);
+    }
+
+    #[test]
+    fn glueless_loop() {
+        // The name servers of org. and net. are only known by name and
+        // each is within the other zone.
+        const ROOT: &[Zone] = &[Zone { origin: ".", records: &[
+            ("org.", Data::Ns("ns.example.net.")),
+            ("net.", Data::Ns("ns.example.org.")),
+        ]}];
+
+        let (mut conf, mut socks) = bind(1);
+        recursion(&mut conf).max_queries = 10;
+        let log = serve(socks.pop().unwrap(), ROOT);
+        match lookup(conf, "www.example.org.") {
+            Err(ref err) if err.kind() == ErrorKind::Timeout => { }
+            res => panic!("unexpected result {:?}", res)
+        }
+        assert_eq!(log.lock().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn lame_server() {
+        // The first name server refuses all queries, having no zones.
+        const ROOT: &[Zone] = &[Zone { origin: ".", records: &[
+            ("example.", Data::Ns("ns1.example.")),
+            ("example.", Data::Ns("ns2.example.")),
+            ("ns1.example.", Data::A([127, 0, 0, 2])),
+            ("ns2.example.", Data::A([127, 0, 0, 3])),
+        ]}];
+        const EXAMPLE: &[Zone] = &[Zone { origin: "example.", records: &[
+            ("www.example.", Data::A([192, 0, 2, 1])),
+        ]}];
+
+        let (conf, mut socks) = bind(3);
+        serve(socks.pop().unwrap(), EXAMPLE);
+        let lame = serve(socks.pop().unwrap(), &[]);
+        serve(socks.pop().unwrap(), ROOT);
+        assert_eq!(lookup(conf, "www.example.").unwrap(),
+                   vec![v4(192, 0, 2, 1)]);
+        assert_eq!(*lame.lock().unwrap(), vec!["www.example. A".to_string()]);
+    }
+
+    #[test]
+    fn out_of_zone_glue() {
+        // The com. server gives glue for a name server in net. which must
+        // be ignored in favour of looking up its address.
+        const ROOT: &[Zone] = &[Zone { origin: ".", records: &[
+            ("com.", Data::Ns("ns.com.")),
+            ("ns.com.", Data::A([127, 0, 0, 2])),
+            ("net.", Data::Ns("ns.net.")),
+            ("ns.net.", Data::A([127, 0, 0, 3])),
+        ]}];
+        const COM: &[Zone] = &[Zone { origin: "com.", records: &[
+            ("example.com.", Data::Ns("ns.example.net.")),
+            ("ns.example.net.", Data::A([127, 0, 0, 4])),
+        ]}];
+        const NET: &[Zone] = &[Zone { origin: "net.", records: &[
+            ("ns.example.net.", Data::A([127, 0, 0, 5])),
+        ]}];
+        const BOGUS: &[Zone] = &[Zone { origin: "example.com.", records: &[
+            ("www.example.com.", Data::A([192, 0, 2, 66])),
+        ]}];
+        const EXAMPLE: &[Zone] = &[Zone { origin: "example.com.", records: &[
+            ("www.example.com.", Data::A([192, 0, 2, 1])),
+        ]}];
+
+        let (conf, mut socks) = bind(5);
+        serve(socks.pop().unwrap(), EXAMPLE);
+        let bogus = serve(socks.pop().unwrap(), BOGUS);
+        serve(socks.pop().unwrap(), NET);
+        serve(socks.pop().unwrap(), COM);
+        serve(socks.pop().unwrap(), ROOT);
+        assert_eq!(lookup(conf, "www.example.com.").unwrap(),
+                   vec![v4(192, 0, 2, 1)]);
+        assert!(bogus.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn cname_chain() {
+        // The chain leads through three zones on three servers.
+        const ROOT: &[Zone] = &[Zone { origin: ".", records: &[
+            ("com.", Data::Ns("ns.com.")),
+            ("ns.com.", Data::A([127, 0, 0, 2])),
+            ("net.", Data::Ns("ns.net.")),
+            ("ns.net.", Data::A([127, 0, 0, 3])),
+            ("org.", Data::Ns("ns.org.")),
+            ("ns.org.", Data::A([127, 0, 0, 4])),
+        ]}];
+        const COM: &[Zone] = &[Zone { origin: "com.", records: &[
+            ("a.example.com.", Data::Cname("b.example.net.")),
+        ]}];
+        const NET: &[Zone] = &[Zone { origin: "net.", records: &[
+            ("b.example.net.", Data::Cname("c.example.org.")),
+        ]}];
+        const ORG: &[Zone] = &[Zone { origin: "org.", records: &[
+            ("c.example.org.", Data::A([192, 0, 2, 3])),
+        ]}];
+
+        let (conf, mut socks) = bind(4);
+        serve(socks.pop().unwrap(), ORG);
+        serve(socks.pop().unwrap(), NET);
+        serve(socks.pop().unwrap(), COM);
+        serve(socks.pop().unwrap(), ROOT);
+        let response = Resolver::run_with_conf(conf, |resolv| {
+            resolv.query((name("a.example.com."), Rtype::A))
+        }).unwrap();
+        let question = response.first_question().unwrap();
+        assert_eq!(*question.qname(), name("a.example.com."));
+        let owners = response.answer().unwrap().map(|record| {
+            record.unwrap().name().to_string()
+        }).collect::<Vec<_>>();
+        assert_eq!(owners, vec!["a.example.com.", "b.example.net.",
+                                "c.example.org."]);
+        assert_eq!(answer_addrs(&response).unwrap(), vec![v4(192, 0, 2, 3)]);
+    }
+
+    #[test]
+    fn cname_loop() {
+        // Each zone points back to the other.
+        const ROOT: &[Zone] = &[Zone { origin: ".", records: &[
+            ("com.", Data::Ns("ns.com.")),
+            ("ns.com.", Data::A([127, 0, 0, 2])),
+            ("net.", Data::Ns("ns.net.")),
+            ("ns.net.", Data::A([127, 0, 0, 3])),
+        ]}];
+        const COM: &[Zone] = &[Zone { origin: "com.", records: &[
+            ("x.example.com.", Data::Cname("x.example.net.")),
+        ]}];
+        const NET: &[Zone] = &[Zone { origin: "net.", records: &[
+            ("x.example.net.", Data::Cname("x.example.com.")),
+        ]}];
+
+        let (conf, mut socks) = bind(3);
+        serve(socks.pop().unwrap(), NET);
+        serve(socks.pop().unwrap(), COM);
+        serve(socks.pop().unwrap(), ROOT);
+        match lookup(conf, "x.example.com.") {
+            Err(ref err) if err.kind() == ErrorKind::Timeout => { }
+            res => panic!("unexpected result {:?}", res)
+        }
+    }
+}
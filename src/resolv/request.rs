@@ -34,12 +34,25 @@
 //! requests are in flight.
 
 use std::{fmt, io, ops};
-use futures::{Async, Future, Poll};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use futures::{Async, Future, IntoFuture, Poll};
 use futures::sync::{mpsc, oneshot, BiLock, BiLockGuard};
-use ::bits::{AdditionalBuilder, ComposeMode, ComposeResult, DName,
-             Message, MessageBuf, MessageBuilder, Question};
-use super::conf::ResolvConf;
-use super::error::Error;
+use tokio_core::reactor;
+use ::bits::{AdditionalBuilder, ComposeMode, ComposeResult, Composer, DName,
+             DNameBuf, Message, MessageBuf, MessageBuilder, Question};
+use ::bits::opt::OptData;
+use ::bits::opt::rfc7828::TcpKeepalive;
+use ::bits::opt::rfc7830::PaddingPolicy;
+use ::bits::opt::rfc7873::Cookie;
+use ::iana::{OptRcode, Rcode};
+use super::case;
+use super::conf::{ResolvConf, ServerConf};
+use super::error::{Attempt, Error, ErrorKind};
+use super::observer::{Event, EventKind, Observer, Protocol};
+use super::policy::{ResponsePolicy, Verdict};
+use super::server::ServerInfo;
 
 
 //============ The Path of a Message Through a Request =======================
@@ -55,20 +68,97 @@ use super::error::Error;
 /// with that, rewind their additions for reuse.
 ///
 /// The only thing you can do with a request message is turn them into a
-/// transport message using the `into_service()` method.
-pub struct RequestMessage(AdditionalBuilder);
+/// transport message using the `into_service()` method. Request messages
+/// can be cloned for sending the same request to several transports at
+/// once.
+///
+/// Along with the message, the request options of the query are kept so
+/// that every request made for the query uses them.
+#[derive(Clone)]
+pub struct RequestMessage {
+    /// The message.
+    msg: AdditionalBuilder,
+
+    /// The request options of the query.
+    options: Arc<RequestOptions>,
+}
 
 impl RequestMessage {
     /// Creates a new request message from a question and resolver config.
     ///
-    /// This may fail if the domain name of the question isn’t absolute.
+    /// If the resolver validates answers itself, the checking disabled bit
+    /// is set so the upstream server doesn’t withhold answers it considers
+    /// bogus. This may fail if the domain name of the question isn’t
+    /// absolute.
     pub fn new<N, Q>(question: Q, conf: &ResolvConf) -> ComposeResult<Self>
                where N: DName,
                      Q: Into<Question<N>> {
+        let mut res = Self::with_rd(question, conf.options.recurse)?;
+        res.msg.header_mut().set_cd(conf.validates());
+        Ok(res)
+    }
+
+    /// Creates a new request message for asking a name server directly.
+    ///
+    /// Such a message doesn’t have the recursion desired bit set.
+    pub fn iterative<N, Q>(question: Q) -> ComposeResult<Self>
+                     where N: DName,
+                           Q: Into<Question<N>> {
+        Self::with_rd(question, false)
+    }
+
+    /// Creates a new request message with the given recursion desired bit.
+    fn with_rd<N, Q>(question: Q, rd: bool) -> ComposeResult<Self>
+               where N: DName,
+                     Q: Into<Question<N>> {
         let mut msg = MessageBuilder::new(ComposeMode::Stream, false)?;
-        msg.header_mut().set_rd(conf.options.recurse);
+        msg.header_mut().set_rd(rd);
         msg.push(question)?;
-        Ok(RequestMessage(msg.additional()))
+        Ok(RequestMessage {
+            msg: msg.additional(),
+            options: Arc::new(RequestOptions::default()),
+        })
+    }
+
+    /// Sets the recursion desired bit of the message.
+    pub fn set_rd(&mut self, rd: bool) {
+        self.msg.header_mut().set_rd(rd)
+    }
+
+    /// Sets the checking disabled bit of the message.
+    pub fn set_cd(&mut self, cd: bool) {
+        self.msg.header_mut().set_cd(cd)
+    }
+
+    /// Sets the request options used for sending the message.
+    pub fn set_options(&mut self, options: RequestOptions) {
+        self.options = Arc::new(options)
+    }
+
+    /// Returns a copy of the message with the case of the query name
+    /// randomized.
+    ///
+    /// Also returns the query name as it appears in the new message.
+    /// Returns `None` if the message has no question, which shouldn’t
+    /// happen, or if assembling the new message fails.
+    fn randomize_case(&self) -> Option<(Self, DNameBuf)> {
+        let question = match self.msg.as_ref().first_question() {
+            Some(question) => question,
+            None => return None
+        };
+        let qname = case::randomize(question.qname());
+        let mut msg = match MessageBuilder::new(ComposeMode::Stream, false) {
+            Ok(msg) => msg,
+            Err(_) => return None
+        };
+        *msg.header_mut() = self.msg.header().clone();
+        if msg.push((qname.clone(), question.qtype(), question.qclass()))
+              .is_err() {
+            return None
+        }
+        Some((RequestMessage { msg: msg.additional(),
+                               options: self.options.clone() },
+              qname))
     }
 
     /// Converts the request message into a transport message.
@@ -78,7 +168,77 @@ impl RequestMessage {
     /// is useful.
     fn into_service(self) -> (BiLock<Option<TransportMessage>>,
                               BiLock<Option<TransportMessage>>) {
-        BiLock::new(Some(TransportMessage(self.0)))
+        BiLock::new(Some(TransportMessage {
+            msg: self.msg,
+            opt: None,
+            options: self.options,
+        }))
+    }
+}
+
+
+//--- AsRef
+
+impl AsRef<Message> for RequestMessage {
+    fn as_ref(&self) -> &Message {
+        self.msg.as_ref()
+    }
+}
+
+
+//------------ RequestOptions ------------------------------------------------
+
+/// Options of a query that apply to each of its requests.
+///
+/// These override what the server info would otherwise decide.
+#[derive(Clone, Debug, Default)]
+pub struct RequestOptions {
+    /// Whether to set the DO bit.
+    ///
+    /// If this is `None`, the server info decides.
+    pub dnssec_ok: Option<bool>,
+
+    /// EDNS options to include in the OPT record.
+    ///
+    /// Each option is stored in wire format including its option code and
+    /// length.
+    pub edns_options: Vec<Vec<u8>>,
+
+    /// The timeout for each request.
+    ///
+    /// If this is `None`, the transport’s request timeout is used.
+    pub timeout: Option<Duration>,
+}
+
+impl RequestOptions {
+    /// Adds an EDNS option to be included in the OPT record.
+    pub fn push_edns_option<O: OptData>(&mut self, option: O)
+                                        -> ComposeResult<()> {
+        let mut composer = Composer::new(ComposeMode::Unlimited, false);
+        option.compose(&mut composer)?;
+        self.edns_options.push(composer.finish());
+        Ok(())
+    }
+
+    /// Returns whether an OPT record is needed regardless of the server.
+    ///
+    /// This is the case if the DO bit is to be set or there are EDNS
+    /// options to include.
+    fn forces_edns(&self) -> bool {
+        self.dnssec_ok == Some(true) || !self.edns_options.is_empty()
+    }
+}
+
+
+//------------ RawOption -----------------------------------------------------
+
+/// An EDNS option already in wire format.
+struct RawOption<'a>(&'a [u8]);
+
+impl<'a> OptData for RawOption<'a> {
+    fn compose<C: AsMut<Composer>>(&self, mut target: C)
+                                   -> ComposeResult<()> {
+        target.as_mut().compose_bytes(self.0)
     }
 }
 
@@ -92,8 +252,6 @@ impl RequestMessage {
 /// sending. Once done, the transport message can be returned into a
 /// request message by dropping all EDNS0 information thus making it ready
 /// for reuse by the next transport.
-/// 
-/// *Note:* EDNS0 is not yet implemented.
 ///
 /// Transport messages are always kept wrapped into a pair of bi-locks. One
 /// of those locks goes into the transport request for use by the transport,
@@ -111,39 +269,128 @@ impl RequestMessage {
 /// however, not `take()` out the message. By resolving the oneshot, access
 /// is transferred back to the query request. It then can `take()` out the
 /// message.
-pub struct TransportMessage(AdditionalBuilder);
+pub struct TransportMessage {
+    /// The message itself.
+    msg: AdditionalBuilder,
+
+    /// The content of the OPT record if one has been added.
+    ///
+    /// We need to keep this around so we can rebuild the record when a
+    /// transport wants to add further options.
+    opt: Option<OptParams>,
+
+    /// The request options of the query.
+    options: Arc<RequestOptions>,
+}
 
 impl TransportMessage {
     /// Sets the message ID to the given value.
     pub fn set_id(&mut self, id: u16) {
-        self.0.header_mut().set_id(id)
+        self.msg.header_mut().set_id(id)
+    }
+
+    /// Adds an OPT record with the given UDP payload size to the message.
+    ///
+    /// If `dnssec_ok` is `true`, the DO bit is set. If `cookie` is given,
+    /// the record contains it as a COOKIE option. If `padding` is given,
+    /// the message is padded according to this policy. If adding the record
+    /// fails, the message is left unchanged.
+    pub fn add_opt(&mut self, payload_size: u16, dnssec_ok: bool,
+                   cookie: Option<Cookie>, padding: Option<PaddingPolicy>)
+                   -> ComposeResult<()> {
+        self.build_opt(OptParams {
+            payload_size: payload_size,
+            dnssec_ok: dnssec_ok,
+            cookie: cookie,
+            keepalive: false,
+            padding: padding,
+        })
+    }
+
+    /// Adds an empty edns-tcp-keepalive option to the OPT record.
+    ///
+    /// The option is only added if the message already has an OPT record
+    /// since a server that doesn’t do EDNS wouldn’t understand it anyway.
+    /// Returns whether the option was added. If adding fails, the message
+    /// is left unchanged.
+    pub fn add_tcp_keepalive(&mut self) -> bool {
+        let params = match self.opt {
+            Some(params) => OptParams { keepalive: true, .. params },
+            None => return false
+        };
+        self.build_opt(params).is_ok()
     }
 
-    /// Checks whether `answer` is an answer to this message.
-    pub fn is_answer(&self, answer: &Message) -> bool {
-        answer.is_answer(&self.0)
+    /// Replaces the OPT record of the message with one built from `params`.
+    ///
+    /// Building the OPT record consumes the builder, so we work on a copy.
+    /// This is cheap enough for a message with a single question and leaves
+    /// us with an untouched message if anything goes wrong.
+    fn build_opt(&mut self, params: OptParams) -> ComposeResult<()> {
+        let mut msg = self.msg.clone();
+        msg.rewind();
+        let mut opt = msg.build_opt(params.payload_size, OptRcode::NoError,
+                                    params.dnssec_ok)?;
+        if let Some(cookie) = params.cookie {
+            opt.push(cookie)?;
+        }
+        for option in &self.options.edns_options {
+            opt.push(RawOption(option))?;
+        }
+        if params.keepalive {
+            opt.push(TcpKeepalive::empty())?;
+        }
+        if let Some(padding) = params.padding {
+            opt.pad(padding)?;
+        }
+        self.msg = opt.complete()?;
+        self.opt = Some(params);
+        Ok(())
     }
 
     /// Trades in this transport message for a request message.
     ///
     /// This rewinds all additions made to the message since creation but
     /// leaves the ID in place.
-    pub fn rewind(self) -> RequestMessage {
-        RequestMessage(self.0)
+    pub fn rewind(mut self) -> RequestMessage {
+        self.msg.rewind();
+        RequestMessage { msg: self.msg, options: self.options }
     }
 
     /// Returns a bytes slice with the data to be sent over stream transports.
     pub fn stream_bytes(&mut self) -> &[u8] {
-        self.0.preview()
+        self.msg.preview()
     }
 
     /// Returns a bytes slice for sending over datagram transports.
     pub fn dgram_bytes(&mut self) -> &[u8] {
-        &self.0.preview()[2..]
+        &self.msg.preview()[2..]
     }
 }
 
 
+//------------ OptParams -----------------------------------------------------
+
+/// The content of the OPT record of a transport message.
+#[derive(Clone, Copy, Debug)]
+struct OptParams {
+    /// The UDP payload size.
+    payload_size: u16,
+
+    /// Whether to set the DO bit.
+    dnssec_ok: bool,
+
+    /// The DNS cookie to include, if any.
+    cookie: Option<Cookie>,
+
+    /// Whether to include an edns-tcp-keepalive option.
+    keepalive: bool,
+
+    /// How to pad the message, if at all.
+    padding: Option<PaddingPolicy>,
+}
+
+
 //------------ TransportMessageGuard -----------------------------------------
 
 /// A RAII guard for a locked transport message.
@@ -196,6 +443,52 @@ pub struct QueryRequest {
 
     /// Our side of the bi-locked transport message.
     msg: BiLock<Option<TransportMessage>>,
+
+    /// The transport the request has been sent to.
+    ///
+    /// We keep this so we can resend the request without EDNS should the
+    /// server not like it.
+    transport: TransportHandle,
+
+    /// Did the request message include an OPT record?
+    edns: bool,
+
+    /// Did the query demand an OPT record?
+    ///
+    /// If so, we don’t retry without one if the server doesn’t support
+    /// EDNS.
+    edns_forced: bool,
+
+    /// Did the request message include a DNS cookie?
+    cookie: bool,
+
+    /// Is this the retry after a BADCOOKIE response?
+    cookie_retry: bool,
+
+    /// The query name with randomized case if randomization is used.
+    ///
+    /// The response has to echo this name exactly.
+    qname: Option<DNameBuf>,
+
+    /// The request timeout if it differs from the server’s.
+    timeout: Option<Duration>,
+
+    /// The original request message if randomization is used.
+    ///
+    /// This is the message handed back when the request resolves, so
+    /// that the randomized name doesn’t leak into later requests.
+    original: Option<RequestMessage>,
+
+    /// When the request was sent.
+    ///
+    /// This is used to measure the round trip time for the server’s
+    /// statistics.
+    started: Instant,
+
+    /// When the request was sent according to the system clock.
+    ///
+    /// This is used for the events reported to an observer.
+    started_at: SystemTime,
 }
 
 impl QueryRequest {
@@ -204,18 +497,201 @@ impl QueryRequest {
     /// The request will attempt to answer `message` using the transport
     /// referenced by `transport`.
     pub fn new(message: RequestMessage, transport: &TransportHandle) -> Self {
+        Self::start(message, transport, false)
+    }
+
+    /// Creates a new query request, possibly as a BADCOOKIE retry.
+    fn start(message: RequestMessage, transport: &TransportHandle,
+             cookie_retry: bool) -> Self {
+        // Randomization only protects UDP, the other transports don’t
+        // need it.
+        let randomize = transport.protocol() == Protocol::Udp
+                     && transport.info().randomize_case();
+        let (message, original, qname) = if randomize {
+            match message.randomize_case() {
+                Some((random, qname)) => (random, Some(message), Some(qname)),
+                None => (message, None, None)
+            }
+        }
+        else {
+            (message, None, None)
+        };
+        let edns_forced = message.options.forces_edns();
+        let timeout = message.options.timeout;
         let (tx, rx) = oneshot::channel();
         let (smsg, qmsg) = message.into_service();
-        let sreq = TransportRequest::new(smsg, tx);
+        let sreq = TransportRequest::new(smsg, tx, timeout,
+                                         transport.observed());
+        let edns = sreq.add_edns(transport.info());
         let rx = transport.send(sreq).ok().map(|_| rx);
+        if rx.is_some() {
+            transport.info().record_request();
+        }
         QueryRequest {
             rx: rx,
-            msg: qmsg
+            msg: qmsg,
+            transport: transport.clone(),
+            edns: edns,
+            edns_forced: edns_forced,
+            cookie: edns && transport.info().cookie().is_some(),
+            cookie_retry: cookie_retry,
+            qname: qname,
+            timeout: timeout,
+            original: original,
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+        }
+    }
+
+    /// Returns the configuration of the server the request was sent to.
+    pub fn server(&self) -> &ServerConf {
+        self.transport.info().conf()
+    }
+
+    /// Reports an event for this request to the observer if there is one.
+    ///
+    /// The request message given is the one returned when the request
+    /// resolved.
+    pub fn observe(&self, kind: EventKind, message: &RequestMessage,
+                   response: Option<&MessageBuf>) {
+        let info = self.transport.info();
+        if let Some(observer) = info.observer() {
+            observer.observe(&Event::new(
+                kind, info.conf().addr, self.transport.protocol(),
+                self.started_at, SystemTime::now(),
+                message.as_ref().as_bytes(),
+                response.map(|response| response.as_bytes())
+            ))
+        }
+    }
+
+    /// Updates the server statistics with the result of the request.
+    fn record_result(&self, result: &TransportResult) {
+        let info = self.transport.info();
+        match *result {
+            Ok(ref response) => {
+                info.record_response(self.started.elapsed(),
+                                     response.header().rcode())
+            }
+            Err(ref err) if err.kind() == ErrorKind::Timeout => {
+                info.record_timeout(
+                    self.timeout.unwrap_or(info.conf().request_timeout)
+                )
+            }
+            Err(_) => info.record_error(),
+        }
+    }
+
+    /// Takes the request message back from the transport.
+    ///
+    /// If the case of the query name was randomized, the original message
+    /// is returned instead.
+    fn take_message(&mut self) -> RequestMessage {
+        let msg = into_message(&mut self.msg);
+        self.original.take().unwrap_or(msg)
+    }
+
+    /// Checks whether a response indicates that EDNS isn’t supported.
+    ///
+    /// This is the case if the request included an OPT record and the
+    /// server either responded with FORMERR or NOTIMP without including
+    /// an OPT record itself or responded with BADVERS.
+    ///
+    /// If this happens, the server will be marked as not supporting EDNS.
+    /// Returns `true` if the request should be retried without EDNS, which
+    /// isn’t the case if the query insists on EDNS.
+    fn check_edns_failure(&self, response: &MessageBuf) -> bool {
+        if !self.edns {
+            return false
+        }
+        let failed = match response.header().rcode() {
+            Rcode::FormErr | Rcode::NotImp => response.opt().is_none(),
+            _ => {
+                match response.opt_rcode() {
+                    OptRcode::BadVers => true,
+                    _ => false
+                }
+            }
+        };
+        if failed {
+            self.transport.info().disable_edns();
+        }
+        failed && !self.edns_forced
+    }
+
+    /// Checks whether the server preserved the case of the query name.
+    ///
+    /// If the case was randomized and the response’s question has the same
+    /// name but in different case, the mismatch is recorded with the
+    /// server and `false` is returned so the request is repeated. Since
+    /// such a response may be spoofed, randomization is only switched off
+    /// for the server after several mismatches in a row. If the name is
+    /// different altogether, the response is left for the response policy
+    /// to reject.
+    fn check_case(&self, response: &MessageBuf) -> bool {
+        let qname = match self.qname {
+            Some(ref qname) => qname,
+            None => return true
+        };
+        let question = match response.first_question() {
+            Some(question) => question,
+            None => return true
+        };
+        if *qname != *question.qname() {
+            return true
+        }
+        if case::eq_exact(qname, question.qname()) {
+            self.transport.info().record_case_match();
+            return true
+        }
+        self.transport.info().record_case_mismatch();
+        false
+    }
+
+    /// Checks the DNS cookie of a response.
+    ///
+    /// If the request contained a cookie and the response does, too, it
+    /// has to echo our client cookie. A response without a cookie is fine
+    /// since the server may not support cookies. A server cookie in the
+    /// response is remembered for the next request.
+    fn check_cookie(&self, response: &MessageBuf) -> CookieCheck {
+        if !self.cookie {
+            return CookieCheck::Valid
+        }
+        let opt = match response.opt() {
+            Some(opt) => opt,
+            None => return CookieCheck::Valid
+        };
+        match opt.data().iter::<Cookie>().next() {
+            Some(Ok(cookie)) => {
+                if !self.transport.info().update_cookie(&cookie) {
+                    return CookieCheck::Mismatch
+                }
+            }
+            Some(Err(_)) => return CookieCheck::Mismatch,
+            None => { }
+        }
+        match response.opt_rcode() {
+            OptRcode::BadCookie => CookieCheck::BadCookie,
+            _ => CookieCheck::Valid
         }
     }
 }
 
 
+/// The outcome of checking the DNS cookie of a response.
+enum CookieCheck {
+    /// The cookie is fine or there wasn’t one.
+    Valid,
+
+    /// The cookie doesn’t echo our client cookie.
+    Mismatch,
+
+    /// The server didn’t like our cookie and sent BADCOOKIE.
+    BadCookie,
+}
+
+
 //--- Future
 
 impl Future for QueryRequest {
@@ -228,36 +704,310 @@ impl Future for QueryRequest {
                 match rx.poll() {
                     Ok(Async::NotReady) => Ok(Async::NotReady),
                     Ok(Async::Ready(response)) => {
-                        let msg = into_message(&mut self.msg);
-                        match response {
-                            Ok(response) => Ok(Async::Ready((response, msg))),
-                            Err(err) => Err((err, msg)),
+                        let msg = self.take_message();
+                        self.record_result(&response);
+                        let response = match response {
+                            Ok(response) => response,
+                            Err(err) => return Err((err, msg)),
+                        };
+                        if self.check_edns_failure(&response) {
+                            // Try again right away. The server is now
+                            // marked as not supporting EDNS, so the new
+                            // request will go out without it. A retry
+                            // after BADCOOKIE stays one.
+                            let transport = self.transport.clone();
+                            *self = QueryRequest::start(msg, &transport,
+                                                        self.cookie_retry);
+                            return self.poll()
+                        }
+                        if !self.check_case(&response) {
+                            // Ask again with a new ID and name. After
+                            // repeated mismatches, randomization is off
+                            // for the server and the request goes out
+                            // without it.
+                            let transport = self.transport.clone();
+                            *self = QueryRequest::start(msg, &transport,
+                                                        self.cookie_retry);
+                            return self.poll()
+                        }
+                        match self.check_cookie(&response) {
+                            CookieCheck::Valid => {
+                                Ok(Async::Ready((response, msg)))
+                            }
+                            CookieCheck::BadCookie if !self.cookie_retry => {
+                                // The server cookie from the response has
+                                // been stored, so retry once with it.
+                                let transport = self.transport.clone();
+                                *self = QueryRequest::start(msg, &transport,
+                                                            true);
+                                self.poll()
+                            }
+                            _ => Err((unacceptable_response(), msg))
                         }
                     }
                     Err(_) => {
                         // The transport disappeared. Let’s do a connection
                         // aborted error even if that isn’t quite right for
                         // UDP.
-                        let msg = into_message(&mut self.msg);
-                        Err((Error::Io(
-                                io::Error::new(
-                                    io::ErrorKind::ConnectionAborted,
-                                    "transport disappeared")),
+                        self.transport.info().record_error();
+                        let msg = self.take_message();
+                        Err((io::Error::new(io::ErrorKind::ConnectionAborted,
+                                            "transport disappeared").into(),
                              msg))
                     }
                 }
             }
             None => {
-                let msg = into_message(&mut self.msg);
-                Err((Error::Io(
-                        io::Error::new(io::ErrorKind::ConnectionAborted,
-                                       "service disappeared")),
-                        msg))
+                let msg = self.take_message();
+                Err((io::Error::new(io::ErrorKind::ConnectionAborted,
+                                    "service disappeared").into(),
+                     msg))
+            }
+        }
+    }
+}
+
+
+//------------ BlastRequest --------------------------------------------------
+
+/// A request sent to several transports in parallel.
+///
+/// This type is used by `Query` if the `blast` option is set. It sends the
+/// request to all transports in the order given, resolving into the first
+/// response accepted by the response policy. Responses the policy deems
+/// a fallback are kept and rejected responses are treated as errors. If
+/// all transports failed, the last fallback response is returned or, if
+/// there wasn’t any, an error.
+///
+/// The requests can be staggered: if a delay is given, the next transport
+/// is only asked once this delay has passed or the previous transport has
+/// failed. Requests still outstanding when the blast request resolves or
+/// is dropped are abandoned.
+pub struct BlastRequest {
+    /// The transports to send the request to.
+    track: Vec<TransportHandle>,
+
+    /// The index in `track` of the next transport to send the request to.
+    next: usize,
+
+    /// The request message.
+    ///
+    /// Each request gets its own copy of this message.
+    message: RequestMessage,
+
+    /// The requests currently in flight.
+    running: Vec<QueryRequest>,
+
+    /// The policy for deciding whether a response is acceptable.
+    policy: Arc<ResponsePolicy>,
+
+    /// The delay between requests and a remote for starting timers.
+    delay: Option<(Duration, reactor::Remote)>,
+
+    /// A timer signalling that the next request is due.
+    timer: Option<oneshot::Receiver<()>>,
+
+    /// The last unacceptable response we received and its server.
+    fallback: Option<(MessageBuf, ServerConf)>,
+
+    /// The server whose response we resolved into.
+    answered: Option<ServerConf>,
+
+    /// The error we will return if all requests failed.
+    ///
+    /// It has an attempt for each failed request.
+    error: Option<Error>,
+}
+
+impl BlastRequest {
+    /// Creates a new blast request.
+    ///
+    /// The request will be sent to the transports in `track` in the order
+    /// given. If `delay` is `Some(_)`, the requests are staggered by the
+    /// given duration using timers spawned via the given remote.
+    pub fn new(message: RequestMessage, track: Vec<TransportHandle>,
+               policy: Arc<ResponsePolicy>,
+               delay: Option<(Duration, reactor::Remote)>) -> Self {
+        let mut res = BlastRequest {
+            track: track,
+            next: 0,
+            message: message,
+            running: Vec::new(),
+            policy: policy,
+            delay: delay,
+            timer: None,
+            fallback: None,
+            answered: None,
+            error: None,
+        };
+        res.start_next();
+        res
+    }
+
+    /// Starts the request on the next transport if there is one.
+    fn start_next(&mut self) {
+        if self.next == self.track.len() {
+            self.timer = None;
+            return
+        }
+        self.running.push(QueryRequest::new(self.message.clone(),
+                                            &self.track[self.next]));
+        self.next += 1;
+        self.timer = match self.delay {
+            Some((delay, ref remote)) if self.next < self.track.len() => {
+                Some(start_timer(remote, delay))
+            }
+            _ => None
+        };
+        if self.delay.is_none() {
+            self.start_next()
+        }
+    }
+
+    /// Returns the configuration of the server that sent the response.
+    ///
+    /// This is `None` until the request has resolved successfully.
+    pub fn server(&self) -> Option<&ServerConf> {
+        self.answered.as_ref()
+    }
+
+    /// Takes the failed requests so far.
+    ///
+    /// If the request resolved successfully, these are the requests that
+    /// failed before.
+    pub fn take_attempts(&mut self) -> Vec<Attempt> {
+        match self.error.take() {
+            Some(error) => error.into_attempts(),
+            None => Vec::new()
+        }
+    }
+
+    /// Adds an error to the error to be returned if all requests fail.
+    ///
+    /// The error is recorded as an attempt for the given server.
+    fn add_error(&mut self, server: ServerConf, err: Error) {
+        let err = Error::from_attempt(server, err);
+        self.error = Some(match self.error.take() {
+            Some(error) => error.merge(err),
+            None => err
+        });
+    }
+
+    /// Returns whether the timer for the next request has fired.
+    fn timer_fired(&mut self) -> bool {
+        match self.timer {
+            Some(ref mut timer) => {
+                match timer.poll() {
+                    Ok(Async::NotReady) => false,
+                    _ => true
+                }
+            }
+            None => false
+        }
+    }
+}
+
+
+//--- Future
+
+impl Future for BlastRequest {
+    type Item = (MessageBuf, RequestMessage);
+    type Error = (Error, RequestMessage);
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            // Each new request comes with a new timer which needs to be
+            // polled, too, so that we get woken up when it fires.
+            while self.timer_fired() {
+                self.start_next()
+            }
+            let mut started = false;
+            let mut i = 0;
+            while i < self.running.len() {
+                match self.running[i].poll() {
+                    Ok(Async::NotReady) => {
+                        i += 1;
+                        continue
+                    }
+                    Ok(Async::Ready((response, _))) => {
+                        let server = self.running.swap_remove(i).server()
+                                         .clone();
+                        match self.policy.check(self.message.as_ref(),
+                                                &response) {
+                            Verdict::Accept => {
+                                self.running.clear();
+                                self.answered = Some(server);
+                                return Ok(Async::Ready((
+                                    response, self.message.clone()
+                                )))
+                            }
+                            Verdict::Fallback => {
+                                self.fallback = Some((response, server))
+                            }
+                            Verdict::Reject => {
+                                self.add_error(server,
+                                               unacceptable_response())
+                            }
+                        }
+                    }
+                    Err((err, _)) => {
+                        let server = self.running.swap_remove(i).server()
+                                         .clone();
+                        self.add_error(server, err)
+                    }
+                }
+                // One transport is done, so there is no point in waiting
+                // for the timer before asking the next one. The new
+                // request is added to the end of `running` and thus will
+                // be polled, too.
+                self.start_next();
+                started = true;
+            }
+            if !started {
+                break
             }
         }
+        if !self.running.is_empty() {
+            return Ok(Async::NotReady)
+        }
+        let message = self.message.clone();
+        if let Some((response, server)) = self.fallback.take() {
+            self.answered = Some(server);
+            return Ok(Async::Ready((response, message)))
+        }
+        let error = self.error.take().unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound,
+                           "no transports available").into()
+        });
+        Err((error, message))
     }
 }
 
+/// Starts a timer in the reactor that fires after `delay`.
+///
+/// The timer is spawned into the reactor via the given remote and reports
+/// back through a oneshot. If anything goes wrong with the timer, the
+/// oneshot fires early.
+fn start_timer(remote: &reactor::Remote, delay: Duration)
+               -> oneshot::Receiver<()> {
+    let (tx, rx) = oneshot::channel();
+    remote.spawn(move |handle| {
+        reactor::Timeout::new(delay, handle).into_future().flatten()
+                         .then(move |_| {
+                             tx.send(()).ok();
+                             Ok(())
+                         })
+    });
+    rx
+}
+
+
+/// Returns the error for a response rejected by the response policy.
+pub fn unacceptable_response() -> Error {
+    io::Error::new(io::ErrorKind::Other, "unacceptable response").into()
+}
+
+
 /// Helper function for unwrapping the transport message.
 /// 
 /// The function will take the transport message out of the lock if and only
@@ -301,17 +1051,25 @@ pub struct TransportRequest {
     /// This is initially `None` to indicate that it hasn’t been set for this
     /// particular iteration yet.
     id: Option<u16>,
+
+    /// The timeout for the request if it differs from the transport’s.
+    timeout: Option<Duration>,
+
+    /// What an observer needs to know if there is one.
+    observed: Option<Observed>,
 }
 
 impl TransportRequest {
     /// Creates a new transport request from its components.
     fn new(message: BiLock<Option<TransportMessage>>,
-           complete: oneshot::Sender<TransportResult>) 
-           -> Self {
+           complete: oneshot::Sender<TransportResult>,
+           timeout: Option<Duration>, observed: Option<Observed>) -> Self {
         TransportRequest {
             message: message,
             complete: complete,
-            id: None
+            id: None,
+            timeout: timeout,
+            observed: observed,
         }
     }
 
@@ -333,17 +1091,70 @@ impl TransportRequest {
         }
     }
 
+    /// Adds EDNS information to the request message if appropriate.
+    ///
+    /// An OPT record is only added if the server described by `info` is
+    /// believed to support EDNS or the query’s request options demand one.
+    /// If cookies are enabled, it contains the server’s cookie. If the
+    /// server is reached over an encrypted transport, the message is
+    /// padded. Returns whether the record was added.
+    fn add_edns(&self, info: &ServerInfo) -> bool {
+        let mut message = self.message();
+        let size = match info.edns_payload_size() {
+            Some(size) => size,
+            None if message.options.forces_edns() => info.payload_size(),
+            None => return false
+        };
+        let dnssec_ok = message.options.dnssec_ok
+                               .unwrap_or_else(|| info.dnssec_ok());
+        message.add_opt(size, dnssec_ok, info.cookie(), info.padding())
+               .is_ok()
+    }
+
     /// Returns the request message’s ID or `None` if it hasn’t been set yet.
     pub fn id(&self) -> Option<u16> {
         self.id
     }
 
+    /// Returns the request’s timeout if it differs from the transport’s.
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
     /// Sets the request message’s ID to the given value.
     pub fn set_id(&mut self, id: u16) {
         self.id = Some(id);
         self.message().set_id(id)
     }
 
+    /// Marks the request as sent.
+    ///
+    /// Transports call this once the channel has given the request back
+    /// after sending it. If there is an observer, it is told about the
+    /// request.
+    pub fn sent(&mut self) {
+        if let Some(ref mut observed) = self.observed {
+            observed.sent = SystemTime::now();
+        }
+        self.observe(EventKind::Query, None)
+    }
+
+    /// Reports an event for this request to the observer if there is one.
+    fn observe(&self, kind: EventKind, response: Option<&MessageBuf>) {
+        let observed = match self.observed {
+            Some(ref observed) => observed,
+            None => return
+        };
+        let time = if kind == EventKind::Query { observed.sent }
+                   else { SystemTime::now() };
+        let mut message = self.message();
+        observed.observer.observe(&Event::new(
+            kind, observed.server, observed.protocol, observed.sent, time,
+            message.dgram_bytes(),
+            response.map(|response| response.as_bytes())
+        ))
+    }
+
     /// Completes the request with the given result.
     pub fn complete(self, result: TransportResult) {
         // Drop the message’s lock before completing as per the rules for
@@ -355,17 +1166,11 @@ impl TransportRequest {
 
     /// Completes the request with a response message.
     ///
-    /// This will produce a successful result only if `response` actually is
-    /// an answer to the request message. Else drops the message and produces
-    /// an error.
+    /// Whether `response` actually is an answer to the request message is
+    /// left to the query’s response policy.
     pub fn response(self, response: MessageBuf) {
-        if self.message().is_answer(&response) {
-            self.complete(Ok(response))
-        }
-        else {
-            self.fail(io::Error::new(io::ErrorKind::Other, "server failure")
-                                .into())
-        }
+        self.observe(EventKind::Response, Some(&response));
+        self.complete(Ok(response))
     }
 
     /// Completes the request with the given error.
@@ -375,37 +1180,92 @@ impl TransportRequest {
 
     /// Completes the request with a timeout error.
     pub fn timeout(self) {
-        self.complete(Err(Error::Timeout))
+        self.observe(EventKind::Timeout, None);
+        self.complete(Err(ErrorKind::Timeout.into()))
     }
 }
 
 
+//------------ Observed ------------------------------------------------------
+
+/// What an observer needs to know about a transport request.
+struct Observed {
+    /// The observer.
+    observer: Arc<Observer>,
+
+    /// The address of the server.
+    server: SocketAddr,
+
+    /// The protocol of the transport.
+    protocol: Protocol,
+
+    /// When the request was sent.
+    ///
+    /// Until the transport has sent the request, this is when it was
+    /// created.
+    sent: SystemTime,
+}
+
+
 //------------ TransportHandle -----------------------------------------------
 
 /// A handle for communicating with a transport.
 ///
 /// You can use this handle to send requests to the transport via the `send()`
-/// method.
+/// method. The handle also provides access to the shared information about
+/// the server the transport talks to.
 #[derive(Clone)]
 pub struct TransportHandle {
     /// The sending end of the transport’s request queue
     tx: mpsc::UnboundedSender<TransportRequest>,
+
+    /// Information about the transport’s server.
+    info: Arc<ServerInfo>,
+
+    /// The protocol the transport uses.
+    protocol: Protocol,
 }
 
 impl TransportHandle {
     /// Creates a new request channel, returning both ends.
-    pub fn channel() -> (TransportHandle, RequestReceiver) {
+    pub fn channel(info: Arc<ServerInfo>, protocol: Protocol)
+                   -> (TransportHandle, RequestReceiver) {
         let (tx, rx) = mpsc::unbounded();
-        (TransportHandle::from_sender(tx), rx)
+        (TransportHandle::from_sender(tx, info, protocol), rx)
     } 
 
     /// Creates a new handle from the sender side of an MPCS channel.
-    pub fn from_sender(tx: mpsc::UnboundedSender<TransportRequest>) -> Self {
+    pub fn from_sender(tx: mpsc::UnboundedSender<TransportRequest>,
+                       info: Arc<ServerInfo>, protocol: Protocol) -> Self {
         TransportHandle {
-            tx: tx
+            tx: tx,
+            info: info,
+            protocol: protocol,
         }
     }
 
+    /// Returns a reference to the information about the server.
+    pub fn info(&self) -> &ServerInfo {
+        &self.info
+    }
+
+    /// Returns the protocol the transport uses.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Returns what an observer needs to know about a new request.
+    fn observed(&self) -> Option<Observed> {
+        self.info.observer().map(|observer| {
+            Observed {
+                observer: observer.clone(),
+                server: self.info.conf().addr,
+                protocol: self.protocol,
+                sent: SystemTime::now(),
+            }
+        })
+    }
+
     /// Sends a transport request to the transport.
     ///
     /// This only fails if the receiver was dropped.
@@ -432,3 +1292,141 @@ impl fmt::Debug for TransportHandle {
 pub type RequestReceiver = mpsc::UnboundedReceiver<TransportRequest>;
 
 
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use ::bits::{ComposeMode, DNameBuf, MessageBuf, MessageBuilder};
+    use ::iana::{Rcode, Rtype};
+    use ::rdata::A;
+    use super::super::conf::{ResolvConf, ServerConf};
+    use super::super::Resolver;
+
+    /// What a test server does with requests.
+    #[derive(Clone, Copy)]
+    enum Behaviour {
+        /// Answer with an A record.
+        Answer,
+
+        /// Answer with Refused.
+        Refuse,
+
+        /// Don’t answer at all.
+        Ignore,
+    }
+
+    /// Starts a server and returns its address.
+    ///
+    /// The server adds its index and the time of each request to `log`.
+    fn serve(index: usize, behaviour: Behaviour,
+             log: Arc<Mutex<Vec<(usize, Instant)>>>) -> SocketAddr {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = sock.local_addr().unwrap();
+        sock.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok((len, peer)) = sock.recv_from(&mut buf) {
+                log.lock().unwrap().push((index, Instant::now()));
+                let request = MessageBuf::from_bytes(&buf[..len]).unwrap();
+                let question = request.first_question().unwrap();
+                let mut msg = MessageBuilder::new(ComposeMode::Unlimited,
+                                                  true).unwrap();
+                msg.header_mut().set_id(request.header().id());
+                msg.header_mut().set_qr(true);
+                msg.header_mut().set_rd(request.header().rd());
+                msg.header_mut().set_ra(true);
+                msg.push(question.clone()).unwrap();
+                let mut msg = msg.answer();
+                match behaviour {
+                    Behaviour::Answer => {
+                        msg.push((question.qname().clone(), 3600,
+                                  A::new(Ipv4Addr::new(192, 0, 2, 1))))
+                           .unwrap();
+                    }
+                    Behaviour::Refuse => {
+                        msg.header_mut().set_rcode(Rcode::Refused)
+                    }
+                    Behaviour::Ignore => continue
+                }
+                sock.send_to(&msg.finish(), peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    /// Runs a query in blast mode against three servers.
+    ///
+    /// The first two servers behave as given, the third one answers.
+    /// Returns the indexes of the servers asked and how long after the
+    /// start of the query the third server was asked.
+    fn blast(delay: Duration, first: Behaviour, second: Behaviour)
+             -> (Vec<usize>, Duration) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut conf = ResolvConf::new();
+        for (index, &behaviour) in [first, second, Behaviour::Answer]
+                                       .iter().enumerate() {
+            let addr = serve(index, behaviour, log.clone());
+            conf.servers.push(ServerConf::new(addr));
+        }
+        conf.timeout = Duration::from_secs(2);
+        conf.attempts = 1;
+        conf.options.blast = true;
+        conf.blast_delay = delay;
+        conf.finalize();
+
+        let name = DNameBuf::from_str("www.example.com.").unwrap();
+        let start = Instant::now();
+        let response = Resolver::run_with_conf(conf, |resolv| {
+            resolv.query((name, Rtype::A))
+        }).unwrap();
+        assert_eq!(response.header().rcode(), Rcode::NoError);
+        assert_eq!(response.answer().unwrap().count(), 1);
+
+        // The servers log from their own threads, so requests to servers
+        // that never answer may not have made it into the log yet.
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while log.lock().unwrap().len() < 3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1))
+        }
+        let log = log.lock().unwrap();
+        let asked = log.iter().map(|item| item.0).collect();
+        let last = log.iter().find(|item| item.0 == 2).unwrap().1;
+        (asked, last.duration_since(start))
+    }
+
+    #[test]
+    fn blast_all() {
+        let (asked, elapsed) = blast(Duration::from_secs(0),
+                                     Behaviour::Ignore, Behaviour::Ignore);
+        let mut asked = asked;
+        asked.sort();
+        assert_eq!(asked, vec![0, 1, 2]);
+        assert!(elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn blast_staggered() {
+        // The first two servers never answer, so the third server is only
+        // asked once both delays have passed.
+        let (asked, elapsed) = blast(Duration::from_millis(50),
+                                     Behaviour::Ignore, Behaviour::Ignore);
+        assert_eq!(asked, vec![0, 1, 2]);
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn blast_staggered_failure() {
+        // A server that has answered makes the next one be asked right
+        // away.
+        let (asked, elapsed) = blast(Duration::from_secs(5),
+                                     Behaviour::Refuse, Behaviour::Refuse);
+        assert_eq!(asked, vec![0, 1, 2]);
+        assert!(elapsed < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,258 @@
+//! Removing out-of-bailiwick records from responses.
+//!
+//! A response may contain records in its authority and additional sections
+//! that have nothing to do with the question asked. If these were trusted,
+//! anyone able to get a response to us could inject data for arbitrary
+//! names. Recursive resolvers therefore only accept records that are *in
+//! bailiwick,* that is, that belong to the zone the response came from.
+//!
+//! The [`scrub()`] function performs a similar check for a stub resolver.
+//! Since a stub resolver doesn’t know from which zone an answer came, it
+//! derives the zone from the response itself:
+//!
+//! * The question’s name and, following any CNAME records in the answer
+//!   section, the names it is an alias for are the *answer names.*
+//!
+//! * Records in the authority section are only kept if their owner is one
+//!   of the answer names or one of their ancestors.
+//!
+//! * The zone of the response is given by the closest of the remaining SOA
+//!   or NS records.
+//!
+//! * Records in the additional section are only kept if their owner is at
+//!   or below this zone. The OPT record is always kept. If the response
+//!   doesn’t reveal its zone, all other records are dropped.
+//!
+//! The answer section is left untouched. Lookups such as `lookup_srv()`
+//! that make use of addresses from the additional section will resolve
+//! names for which the addresses have been dropped separately.
+//!
+//! Unless the `scrub` field of the resolver configuration is cleared, the
+//! resolver scrubs all responses before using them.
+//!
+//! [`scrub()`]: fn.scrub.html
+
+use ::bits::{ComposeError, ComposeMode, DName, DNameBuf, GenericRecord,
+             Message, MessageBuf, MessageBuilder, ParseError, ParseResult};
+use ::iana::Rtype;
+use ::rdata::parsed::Cname;
+
+
+//------------ scrub ---------------------------------------------------------
+
+/// Returns a copy of `message` without out-of-bailiwick records.
+///
+/// See the [module documentation] for which records are removed. If the
+/// message doesn’t have a question, it is returned unchanged.
+///
+/// Returns an error if the message cannot be parsed.
+///
+/// [module documentation]: index.html
+pub fn scrub(message: &Message) -> ParseResult<MessageBuf> {
+    let names = answer_names(message)?;
+    if names.is_empty() {
+        return MessageBuf::from_bytes(message.as_bytes())
+    }
+
+    let mut authority = Vec::new();
+    let mut zone: Option<DNameBuf> = None;
+    let mut dropped = false;
+    for record in message.authority()? {
+        let record = record?;
+        let owner = record.name().to_cow();
+        if !names.iter().any(|name| name.ends_with(&owner.as_ref())) {
+            dropped = true;
+            continue
+        }
+        if record.rtype() == Rtype::Soa || record.rtype() == Rtype::Ns {
+            let closer = match zone {
+                Some(ref zone) => owner.ends_with(zone),
+                None => true
+            };
+            if closer {
+                zone = Some(owner.into_owned())
+            }
+        }
+        authority.push(record);
+    }
+
+    let mut additional = Vec::new();
+    for record in message.additional()? {
+        let record = record?;
+        let keep = record.rtype() == Rtype::Opt || match zone {
+            Some(ref zone) => record.name().to_cow().ends_with(zone),
+            None => false
+        };
+        if keep {
+            additional.push(record)
+        }
+        else {
+            dropped = true
+        }
+    }
+
+    if !dropped {
+        return MessageBuf::from_bytes(message.as_bytes())
+    }
+    // Since the new message is unlimited and made from parts of a valid
+    // message, composing can really only fail if the message is broken.
+    rebuild(message, authority, additional).map_err(|_| ParseError::FormErr)
+}
+
+/// Returns the answer names of a message.
+///
+/// These are the name of the first question plus all the names reached
+/// by following CNAME records in the answer section. If the message
+/// doesn’t have a question, the result is empty.
+fn answer_names(message: &Message) -> ParseResult<Vec<DNameBuf>> {
+    let mut names = match message.first_question() {
+        Some(question) => vec![question.qname().to_cow().into_owned()],
+        None => return Ok(Vec::new())
+    };
+    // Since CNAME records can appear in any order, we keep going until we
+    // don’t find a new name anymore. Each CNAME record can only add one
+    // name, so this ends at the latest after going through all of them.
+    loop {
+        let mut found = None;
+        for record in message.answer()?.limit_to::<Cname>() {
+            let record = record?;
+            let last = &names[names.len() - 1];
+            if *record.name() == *last {
+                let target = record.data().cname().to_cow().into_owned();
+                if !names.contains(&target) {
+                    found = Some(target);
+                    break
+                }
+            }
+        }
+        match found {
+            Some(name) => names.push(name),
+            None => return Ok(names)
+        }
+    }
+}
+
+/// Builds a new message from the question and answer of `message`.
+fn rebuild(message: &Message, authority: Vec<GenericRecord>,
+           additional: Vec<GenericRecord>)
+           -> Result<MessageBuf, ComposeError> {
+    let mut builder = MessageBuilder::new(ComposeMode::Unlimited, true)?;
+    *builder.header_mut() = message.header().clone();
+    for question in message.question() {
+        builder.push(question?)?;
+    }
+    let mut builder = builder.answer();
+    for record in message.answer()? {
+        builder.push(record?)?;
+    }
+    let mut builder = builder.authority();
+    for record in authority {
+        builder.push(record)?;
+    }
+    let mut builder = builder.additional();
+    for record in additional {
+        builder.push(record)?;
+    }
+    MessageBuf::from_vec(builder.finish()).map_err(Into::into)
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+    use ::bits::{ComposeMode, DNameBuf, MessageBuf, MessageBuilder};
+    use ::iana::{OptRcode, Rtype};
+    use ::rdata::{A, Cname, Ns, Srv};
+    use super::*;
+
+    fn name(s: &str) -> DNameBuf {
+        DNameBuf::from_str(s).unwrap()
+    }
+
+    fn response(cname: bool, authority: &[&str], additional: &[&str])
+                -> MessageBuf {
+        let mut msg = MessageBuilder::new(ComposeMode::Unlimited, true)
+                                     .unwrap();
+        msg.header_mut().set_qr(true);
+        msg.push((name("_sip._tcp.example.com."), Rtype::Srv)).unwrap();
+        let mut msg = msg.answer();
+        let owner = if cname {
+            msg.push((name("_sip._tcp.example.com."), 3600,
+                      Cname::new(name("_sip._tcp.example.net."))))
+               .unwrap();
+            name("_sip._tcp.example.net.")
+        }
+        else {
+            name("_sip._tcp.example.com.")
+        };
+        msg.push((owner, 3600, Srv::new(10, 10, 5060,
+                                        name("sip.example.com."))))
+           .unwrap();
+        let mut msg = msg.authority();
+        for zone in authority {
+            msg.push((name(zone), 3600, Ns::new(name("ns.example.com."))))
+               .unwrap();
+        }
+        let mut msg = msg.additional();
+        for host in additional {
+            msg.push((name(host), 3600, A::new(Ipv4Addr::new(192, 0, 2, 1))))
+               .unwrap();
+        }
+        let opt = msg.build_opt(1232, OptRcode::NoError, false).unwrap();
+        MessageBuf::from_vec(opt.complete().unwrap().finish()).unwrap()
+    }
+
+    fn owners(msg: &MessageBuf) -> (Vec<String>, Vec<String>) {
+        let authority = msg.authority().unwrap().map(|record| {
+            format!("{}", record.unwrap().name())
+        }).collect();
+        let additional = msg.additional().unwrap().filter_map(|record| {
+            let record = record.unwrap();
+            if record.rtype() == Rtype::Opt { None }
+            else { Some(format!("{}", record.name())) }
+        }).collect();
+        (authority, additional)
+    }
+
+    #[test]
+    fn in_bailiwick() {
+        let msg = response(false, &["example.com."],
+                           &["sip.example.com.", "ns.example.com."]);
+        let scrubbed = scrub(&msg).unwrap();
+        assert_eq!(scrubbed.as_bytes(), msg.as_bytes());
+    }
+
+    #[test]
+    fn out_of_bailiwick() {
+        let msg = response(false, &["example.com.", "example.org."],
+                           &["sip.example.com.", "sip.example.org."]);
+        let scrubbed = scrub(&msg).unwrap();
+        assert_eq!(owners(&scrubbed),
+                   (vec!["example.com.".into()],
+                    vec!["sip.example.com.".into()]));
+        assert_eq!(scrubbed.answer().unwrap().count(), 1);
+        assert!(scrubbed.opt().is_some());
+    }
+
+    #[test]
+    fn no_zone() {
+        let msg = response(false, &[], &["sip.example.com."]);
+        let scrubbed = scrub(&msg).unwrap();
+        assert_eq!(owners(&scrubbed), (vec![], vec![]));
+        assert!(scrubbed.opt().is_some());
+    }
+
+    #[test]
+    fn follow_cname() {
+        let msg = response(true, &["example.net."], &["sip.example.com.",
+                                                      "sip.example.net."]);
+        let scrubbed = scrub(&msg).unwrap();
+        assert_eq!(owners(&scrubbed),
+                   (vec!["example.net.".into()],
+                    vec!["sip.example.net.".into()]));
+        assert_eq!(scrubbed.answer().unwrap().count(), 2);
+    }
+}
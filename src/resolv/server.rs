@@ -0,0 +1,491 @@
+//! Information about upstream servers shared by transports and queries.
+//!
+//! Each server from the resolver configuration gets one `ServerInfo` value
+//! behind an arc. It is shared by all transports for this server and by the
+//! transport handles referencing them. This way, queries can learn things
+//! about a server, such as that it doesn’t support EDNS, and all later
+//! requests for this server benefit from this knowledge.
+//!
+//! The server info also keeps statistics about the server’s health. These
+//! are used for ordering servers and are available to users as
+//! `ServerStats` values via `Resolver::server_stats()`.
+
+use std::cmp;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use rand::random;
+use ::bits::opt::rfc7830::PaddingPolicy;
+use ::bits::opt::rfc7873::Cookie;
+use ::iana::Rcode;
+use super::conf::{ResolvConf, ServerConf};
+use super::observer::Observer;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The number of responses in a row with the query name in the wrong case
+/// before case randomization is switched off for a server.
+///
+/// A single such response may just as well have been spoofed.
+const CASE_MISMATCHES: usize = 3;
+
+/// For how long case randomization is switched off for a server.
+const CASE_HOLDDOWN: Duration = Duration::from_secs(3600);
+
+
+//------------ ServerInfo ----------------------------------------------------
+
+/// Information about an upstream server.
+#[derive(Debug)]
+pub struct ServerInfo {
+    /// The configuration of the server.
+    conf: ServerConf,
+
+    /// Do we think the server supports EDNS?
+    ///
+    /// This starts out as the value of the `use_edns0` resolver option and
+    /// is cleared if the server indicates that it didn’t understand a
+    /// request with an OPT record.
+    edns: AtomicBool,
+
+    /// Do we ask the server for DNSSEC records?
+    ///
+    /// This is the case if the resolver validates answers itself.
+    dnssec_ok: bool,
+
+    /// Do we randomize the case of query names sent to the server?
+    ///
+    /// This is the `randomize_case` field of the resolver config. Even if
+    /// it is set, randomization may be switched off for a while if the
+    /// server doesn’t seem to preserve the case.
+    randomize_case: bool,
+
+    /// What we learned about the server preserving case.
+    case: Mutex<CaseState>,
+
+    /// The DNS cookie to send to the server if cookies are enabled.
+    ///
+    /// The client cookie is chosen randomly for each server. Once the
+    /// server has told us its server cookie, it is included, too.
+    cookie: Option<Mutex<Cookie>>,
+
+    /// The number of consecutive failures before the server is held down.
+    ///
+    /// If this is zero, the server is never held down.
+    holddown_failures: usize,
+
+    /// For how long the server is held down.
+    holddown_time: Duration,
+
+    /// The observer to tell about requests to the server, if any.
+    observer: Option<Arc<Observer>>,
+
+    /// The statistics for the server.
+    stats: Mutex<ServerStats>,
+}
+
+impl ServerInfo {
+    /// Creates a new server info value.
+    ///
+    /// Whether to use EDNS and when to hold down the server is taken from
+    /// the resolver config `resolv`. If the `use_edns0` option isn’t set,
+    /// requests to the server will only include EDNS information if they
+    /// are to be padded or the resolver validates answers and thus needs
+    /// the DO bit.
+    pub fn new(conf: ServerConf, resolv: &ResolvConf) -> Self {
+        let edns = resolv.options.use_edns0
+                || (conf.is_tls() && conf.padding.is_some())
+                || resolv.validates();
+        let randomize_case = resolv.randomize_case && !conf.is_tls();
+        ServerInfo {
+            stats: Mutex::new(ServerStats::new(conf.addr)),
+            conf: conf,
+            randomize_case: randomize_case,
+            case: Mutex::new(CaseState::default()),
+            edns: AtomicBool::new(edns),
+            dnssec_ok: resolv.validates(),
+            cookie: if resolv.cookies {
+                Some(Mutex::new(Cookie::new(random())))
+            }
+            else {
+                None
+            },
+            holddown_failures: resolv.holddown_failures,
+            holddown_time: resolv.holddown_time,
+            observer: resolv.observer.clone(),
+        }
+    }
+
+    /// Returns a reference to the server’s configuration.
+    pub fn conf(&self) -> &ServerConf {
+        &self.conf
+    }
+
+    /// Returns the UDP payload size to advertise or `None` for no EDNS.
+    ///
+    /// The payload size is derived from the server’s receive buffer size.
+    /// As per RFC 6891, it is never smaller than 512 bytes.
+    pub fn edns_payload_size(&self) -> Option<u16> {
+        if self.edns.load(Ordering::Relaxed) {
+            Some(self.payload_size())
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns the UDP payload size to advertise if EDNS is used anyway.
+    pub fn payload_size(&self) -> u16 {
+        cmp::max(cmp::min(self.conf.recv_size, ::std::u16::MAX as usize),
+                 512) as u16
+    }
+
+    /// Marks the server as not supporting EDNS.
+    pub fn disable_edns(&self) {
+        self.edns.store(false, Ordering::Relaxed)
+    }
+
+    /// Returns whether to set the DO bit in requests to the server.
+    pub fn dnssec_ok(&self) -> bool {
+        self.dnssec_ok
+    }
+
+    /// Returns whether to randomize the case of query names.
+    pub fn randomize_case(&self) -> bool {
+        if !self.randomize_case {
+            return false
+        }
+        match self.case.lock().unwrap().off_until {
+            Some(until) => Instant::now() >= until,
+            None => true
+        }
+    }
+
+    /// Records a response that echoed the randomized query name exactly.
+    pub fn record_case_match(&self) {
+        self.case.lock().unwrap().mismatches = 0
+    }
+
+    /// Records a response with the query name in a different case.
+    ///
+    /// Once this has happened `CASE_MISMATCHES` times in a row,
+    /// randomization is switched off for `CASE_HOLDDOWN`. Returns whether
+    /// that is the case now.
+    pub fn record_case_mismatch(&self) -> bool {
+        let mut case = self.case.lock().unwrap();
+        case.mismatches += 1;
+        if case.mismatches < CASE_MISMATCHES {
+            return false
+        }
+        case.mismatches = 0;
+        case.off_until = Some(Instant::now() + CASE_HOLDDOWN);
+        true
+    }
+
+    /// Returns the padding policy for requests to the server.
+    ///
+    /// Requests are only padded if the server is reached via an encrypted
+    /// transport. Otherwise, padding would only waste bandwidth.
+    pub fn padding(&self) -> Option<PaddingPolicy> {
+        if self.conf.is_tls() {
+            self.conf.padding
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns the observer to tell about requests to the server.
+    pub fn observer(&self) -> Option<&Arc<Observer>> {
+        self.observer.as_ref()
+    }
+
+    /// Returns the DNS cookie to include in requests to the server.
+    ///
+    /// Returns `None` if cookies are disabled.
+    pub fn cookie(&self) -> Option<Cookie> {
+        self.cookie.as_ref().map(|cookie| *cookie.lock().unwrap())
+    }
+
+    /// Processes the DNS cookie received in a response.
+    ///
+    /// Returns whether the cookie echoes our client cookie. If it does, the
+    /// server cookie is remembered for future requests.
+    pub fn update_cookie(&self, received: &Cookie) -> bool {
+        let mut cookie = match self.cookie {
+            Some(ref cookie) => cookie.lock().unwrap(),
+            None => return false
+        };
+        if received.cookie() != cookie.cookie() {
+            return false
+        }
+        if received.server().is_some() {
+            *cookie = *received
+        }
+        true
+    }
+
+    /// Returns a snapshot of the server’s statistics.
+    pub fn stats(&self) -> ServerStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Returns the smoothed round trip time of the server.
+    pub fn srtt(&self) -> Duration {
+        self.stats.lock().unwrap().srtt
+    }
+
+    /// Returns whether the server is currently held down.
+    pub fn is_held_down(&self) -> bool {
+        match self.stats.lock().unwrap().held_down_until {
+            Some(until) => Instant::now() < until,
+            None => false
+        }
+    }
+
+    /// Records that a request has been sent to the server.
+    pub fn record_request(&self) {
+        self.stats.lock().unwrap().requests += 1
+    }
+
+    /// Records a response that arrived after `rtt`.
+    ///
+    /// A response resets the count of consecutive failures even if it is
+    /// a SERVFAIL or REFUSED response since those say more about the
+    /// question than about the server.
+    pub fn record_response(&self, rtt: Duration, rcode: Rcode) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.responses += 1;
+        match rcode {
+            Rcode::ServFail => stats.servfails += 1,
+            Rcode::Refused => stats.refused += 1,
+            _ => { }
+        }
+        stats.failures = 0;
+        stats.held_down_until = None;
+        stats.update_srtt(rtt);
+    }
+
+    /// Records that a request timed out after `timeout`.
+    ///
+    /// The timeout of the request is used as the round trip time sample
+    /// for the purpose of updating the smoothed round trip time. Since a
+    /// query may have its own timeout, this isn’t necessarily the
+    /// server’s request timeout.
+    pub fn record_timeout(&self, timeout: Duration) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.timeouts += 1;
+        stats.update_srtt(timeout);
+        self.record_failure(&mut stats);
+    }
+
+    /// Records that a request failed with an error.
+    pub fn record_error(&self) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.errors += 1;
+        self.record_failure(&mut stats);
+    }
+
+    /// Ages the smoothed round trip time of a server not chosen.
+    ///
+    /// This slowly decreases the round trip time of servers that aren’t
+    /// asked so that they are eventually tried again.
+    pub fn decay_srtt(&self) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.srtt = stats.srtt / 50 * 49;
+    }
+
+    /// Records a timeout or error and holds the server down if necessary.
+    fn record_failure(&self, stats: &mut ServerStats) {
+        stats.failures += 1;
+        if self.holddown_failures > 0
+                && stats.failures >= self.holddown_failures {
+            stats.held_down_until = Some(Instant::now()
+                                         + self.holddown_time);
+            stats.holddowns += 1;
+            stats.failures = 0;
+        }
+    }
+}
+
+
+//------------ CaseState -----------------------------------------------------
+
+/// What we know about a server preserving the case of query names.
+#[derive(Debug, Default)]
+struct CaseState {
+    /// The number of responses in a row with the query name in the wrong
+    /// case.
+    mismatches: usize,
+
+    /// The time until which case randomization is switched off.
+    off_until: Option<Instant>,
+}
+
+
+//------------ ServerStats ---------------------------------------------------
+
+/// Statistics about an upstream server.
+///
+/// Values of this type are a snapshot of the statistics kept by the
+/// resolver for each server of its configuration. They are returned by
+/// `Resolver::server_stats()`.
+#[derive(Clone, Debug)]
+pub struct ServerStats {
+    /// The address of the server.
+    pub addr: SocketAddr,
+
+    /// The smoothed round trip time.
+    ///
+    /// This is updated BIND-style with each response and timeout. It
+    /// starts out as zero so that all servers get tried early on.
+    pub srtt: Duration,
+
+    /// The number of requests sent to the server.
+    pub requests: u64,
+
+    /// The number of responses received from the server.
+    pub responses: u64,
+
+    /// The number of requests that timed out.
+    pub timeouts: u64,
+
+    /// The number of requests that failed with some other error.
+    pub errors: u64,
+
+    /// The number of responses with the SERVFAIL rcode.
+    pub servfails: u64,
+
+    /// The number of responses with the REFUSED rcode.
+    pub refused: u64,
+
+    /// The number of times the server has been held down.
+    pub holddowns: u64,
+
+    /// The number of consecutive timeouts or errors.
+    pub failures: usize,
+
+    /// The time until which the server is held down if it is.
+    pub held_down_until: Option<Instant>,
+}
+
+impl ServerStats {
+    /// Creates new, empty statistics for the server at `addr`.
+    fn new(addr: SocketAddr) -> Self {
+        ServerStats {
+            addr: addr,
+            srtt: Duration::from_secs(0),
+            requests: 0,
+            responses: 0,
+            timeouts: 0,
+            errors: 0,
+            servfails: 0,
+            refused: 0,
+            holddowns: 0,
+            failures: 0,
+            held_down_until: None,
+        }
+    }
+
+    /// Updates the smoothed round trip time with a new sample.
+    ///
+    /// As in BIND, the new value is 70 percent the old value and 30
+    /// percent the sample.
+    fn update_srtt(&mut self, rtt: Duration) {
+        self.srtt = self.srtt / 10 * 7 + rtt / 10 * 3;
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::time::Duration;
+    use ::iana::Rcode;
+    use super::super::conf::{ResolvConf, ServerConf};
+    use super::*;
+
+    fn server_info(holddown_failures: usize) -> ServerInfo {
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                                   53);
+        let mut conf = ResolvConf::new();
+        conf.holddown_failures = holddown_failures;
+        conf.cookies = true;
+        conf.randomize_case = true;
+        ServerInfo::new(ServerConf::new(addr), &conf)
+    }
+
+    #[test]
+    fn cookie() {
+        let info = server_info(0);
+        let cookie = info.cookie().unwrap();
+        assert_eq!(cookie.server(), None);
+        let client = *cookie.cookie();
+        let other = Cookie::with_server([!client[0]; 8], b"servercookie")
+                           .unwrap();
+        assert!(!info.update_cookie(&other));
+        assert_eq!(info.cookie(), Some(cookie));
+        let received = Cookie::with_server(client, b"servercookie").unwrap();
+        assert!(info.update_cookie(&received));
+        assert_eq!(info.cookie(), Some(received));
+        assert!(info.update_cookie(&Cookie::new(client)));
+        assert_eq!(info.cookie(), Some(received));
+    }
+
+    #[test]
+    fn srtt() {
+        let info = server_info(0);
+        info.record_response(Duration::from_millis(100), Rcode::NoError);
+        assert_eq!(info.srtt(), Duration::from_millis(30));
+        info.record_response(Duration::from_millis(100), Rcode::ServFail);
+        assert_eq!(info.srtt(), Duration::from_millis(51));
+        info.decay_srtt();
+        assert_eq!(info.srtt(), Duration::from_millis(51) / 50 * 49);
+        let stats = info.stats();
+        assert_eq!(stats.responses, 2);
+        assert_eq!(stats.servfails, 1);
+
+        let info = server_info(0);
+        info.record_timeout(Duration::from_millis(500));
+        assert_eq!(info.srtt(), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn holddown() {
+        let info = server_info(2);
+        info.record_timeout(Duration::from_secs(1));
+        assert!(!info.is_held_down());
+        info.record_response(Duration::from_millis(10), Rcode::NoError);
+        info.record_timeout(Duration::from_secs(1));
+        assert!(!info.is_held_down());
+        info.record_error();
+        assert!(info.is_held_down());
+        let stats = info.stats();
+        assert_eq!((stats.timeouts, stats.errors, stats.holddowns),
+                   (2, 1, 1));
+        info.record_response(Duration::from_millis(10), Rcode::NoError);
+        assert!(!info.is_held_down());
+
+        let info = server_info(0);
+        for _ in 0..10 { info.record_timeout(Duration::from_secs(1)) }
+        assert!(!info.is_held_down());
+    }
+
+    #[test]
+    fn case_mismatches() {
+        let info = server_info(0);
+        assert!(info.randomize_case());
+        assert!(!info.record_case_mismatch());
+        assert!(!info.record_case_mismatch());
+        info.record_case_match();
+        assert!(!info.record_case_mismatch());
+        assert!(!info.record_case_mismatch());
+        assert!(info.randomize_case());
+        assert!(info.record_case_mismatch());
+        assert!(!info.randomize_case());
+    }
+}
@@ -1,14 +1,17 @@
 /// TCP channel and transport.
 
 use std::io;
+use std::sync::Arc;
 use std::net::SocketAddr;
+use std::time::Duration;
 use futures::{Poll, StartSend};
 use tokio_core::net::{TcpStream, TcpStreamNew};
 use tokio_core::reactor;
 use ::bits::MessageBuf;
-use super::conf::ServerConf;
 use super::channel::{Channel, ConnectStream, StreamChannel};
+use super::observer::Protocol;
 use super::request::{TransportHandle, TransportRequest};
+use super::server::ServerInfo;
 use super::transport::{TransportMode, spawn_transport};
 
 
@@ -17,16 +20,30 @@ use super::transport::{TransportMode, spawn_transport};
 /// Spawns a new TCP transport for the given server config into a reactor.
 ///
 /// Returns the transport handle for the TCP transport or `None` if TCP
-/// was disabled for this server.
-pub fn tcp_transport(reactor: &reactor::Handle, conf: &ServerConf)
+/// was disabled for this server. If the server is to be reached via TLS
+/// or HTTP, a transport for that protocol is spawned instead.
+pub fn tcp_transport(reactor: &reactor::Handle, info: &Arc<ServerInfo>)
                      -> Option<TransportHandle> {
+    let conf = info.conf();
+    #[cfg(feature = "doh")]
+    {
+        if conf.is_doh() {
+            return super::doh::doh_transport(reactor, info)
+        }
+    }
+    #[cfg(feature = "tls")]
+    {
+        if conf.is_tls() {
+            return super::tls::tls_transport(reactor, info)
+        }
+    }
     let mode = match TransportMode::resolve(conf.tcp,
                                          Some(TransportMode::SingleRequest)) {
         Some(mode) => mode,
         None => return None,
     };
     let channel = TcpChannel::new(conf.addr, reactor.clone());
-    Some(spawn_transport(reactor, channel, mode, conf))
+    Some(spawn_transport(reactor, channel, mode, info, Protocol::Tcp))
 }
 
 
@@ -65,6 +82,10 @@ impl Channel for TcpChannel {
     fn sleep(&mut self) -> Result<(), io::Error> {
         self.0.sleep()
     }
+
+    fn keep_alive(&self) -> Option<Duration> {
+        self.0.keep_alive()
+    }
 }
 
 
@@ -15,16 +15,19 @@
 ///
 /// Consequently, this module contains three different transport
 /// implementations in the `multiplex`, `sequential`, and `single` submodules.
+/// In addition, the `pool` submodule contains a variant of the multiplexing
+/// transport that spreads requests over several channels.
 ///
-/// Towards the outside world, only the `spawn_transport()` function is
-/// really relevant which spawns a transport into a reactor core. Which
-/// particular strategy the transport will be using is defined by the
-/// `TransportMode` enum
+/// Towards the outside world, only the `spawn_transport()` and
+/// `spawn_pool_transport()` functions are really relevant which spawn a
+/// transport into a reactor core. Which particular strategy the transport
+/// will be using is defined by the `TransportMode` enum
 
-pub use self::spawn::{TransportMode, spawn_transport};
+pub use self::spawn::{TransportMode, spawn_pool_transport, spawn_transport};
 
 mod multiplex;
 mod pending;
+mod pool;
 mod sequential;
 mod single;
 mod spawn;
@@ -24,7 +24,7 @@ use super::pending::PendingRequests;
 ///
 /// The transport will use the configuration’s keep alive duration as an
 /// indicator for how long to keep a channel active when there are no new
-/// requests.
+/// requests unless the channel knows the idle timeout of its peer.
 pub struct Transport<C: Channel> {
     /// The receiver for new requests.
     ///
@@ -49,6 +49,12 @@ pub struct Transport<C: Channel> {
 
     /// A request that is waiting to be sent.
     send_request: Option<TransportRequest>,
+
+    /// Is the channel currently sending a request?
+    ///
+    /// While it is, the request is neither in `send_request` nor in
+    /// `pending`, so we must not send the channel to sleep.
+    sending: bool,
 }
 
 
@@ -63,6 +69,7 @@ impl<C: Channel> Transport<C> {
             pending: PendingRequests::new(reactor, conf.request_timeout),
             sleep_timeout: None,
             send_request: None,
+            sending: false,
         }
     }
 }
@@ -105,14 +112,18 @@ impl<C: Channel> Transport<C> {
     /// sorted out by the `poll()` method.
     fn poll_step(&mut self) -> io::Result<()> {
         self.poll_sleep()?;
-        self.poll_recv()?;
         self.poll_send()?;
+        // Receive after sending so that a stream channel that only just
+        // became active gets a chance to register for reading.
+        self.poll_recv()?;
         self.pending.expire();
         self.set_sleep_timeout()?;
         Ok(())
     }
 
     /// Reads and processes responses until reading blocks.
+    ///
+    /// Afterwards, fails all requests the channel has given up on.
     fn poll_recv(&mut self) -> io::Result<()> {
         while let Async::Ready(response) = self.channel.poll_recv()? {
             let id = response.header().id();
@@ -120,6 +131,11 @@ impl<C: Channel> Transport<C> {
                 request.response(response);
             }
         }
+        while let Some((id, err)) = self.channel.poll_failed() {
+            if let Some(request) = self.pending.pop(id) {
+                request.fail(err.into());
+            }
+        }
         Ok(())
     }
 
@@ -129,7 +145,9 @@ impl<C: Channel> Transport<C> {
             match self.channel.poll_send()? {
                 Async::NotReady => return Ok(()),
                 Async::Ready(None) => { }
-                Async::Ready(Some(request)) => {
+                Async::Ready(Some(mut request)) => {
+                    self.sending = false;
+                    request.sent();
                     self.pending.push(request)
                 }
             }
@@ -176,9 +194,9 @@ impl<C: Channel> Transport<C> {
             None => return Ok(()),
             Some(request) => request
         };
-        if let AsyncSink::NotReady(request)
-                    = self.channel.start_send(request)? {
-            self.send_request = Some(request)
+        match self.channel.start_send(request)? {
+            AsyncSink::Ready => self.sending = true,
+            AsyncSink::NotReady(request) => self.send_request = Some(request)
         }
         Ok(())
     }
@@ -199,13 +217,21 @@ impl<C: Channel> Transport<C> {
     }
 
     /// Sets the sleep timer if necessary.
+    ///
+    /// The timer runs for the idle timeout of the channel’s peer if it is
+    /// known or the configured keep alive duration otherwise. It is polled
+    /// right away so that we get woken up when it expires and so that a
+    /// zero timeout sends the channel to sleep immediately.
     fn set_sleep_timeout(&mut self) -> io::Result<()> {
-        if self.sleep_timeout.is_none() && self.pending.is_empty() {
-            self.sleep_timeout
-                = Some(reactor::Timeout::new(self.keep_alive,
-                                             self.pending.reactor())?);
+        if self.sleep_timeout.is_some() || !self.pending.is_empty()
+                || self.sending || self.send_request.is_some() {
+            return Ok(())
         }
-        Ok(())
+        let keep_alive = self.channel.keep_alive().unwrap_or(self.keep_alive);
+        self.sleep_timeout = Some(reactor::Timeout::new(
+            keep_alive, self.pending.reactor()
+        )?);
+        self.poll_sleep()
     }
 }
 
@@ -1,6 +1,7 @@
 //! `PendingRequests`, a collection of pending requests.
 //!
-//! This is used by `multiplex::Transport` to store all the pending requests.
+//! This is used by `multiplex::Transport` and `pool::Transport` to store all
+//! the pending requests.
 
 use std::io;
 use std::collections::{HashMap, VecDeque};
@@ -8,7 +9,7 @@ use std::time::{Duration, Instant};
 use futures::{Async, Future};
 use rand::random;
 use tokio_core::reactor;
-use super::super::error::Error;
+use super::super::error::ErrorKind;
 use super::super::request::TransportRequest;
 
 
@@ -25,8 +26,9 @@ pub struct PendingRequests {
 
     /// An ordered list of message IDs and when they expire.
     ///
-    /// Since we have a fixed duration and monotone time, we can use a
-    /// simple deque here and push new requests to its end.
+    /// Since most requests use the default duration and time is monotone,
+    /// new requests almost always go to the end of the deque. Only those
+    /// with a shorter timeout of their own need to be inserted further up.
     expires: VecDeque<(u16, Instant)>,
 
     /// The optional future for the next time a request expires.
@@ -36,6 +38,8 @@ pub struct PendingRequests {
     reactor: reactor::Handle,
 
     /// The duration until a request expires.
+    ///
+    /// Requests can override this with a timeout of their own.
     duration: Duration,
 }
 
@@ -63,6 +67,13 @@ impl PendingRequests {
         self.requests.is_empty()
     }
 
+    /// Returns whether no more IDs can be reserved.
+    pub fn is_full(&self) -> bool {
+        // Pick a reasonably low number here so that we won’t hang too long
+        // in `reserve()`.
+        self.requests.len() > 0xA000
+    }
+
     /// Prepares a request for later insertion.
     ///
     /// Returns the prepared request on success. If preparing fails, then
@@ -86,9 +97,7 @@ impl PendingRequests {
     pub fn reserve(&mut self) -> Result<u16, ReserveError> {
         use std::collections::hash_map::Entry;
 
-        // Pick a reasonably low number here so that we won’t hang too long
-        // below.
-        if self.requests.len() > 0xA000 {
+        if self.is_full() {
             return Err(ReserveError);
         }
         // XXX I suppose there is a better way to do this. Anyone?
@@ -107,6 +116,8 @@ impl PendingRequests {
     /// for this ID since. Panics otherwise.
     pub fn push(&mut self, request: TransportRequest) {
         let id = request.id().unwrap();
+        let request_timeout = request.request_timeout()
+                                     .unwrap_or(self.duration);
         {
             let entry = self.requests.get_mut(&id)
                                      .expect("pushed unreserved ID");
@@ -115,8 +126,11 @@ impl PendingRequests {
             }
             *entry = Some(request);
         }
-        self.expires.push_back((id, Instant::now() + self.duration));
-        if self.timeout.is_none() {
+        let at = Instant::now() + request_timeout;
+        let pos = self.expires.iter().rposition(|&(_, item)| item <= at)
+                              .map_or(0, |pos| pos + 1);
+        self.expires.insert(pos, (id, at));
+        if pos == 0 || self.timeout.is_none() {
             self.update_timeout();
         }
     }
@@ -193,7 +207,7 @@ impl PendingRequests {
     pub fn fail_all(&mut self) {
         for (_, item) in self.requests.drain() {
             if let Some(item) = item {
-                item.fail(Error::Timeout)
+                item.fail(ErrorKind::Timeout.into())
             }
         }
     }
@@ -0,0 +1,530 @@
+//! A transport that multiplexes requests over a pool of channels.
+
+use std::io;
+use std::time::Duration;
+use futures::{Async, AsyncSink, Future, Poll, Stream};
+use rand::{self, Rng};
+use tokio_core::reactor;
+use super::super::channel::Channel;
+use super::super::conf::ServerConf;
+use super::super::request::{RequestReceiver, TransportRequest};
+use super::pending::PendingRequests;
+
+
+//------------ Transport -----------------------------------------------------
+
+/// A transport that multiplexes requests over a pool of channels.
+///
+/// This works like the multiplexing transport except that it has several
+/// channels, each with its own set of pending requests. Each new request
+/// is sent over one of the channels that can currently take a request,
+/// picked at random. For UDP, where each channel binds its own socket,
+/// this spreads requests over several source ports.
+///
+/// If the configuration’s `udp_socket_uses` is not zero, a channel is
+/// retired after that many requests were sent over it: it doesn’t get any
+/// new requests and once all its pending requests have been resolved, it
+/// is sent to sleep. The next request sent over it will wake it up again
+/// with a fresh socket.
+///
+/// A channel that receives a response whose ID doesn’t match any of its
+/// pending requests is retired the same way, regardless of how often it
+/// was used. Such a response may be an attempt at spoofing, so the socket
+/// is replaced as soon as possible.
+///
+/// A channel is also sent to sleep if it hasn’t been used for the keep
+/// alive duration. If all channels are retired or busy sending, new
+/// requests wait until one becomes available.
+pub struct Transport<C: Channel> {
+    /// The receiver for new requests.
+    ///
+    /// This gets switched to `None` when the receiver gets disconnected. If
+    /// that has happened, we wait until all pending request have expired
+    /// and then end the stream.
+    receiver: Option<RequestReceiver>,
+
+    /// The channels.
+    slots: Vec<Slot<C>>,
+
+    /// The number of requests to send over a channel before retiring it.
+    ///
+    /// If this is zero, channels are never retired.
+    uses: usize,
+
+    /// The duration before we send an idle channel to sleep.
+    keep_alive: Duration,
+
+    /// A handle to the reactor for creating timeouts.
+    reactor: reactor::Handle,
+
+    /// A request that is waiting to be sent and the index of its channel.
+    send_request: Option<(usize, TransportRequest)>,
+}
+
+impl<C: Channel> Transport<C> {
+    /// Creates a new pool transport from a list of channels.
+    pub fn new(receiver: RequestReceiver, channels: Vec<C>,
+               reactor: reactor::Handle, conf: &ServerConf) -> Self {
+        let slots = channels.into_iter().map(|channel| {
+            Slot::new(channel, reactor.clone(), conf)
+        }).collect();
+        Transport {
+            receiver: Some(receiver),
+            slots: slots,
+            uses: conf.udp_socket_uses,
+            keep_alive: conf.keep_alive,
+            reactor: reactor,
+            send_request: None,
+        }
+    }
+}
+
+impl<C: Channel + 'static> Transport<C> {
+    /// Spawns a new pool transport into a reactor.
+    pub fn spawn(receiver: RequestReceiver, channels: Vec<C>,
+                 reactor: &reactor::Handle, conf: &ServerConf) {
+        let transport = Self::new(receiver, channels, reactor.clone(), conf);
+        reactor.spawn(transport);
+    }
+}
+
+
+//--- Future
+
+impl<C: Channel> Future for Transport<C> {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        match self.poll_step() {
+            Ok(()) => Ok(Async::NotReady),
+            Err(_) => {
+                for slot in &mut self.slots {
+                    slot.pending.fail_all();
+                }
+                self.receiver = None;
+                Ok(Async::Ready(()))
+            }
+        }
+    }
+}
+
+impl<C: Channel> Transport<C> {
+    /// Does everything necessary for a single poll.
+    ///
+    /// If this returns `Ok(())`, then polling is done and
+    /// `Ok(Async::NotReady)` should be returned by the `poll()` method.
+    ///
+    /// Any errors happening are passed straight through and need to be
+    /// sorted out by the `poll()` method.
+    fn poll_step(&mut self) -> io::Result<()> {
+        for slot in &mut self.slots {
+            slot.poll_sleep()?;
+        }
+        loop {
+            self.poll_send()?;
+            // Receive after sending so that a channel that only just
+            // became active gets a chance to register for reading.
+            let mut retired = false;
+            for slot in &mut self.slots {
+                slot.poll_recv()?;
+                slot.pending.expire();
+                retired |= slot.retire(self.uses)?;
+            }
+            // A retired channel is available again, so we need to have
+            // another go at sending.
+            if !retired {
+                break
+            }
+        }
+        for index in 0..self.slots.len() {
+            let waiting = match self.send_request {
+                Some((slot, _)) => slot == index,
+                None => false
+            };
+            if !waiting {
+                self.slots[index].set_sleep_timeout(self.keep_alive,
+                                                    &self.reactor)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends out requests until no channel is available or there are none.
+    fn poll_send(&mut self) -> io::Result<()> {
+        for slot in &mut self.slots {
+            slot.poll_send()?;
+        }
+        loop {
+            self.get_send_request()?;
+            let (index, request) = match self.send_request.take() {
+                Some(request) => request,
+                None => return Ok(())
+            };
+            let slot = &mut self.slots[index];
+            match slot.channel.start_send(request)? {
+                AsyncSink::Ready => {
+                    slot.sending = true;
+                    slot.used += 1;
+                }
+                AsyncSink::NotReady(request) => {
+                    self.send_request = Some((index, request));
+                    return Ok(())
+                }
+            }
+            slot.poll_send()?;
+        }
+    }
+
+    /// Attempts to provide a send request.
+    ///
+    /// A request is only taken from the receiver if there is a channel
+    /// available for it. In this case, the request and the index of the
+    /// channel are placed in `self.send_request`.
+    fn get_send_request(&mut self) -> io::Result<()> {
+        if self.send_request.is_some() {
+            return Ok(())
+        }
+        let index = match self.pick_slot() {
+            Some(index) => index,
+            None => return Ok(())
+        };
+        match self.receiver {
+            None => return Ok(()),
+            Some(ref mut receiver) => {
+                match receiver.poll() {
+                    Ok(Async::NotReady) => return Ok(()),
+                    Ok(Async::Ready(Some(request))) => {
+                        let slot = &mut self.slots[index];
+                        self.send_request = slot.pending
+                                                .prepare_request(request)
+                                                .map(|request| {
+                                                    (index, request)
+                                                });
+                        slot.sleep_timeout = None;
+                        return Ok(())
+                    }
+                    Ok(Async::Ready(None)) | Err(_) => {
+                        // Fall through to drop the receiver.
+                    }
+                }
+            }
+        }
+        self.receiver = None;
+        Ok(())
+    }
+
+    /// Picks a random channel among those available for a new request.
+    fn pick_slot(&self) -> Option<usize> {
+        let available: Vec<usize> = self.slots.iter().enumerate()
+            .filter(|&(_, slot)| slot.is_available(self.uses))
+            .map(|(index, _)| index)
+            .collect();
+        if available.is_empty() {
+            None
+        }
+        else {
+            Some(available[rand::thread_rng().gen_range(0, available.len())])
+        }
+    }
+}
+
+
+//------------ Slot ----------------------------------------------------------
+
+/// A channel of the pool and its requests.
+struct Slot<C: Channel> {
+    /// The channel.
+    channel: C,
+
+    /// A map with all the requests currently in flight on the channel.
+    pending: PendingRequests,
+
+    /// A timeout that is started whenever the channel runs out of requests.
+    ///
+    /// When it fires, we send the channel to sleep.
+    sleep_timeout: Option<reactor::Timeout>,
+
+    /// Is the channel currently sending a request?
+    sending: bool,
+
+    /// The number of requests sent since the channel last went to sleep.
+    used: usize,
+
+    /// Has the channel received a response not matching any request?
+    mismatched: bool,
+}
+
+impl<C: Channel> Slot<C> {
+    /// Creates a new slot for a channel.
+    fn new(channel: C, reactor: reactor::Handle, conf: &ServerConf) -> Self {
+        Slot {
+            channel: channel,
+            pending: PendingRequests::new(reactor, conf.request_timeout),
+            sleep_timeout: None,
+            sending: false,
+            used: 0,
+            mismatched: false,
+        }
+    }
+
+    /// Returns whether the channel can take a new request.
+    fn is_available(&self, uses: usize) -> bool {
+        !self.sending && !self.pending.is_full() && !self.mismatched
+            && (uses == 0 || self.used < uses)
+    }
+
+    /// Returns whether the channel has nothing to do.
+    fn is_idle(&self) -> bool {
+        !self.sending && self.pending.is_empty()
+    }
+
+    /// Completes sending of the current request if there is one.
+    fn poll_send(&mut self) -> io::Result<()> {
+        if let Async::Ready(Some(mut request)) = self.channel.poll_send()? {
+            self.sending = false;
+            request.sent();
+            self.pending.push(request)
+        }
+        Ok(())
+    }
+
+    /// Reads and processes responses until reading blocks.
+    ///
+    /// If a response doesn’t match any pending request, the channel is
+    /// marked for retirement.
+    fn poll_recv(&mut self) -> io::Result<()> {
+        while let Async::Ready(response) = self.channel.poll_recv()? {
+            let id = response.header().id();
+            match self.pending.pop(id) {
+                Some(request) => request.response(response),
+                None => self.mismatched = true
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a retired channel to sleep once it is idle.
+    ///
+    /// A channel is retired if it has been used `uses` times or if it
+    /// has received a mismatched response. Returns whether the channel was
+    /// sent to sleep.
+    fn retire(&mut self, uses: usize) -> io::Result<bool> {
+        let retired = self.mismatched || (uses != 0 && self.used >= uses);
+        if !retired || !self.is_idle() {
+            return Ok(false)
+        }
+        self.sleep()?;
+        Ok(true)
+    }
+
+    /// Sends the channel to sleep.
+    fn sleep(&mut self) -> io::Result<()> {
+        self.channel.sleep()?;
+        self.sleep_timeout = None;
+        self.used = 0;
+        self.mismatched = false;
+        Ok(())
+    }
+
+    /// Checks if the sleep timeout expired and sends the channel to sleep.
+    fn poll_sleep(&mut self) -> io::Result<()> {
+        match self.sleep_timeout {
+            None => return Ok(()),
+            Some(ref mut timeout) => {
+                if let Async::NotReady = timeout.poll()? {
+                    return Ok(())
+                }
+            }
+        }
+        self.sleep()
+    }
+
+    /// Sets the sleep timer if the channel is idle.
+    ///
+    /// The timer is polled right away so that we get woken up when it
+    /// expires.
+    fn set_sleep_timeout(&mut self, keep_alive: Duration,
+                         reactor: &reactor::Handle) -> io::Result<()> {
+        if self.sleep_timeout.is_some() || !self.is_idle() || self.used == 0 {
+            return Ok(())
+        }
+        let keep_alive = self.channel.keep_alive().unwrap_or(keep_alive);
+        self.sleep_timeout = Some(reactor::Timeout::new(keep_alive,
+                                                        reactor)?);
+        self.poll_sleep()
+    }
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::rc::Rc;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use futures::{Async, AsyncSink, Poll, StartSend};
+    use futures::task;
+    use tokio_core::reactor::Core;
+    use ::bits::{DNameBuf, MessageBuf};
+    use ::iana::Rtype;
+    use super::super::super::channel::Channel;
+    use super::super::super::conf::{ResolvConf, ServerConf};
+    use super::super::super::observer::Protocol;
+    use super::super::super::request::{QueryRequest, RequestMessage,
+                                       TransportRequest};
+    use super::super::super::server::ServerInfo;
+    use super::super::spawn_pool_transport;
+
+    /// What happened to the channels of a pool.
+    ///
+    /// Each entry has the index of the channel and either `"send"` or
+    /// `"sleep"`.
+    type Log = Rc<RefCell<Vec<(usize, &'static str)>>>;
+
+    /// A channel answering each request by echoing it.
+    struct EchoChannel {
+        index: usize,
+        log: Log,
+
+        /// Precede the response to each request with a mismatched one.
+        spoof: Rc<RefCell<bool>>,
+
+        wr: Option<TransportRequest>,
+        responses: Vec<MessageBuf>,
+    }
+
+    impl Channel for EchoChannel {
+        fn start_send(&mut self, request: TransportRequest)
+                      -> StartSend<TransportRequest, io::Error> {
+            if self.wr.is_some() {
+                return Ok(AsyncSink::NotReady(request))
+            }
+            self.log.borrow_mut().push((self.index, "send"));
+            self.wr = Some(request);
+            Ok(AsyncSink::Ready)
+        }
+
+        fn poll_send(&mut self)
+                     -> Poll<Option<TransportRequest>, io::Error> {
+            let wr = match self.wr.take() {
+                Some(wr) => wr,
+                None => return Ok(Async::Ready(None))
+            };
+            let mut response = MessageBuf::from_vec(
+                wr.message().dgram_bytes().to_vec()
+            ).unwrap();
+            response.header_mut().set_qr(true);
+            if *self.spoof.borrow() {
+                let mut spoofed = response.clone();
+                let id = spoofed.header().id();
+                spoofed.header_mut().set_id(id ^ 0x5555);
+                self.responses.push(spoofed);
+            }
+            self.responses.push(response);
+            task::current().notify();
+            Ok(Async::Ready(Some(wr)))
+        }
+
+        fn poll_recv(&mut self) -> Poll<MessageBuf, io::Error> {
+            if self.responses.is_empty() {
+                Ok(Async::NotReady)
+            }
+            else {
+                Ok(Async::Ready(self.responses.remove(0)))
+            }
+        }
+
+        fn sleep(&mut self) -> io::Result<()> {
+            self.log.borrow_mut().push((self.index, "sleep"));
+            self.wr = None;
+            Ok(())
+        }
+    }
+
+    /// Sends `count` queries one after another over a pool transport.
+    ///
+    /// The pool has `sockets` channels, each used `uses` times. If
+    /// `spoof` is `true`, the first query receives a mismatched response
+    /// before the real one.
+    fn run(sockets: usize, uses: usize, count: usize, spoof: bool) -> Log {
+        let mut core = Core::new().unwrap();
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                                   53);
+        let mut conf = ServerConf::new(addr);
+        conf.udp_sockets = sockets;
+        conf.udp_socket_uses = uses;
+        let resolv = ResolvConf::new();
+        let info = Arc::new(ServerInfo::new(conf, &resolv));
+
+        let log = Log::default();
+        let flag = Rc::new(RefCell::new(spoof));
+        let mut index = 0;
+        let transport = spawn_pool_transport(&core.handle(), || {
+            index += 1;
+            EchoChannel {
+                index: index - 1,
+                log: log.clone(),
+                spoof: flag.clone(),
+                wr: None,
+                responses: Vec::new(),
+            }
+        }, &info, Protocol::Udp);
+
+        let qname = DNameBuf::from_str("www.example.com.").unwrap();
+        for _ in 0..count {
+            let message = RequestMessage::new((&qname, Rtype::A), &resolv)
+                                         .unwrap();
+            let request = QueryRequest::new(message, &transport);
+            let (response, _) = core.run(request).ok().unwrap();
+            assert!(response.header().qr());
+            *flag.borrow_mut() = false;
+        }
+        log
+    }
+
+    #[test]
+    fn rotation() {
+        let log = run(1, 2, 5, false);
+        assert_eq!(*log.borrow(),
+                   vec![(0, "send"), (0, "send"), (0, "sleep"),
+                        (0, "send"), (0, "send"), (0, "sleep"),
+                        (0, "send")]);
+
+        // No channel is used more often than allowed before sleeping.
+        let log = run(3, 2, 20, false);
+        let mut used = [0; 3];
+        for &(index, event) in log.borrow().iter() {
+            if event == "send" {
+                used[index] += 1;
+                assert!(used[index] <= 2);
+            }
+            else {
+                assert_eq!(used[index], 2);
+                used[index] = 0;
+            }
+        }
+        assert_eq!(log.borrow().iter().filter(|item| item.1 == "send")
+                                      .count(), 20);
+    }
+
+    #[test]
+    fn reuse_without_limit() {
+        let log = run(1, 0, 5, false);
+        assert_eq!(*log.borrow(), vec![(0, "send"); 5]);
+    }
+
+    #[test]
+    fn mismatched_response() {
+        // The channel is replaced after the mismatched response even
+        // though it could be used as often as we like.
+        let log = run(1, 0, 3, true);
+        assert_eq!(*log.borrow(),
+                   vec![(0, "send"), (0, "sleep"), (0, "send"),
+                        (0, "send")]);
+    }
+}
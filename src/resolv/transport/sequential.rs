@@ -20,8 +20,9 @@ use super::super::request::{RequestReceiver, TransportRequest};
 ///
 /// A request will time out if a response isn’t received within the time
 /// given by the config’s request timeout. If no new request is received
-/// for the time given by the config’s keep alive duration, the underlying
-/// channel will be sent to sleep.
+/// for the time given by the config’s keep alive duration or the idle
+/// timeout announced by the peer, the underlying channel will be sent to
+/// sleep.
 pub struct Transport<C: Channel> {
     /// The request receiver.
     receiver: RequestReceiver,
@@ -148,9 +149,7 @@ impl<C: Channel> Transport<C> {
             if let Async::Ready(()) = timeout.poll()? {
                 let request = self.request.take().unwrap();
                 request.timeout();
-                let timeout = reactor::Timeout::new(self.keep_alive,
-                                                    &self.reactor)?;
-                return Ok(Async::Ready(State::Doze(timeout)));
+                return self.doze().map(Async::Ready);
             }
         }
         if let State::Doze(ref mut timeout) = self.state {
@@ -208,9 +207,12 @@ impl<C: Channel> Transport<C> {
     /// the request back, proceeding to receiving state.
     fn poll_sending(&mut self) -> Poll<State, io::Error> {
         self.request = try_ready!(self.channel.poll_send());
-        assert!(self.request.is_some());
-        let timeout = reactor::Timeout::new(self.request_timeout,
-                                            &self.reactor)?;
+        let timeout = {
+            let request = self.request.as_mut().expect("no request sent");
+            request.sent();
+            request.request_timeout().unwrap_or(self.request_timeout)
+        };
+        let timeout = reactor::Timeout::new(timeout, &self.reactor)?;
         Ok(Async::Ready(State::Receiving(timeout)))
     }
 
@@ -228,8 +230,16 @@ impl<C: Channel> Transport<C> {
         }
         let request = self.request.take().unwrap();
         request.response(response);
-        let timeout = reactor::Timeout::new(self.keep_alive, &self.reactor)?;
-        Ok(Async::Ready(State::Doze(timeout)))
+        self.doze().map(Async::Ready)
+    }
+
+    /// Returns the dozing state with a freshly started sleep timeout.
+    ///
+    /// If the channel knows the peer’s idle timeout, that is used.
+    /// Otherwise we fall back to the configured keep alive duration.
+    fn doze(&self) -> io::Result<State> {
+        let keep_alive = self.channel.keep_alive().unwrap_or(self.keep_alive);
+        Ok(State::Doze(reactor::Timeout::new(keep_alive, &self.reactor)?))
     }
 }
 
@@ -189,9 +189,12 @@ impl<C: Channel> Transport<C> {
     /// the request back, proceeding to receiving state.
     fn poll_sending(&mut self) -> Poll<State, io::Error> {
         self.request = try_ready!(self.channel.poll_send());
-        assert!(self.request.is_some());
-        let timeout = reactor::Timeout::new(self.request_timeout,
-                                            &self.reactor)?;
+        let timeout = {
+            let request = self.request.as_mut().expect("no request sent");
+            request.sent();
+            request.request_timeout().unwrap_or(self.request_timeout)
+        };
+        let timeout = reactor::Timeout::new(timeout, &self.reactor)?;
         Ok(Async::Ready(State::Receiving(timeout)))
     }
 
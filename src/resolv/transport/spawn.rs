@@ -1,11 +1,14 @@
 //! The `spawn_transport()` function for starting transport and what it needs.
 
+use std::cmp;
+use std::sync::Arc;
 use tokio_core::reactor;
 use super::super::channel::Channel;
-use super::super::conf::ServerConf;
 use super::super::request::TransportHandle;
 use super::super::conf::ServerMode;
-use super::{single, sequential, multiplex};
+use super::super::observer::Protocol;
+use super::super::server::ServerInfo;
+use super::{single, sequential, multiplex, pool};
 
 
 //------------ spawn_transport -----------------------------------------------
@@ -15,15 +18,18 @@ use super::{single, sequential, multiplex};
 /// The transport will be spawned into `reactor` which will also be used by
 /// both the transport and the underlying channel given by `channel`. The
 /// strategy for dispatching messages is given through `mode`. Any additional
-/// information that the transport may need is taken from `conf`.
+/// information that the transport may need is taken from the server
+/// configuration in `info`. The protocol used by the channel is given via
+/// `protocol` and reported to observers.
 ///
 /// The function returns a transport handle for dispatching requests to the
 /// newly spawned transport.
 pub fn spawn_transport<C>(reactor: &reactor::Handle, channel: C,
-                          mode: TransportMode, conf: &ServerConf)
-                          -> TransportHandle
+                          mode: TransportMode, info: &Arc<ServerInfo>,
+                          protocol: Protocol) -> TransportHandle
                        where C : Channel + 'static {
-    let (tx, rx) = TransportHandle::channel();
+    let (tx, rx) = TransportHandle::channel(info.clone(), protocol);
+    let conf = info.conf();
     match mode {
         TransportMode::SingleRequest
             => single::Transport::spawn(rx, channel, reactor, conf),
@@ -36,6 +42,28 @@ pub fn spawn_transport<C>(reactor: &reactor::Handle, channel: C,
 }
 
 
+//------------ spawn_pool_transport ------------------------------------------
+
+/// Spawns a new transport multiplexing over a pool of channels.
+///
+/// This is like `spawn_transport()` with multiplex mode except that the
+/// transport spreads requests over the channels produced by calling
+/// `channel` as many times as given by the `udp_sockets` field of the
+/// server configuration in `info`, but at least once.
+pub fn spawn_pool_transport<C, F>(reactor: &reactor::Handle,
+                                  mut channel: F, info: &Arc<ServerInfo>,
+                                  protocol: Protocol) -> TransportHandle
+                               where C: Channel + 'static,
+                                     F: FnMut() -> C {
+    let (tx, rx) = TransportHandle::channel(info.clone(), protocol);
+    let conf = info.conf();
+    let channels = (0..cmp::max(conf.udp_sockets, 1)).map(|_| channel())
+                                                    .collect();
+    pool::Transport::spawn(rx, channels, reactor, conf);
+    tx
+}
+
+
 //------------ TransportMode -------------------------------------------------
 
 /// The mode a transport will operate in.
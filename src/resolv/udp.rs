@@ -1,32 +1,50 @@
 /// UDP channel and transport.
 
 use std::io;
+use std::sync::Arc;
 use std::net::{IpAddr, SocketAddr};
 use futures::{Async, AsyncSink, Poll, StartSend};
+use rand::{self, Rng};
 use tokio_core::net::UdpSocket;
 use tokio_core::reactor;
 use ::bits::MessageBuf;
-use super::conf::ServerConf;
+use super::observer::Protocol;
 use super::request::{TransportHandle, TransportRequest};
 use super::channel::Channel;
-use super::transport::{TransportMode, spawn_transport};
+use super::server::ServerInfo;
+use super::transport::{TransportMode, spawn_pool_transport,
+                       spawn_transport};
 
 
 //------------ udp_transport -------------------------------------------------
 
-/// Spawns a new TCP transport for the given server config into a reactor.
+/// Spawns a new UDP transport for the given server config into a reactor.
 ///
-/// Returns the transport handle for the TCP transport or `None` if TCP
-/// was disabled for this server.
-pub fn udp_transport(reactor: &reactor::Handle, conf: &ServerConf)
+/// Returns the transport handle for the UDP transport or `None` if UDP
+/// was disabled for this server. UDP is always disabled for servers that
+/// are to be reached via TLS or HTTP.
+pub fn udp_transport(reactor: &reactor::Handle, info: &Arc<ServerInfo>)
                      -> Option<TransportHandle> {
+    let conf = info.conf();
+    if conf.is_tls() || conf.is_doh() {
+        return None
+    }
     let mode = match TransportMode::resolve(conf.udp,
                                          Some(TransportMode::Multiplex)) {
         Some(mode) => mode,
         None => return None,
     };
-    let channel = UdpChannel::new(conf.addr, reactor.clone(), conf.recv_size);
-    Some(spawn_transport(reactor, channel, mode, conf))
+    if let TransportMode::Multiplex = mode {
+        if conf.is_udp_pool() {
+            return Some(spawn_pool_transport(reactor, || {
+                UdpChannel::new(conf.addr, reactor.clone(), conf.recv_size,
+                                true)
+            }, info, Protocol::Udp))
+        }
+    }
+    let channel = UdpChannel::new(conf.addr, reactor.clone(), conf.recv_size,
+                                  false);
+    Some(spawn_transport(reactor, channel, mode, info, Protocol::Udp))
 }
 
 
@@ -47,6 +65,11 @@ struct UdpChannel {
     /// The maximum size of an incoming message.
     recv_size: usize,
 
+    /// Bind the socket to a random port chosen by us?
+    ///
+    /// If this is `false`, the operating system picks the port.
+    random_port: bool,
+
     /// The socket if we currently have one.
     sock: Option<UdpSocket>,
 
@@ -56,18 +79,47 @@ struct UdpChannel {
 
 impl UdpChannel {
     /// Creates a new UDP channel.
-    fn new(peer: SocketAddr, handle: reactor::Handle, recv_size: usize)
-           -> Self {
+    fn new(peer: SocketAddr, handle: reactor::Handle, recv_size: usize,
+           random_port: bool) -> Self {
         UdpChannel {
             peer: peer,
             handle: handle,
             recv_size: recv_size,
+            random_port: random_port,
             sock: None,
             wr: None,
         }
     }
+
+    /// Binds a new socket.
+    ///
+    /// If we are to pick a random port, we try a few times to find a free
+    /// one before leaving it to the operating system after all.
+    fn bind(&self) -> io::Result<UdpSocket> {
+        let ip = match self.peer {
+            SocketAddr::V4(_) => IpAddr::V4(0.into()),
+            SocketAddr::V6(_) => IpAddr::V6([0;16].into())
+        };
+        if self.random_port {
+            let mut rng = rand::thread_rng();
+            for _ in 0..BIND_ATTEMPTS {
+                let port = rng.gen_range(MIN_PORT, u16::max_value());
+                let local = SocketAddr::new(ip, port);
+                if let Ok(sock) = UdpSocket::bind(&local, &self.handle) {
+                    return Ok(sock)
+                }
+            }
+        }
+        UdpSocket::bind(&SocketAddr::new(ip, 0), &self.handle)
+    }
 }
 
+/// The lowest port to pick when binding to a random port.
+const MIN_PORT: u16 = 1024;
+
+/// The number of random ports to try before leaving it to the system.
+const BIND_ATTEMPTS: usize = 10;
+
 
 //--- Channel
 
@@ -79,13 +131,7 @@ impl Channel for UdpChannel {
         }
         self.wr = Some(request);
         if self.sock.is_none() {
-            let local = match self.peer {
-                SocketAddr::V4(_)
-                    => SocketAddr::new(IpAddr::V4(0.into()), 0),
-                SocketAddr::V6(_)
-                    => SocketAddr::new(IpAddr::V6([0;16].into()), 0)
-            };
-            self.sock = Some(UdpSocket::bind(&local, &self.handle)?);
+            self.sock = Some(self.bind()?);
         }
         Ok(AsyncSink::Ready)
     }
@@ -138,3 +184,71 @@ impl Channel for UdpChannel {
         Ok(())
     }
 }
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::net::UdpSocket as StdUdpSocket;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use ::bits::{DNameBuf, MessageBuf};
+    use ::iana::Rtype;
+    use ::master::reader::Reader;
+    use super::super::conf::{ResolvConf, ServerConf};
+    use super::super::mock::{Reply, Request, Responder, Zone};
+    use super::super::Resolver;
+
+    /// Runs a server answering from a zone and returns its source ports.
+    fn serve(sock: StdUdpSocket) -> Arc<Mutex<Vec<u16>>> {
+        let ports = Arc::new(Mutex::new(Vec::new()));
+        let res = ports.clone();
+        sock.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        thread::spawn(move || {
+            let zone = Zone::from_reader(Reader::create(
+                "example.com. 3600 IN SOA ns.example.com. \
+                     hostmaster.example.com. 1 3600 600 86400 300\n\
+                 host.example.com. 3600 IN A 192.0.2.1\n"
+            )).unwrap();
+            let server = sock.local_addr().unwrap();
+            let mut buf = [0u8; 4096];
+            while let Ok((len, peer)) = sock.recv_from(&mut buf) {
+                ports.lock().unwrap().push(peer.port());
+                let msg = MessageBuf::from_bytes(&buf[..len]).unwrap();
+                let request = Request::new(&msg, server, false);
+                if let Reply::Response(response) = zone.respond(&request) {
+                    sock.send_to(response.as_bytes(), peer).unwrap();
+                }
+            }
+        });
+        res
+    }
+
+    #[test]
+    fn socket_pool() {
+        let sock = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut server = ServerConf::new(sock.local_addr().unwrap());
+        server.udp_sockets = 2;
+        server.udp_socket_uses = 1;
+        let ports = serve(sock);
+        let mut conf = ResolvConf::new();
+        conf.servers.push(server);
+        conf.finalize();
+
+        let name = DNameBuf::from_str("host.example.com.").unwrap();
+        for _ in 0..4 {
+            let response = Resolver::run_with_conf(conf.clone(), |resolv| {
+                resolv.query((name.clone(), Rtype::A))
+            }).unwrap();
+            assert_eq!(response.answer().unwrap().count(), 1);
+        }
+        let ports = ports.lock().unwrap();
+        assert_eq!(ports.len(), 4);
+        assert!(ports.iter().all(|&port| port >= super::MIN_PORT));
+        assert!(ports.iter().collect::<HashSet<_>>().len() > 1);
+    }
+}
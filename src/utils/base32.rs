@@ -0,0 +1,62 @@
+//! Encoding binary data in Base 32 with extended hex alphabet.
+//!
+//! NSEC3 records use the variant of Base 32 defined in section 7 of
+//! [RFC 4648] for the hashed owner names. Unlike the plain Base 32
+//! encoding, it preserves the sort order of the encoded data which makes
+//! it possible to compare hashed names by their encoding. As with Base 64,
+//! we only provide encoding. NSEC3 omits the padding, so we do, too.
+//!
+//! [RFC 4648]: https://tools.ietf.org/html/rfc4648
+
+use std::fmt;
+
+
+/// The alphabet of the Base 32 encoding with extended hex alphabet.
+///
+/// DNS names are case insensitive and the NSEC3 hashes are normally
+/// displayed in lower case, so we use lower case letters.
+const ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+/// Returns the encoding of `bytes` without padding.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let mut bits = 0u16;
+    let mut len = 0;
+    for &byte in bytes {
+        bits = bits << 8 | u16::from(byte);
+        len += 8;
+        while len >= 5 {
+            len -= 5;
+            res.push(ALPHABET[(bits >> len & 0x1F) as usize] as char);
+        }
+    }
+    if len > 0 {
+        res.push(ALPHABET[(bits << (5 - len) & 0x1F) as usize] as char);
+    }
+    res
+}
+
+/// Writes the encoding of `bytes` without padding to a formatter.
+pub fn display_hex(bytes: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&encode_hex(bytes))
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        // Test vectors from RFC 4648, section 10, sans padding.
+        assert_eq!(encode_hex(b""), "");
+        assert_eq!(encode_hex(b"f"), "co");
+        assert_eq!(encode_hex(b"fo"), "cpng");
+        assert_eq!(encode_hex(b"foo"), "cpnmu");
+        assert_eq!(encode_hex(b"foob"), "cpnmuog");
+        assert_eq!(encode_hex(b"fooba"), "cpnmuoj1");
+        assert_eq!(encode_hex(b"foobar"), "cpnmuoj1e8");
+    }
+}
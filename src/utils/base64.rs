@@ -0,0 +1,71 @@
+//! Encoding binary data in Base 64.
+//!
+//! Record types such as DNSKEY or RRSIG present their binary data in the
+//! Base 64 encoding defined in section 4 of [RFC 4648]. Since we only
+//! need the encoding for displaying such data, this module only provides
+//! that direction.
+//!
+//! [RFC 4648]: https://tools.ietf.org/html/rfc4648
+
+use std::fmt;
+
+
+/// The alphabet of the Base 64 encoding.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                              abcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Writes the Base 64 encoding of `bytes` to a formatter.
+pub fn display(bytes: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+    use std::fmt::Write;
+
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = chunk.get(1).cloned().unwrap_or(0);
+        let b2 = chunk.get(2).cloned().unwrap_or(0);
+        f.write_char(ALPHABET[(b0 >> 2) as usize] as char)?;
+        f.write_char(ALPHABET[((b0 & 0x03) << 4 | b1 >> 4) as usize]
+                     as char)?;
+        if chunk.len() > 1 {
+            f.write_char(ALPHABET[((b1 & 0x0F) << 2 | b2 >> 6) as usize]
+                         as char)?;
+        }
+        else {
+            f.write_char('=')?;
+        }
+        if chunk.len() > 2 {
+            f.write_char(ALPHABET[(b2 & 0x3F) as usize] as char)?;
+        }
+        else {
+            f.write_char('=')?;
+        }
+    }
+    Ok(())
+}
+
+
+//============ Testing =======================================================
+
+#[cfg(test)]
+mod test {
+    use std::fmt;
+
+    struct Base64(&'static [u8]);
+
+    impl fmt::Display for Base64 {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            super::display(self.0, f)
+        }
+    }
+
+    #[test]
+    fn display() {
+        // Test vectors from RFC 4648, section 10.
+        assert_eq!(format!("{}", Base64(b"")), "");
+        assert_eq!(format!("{}", Base64(b"f")), "Zg==");
+        assert_eq!(format!("{}", Base64(b"fo")), "Zm8=");
+        assert_eq!(format!("{}", Base64(b"foo")), "Zm9v");
+        assert_eq!(format!("{}", Base64(b"foob")), "Zm9vYg==");
+        assert_eq!(format!("{}", Base64(b"fooba")), "Zm9vYmE=");
+        assert_eq!(format!("{}", Base64(b"foobar")), "Zm9vYmFy");
+    }
+}